@@ -1,33 +1,64 @@
 //! AST-aware structural search and rewrite powered by ast-grep.
 
 use std::{
-	collections::{BTreeMap, BTreeSet, HashMap},
+	borrow::Cow,
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
 	path::{Path, PathBuf},
+	sync::Arc,
 };
 
 use ast_grep_core::{
-	Language, MatchStrictness, matcher::Pattern, source::Edit, tree_sitter::LanguageExt,
+	AstGrep, Doc, Language, MatchStrictness, Node,
+	matcher::{Matcher, Pattern},
+	meta_var::MetaVarEnv,
+	source::Edit,
+	tree_sitter::{LanguageExt, StrDoc},
 };
+use bit_set::BitSet;
+use dashmap::DashMap;
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
-
-use crate::{fs_cache, glob_util, language::SupportLang, task};
+use rayon::prelude::*;
+
+use crate::{
+	ast_fix::{self, Fixer, Transforms},
+	ast_rule::{self, RuleDocument, RuleMatcher},
+	diff, fs_cache, git,
+	glob_util::{self, GlobFilter},
+	grep::ContextLine,
+	language::{DynamicLang, SupportLang},
+	outline::{self, SymbolKind},
+	parse_cache, task,
+};
 
 const DEFAULT_FIND_LIMIT: u32 = 50;
 
 #[napi(object)]
 pub struct AstFindOptions<'env> {
 	pub patterns:     Option<Vec<String>>,
+	/// ast-grep rule documents (YAML or JSON), searched alongside `patterns`.
+	pub rules:        Option<String>,
 	pub lang:         Option<String>,
 	pub path:         Option<String>,
-	pub glob:         Option<String>,
+	pub glob:         Option<Either<String, Vec<String>>>,
+	pub exclude:      Option<Vec<String>>,
 	pub selector:     Option<String>,
 	pub strictness:   Option<String>,
 	pub limit:        Option<u32>,
 	pub offset:       Option<u32>,
 	#[napi(js_name = "includeMeta")]
 	pub include_meta: Option<bool>,
+	/// Source lines to return before and after each match; also reports the
+	/// enclosing named scope.
 	pub context:      Option<u32>,
+	/// Search file contents at this git revision instead of the working tree.
+	pub rev:          Option<String>,
+	/// Only search files changed versus `head`, `index`, `staged` or
+	/// `mergeBase`.
+	pub changed:      Option<String>,
+	/// Branch for `changed: "mergeBase"`.
+	#[napi(js_name = "changedBase")]
+	pub changed_base: Option<String>,
 	pub signal:       Option<Unknown<'env>>,
 	#[napi(js_name = "timeoutMs")]
 	pub timeout_ms:   Option<u32>,
@@ -51,6 +82,29 @@ pub struct AstFindMatch {
 	pub end_column:     u32,
 	#[napi(js_name = "metaVariables")]
 	pub meta_variables: Option<HashMap<String, String>>,
+	/// Id of the rule document that matched (for `rules`).
+	#[napi(js_name = "ruleId")]
+	pub rule_id:        Option<String>,
+	/// Lines before the match (with `context`).
+	#[napi(js_name = "contextBefore")]
+	pub context_before: Option<Vec<ContextLine>>,
+	/// Lines after the match (with `context`).
+	#[napi(js_name = "contextAfter")]
+	pub context_after:  Option<Vec<ContextLine>>,
+	/// Innermost named definition containing the match (with `context`).
+	pub scope:          Option<AstMatchScope>,
+}
+
+/// A named definition (function, class, impl, ...) enclosing a match.
+#[napi(object)]
+pub struct AstMatchScope {
+	/// Tree-sitter node kind, e.g. `function_item` or `class_declaration`.
+	pub kind:       String,
+	pub name:       String,
+	#[napi(js_name = "startLine")]
+	pub start_line: u32,
+	#[napi(js_name = "endLine")]
+	pub end_line:   u32,
 }
 
 #[napi(object)]
@@ -71,9 +125,14 @@ pub struct AstFindResult {
 #[napi(object)]
 pub struct AstReplaceOptions<'env> {
 	pub rewrites:            Option<HashMap<String, String>>,
+	/// ast-grep rule documents (YAML or JSON); each needs a `fix` template.
+	pub rules:               Option<String>,
+	/// ast-grep `transform` mapping (YAML or JSON) for `rewrites` templates.
+	pub transform:           Option<String>,
 	pub lang:                Option<String>,
 	pub path:                Option<String>,
-	pub glob:                Option<String>,
+	pub glob:                Option<Either<String, Vec<String>>>,
+	pub exclude:             Option<Vec<String>>,
 	pub selector:            Option<String>,
 	pub strictness:          Option<String>,
 	#[napi(js_name = "dryRun")]
@@ -84,6 +143,8 @@ pub struct AstReplaceOptions<'env> {
 	pub max_files:           Option<u32>,
 	#[napi(js_name = "failOnParseError")]
 	pub fail_on_parse_error: Option<bool>,
+	/// Attach a unified diff to each entry of `fileChanges`.
+	pub diff:                Option<bool>,
 	pub signal:              Option<Unknown<'env>>,
 	#[napi(js_name = "timeoutMs")]
 	pub timeout_ms:          Option<u32>,
@@ -114,6 +175,8 @@ pub struct AstReplaceChange {
 pub struct AstReplaceFileChange {
 	pub path:  String,
 	pub count: u32,
+	/// Unified diff of the file, when `diff` is set.
+	pub diff:  Option<String>,
 }
 
 #[napi(object)]
@@ -137,6 +200,8 @@ pub struct AstReplaceResult {
 struct FileCandidate {
 	absolute_path: PathBuf,
 	display_path:  String,
+	/// Contents at a git revision, read instead of `absolute_path`.
+	blob:          Option<git::Blob>,
 }
 
 struct PendingFileChange {
@@ -148,9 +213,83 @@ fn to_u32(value: usize) -> u32 {
 	value.min(u32::MAX as usize) as u32
 }
 
+/// Lines `range` (0-based, clamped to the file) as 1-based context lines.
+fn context_lines(lines: &[&str], range: std::ops::Range<usize>) -> Vec<ContextLine> {
+	let end = range.end.min(lines.len());
+	let start = range.start.min(end);
+	(start..end)
+		.map(|index| ContextLine {
+			line_number: to_u32(index + 1),
+			line:        lines[index].to_string(),
+		})
+		.collect()
+}
+
+/// Whether a node kind declares a named scope: a declaration `outline` lists
+/// that can hold code (not constants or type aliases), or an anonymous
+/// function or class named after its binding.
+fn is_scope_kind(kind: &str) -> bool {
+	match kind {
+		"arrow_function"
+		| "function"
+		| "function_expression"
+		| "generator_function"
+		| "class_expression"
+		| "class_declaration"
+		| "secondary_constructor" => true,
+		_ => outline::generic_kind(kind)
+			.is_some_and(|kind| !matches!(kind, SymbolKind::Const | SymbolKind::Type)),
+	}
+}
+
+/// Name of a scope node: its `name` field, the innermost `declarator` (C and
+/// C++ functions) or the `type` of a Rust `impl`. Anonymous functions take the
+/// name of the variable or key they are bound to; grammars without fields
+/// fall back to the first identifier child.
+fn scope_name<D: Doc>(node: &Node<'_, D>) -> Option<String> {
+	if let Some(name) = node.field("name") {
+		return Some(name.text().into_owned());
+	}
+	if let Some(mut declarator) = node.field("declarator") {
+		while let Some(inner) = declarator.field("declarator") {
+			declarator = inner;
+		}
+		return Some(declarator.text().into_owned());
+	}
+	if let Some(ty) = node.field("type") {
+		return Some(ty.text().into_owned());
+	}
+	if let Some(parent) = node.parent()
+		&& let Some(name) = parent
+			.field("name")
+			.or_else(|| parent.field("key"))
+			.or_else(|| parent.field("left"))
+	{
+		return Some(name.text().into_owned());
+	}
+	node
+		.children()
+		.find(|child| child.is_named() && child.kind().ends_with("identifier"))
+		.map(|ident| ident.text().into_owned())
+}
+
+fn enclosing_scope<D: Doc>(node: &Node<'_, D>, origin: RegionOrigin) -> Option<AstMatchScope> {
+	node.ancestors().find_map(|ancestor| {
+		if !ancestor.is_named() || !is_scope_kind(&ancestor.kind()) {
+			return None;
+		}
+		Some(AstMatchScope {
+			kind:       ancestor.kind().into_owned(),
+			name:       scope_name(&ancestor)?,
+			start_line: to_u32(origin.line(ancestor.start_pos().line()).saturating_add(1)),
+			end_line:   to_u32(origin.line(ancestor.end_pos().line()).saturating_add(1)),
+		})
+	})
+}
+
 /// Single source of truth: every recognised alias (lowercased) → `SupportLang`.
 /// `resolve_supported_lang` does a lookup here; error messages list the keys.
-static LANG_ALIASES: phf::Map<&'static str, SupportLang> = phf::phf_map! {
+pub(crate) static LANG_ALIASES: phf::Map<&'static str, SupportLang> = phf::phf_map! {
 	"bash"           => SupportLang::Bash,
 	"sh"             => SupportLang::Bash,
 	"c"              => SupportLang::C,
@@ -162,6 +301,7 @@ static LANG_ALIASES: phf::Map<&'static str, SupportLang> = phf::phf_map! {
 	"c#"             => SupportLang::CSharp,
 	"cs"             => SupportLang::CSharp,
 	"css"            => SupportLang::Css,
+	"scss"           => SupportLang::Css,
 	"diff"           => SupportLang::Diff,
 	"patch"          => SupportLang::Diff,
 	"elixir"         => SupportLang::Elixir,
@@ -229,19 +369,28 @@ static LANG_ALIASES: phf::Map<&'static str, SupportLang> = phf::phf_map! {
 };
 
 fn supported_lang_list() -> String {
-	let mut keys: Vec<&str> = LANG_ALIASES.keys().copied().collect();
+	let mut keys: Vec<String> = LANG_ALIASES.keys().map(|key| (*key).to_string()).collect();
+	for lang in DynamicLang::all() {
+		keys.push(lang.name().to_string());
+		keys.extend(lang.aliases());
+	}
 	keys.sort_unstable();
 	keys.join(", ")
 }
 
-fn resolve_supported_lang(value: &str) -> Result<SupportLang> {
+pub(crate) fn resolve_supported_lang(value: &str) -> Result<SupportLang> {
 	let lower = value.to_ascii_lowercase();
-	LANG_ALIASES.get(lower.as_str()).copied().ok_or_else(|| {
-		Error::from_reason(format!(
-			"Unsupported language '{value}'. Supported: {}",
-			supported_lang_list()
-		))
-	})
+	let registered = || DynamicLang::find(&lower).map(SupportLang::Dynamic);
+	LANG_ALIASES
+		.get(lower.as_str())
+		.copied()
+		.or_else(registered)
+		.ok_or_else(|| {
+			Error::from_reason(format!(
+				"Unsupported language '{value}'. Supported: {}",
+				supported_lang_list()
+			))
+		})
 }
 
 fn resolve_language(lang: Option<&str>, file_path: &Path) -> Result<SupportLang> {
@@ -256,6 +405,30 @@ fn resolve_language(lang: Option<&str>, file_path: &Path) -> Result<SupportLang>
 	})
 }
 
+/// Language and source text for single-file APIs that take `path` and/or
+/// inline `content`; `path` only names the language when `content` is given.
+pub(crate) fn load_single_source(
+	api: &str,
+	path: Option<&Path>,
+	content: Option<String>,
+	lang: Option<&str>,
+) -> Result<(SupportLang, String)> {
+	let language = match (path, lang) {
+		(Some(path), lang) => resolve_language(lang, path)?,
+		(None, Some(lang)) => resolve_language(Some(lang), Path::new(""))?,
+		(None, None) => {
+			return Err(Error::from_reason(format!("`{api}` needs `lang` when `path` is not given")));
+		},
+	};
+	let source = match (content, path) {
+		(Some(content), _) => content,
+		(None, Some(path)) => std::fs::read_to_string(path)
+			.map_err(|err| Error::from_reason(format!("Failed to read {}: {err}", path.display())))?,
+		(None, None) => return Err(Error::from_reason(format!("`{api}` needs `path` or `content`"))),
+	};
+	Ok((language, source))
+}
+
 /// Returns true if the file's extension resolves to a supported language.
 /// When `lang` is explicitly provided, all files are considered candidates
 /// (the user chose to treat them as that language). When `lang` is None,
@@ -306,7 +479,7 @@ fn infer_single_replace_lang(
 	}
 	Ok(inferred.into_iter().next().expect("non-empty inferred set"))
 }
-fn parse_strictness(value: Option<&str>) -> Result<MatchStrictness> {
+pub(crate) fn parse_strictness(value: Option<&str>) -> Result<MatchStrictness> {
 	let Some(raw) = value.map(str::trim).filter(|v| !v.is_empty()) else {
 		return Ok(MatchStrictness::Smart);
 	};
@@ -330,7 +503,7 @@ fn normalize_search_path(path: Option<String>) -> Result<PathBuf> {
 fn collect_from_entries(
 	root: &Path,
 	entries: &[fs_cache::GlobMatch],
-	glob_set: Option<&globset::GlobSet>,
+	glob_filter: Option<&GlobFilter>,
 	mentions_node_modules: bool,
 	ct: &task::CancelToken,
 ) -> Result<Vec<FileCandidate>> {
@@ -344,33 +517,40 @@ fn collect_from_entries(
 		if fs_cache::should_skip_path(Path::new(&relative), mentions_node_modules) {
 			continue;
 		}
-		if let Some(glob_set) = glob_set
-			&& !glob_set.is_match(&relative)
+		if let Some(glob_filter) = glob_filter
+			&& !glob_filter.is_match(&relative)
 		{
 			continue;
 		}
-		files.push(FileCandidate { absolute_path: root.join(&relative), display_path: relative });
+		files.push(FileCandidate {
+			absolute_path: root.join(&relative),
+			display_path:  relative,
+			blob:          None,
+		});
 	}
 	Ok(files)
 }
 
+/// Display path for a search path that names a single file.
+fn file_display_path(path: &Path) -> String {
+	path
+		.file_name()
+		.and_then(|name| name.to_str())
+		.map_or_else(|| path.to_string_lossy().into_owned(), std::string::ToString::to_string)
+}
+
 fn collect_candidates(
 	path: Option<String>,
-	glob: Option<&str>,
+	glob: Option<&Either<String, Vec<String>>>,
+	exclude: Option<&[String]>,
 	ct: &task::CancelToken,
 ) -> Result<Vec<FileCandidate>> {
 	let search_path = normalize_search_path(path)?;
 	let metadata = std::fs::metadata(&search_path)
 		.map_err(|err| Error::from_reason(format!("Path not found: {err}")))?;
 	if metadata.is_file() {
-		let display_path = search_path
-			.file_name()
-			.and_then(|name| name.to_str())
-			.map_or_else(
-				|| search_path.to_string_lossy().into_owned(),
-				std::string::ToString::to_string,
-			);
-		return Ok(vec![FileCandidate { absolute_path: search_path, display_path }]);
+		let display_path = file_display_path(&search_path);
+		return Ok(vec![FileCandidate { absolute_path: search_path, display_path, blob: None }]);
 	}
 	if !metadata.is_dir() {
 		return Err(Error::from_reason(format!(
@@ -379,27 +559,166 @@ fn collect_candidates(
 		)));
 	}
 
-	let glob_set = glob_util::try_compile_glob(glob, false)?;
-	let mentions_node_modules = glob.is_some_and(|value| value.contains("node_modules"));
+	let glob_filter = glob_util::compile_glob_filter(glob, exclude, false)?;
+	let mentions_node_modules = glob_filter
+		.as_ref()
+		.is_some_and(|filter| filter.includes_text("node_modules"));
 	let scan = fs_cache::get_or_scan(&search_path, true, true, ct)?;
 	let mut files = collect_from_entries(
 		&search_path,
 		&scan.entries,
-		glob_set.as_ref(),
+		glob_filter.as_ref(),
 		mentions_node_modules,
 		ct,
 	)?;
 
 	if files.is_empty() && scan.cache_age_ms >= fs_cache::empty_recheck_ms() {
 		let fresh = fs_cache::force_rescan(&search_path, true, true, true, ct)?;
-		files =
-			collect_from_entries(&search_path, &fresh, glob_set.as_ref(), mentions_node_modules, ct)?;
+		files = collect_from_entries(
+			&search_path,
+			&fresh,
+			glob_filter.as_ref(),
+			mentions_node_modules,
+			ct,
+		)?;
 	}
 
 	files.sort_by(|a, b| a.display_path.cmp(&b.display_path));
 	Ok(files)
 }
 
+/// Like [`collect_candidates`], but lists the files at git revision `rev`;
+/// their blobs are read as each file is searched.
+fn collect_revision_candidates(
+	path: Option<String>,
+	rev: &str,
+	glob: Option<&Either<String, Vec<String>>>,
+	exclude: Option<&[String]>,
+	keep: impl Fn(&Path) -> bool,
+	ct: &task::CancelToken,
+) -> Result<Vec<FileCandidate>> {
+	let search_path = normalize_search_path(path)?;
+	let tree = git::tree_under(&search_path, rev)?;
+	let glob_filter = glob_util::compile_glob_filter(glob, exclude, false)?;
+	let mentions_node_modules = glob_filter
+		.as_ref()
+		.is_some_and(|filter| filter.includes_text("node_modules"));
+
+	let pool = tree.blob_pool();
+	let mut files = Vec::new();
+	for entry in &tree.entries {
+		ct.heartbeat()?;
+		let display_path = if entry.relative.is_empty() {
+			file_display_path(&search_path)
+		} else {
+			if fs_cache::should_skip_path(Path::new(&entry.relative), mentions_node_modules) {
+				continue;
+			}
+			if let Some(glob_filter) = &glob_filter
+				&& !glob_filter.is_match(&entry.relative)
+			{
+				continue;
+			}
+			entry.relative.clone()
+		};
+		if !keep(&entry.path) {
+			continue;
+		}
+		files.push(FileCandidate {
+			absolute_path: entry.path.clone(),
+			display_path,
+			blob: Some(entry.blob(&pool)),
+		});
+	}
+
+	files.sort_by(|a, b| a.display_path.cmp(&b.display_path));
+	Ok(files)
+}
+
+/// A host file whose extension names a language with injections (HTML,
+/// Markdown) is parsed as that language when searched for another one; the
+/// search then runs inside its embedded regions.
+fn injection_host(file_path: &Path, language: SupportLang) -> Option<SupportLang> {
+	SupportLang::from_path(file_path).filter(|host| host.has_injections() && *host != language)
+}
+
+/// Where a parsed tree starts in its host file: zero for the host itself, the
+/// region start for an embedded region.
+#[derive(Clone, Copy, Default)]
+struct RegionOrigin {
+	byte:   usize,
+	line:   usize,
+	/// Character column of the region start on its first line.
+	column: usize,
+}
+
+impl RegionOrigin {
+	const fn line(self, line: usize) -> usize {
+		self.line + line
+	}
+
+	/// Host column of `column` on the region's `line`; only the first line is
+	/// shifted.
+	const fn column(self, line: usize, column: usize) -> usize {
+		if line == 0 {
+			self.column + column
+		} else {
+			column
+		}
+	}
+}
+
+/// An embedded region parsed from its own slice of the host source.
+struct InjectedRoot {
+	ast:    AstGrep<StrDoc<SupportLang>>,
+	origin: RegionOrigin,
+}
+
+/// Embedded regions of `ast` (HTML `<script>`/`<style>`, Markdown fenced code)
+/// in document order, each parsed on its own with the language it names.
+/// Only the region's text is copied; its positions are relative to `origin`.
+/// Regions in unsupported languages are skipped.
+fn injected_roots(ast: &AstGrep<StrDoc<SupportLang>>) -> Vec<InjectedRoot> {
+	let source = ast.get_text();
+	let mut roots = Vec::new();
+	for (name, ranges) in ast.lang().extract_injections(ast.root()) {
+		let Ok(lang) = resolve_supported_lang(&name) else {
+			continue;
+		};
+		for range in ranges {
+			let Some(region) = source.get(range.start_byte..range.end_byte) else {
+				continue;
+			};
+			let mut parser = tree_sitter::Parser::new();
+			if parser.set_language(&lang.get_ts_language()).is_err() {
+				continue;
+			}
+			if let Some(tree) = parser.parse(region, None) {
+				let line_start = source[..range.start_byte]
+					.rfind('\n')
+					.map_or(0, |index| index + 1);
+				let origin = RegionOrigin {
+					byte:   range.start_byte,
+					line:   range.start_point.row,
+					column: source[line_start..range.start_byte].chars().count(),
+				};
+				let doc = StrDoc { src: region.to_string(), lang, tree };
+				roots.push(InjectedRoot { ast: AstGrep::doc(doc), origin });
+			}
+		}
+	}
+	roots.sort_by_key(|root| root.origin.byte);
+	roots
+}
+
+/// 1-based line of the first error node in `ast`, if any.
+fn first_error_line(ast: &AstGrep<StrDoc<SupportLang>>) -> Option<usize> {
+	ast.root()
+		.dfs()
+		.find(|node| node.is_error())
+		.map(|node| node.start_pos().line() + 1)
+}
+
 fn compile_pattern(
 	pattern: &str,
 	selector: Option<&str>,
@@ -486,10 +805,90 @@ fn normalize_rewrite_map(
 	normalized.sort_by(|left, right| left.0.cmp(&right.0));
 	Ok(normalized)
 }
-struct CompiledFindPattern {
-	pattern:                String,
-	compiled_by_lang:       HashMap<String, Pattern>,
-	compile_errors_by_lang: HashMap<String, String>,
+/// A compiled `patterns` entry or rule document.
+enum FindMatcher {
+	Pattern(Pattern),
+	Rule(RuleMatcher),
+}
+
+impl Matcher for FindMatcher {
+	fn match_node_with_env<'tree, D: Doc>(
+		&self,
+		node: Node<'tree, D>,
+		env: &mut Cow<MetaVarEnv<'tree, D>>,
+	) -> Option<Node<'tree, D>> {
+		match self {
+			Self::Pattern(pattern) => pattern.match_node_with_env(node, env),
+			Self::Rule(rule) => rule.match_node_with_env(node, env),
+		}
+	}
+
+	fn potential_kinds(&self) -> Option<BitSet> {
+		match self {
+			Self::Pattern(pattern) => pattern.potential_kinds(),
+			Self::Rule(rule) => rule.potential_kinds(),
+		}
+	}
+
+	fn get_match_len<D: Doc>(&self, node: Node<'_, D>) -> Option<usize> {
+		match self {
+			Self::Pattern(pattern) => pattern.get_match_len(node),
+			Self::Rule(rule) => rule.get_match_len(node),
+		}
+	}
+}
+
+struct CompiledFindPattern<'a> {
+	/// Pattern source, or the rule id for rule documents.
+	pattern:          String,
+	rule_id:          Option<String>,
+	rule:             Option<&'a RuleDocument>,
+	selector:         Option<&'a str>,
+	strictness:       &'a MatchStrictness,
+	/// Compile for languages met only in embedded regions, on first use.
+	compile_lazily:   bool,
+	/// Matcher or compile error per canonical language name.
+	compiled_by_lang: DashMap<&'static str, std::result::Result<Arc<FindMatcher>, String>>,
+}
+
+impl CompiledFindPattern<'_> {
+	/// Whether the pattern runs on code in `language`; rules with a
+	/// `language` only apply to that one.
+	fn applies_to(&self, language: SupportLang) -> bool {
+		self
+			.rule
+			.is_none_or(|rule| rule.language.is_none_or(|lang| lang == language))
+	}
+
+	fn compile(&self, language: SupportLang) -> std::result::Result<Arc<FindMatcher>, String> {
+		let matcher = match self.rule {
+			Some(rule) => rule
+				.compile(language, self.strictness)
+				.map(FindMatcher::Rule),
+			None => compile_pattern(&self.pattern, self.selector, self.strictness, language)
+				.map(FindMatcher::Pattern),
+		};
+		let matcher = matcher.map(Arc::new).map_err(|err| err.to_string());
+		self
+			.compiled_by_lang
+			.insert(language.canonical_name(), matcher.clone());
+		matcher
+	}
+
+	/// Matcher for `language`, or the error compiling it; `None` when the
+	/// pattern does not run on that language.
+	fn matcher(
+		&self,
+		language: SupportLang,
+	) -> Option<std::result::Result<Arc<FindMatcher>, String>> {
+		if !self.applies_to(language) {
+			return None;
+		}
+		if let Some(compiled) = self.compiled_by_lang.get(language.canonical_name()) {
+			return Some(compiled.clone());
+		}
+		self.compile_lazily.then(|| self.compile(language))
+	}
 }
 
 struct ResolvedCandidate {
@@ -512,9 +911,10 @@ fn resolve_candidates_for_find(
 			Ok(language) => {
 				let key = language.canonical_name().to_string();
 				languages.entry(key).or_insert(language);
+				let host = injection_host(&candidate.absolute_path, language);
 				resolved.push(ResolvedCandidate {
 					candidate,
-					language: Some(language),
+					language: Some(host.unwrap_or(language)),
 					language_error: None,
 				});
 			},
@@ -531,54 +931,473 @@ fn resolve_candidates_for_find(
 	Ok((resolved, languages))
 }
 
-fn compile_find_patterns(
+/// Compile `patterns` and `rules` for every candidate language. With
+/// `compile_lazily`, languages found in embedded regions are compiled as they
+/// are met; otherwise only regions in candidate languages are searched.
+fn compile_find_patterns<'a>(
 	patterns: &[String],
+	rules: &'a [RuleDocument],
 	languages: &HashMap<String, SupportLang>,
-	selector: Option<&str>,
-	strictness: &MatchStrictness,
+	selector: Option<&'a str>,
+	strictness: &'a MatchStrictness,
+	compile_lazily: bool,
+	ct: &task::CancelToken,
+) -> Result<Vec<CompiledFindPattern<'a>>> {
+	let sources = patterns
+		.iter()
+		.map(|pattern| (pattern.clone(), None))
+		.chain(rules.iter().map(|rule| (rule.id.clone(), Some(rule))));
+	let mut compiled = Vec::with_capacity(patterns.len() + rules.len());
+	for (pattern, rule) in sources {
+		ct.heartbeat()?;
+		let find_pattern = CompiledFindPattern {
+			pattern,
+			rule_id: rule.map(|rule| rule.id.clone()),
+			rule,
+			selector,
+			strictness,
+			compile_lazily,
+			compiled_by_lang: DashMap::new(),
+		};
+		for &language in languages.values() {
+			ct.heartbeat()?;
+			if find_pattern.applies_to(language) {
+				let _ = find_pattern.compile(language);
+			}
+		}
+		compiled.push(find_pattern);
+	}
+
+	Ok(compiled)
+}
+
+/// Matches and parse errors of one `ast_grep` candidate.
+struct FileFindOutcome {
+	matches:      Vec<AstFindMatch>,
+	parse_errors: Vec<String>,
+}
+
+/// Read, parse and match a single candidate against every compiled pattern.
+fn find_in_candidate(
+	resolved: ResolvedCandidate,
+	compiled_patterns: &[CompiledFindPattern],
+	include_meta: bool,
+	context: Option<u32>,
 	ct: &task::CancelToken,
-) -> Result<Vec<CompiledFindPattern>> {
-	let mut compiled = Vec::with_capacity(patterns.len());
+) -> Result<FileFindOutcome> {
+	let ResolvedCandidate { mut candidate, language, language_error } = resolved;
+	let mut outcome = FileFindOutcome { matches: Vec::new(), parse_errors: Vec::new() };
+
+	if let Some(error) = language_error.as_deref() {
+		for compiled in compiled_patterns {
+			outcome
+				.parse_errors
+				.push(format!("{}: {}: {error}", compiled.pattern, candidate.display_path));
+		}
+		return Ok(outcome);
+	}
 
-	for pattern in patterns {
+	let Some(language) = language else {
+		return Ok(outcome);
+	};
+	let applies = |language: SupportLang| {
+		compiled_patterns
+			.iter()
+			.any(|compiled| compiled.applies_to(language))
+	};
+	if !language.has_injections() && !applies(language) {
+		return Ok(outcome);
+	}
+
+	// Working-tree files go through the parse cache; revision blobs are parsed
+	// as is.
+	let ast = match candidate.blob.take() {
+		Some(blob) => blob
+			.read()
+			.map_err(|err| err.to_string())
+			.and_then(|data| String::from_utf8(data).map_err(|err| err.to_string()))
+			.map(|source| language.ast_grep(source)),
+		None => {
+			parse_cache::parse_file(&candidate.absolute_path, language).map_err(|err| err.to_string())
+		},
+	};
+	let ast = match ast {
+		Ok(ast) => ast,
+		Err(err) => {
+			for compiled in compiled_patterns {
+				outcome
+					.parse_errors
+					.push(format!("{}: {}: {err}", compiled.pattern, candidate.display_path));
+			}
+			return Ok(outcome);
+		},
+	};
+	if ast.root().dfs().any(|node| node.is_error()) {
+		outcome.parse_errors.push(format!(
+			"{}: parse error (syntax tree contains error nodes)",
+			candidate.display_path
+		));
+	}
+	let injections = if language.has_injections() {
+		injected_roots(&ast)
+	} else {
+		Vec::new()
+	};
+	let text = ast.root().text();
+	let lines: Vec<&str> = if context.is_some() {
+		text.lines().collect()
+	} else {
+		Vec::new()
+	};
+
+	// The host document first, then each embedded region; compile errors are
+	// reported once per language.
+	let mut seen_langs = HashSet::new();
+	let roots = std::iter::once((&ast, RegionOrigin::default()))
+		.chain(injections.iter().map(|root| (&root.ast, root.origin)));
+	for (index, (root, origin)) in roots.enumerate() {
 		ct.heartbeat()?;
-		let mut compiled_by_lang = HashMap::with_capacity(languages.len());
-		let mut compile_errors_by_lang = HashMap::new();
+		let root_lang = *root.lang();
+		let root_key = root_lang.canonical_name();
+		let first_of_lang = seen_langs.insert(root_key);
+		let searched = compiled_patterns
+			.iter()
+			.any(|compiled| compiled.matcher(root_lang).is_some());
+		if index > 0
+			&& searched
+			&& let Some(line) = first_error_line(root)
+		{
+			outcome.parse_errors.push(format!(
+				"{}:{}: parse error in embedded {root_key} (syntax tree contains error nodes)",
+				candidate.display_path,
+				origin.line(line)
+			));
+		}
+		for compiled in compiled_patterns {
+			ct.heartbeat()?;
+			let matcher = match compiled.matcher(root_lang) {
+				Some(Ok(matcher)) => matcher,
+				Some(Err(error)) => {
+					if first_of_lang {
+						outcome
+							.parse_errors
+							.push(format!("{}: {}: {error}", compiled.pattern, candidate.display_path));
+					}
+					continue;
+				},
+				None => continue,
+			};
+			for matched in root.root().find_all(&*matcher) {
+				ct.heartbeat()?;
+				let range = matched.range();
+				let (start, end) = (matched.start_pos(), matched.end_pos());
+				let (start_line, end_line) = (origin.line(start.line()), origin.line(end.line()));
+				let meta_variables = if include_meta {
+					Some(HashMap::<String, String>::from(matched.get_env().clone()))
+				} else {
+					None
+				};
+				let (context_before, context_after, scope) = match context {
+					Some(context) => {
+						let context = context as usize;
+						(
+							Some(context_lines(&lines, start_line.saturating_sub(context)..start_line)),
+							Some(context_lines(&lines, end_line + 1..end_line + 1 + context)),
+							enclosing_scope(matched.get_node(), origin),
+						)
+					},
+					None => (None, None, None),
+				};
+				outcome.matches.push(AstFindMatch {
+					path: candidate.display_path.clone(),
+					text: matched.text().into_owned(),
+					byte_start: to_u32(origin.byte + range.start),
+					byte_end: to_u32(origin.byte + range.end),
+					start_line: to_u32(start_line.saturating_add(1)),
+					start_column: to_u32(
+						origin
+							.column(start.line(), start.column(matched.get_node()))
+							.saturating_add(1),
+					),
+					end_line: to_u32(end_line.saturating_add(1)),
+					end_column: to_u32(
+						origin
+							.column(end.line(), end.column(matched.get_node()))
+							.saturating_add(1),
+					),
+					meta_variables,
+					rule_id: compiled.rule_id.clone(),
+					context_before,
+					context_after,
+					scope,
+				});
+			}
+		}
+	}
+
+	Ok(outcome)
+}
+
+/// Result of scanning one `ast_edit` candidate, before limits are applied.
+enum FileReplaceOutcome {
+	/// The file could not be read or parsed cleanly.
+	Failed(String),
+	Scanned {
+		source:   String,
+		/// At most `max_replacements` changes, in rule order.
+		changes:  Vec<PendingFileChange>,
+		/// More matches existed than were collected.
+		overflow: bool,
+		/// Embedded regions skipped because they do not parse cleanly.
+		issues:   Vec<String>,
+	},
+}
+
+/// Write rewritten `output` over `candidate`.
+///
+/// The cached tree is marked stale: a same-size rewrite within one mtime tick
+/// would otherwise keep serving the tree of the old source.
+fn write_candidate(candidate: &FileCandidate, output: &str) -> Result<()> {
+	std::fs::write(&candidate.absolute_path, output).map_err(|err| {
+		Error::from_reason(format!("Failed to write {}: {err}", candidate.display_path))
+	})?;
+	parse_cache::invalidate_path(&candidate.absolute_path);
+	Ok(())
+}
+
+/// Limits and output settings of an `ast_edit` run.
+struct ReplaceSettings {
+	max_replacements:    u32,
+	max_files:           u32,
+	dry_run:             bool,
+	diff:                bool,
+	fail_on_parse_error: bool,
+}
+
+/// Changes accepted by `replace_candidates`, in candidate order.
+struct ReplaceRun {
+	changes:       Vec<AstReplaceChange>,
+	/// Replacement count and optional diff per display path.
+	file_counts:   BTreeMap<String, (u32, Option<String>)>,
+	files_touched: u32,
+	limit_reached: bool,
+}
+
+/// Scan `candidates` and apply the accepted changes.
+///
+/// Candidates are read, parsed and matched in parallel one batch at a time;
+/// limits and writes are then applied sequentially in candidate order, and no
+/// further batch is scheduled once a limit is reached.
+fn replace_candidates(
+	candidates: &[FileCandidate],
+	language: SupportLang,
+	compiled_rules: &[(String, Fixer, FindMatcher)],
+	settings: &ReplaceSettings,
+	parse_errors: &mut Vec<String>,
+	ct: &task::CancelToken,
+) -> Result<ReplaceRun> {
+	let ReplaceSettings { max_replacements, max_files, dry_run, diff, fail_on_parse_error } =
+		*settings;
+	let mut changes = Vec::new();
+	let mut file_counts: BTreeMap<String, (u32, Option<String>)> = BTreeMap::new();
+	let mut files_touched = 0u32;
+	let mut limit_reached = false;
+
+	let batch_size = rayon::current_num_threads().max(1) * 4;
+	'batches: for batch in candidates.chunks(batch_size) {
+		let outcomes = batch
+			.par_iter()
+			.map(|candidate| {
+				ct.heartbeat()?;
+				replace_in_candidate(candidate, language, compiled_rules, max_replacements, ct)
+			})
+			.collect::<Result<Vec<_>>>()?;
 
-		for (lang_key, &language) in languages {
+		for (candidate, outcome) in batch.iter().zip(outcomes) {
 			ct.heartbeat()?;
-			match compile_pattern(pattern, selector, strictness, language) {
-				Ok(compiled_pattern) => {
-					compiled_by_lang.insert(lang_key.clone(), compiled_pattern);
+			let (source, mut file_changes, overflow) = match outcome {
+				FileReplaceOutcome::Failed(issue) => {
+					if fail_on_parse_error {
+						return Err(Error::from_reason(issue));
+					}
+					parse_errors.push(issue);
+					continue;
 				},
-				Err(err) => {
-					compile_errors_by_lang.insert(lang_key.clone(), err.to_string());
+				FileReplaceOutcome::Scanned { source, changes, overflow, issues } => {
+					if fail_on_parse_error && let Some(issue) = issues.first() {
+						return Err(Error::from_reason(issue.clone()));
+					}
+					parse_errors.extend(issues);
+					(source, changes, overflow)
 				},
+			};
+
+			let remaining = max_replacements as usize - changes.len();
+			let reached_max_replacements = overflow || file_changes.len() > remaining;
+			if reached_max_replacements {
+				limit_reached = true;
+				file_changes.truncate(remaining);
+			}
+
+			if file_changes.is_empty() {
+				if reached_max_replacements {
+					break 'batches;
+				}
+				continue;
+			}
+			if files_touched >= max_files {
+				limit_reached = true;
+				break 'batches;
+			}
+			files_touched = files_touched.saturating_add(1);
+
+			let mut file_diff = None;
+			if !dry_run || diff {
+				let edits: Vec<Edit<String>> = file_changes
+					.iter()
+					.map(|entry| Edit {
+						position:       entry.edit.position,
+						deleted_length: entry.edit.deleted_length,
+						inserted_text:  entry.edit.inserted_text.clone(),
+					})
+					.collect();
+				let output = apply_edits(&source, &edits)?;
+				if diff {
+					file_diff = Some(diff::unified_diff(&candidate.display_path, &source, &output));
+				}
+				if !dry_run && output != source {
+					write_candidate(candidate, &output)?;
+				}
+			}
+
+			file_counts
+				.insert(candidate.display_path.clone(), (to_u32(file_changes.len()), file_diff));
+			changes.extend(file_changes.into_iter().map(|entry| entry.change));
+			if reached_max_replacements {
+				break 'batches;
 			}
 		}
+	}
 
-		compiled.push(CompiledFindPattern {
-			pattern: pattern.clone(),
-			compiled_by_lang,
-			compile_errors_by_lang,
-		});
+	Ok(ReplaceRun { changes, file_counts, files_touched, limit_reached })
+}
+
+/// Read, parse and collect the replacements of a single `ast_edit` candidate.
+fn replace_in_candidate(
+	candidate: &FileCandidate,
+	language: SupportLang,
+	compiled_rules: &[(String, Fixer, FindMatcher)],
+	max_replacements: u32,
+	ct: &task::CancelToken,
+) -> Result<FileReplaceOutcome> {
+	let host = injection_host(&candidate.absolute_path, language);
+	let ast = match parse_cache::parse_file(&candidate.absolute_path, host.unwrap_or(language)) {
+		Ok(ast) => ast,
+		Err(err) => {
+			return Ok(FileReplaceOutcome::Failed(format!("{}: {err}", candidate.display_path)));
+		},
+	};
+	let source = ast.get_text().to_string();
+
+	let mut issues = Vec::new();
+	let roots = match host {
+		None => {
+			if ast.root().dfs().any(|node| node.is_error()) {
+				return Ok(FileReplaceOutcome::Failed(format!(
+					"{}: parse error (syntax tree contains error nodes)",
+					candidate.display_path
+				)));
+			}
+			vec![InjectedRoot { ast, origin: RegionOrigin::default() }]
+		},
+		// Only the embedded regions in `language` are rewritten; one that does
+		// not parse is skipped without holding back the rest of the file.
+		Some(_) => injected_roots(&ast)
+			.into_iter()
+			.filter(|root| *root.ast.lang() == language)
+			.filter(|root| match first_error_line(&root.ast) {
+				Some(line) => {
+					issues.push(format!(
+						"{}:{}: parse error in embedded {}; region skipped",
+						candidate.display_path,
+						root.origin.line(line),
+						language.canonical_name()
+					));
+					false
+				},
+				None => true,
+			})
+			.collect(),
+	};
+
+	let mut changes = Vec::new();
+	let mut overflow = false;
+	'patterns: for (_pattern, rewrite, compiled) in compiled_rules {
+		for InjectedRoot { ast: root, origin } in &roots {
+			for matched in root.root().find_all(compiled) {
+				ct.heartbeat()?;
+				if changes.len() >= max_replacements as usize {
+					overflow = true;
+					break 'patterns;
+				}
+				let mut edit = matched.replace_by(rewrite);
+				edit.position += origin.byte;
+				let range = matched.range();
+				let (start, end) = (matched.start_pos(), matched.end_pos());
+				let after = String::from_utf8(edit.inserted_text.clone()).map_err(|err| {
+					Error::from_reason(format!(
+						"{}: replacement text is not valid UTF-8: {err}",
+						candidate.display_path
+					))
+				})?;
+				changes.push(PendingFileChange {
+					change: AstReplaceChange {
+						path: candidate.display_path.clone(),
+						before: matched.text().into_owned(),
+						after,
+						byte_start: to_u32(origin.byte + range.start),
+						byte_end: to_u32(origin.byte + range.end),
+						deleted_length: to_u32(edit.deleted_length),
+						start_line: to_u32(origin.line(start.line()).saturating_add(1)),
+						start_column: to_u32(
+							origin
+								.column(start.line(), start.column(matched.get_node()))
+								.saturating_add(1),
+						),
+						end_line: to_u32(origin.line(end.line()).saturating_add(1)),
+						end_column: to_u32(
+							origin
+								.column(end.line(), end.column(matched.get_node()))
+								.saturating_add(1),
+						),
+					},
+					edit,
+				});
+			}
+		}
 	}
 
-	Ok(compiled)
+	Ok(FileReplaceOutcome::Scanned { source, changes, overflow, issues })
 }
+
 #[napi(js_name = "astGrep")]
 pub fn ast_grep(options: AstFindOptions<'_>) -> task::Async<AstFindResult> {
 	let AstFindOptions {
 		patterns,
+		rules,
 		lang,
 		path,
 		glob,
+		exclude,
 		selector,
 		strictness,
 		limit,
 		offset,
 		include_meta,
-		context: _,
+		context,
+		rev,
+		changed,
+		changed_base,
 		signal,
 		timeout_ms,
 	} = options;
@@ -588,104 +1407,69 @@ pub fn ast_grep(options: AstFindOptions<'_>) -> task::Async<AstFindResult> {
 	let normalized_offset = offset.unwrap_or(0);
 
 	task::blocking("ast_grep", ct, move |ct| {
-		let patterns = normalize_pattern_list(patterns)?;
+		let rules = rules
+			.as_deref()
+			.map(ast_rule::parse_rules)
+			.transpose()?
+			.unwrap_or_default();
+		let patterns = if rules.is_empty() || patterns.as_ref().is_some_and(|list| !list.is_empty()) {
+			normalize_pattern_list(patterns)?
+		} else {
+			Vec::new()
+		};
 		let strictness = parse_strictness(strictness.as_deref())?;
 		let include_meta = include_meta.unwrap_or(false);
 		let lang_str = lang.as_deref().map(str::trim).filter(|v| !v.is_empty());
-		let candidates: Vec<_> = collect_candidates(path, glob.as_deref(), &ct)?
-			.into_iter()
-			.filter(|candidate| is_supported_file(&candidate.absolute_path, lang_str))
-			.collect();
+		let changed = git::ChangedSince::parse(changed.as_deref(), changed_base)?
+			.map(|since| git::changed_under(&normalize_search_path(path.clone())?, &since))
+			.transpose()?;
+		let keep = |file: &Path| {
+			is_supported_file(file, lang_str) && changed.as_ref().is_none_or(|set| set.contains(file))
+		};
+		let rev = rev.as_deref().map(str::trim).filter(|rev| !rev.is_empty());
+		let candidates: Vec<_> = match rev {
+			Some(rev) => {
+				collect_revision_candidates(path, rev, glob.as_ref(), exclude.as_deref(), keep, &ct)?
+			},
+			None => collect_candidates(path, glob.as_ref(), exclude.as_deref(), &ct)?
+				.into_iter()
+				.filter(|candidate| keep(&candidate.absolute_path))
+				.collect(),
+		};
 
 		let (resolved_candidates, languages) =
 			resolve_candidates_for_find(candidates, lang_str, &ct)?;
-		let compiled_patterns =
-			compile_find_patterns(&patterns, &languages, selector.as_deref(), &strictness, &ct)?;
+		let compiled_patterns = compile_find_patterns(
+			&patterns,
+			&rules,
+			&languages,
+			selector.as_deref(),
+			&strictness,
+			lang_str.is_none(),
+			&ct,
+		)?;
 		let files_searched = to_u32(resolved_candidates.len());
 
-		let mut all_matches = Vec::new();
-		let mut parse_errors = Vec::new();
-		let mut total_matches = 0u32;
-		let mut files_with_matches = BTreeSet::new();
-		for resolved in resolved_candidates {
-			ct.heartbeat()?;
-			let ResolvedCandidate { candidate, language, language_error } = resolved;
-
-			if let Some(error) = language_error.as_deref() {
-				for compiled in &compiled_patterns {
-					parse_errors
-						.push(format!("{}: {}: {error}", compiled.pattern, candidate.display_path));
-				}
-				continue;
-			}
-
-			let Some(language) = language else {
-				continue;
-			};
-			let lang_key = language.canonical_name();
-			let source = match std::fs::read_to_string(&candidate.absolute_path) {
-				Ok(source) => source,
-				Err(err) => {
-					for compiled in &compiled_patterns {
-						parse_errors
-							.push(format!("{}: {}: {err}", compiled.pattern, candidate.display_path));
-					}
-					continue;
-				},
-			};
-
-			let mut runnable_patterns: Vec<(&str, &Pattern)> = Vec::new();
-			for compiled in &compiled_patterns {
+		// Files are parsed and matched in parallel; outcomes keep candidate order.
+		let outcomes = resolved_candidates
+			.into_par_iter()
+			.map(|resolved| {
 				ct.heartbeat()?;
-				if let Some(error) = compiled.compile_errors_by_lang.get(lang_key) {
-					parse_errors
-						.push(format!("{}: {}: {error}", compiled.pattern, candidate.display_path));
-					continue;
-				}
-				if let Some(pattern) = compiled.compiled_by_lang.get(lang_key) {
-					runnable_patterns.push((compiled.pattern.as_str(), pattern));
-				}
-			}
-			if runnable_patterns.is_empty() {
-				continue;
-			}
+				find_in_candidate(resolved, &compiled_patterns, include_meta, context, &ct)
+			})
+			.collect::<Result<Vec<_>>>()?;
 
-			let ast = language.ast_grep(source);
-			if ast.root().dfs().any(|node| node.is_error()) {
-				parse_errors.push(format!(
-					"{}: parse error (syntax tree contains error nodes)",
-					candidate.display_path
-				));
-			}
-
-			for (_, pattern) in runnable_patterns {
-				ct.heartbeat()?;
-				for matched in ast.root().find_all(pattern.clone()) {
-					ct.heartbeat()?;
-					total_matches = total_matches.saturating_add(1);
-					let range = matched.range();
-					let start = matched.start_pos();
-					let end = matched.end_pos();
-					let meta_variables = if include_meta {
-						Some(HashMap::<String, String>::from(matched.get_env().clone()))
-					} else {
-						None
-					};
-					all_matches.push(AstFindMatch {
-						path: candidate.display_path.clone(),
-						text: matched.text().into_owned(),
-						byte_start: to_u32(range.start),
-						byte_end: to_u32(range.end),
-						start_line: to_u32(start.line().saturating_add(1)),
-						start_column: to_u32(start.column(matched.get_node()).saturating_add(1)),
-						end_line: to_u32(end.line().saturating_add(1)),
-						end_column: to_u32(end.column(matched.get_node()).saturating_add(1)),
-						meta_variables,
-					});
-					files_with_matches.insert(candidate.display_path.clone());
-				}
+		let mut all_matches = Vec::new();
+		let mut parse_errors = Vec::new();
+		let mut files_with_matches = 0u32;
+		for outcome in outcomes {
+			if !outcome.matches.is_empty() {
+				files_with_matches = files_with_matches.saturating_add(1);
 			}
+			all_matches.extend(outcome.matches);
+			parse_errors.extend(outcome.parse_errors);
 		}
+		let total_matches = to_u32(all_matches.len());
 
 		all_matches.sort_by(|left, right| {
 			left
@@ -712,7 +1496,7 @@ pub fn ast_grep(options: AstFindOptions<'_>) -> task::Async<AstFindResult> {
 		Ok(AstFindResult {
 			matches,
 			total_matches,
-			files_with_matches: to_u32(files_with_matches.len()),
+			files_with_matches,
 			files_searched,
 			limit_reached,
 			parse_errors: (!parse_errors.is_empty()).then_some(parse_errors),
@@ -724,30 +1508,66 @@ pub fn ast_grep(options: AstFindOptions<'_>) -> task::Async<AstFindResult> {
 pub fn ast_edit(options: AstReplaceOptions<'_>) -> task::Async<AstReplaceResult> {
 	let AstReplaceOptions {
 		rewrites,
+		rules,
+		transform,
 		lang,
 		path,
 		glob,
+		exclude,
 		selector,
 		strictness,
 		dry_run,
 		max_replacements,
 		max_files,
 		fail_on_parse_error,
+		diff,
 		signal,
 		timeout_ms,
 	} = options;
 
 	let ct = task::CancelToken::new(timeout_ms, signal);
 	task::blocking("ast_edit", ct, move |ct| {
-		let rewrite_rules = normalize_rewrite_map(rewrites)?;
+		let rules = rules
+			.as_deref()
+			.map(ast_rule::parse_rules)
+			.transpose()?
+			.unwrap_or_default();
+		if let Some(rule) = rules.iter().find(|rule| rule.fix.is_none()) {
+			return Err(Error::from_reason(format!(
+				"{}: rules passed to ast_edit need a `fix` template",
+				rule.id
+			)));
+		}
+		let rewrite_rules =
+			if rules.is_empty() || rewrites.as_ref().is_some_and(|map| !map.is_empty()) {
+				normalize_rewrite_map(rewrites)?
+			} else {
+				Vec::new()
+			};
+		let transforms = transform
+			.as_deref()
+			.map(ast_fix::parse_transforms)
+			.transpose()?
+			.unwrap_or_default();
 		let strictness = parse_strictness(strictness.as_deref())?;
 		let dry_run = dry_run.unwrap_or(true);
+		let diff = diff.unwrap_or(false);
 		let max_replacements = max_replacements.unwrap_or(u32::MAX).max(1);
 		let max_files = max_files.unwrap_or(u32::MAX).max(1);
 		let fail_on_parse_error = fail_on_parse_error.unwrap_or(false);
 
-		let lang_str = lang.as_deref().map(str::trim).filter(|v| !v.is_empty());
-		let candidates: Vec<_> = collect_candidates(path, glob.as_deref(), &ct)?
+		// Rules that all declare the same language stand in for `lang`.
+		let rule_lang = rules
+			.first()
+			.and_then(|rule| rule.language)
+			.filter(|first| rules.iter().all(|rule| rule.language == Some(*first)))
+			.map(SupportLang::canonical_name);
+		let lang_str = lang
+			.as_deref()
+			.map(str::trim)
+			.filter(|v| !v.is_empty())
+			.or(rule_lang);
+		let candidates: Vec<_> = collect_candidates(path, glob.as_ref(), exclude.as_deref(), &ct)?
 			.into_iter()
 			.filter(|candidate| is_supported_file(&candidate.absolute_path, lang_str))
 			.collect();
@@ -763,7 +1583,11 @@ pub fn ast_edit(options: AstReplaceOptions<'_>) -> task::Async<AstReplaceResult>
 		for (pattern, rewrite) in rewrite_rules {
 			ct.heartbeat()?;
 			match compile_pattern(&pattern, selector.as_deref(), &strictness, language) {
-				Ok(compiled) => compiled_rules.push((pattern, rewrite, compiled)),
+				Ok(compiled) => compiled_rules.push((
+					pattern,
+					Fixer::new(rewrite, transforms.clone()),
+					FindMatcher::Pattern(compiled),
+				)),
 				Err(err) => {
 					if fail_on_parse_error {
 						return Err(err);
@@ -772,6 +1596,32 @@ pub fn ast_edit(options: AstReplaceOptions<'_>) -> task::Async<AstReplaceResult>
 				},
 			}
 		}
+		for rule in &rules {
+			ct.heartbeat()?;
+			let compiled = match rule.language {
+				Some(rule_lang) if rule_lang != language => Err(Error::from_reason(format!(
+					"rule language {} does not match {}",
+					rule_lang.canonical_name(),
+					language.canonical_name()
+				))),
+				_ => rule.compile(language, &strictness),
+			};
+			match compiled {
+				Ok(matcher) => compiled_rules.push((
+					rule.id.clone(),
+					rule
+						.fixer()
+						.unwrap_or_else(|| Fixer::new("", Transforms::default())),
+					FindMatcher::Rule(matcher),
+				)),
+				Err(err) => {
+					if fail_on_parse_error {
+						return Err(Error::from_reason(format!("{}: {err}", rule.id)));
+					}
+					parse_errors.push(format!("{}: {err}", rule.id));
+				},
+			}
+		}
 		if compiled_rules.is_empty() {
 			return Ok(AstReplaceResult {
 				file_changes:       vec![],
@@ -785,114 +1635,20 @@ pub fn ast_edit(options: AstReplaceOptions<'_>) -> task::Async<AstReplaceResult>
 			});
 		}
 
-		let mut changes = Vec::new();
-		let mut file_counts: BTreeMap<String, u32> = BTreeMap::new();
-		let mut files_touched = 0u32;
-		let mut limit_reached = false;
-
-		for candidate in &candidates {
-			ct.heartbeat()?;
-			let source = match std::fs::read_to_string(&candidate.absolute_path) {
-				Ok(source) => source,
-				Err(err) => {
-					if fail_on_parse_error {
-						return Err(Error::from_reason(format!("{}: {err}", candidate.display_path)));
-					}
-					parse_errors.push(format!("{}: {err}", candidate.display_path));
-					continue;
-				},
-			};
-
-			let ast = language.ast_grep(&source);
-			if ast.root().dfs().any(|node| node.is_error()) {
-				let parse_issue = format!(
-					"{}: parse error (syntax tree contains error nodes)",
-					candidate.display_path
-				);
-				if fail_on_parse_error {
-					return Err(Error::from_reason(parse_issue));
-				}
-				parse_errors.push(parse_issue);
-				continue;
-			}
-
-			let mut file_changes = Vec::new();
-			let mut reached_max_replacements = false;
-			'patterns: for (_pattern, rewrite, compiled) in &compiled_rules {
-				for matched in ast.root().find_all(compiled.clone()) {
-					ct.heartbeat()?;
-					if changes.len() + file_changes.len() >= max_replacements as usize {
-						limit_reached = true;
-						reached_max_replacements = true;
-						break 'patterns;
-					}
-					let edit = matched.replace_by(rewrite.as_str());
-					let range = matched.range();
-					let start = matched.start_pos();
-					let end = matched.end_pos();
-					let after = String::from_utf8(edit.inserted_text.clone()).map_err(|err| {
-						Error::from_reason(format!(
-							"{}: replacement text is not valid UTF-8: {err}",
-							candidate.display_path
-						))
-					})?;
-					file_changes.push(PendingFileChange {
-						change: AstReplaceChange {
-							path: candidate.display_path.clone(),
-							before: matched.text().into_owned(),
-							after,
-							byte_start: to_u32(range.start),
-							byte_end: to_u32(range.end),
-							deleted_length: to_u32(edit.deleted_length),
-							start_line: to_u32(start.line().saturating_add(1)),
-							start_column: to_u32(start.column(matched.get_node()).saturating_add(1)),
-							end_line: to_u32(end.line().saturating_add(1)),
-							end_column: to_u32(end.column(matched.get_node()).saturating_add(1)),
-						},
-						edit,
-					});
-				}
-			}
-
-			if file_changes.is_empty() {
-				if reached_max_replacements {
-					break;
-				}
-				continue;
-			}
-			if files_touched >= max_files {
-				limit_reached = true;
-				break;
-			}
-			files_touched = files_touched.saturating_add(1);
-			file_counts.insert(candidate.display_path.clone(), to_u32(file_changes.len()));
-
-			if !dry_run {
-				let edits: Vec<Edit<String>> = file_changes
-					.iter()
-					.map(|entry| Edit {
-						position:       entry.edit.position,
-						deleted_length: entry.edit.deleted_length,
-						inserted_text:  entry.edit.inserted_text.clone(),
-					})
-					.collect();
-				let output = apply_edits(&source, &edits)?;
-				if output != source {
-					std::fs::write(&candidate.absolute_path, output).map_err(|err| {
-						Error::from_reason(format!("Failed to write {}: {err}", candidate.display_path))
-					})?;
-				}
-			}
-
-			changes.extend(file_changes.into_iter().map(|entry| entry.change));
-			if reached_max_replacements {
-				break;
-			}
-		}
+		let settings =
+			ReplaceSettings { max_replacements, max_files, dry_run, diff, fail_on_parse_error };
+		let ReplaceRun { changes, file_counts, files_touched, limit_reached } = replace_candidates(
+			&candidates,
+			language,
+			&compiled_rules,
+			&settings,
+			&mut parse_errors,
+			&ct,
+		)?;
 
 		let file_changes = file_counts
 			.into_iter()
-			.map(|(path, count)| AstReplaceFileChange { path, count })
+			.map(|(path, (count, diff))| AstReplaceFileChange { path, count, diff })
 			.collect::<Vec<_>>();
 
 		Ok(AstReplaceResult {
@@ -910,44 +1666,33 @@ pub fn ast_edit(options: AstReplaceOptions<'_>) -> task::Async<AstReplaceResult>
 
 #[cfg(test)]
 mod tests {
-	use std::{
-		fs,
-		path::PathBuf,
-		time::{SystemTime, UNIX_EPOCH},
-	};
+	use std::fs;
 
 	use super::*;
+	use crate::test_util::TempDir;
 
-	struct TempTree {
-		root: PathBuf,
-	}
-
-	impl Drop for TempTree {
-		fn drop(&mut self) {
-			let _ = fs::remove_dir_all(&self.root);
-		}
+	fn make_temp_tree() -> TempDir {
+		let tree = TempDir::new("ast-glob");
+		tree.write("a.ts", "const a = 1;\n");
+		tree.write("nested/b.ts", "const b = 2;\n");
+		tree
 	}
 
-	fn make_temp_tree() -> TempTree {
-		let unique = SystemTime::now()
-			.duration_since(UNIX_EPOCH)
-			.expect("system time should be after UNIX_EPOCH")
-			.as_nanos();
-		let root = std::env::temp_dir().join(format!("pi-ast-glob-test-{unique}"));
-		fs::create_dir_all(root.join("nested")).expect("temp nested dir should be created");
-		fs::write(root.join("a.ts"), "const a = 1;\n").expect("temp file a.ts should be written");
-		fs::write(root.join("nested").join("b.ts"), "const b = 2;\n")
-			.expect("temp file nested/b.ts should be written");
-		TempTree { root }
+	fn tree_path(tree: &TempDir) -> String {
+		tree.root.to_string_lossy().into_owned()
 	}
 
 	#[test]
 	fn glob_star_matches_only_direct_children() {
 		let tree = make_temp_tree();
 		let ct = task::CancelToken::default();
-		let candidates =
-			collect_candidates(Some(tree.root.to_string_lossy().into_owned()), Some("*.ts"), &ct)
-				.expect("candidate collection should succeed");
+		let candidates = collect_candidates(
+			Some(tree_path(&tree)),
+			Some(&Either::A("*.ts".to_string())),
+			None,
+			&ct,
+		)
+		.expect("candidate collection should succeed");
 		let paths = candidates
 			.into_iter()
 			.map(|file| file.display_path)
@@ -959,34 +1704,61 @@ mod tests {
 	fn glob_double_star_matches_recursively() {
 		let tree = make_temp_tree();
 		let ct = task::CancelToken::default();
-		let candidates =
-			collect_candidates(Some(tree.root.to_string_lossy().into_owned()), Some("**/*.ts"), &ct)
-				.expect("candidate collection should succeed");
+		let candidates = collect_candidates(
+			Some(tree_path(&tree)),
+			Some(&Either::A("**/*.ts".to_string())),
+			None,
+			&ct,
+		)
+		.expect("candidate collection should succeed");
 		let paths = candidates
 			.into_iter()
 			.map(|file| file.display_path)
 			.collect::<Vec<_>>();
 		assert_eq!(paths, vec!["a.ts".to_string(), "nested/b.ts".to_string()]);
 	}
-	fn make_mixed_temp_tree() -> TempTree {
-		let unique = SystemTime::now()
-			.duration_since(UNIX_EPOCH)
-			.expect("system time should be after UNIX_EPOCH")
-			.as_nanos();
-		let root = std::env::temp_dir().join(format!("pi-ast-mixed-lang-test-{unique}"));
-		fs::create_dir_all(&root).expect("temp mixed-lang dir should be created");
-		fs::write(root.join("a.ts"), "const a = 1;\n").expect("temp file a.ts should be written");
-		fs::write(root.join("b.rs"), "fn main() {}\n").expect("temp file b.rs should be written");
-		TempTree { root }
+
+	#[test]
+	fn glob_list_applies_negations_and_excludes() {
+		let tree = make_temp_tree();
+		let ct = task::CancelToken::default();
+		let globs = Either::B(vec!["**/*.ts".to_string(), "!a.ts".to_string()]);
+		let candidates = collect_candidates(Some(tree_path(&tree)), Some(&globs), None, &ct)
+			.expect("candidate collection should succeed");
+		let paths = candidates
+			.into_iter()
+			.map(|file| file.display_path)
+			.collect::<Vec<_>>();
+		assert_eq!(paths, vec!["nested/b.ts".to_string()]);
+
+		let exclude = vec!["nested/**".to_string()];
+		let candidates = collect_candidates(Some(tree_path(&tree)), None, Some(&exclude), &ct)
+			.expect("candidate collection should succeed");
+		let paths = candidates
+			.into_iter()
+			.map(|file| file.display_path)
+			.collect::<Vec<_>>();
+		assert_eq!(paths, vec!["a.ts".to_string()]);
+	}
+
+	fn make_mixed_temp_tree() -> TempDir {
+		let tree = TempDir::new("ast-mixed-lang");
+		tree.write("a.ts", "const a = 1;\n");
+		tree.write("b.rs", "fn main() {}\n");
+		tree
 	}
 
 	#[test]
 	fn infers_single_replace_lang_for_uniform_candidates() {
 		let tree = make_temp_tree();
 		let ct = task::CancelToken::default();
-		let candidates =
-			collect_candidates(Some(tree.root.to_string_lossy().into_owned()), Some("**/*.ts"), &ct)
-				.expect("candidate collection should succeed");
+		let candidates = collect_candidates(
+			Some(tree_path(&tree)),
+			Some(&Either::A("**/*.ts".to_string())),
+			None,
+			&ct,
+		)
+		.expect("candidate collection should succeed");
 		let inferred =
 			infer_single_replace_lang(&candidates, &ct).expect("language should be inferred");
 		assert_eq!(inferred, "typescript");
@@ -996,9 +1768,8 @@ mod tests {
 	fn rejects_mixed_replace_lang_inference() {
 		let tree = make_mixed_temp_tree();
 		let ct = task::CancelToken::default();
-		let candidates =
-			collect_candidates(Some(tree.root.to_string_lossy().into_owned()), None, &ct)
-				.expect("candidate collection should succeed");
+		let candidates = collect_candidates(Some(tree_path(&tree)), None, None, &ct)
+			.expect("candidate collection should succeed");
 		let err = infer_single_replace_lang(&candidates, &ct)
 			.expect_err("mixed language inference should fail");
 		assert!(err.to_string().contains("multiple languages"));
@@ -1012,7 +1783,324 @@ mod tests {
 		assert_eq!(resolve_supported_lang("bash").ok(), Some(SupportLang::Bash));
 		assert_eq!(resolve_supported_lang("c").ok(), Some(SupportLang::C));
 		assert_eq!(resolve_supported_lang("cpp").ok(), Some(SupportLang::Cpp));
+		assert_eq!(resolve_supported_lang("scss").ok(), Some(SupportLang::Css));
+		assert_eq!(SupportLang::from_path("App.svelte"), Some(SupportLang::Html));
 		assert!(resolve_supported_lang("brainfuck").is_err());
+		// No Vue or Svelte grammar: naming one must not silently fall back to HTML.
+		assert!(resolve_supported_lang("vue").is_err());
+	}
+
+	#[test]
+	fn caps_per_file_replacements_and_flags_overflow() {
+		let tree = make_temp_tree();
+		let ct = task::CancelToken::default();
+		let path = tree.root.join("many.ts");
+		fs::write(&path, "f(1);\nf(2);\nf(3);\n").expect("temp file many.ts should be written");
+		let candidate = FileCandidate {
+			absolute_path: path,
+			display_path:  "many.ts".to_string(),
+			blob:          None,
+		};
+		let strictness = parse_strictness(None).expect("default strictness should parse");
+		let pattern = compile_pattern("f($A)", None, &strictness, SupportLang::TypeScript)
+			.expect("pattern should compile");
+		let rules = [(
+			"f($A)".to_string(),
+			Fixer::new("g($A)", Transforms::default()),
+			FindMatcher::Pattern(pattern),
+		)];
+
+		let scan = |max| replace_in_candidate(&candidate, SupportLang::TypeScript, &rules, max, &ct);
+		let Ok(FileReplaceOutcome::Scanned { changes, overflow, .. }) = scan(2) else {
+			panic!("candidate should scan");
+		};
+		let afters: Vec<_> = changes
+			.iter()
+			.map(|entry| entry.change.after.as_str())
+			.collect();
+		assert_eq!(afters, ["g(1)", "g(2)"]);
+		assert!(overflow);
+
+		let Ok(FileReplaceOutcome::Scanned { changes, overflow, .. }) = scan(3) else {
+			panic!("candidate should scan");
+		};
+		assert_eq!(changes.len(), 3);
+		assert!(!overflow);
+	}
+
+	fn replace_settings(max_replacements: u32, max_files: u32) -> ReplaceSettings {
+		ReplaceSettings {
+			max_replacements,
+			max_files,
+			dry_run: true,
+			diff: false,
+			fail_on_parse_error: false,
+		}
+	}
+
+	/// Candidates `f00.ts`, `f01.ts`, … each holding two calls to `f`.
+	fn call_candidates(tree: &TempDir, count: usize) -> Vec<FileCandidate> {
+		(0..count)
+			.map(|index| {
+				let name = format!("f{index:02}.ts");
+				let path = tree.root.join(&name);
+				fs::write(&path, format!("f({index});\nf({index});\n"))
+					.expect("temp candidate should be written");
+				FileCandidate { absolute_path: path, display_path: name, blob: None }
+			})
+			.collect()
+	}
+
+	fn call_rules() -> Vec<(String, Fixer, FindMatcher)> {
+		let strictness = parse_strictness(None).expect("default strictness should parse");
+		let pattern = compile_pattern("f($A)", None, &strictness, SupportLang::TypeScript)
+			.expect("pattern should compile");
+		vec![(
+			"f($A)".to_string(),
+			Fixer::new("g($A)", Transforms::default()),
+			FindMatcher::Pattern(pattern),
+		)]
+	}
+
+	#[test]
+	fn applies_limits_in_candidate_order() {
+		let tree = make_temp_tree();
+		let ct = task::CancelToken::default();
+		// More candidates than one parallel batch holds.
+		let candidates = call_candidates(&tree, rayon::current_num_threads() * 8 + 3);
+		let rules = call_rules();
+		let run = |settings: &ReplaceSettings| {
+			let mut parse_errors = Vec::new();
+			let Ok(run) = replace_candidates(
+				&candidates,
+				SupportLang::TypeScript,
+				&rules,
+				settings,
+				&mut parse_errors,
+				&ct,
+			) else {
+				panic!("candidates should be replaced");
+			};
+			assert_eq!(parse_errors, Vec::<String>::new());
+			run
+		};
+
+		let all = run(&replace_settings(u32::MAX, u32::MAX));
+		assert!(!all.limit_reached);
+		assert_eq!(all.files_touched as usize, candidates.len());
+		let paths: Vec<_> = all
+			.changes
+			.iter()
+			.map(|change| change.path.as_str())
+			.collect();
+		let expected: Vec<_> = candidates
+			.iter()
+			.flat_map(|candidate| [candidate.display_path.as_str(); 2])
+			.collect();
+		assert_eq!(paths, expected);
+
+		let limited = run(&replace_settings(u32::MAX, 3));
+		assert!(limited.limit_reached);
+		let touched: Vec<_> = limited.file_counts.keys().map(String::as_str).collect();
+		assert_eq!(touched, ["f00.ts", "f01.ts", "f02.ts"]);
+
+		let limited = run(&replace_settings(5, u32::MAX));
+		assert!(limited.limit_reached);
+		let afters: Vec<_> = limited
+			.changes
+			.iter()
+			.map(|change| change.after.as_str())
+			.collect();
+		assert_eq!(afters, ["g(0)", "g(0)", "g(1)", "g(1)", "g(2)"]);
+	}
+
+	#[test]
+	fn stops_replacing_once_cancelled() {
+		let tree = make_temp_tree();
+		let mut ct = task::CancelToken::default();
+		ct.emplace_abort_token().abort(task::AbortReason::User);
+		let candidates = call_candidates(&tree, 4);
+		let mut settings = replace_settings(u32::MAX, u32::MAX);
+		settings.dry_run = false;
+		let mut parse_errors = Vec::new();
+		let Err(err) = replace_candidates(
+			&candidates,
+			SupportLang::TypeScript,
+			&call_rules(),
+			&settings,
+			&mut parse_errors,
+			&ct,
+		) else {
+			panic!("a cancelled run should fail");
+		};
+		assert!(err.reason.contains("Aborted"), "{}", err.reason);
+		for candidate in &candidates {
+			let source =
+				fs::read_to_string(&candidate.absolute_path).expect("candidate should be readable");
+			assert!(source.starts_with("f("), "{source}");
+		}
+	}
+
+	#[test]
+	fn back_to_back_edits_see_the_rewritten_source() {
+		let tree = make_temp_tree();
+		let ct = task::CancelToken::default();
+		let path = tree.root.join("edit.ts");
+		fs::write(&path, "f(1);\n").expect("temp file edit.ts should be written");
+		let candidate = FileCandidate {
+			absolute_path: path.clone(),
+			display_path:  "edit.ts".to_string(),
+			blob:          None,
+		};
+		let strictness = parse_strictness(None).expect("default strictness should parse");
+		// Each rewrite keeps the file size and mtime, so only invalidation
+		// exposes it.
+		let edit = |from: &str, to: &str| {
+			let pattern = compile_pattern(from, None, &strictness, SupportLang::TypeScript)
+				.expect("pattern should compile");
+			let rules = [(
+				from.to_string(),
+				Fixer::new(to, Transforms::default()),
+				FindMatcher::Pattern(pattern),
+			)];
+			let Ok(FileReplaceOutcome::Scanned { source, changes, .. }) =
+				replace_in_candidate(&candidate, SupportLang::TypeScript, &rules, u32::MAX, &ct)
+			else {
+				panic!("candidate should scan");
+			};
+			let edits: Vec<_> = changes.into_iter().map(|entry| entry.edit).collect();
+			let output = apply_edits(&source, &edits).expect("edits should apply");
+			let modified = fs::metadata(&path)
+				.and_then(|metadata| metadata.modified())
+				.expect("edit.ts mtime should be readable");
+			write_candidate(&candidate, &output).expect("candidate should be written");
+			// Land the write in the same mtime tick as the previous contents.
+			fs::File::options()
+				.write(true)
+				.open(&path)
+				.and_then(|file| file.set_modified(modified))
+				.expect("edit.ts mtime should be restored");
+			edits.len()
+		};
+		assert_eq!(edit("f($A)", "g($A)"), 1);
+		assert_eq!(edit("g($A)", "h($A)"), 1);
+		assert_eq!(fs::read_to_string(&path).expect("edit.ts should be readable"), "h(1);\n");
+	}
+
+	#[test]
+	fn compiles_patterns_for_embedded_languages_without_lang() {
+		let tree = TempDir::new("ast-md-only");
+		tree.write("notes.md", "# Notes\n\n```ts\nf(1);\n```\n\n```python\nf(2)\n```\n");
+
+		let ct = task::CancelToken::default();
+		let candidates = collect_candidates(Some(tree_path(&tree)), None, None, &ct)
+			.expect("candidate collection should succeed");
+		let (resolved, languages) =
+			resolve_candidates_for_find(candidates, None, &ct).expect("candidates should resolve");
+		assert_eq!(languages.keys().collect::<Vec<_>>(), ["markdown"]);
+		let strictness = parse_strictness(None).expect("default strictness should parse");
+		let compiled = compile_find_patterns(
+			&["f($A)".to_string()],
+			&[],
+			&languages,
+			None,
+			&strictness,
+			true,
+			&ct,
+		)
+		.expect("patterns should compile");
+		let mut found = Vec::new();
+		for candidate in resolved {
+			let Ok(outcome) = find_in_candidate(candidate, &compiled, false, None, &ct) else {
+				panic!("candidate should be searched");
+			};
+			found.extend(outcome.matches.into_iter().map(|m| (m.text, m.start_line)));
+		}
+		assert_eq!(found, [("f(1)".to_string(), 4), ("f(2)".to_string(), 8)]);
+	}
+
+	#[test]
+	fn searches_and_rewrites_embedded_regions() {
+		let tree = make_temp_tree();
+		let ct = task::CancelToken::default();
+		let docs =
+			"# Usage\n\n```ts \
+			 title=\"a.ts\"\nf(1);\n```\n\n```python\nf(2)\n```\n\n```ts\nf(3\n```\n\n- 		            \
+			 item\n\n  ```typescript\n  f(4);\n  ```\n";
+		fs::write(tree.root.join("docs.md"), docs).expect("temp file docs.md should be written");
+		fs::write(
+			tree.root.join("App.vue"),
+			"<template><p>{{ f(0) }}</p></template>\n<script setup lang=\"ts\">\nf(5);\n</script>\n",
+		)
+		.expect("temp file App.vue should be written");
+		fs::write(tree.root.join("inline.html"), "<p>é</p><script lang=\"ts\">f(6)</script>\n")
+			.expect("temp file inline.html should be written");
+
+		let candidates = collect_candidates(
+			Some(tree_path(&tree)),
+			Some(&Either::A("*.{md,vue,html}".to_string())),
+			None,
+			&ct,
+		)
+		.expect("candidate collection should succeed");
+		let (resolved, languages) = resolve_candidates_for_find(candidates, Some("ts"), &ct)
+			.expect("candidates should resolve");
+		let strictness = parse_strictness(None).expect("default strictness should parse");
+		let compiled = compile_find_patterns(
+			&["f($A)".to_string()],
+			&[],
+			&languages,
+			None,
+			&strictness,
+			false,
+			&ct,
+		)
+		.expect("patterns should compile");
+		let mut found = Vec::new();
+		let mut parse_errors = Vec::new();
+		for candidate in resolved {
+			let Ok(outcome) = find_in_candidate(candidate, &compiled, false, None, &ct) else {
+				panic!("candidate should be searched");
+			};
+			found.extend(
+				outcome
+					.matches
+					.into_iter()
+					.map(|m| (m.path, m.text, m.start_line, m.start_column, m.byte_start)),
+			);
+			parse_errors.extend(outcome.parse_errors);
+		}
+		assert_eq!(found, [
+			("App.vue".to_string(), "f(5)".to_string(), 3, 1, 64),
+			("docs.md".to_string(), "f(1)".to_string(), 4, 1, 28),
+			("docs.md".to_string(), "f(4)".to_string(), 18, 3, 114),
+			// A region starting mid-line keeps host columns (in characters).
+			("inline.html".to_string(), "f(6)".to_string(), 1, 27, 27),
+		]);
+		assert_eq!(parse_errors.len(), 1, "{parse_errors:?}");
+		assert!(parse_errors[0].starts_with("docs.md:12: parse error in embedded typescript"));
+
+		let candidate = FileCandidate {
+			absolute_path: tree.root.join("docs.md"),
+			display_path:  "docs.md".to_string(),
+			blob:          None,
+		};
+		let pattern = compile_pattern("f($A)", None, &strictness, SupportLang::TypeScript)
+			.expect("pattern should compile");
+		let rules = [(
+			"f($A)".to_string(),
+			Fixer::new("g($A)", Transforms::default()),
+			FindMatcher::Pattern(pattern),
+		)];
+		let Ok(FileReplaceOutcome::Scanned { source, changes, issues, .. }) =
+			replace_in_candidate(&candidate, SupportLang::TypeScript, &rules, u32::MAX, &ct)
+		else {
+			panic!("candidate should scan");
+		};
+		assert_eq!(issues.len(), 1);
+		let edits: Vec<_> = changes.into_iter().map(|entry| entry.edit).collect();
+		let output = apply_edits(&source, &edits).expect("edits should apply");
+		assert_eq!(output, docs.replace("f(1);", "g(1);").replace("f(4);", "g(4);"));
 	}
 
 	#[test]
@@ -1026,6 +2114,64 @@ mod tests {
 		assert_eq!(output, "const value = 42;");
 	}
 
+	#[test]
+	fn reports_enclosing_scopes_and_context() {
+		let scope_of = |lang: SupportLang, source: &str, pattern: &str| {
+			let ast = lang.ast_grep(source);
+			let pattern = Pattern::new(pattern, lang);
+			let matched = ast.root().find(pattern).expect("pattern should match");
+			enclosing_scope(matched.get_node(), RegionOrigin::default())
+				.map(|scope| (scope.kind, scope.name, scope.start_line))
+		};
+		let rust = "struct S;\nimpl S {\n    fn run(&self) {\n        work();\n    }\n}\n";
+		assert_eq!(
+			scope_of(SupportLang::Rust, rust, "work()"),
+			Some(("function_item".to_string(), "run".to_string(), 3))
+		);
+		assert_eq!(
+			scope_of(SupportLang::Rust, rust, "fn run(&self) { $$$ }"),
+			Some(("impl_item".to_string(), "S".to_string(), 2))
+		);
+		let ts = "const handler = () => {\n  work();\n};\n";
+		assert_eq!(
+			scope_of(SupportLang::TypeScript, ts, "work()"),
+			Some(("arrow_function".to_string(), "handler".to_string(), 1))
+		);
+		let c = "int main(void) {\n  work();\n}\n";
+		assert_eq!(
+			scope_of(SupportLang::C, c, "work()").map(|scope| scope.1),
+			Some("main".to_string())
+		);
+		assert_eq!(scope_of(SupportLang::TypeScript, "work();\n", "work()"), None);
+		// Calls, variants and patterns that mention a scope word are not scopes.
+		let java = "class Greeter {\n  void greet() {\n    System.out.println(work());\n  }\n}\n";
+		assert_eq!(
+			scope_of(SupportLang::Java, java, "work()"),
+			Some(("method_declaration".to_string(), "greet".to_string(), 2))
+		);
+		let rust = "enum E {\n    A = work(),\n}\n";
+		assert_eq!(
+			scope_of(SupportLang::Rust, rust, "work()"),
+			Some(("enum_item".to_string(), "E".to_string(), 1))
+		);
+		let rust = "fn f() {\n    if let S { a: 1 } = s {}\n}\n";
+		assert_eq!(
+			scope_of(SupportLang::Rust, rust, "1").map(|scope| scope.0),
+			Some("function_item".to_string())
+		);
+
+		let lines = ["a", "b", "c"];
+		let context = context_lines(&lines, 1..5);
+		assert_eq!(
+			context
+				.iter()
+				.map(|line| line.line_number)
+				.collect::<Vec<_>>(),
+			[2, 3]
+		);
+		assert!(context_lines(&lines, 0..0).is_empty());
+	}
+
 	#[test]
 	fn rejects_overlapping_edits() {
 		let source = "abcdef";
@@ -0,0 +1,487 @@
+//! Rewrite templates for `astEdit`: metavariable transforms and indentation.
+//!
+//! # Overview
+//! A [`Fixer`] renders a `fix`/`rewrites` template against one match. Before
+//! rendering, ast-grep style `transform` entries derive new metavariables from
+//! captured ones (`substring`, regex `replace`, case `convert`); a transform
+//! may read the output of another, and they run in dependency order.
+//!
+//! Captures are inserted with their relative indentation intact: a multi-line
+//! `$$$BODY` is de-indented from the column it was captured at and re-indented
+//! to the whitespace before the metavariable in the template. The rendered
+//! text is then indented to the line of the matched node. Indentation is
+//! copied as written, so tab-indented sources stay tab-indented.
+
+use std::collections::{HashMap, HashSet};
+
+use ast_grep_core::{Doc, NodeMatch, meta_var::MetaVarEnv, replacer::Replacer};
+use napi::{Error, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+/// One `transform` entry as written in a rule or the `transform` option;
+/// exactly one key must be set.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTransform {
+	substring: Option<RawSubstring>,
+	replace:   Option<RawReplace>,
+	convert:   Option<RawConvert>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct RawSubstring {
+	source:     String,
+	start_char: Option<i64>,
+	end_char:   Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawReplace {
+	source:  String,
+	replace: String,
+	by:      String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct RawConvert {
+	source:       String,
+	to_case:      Casing,
+	separated_by: Option<Vec<Separator>>,
+}
+
+/// Target case of a `convert` transform.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Casing {
+	LowerCase,
+	UpperCase,
+	Capitalize,
+	CamelCase,
+	SnakeCase,
+	KebabCase,
+	PascalCase,
+}
+
+/// Word boundary recognized by a `convert` transform.
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Separator {
+	Dash,
+	Dot,
+	Space,
+	Slash,
+	Underscore,
+	CaseChange,
+}
+
+const ALL_SEPARATORS: &[Separator] = &[
+	Separator::Dash,
+	Separator::Dot,
+	Separator::Space,
+	Separator::Slash,
+	Separator::Underscore,
+	Separator::CaseChange,
+];
+
+#[derive(Clone)]
+enum Operation {
+	Substring { start: Option<i64>, end: Option<i64> },
+	Replace { regex: Regex, by: String },
+	Convert { case: Casing, separators: Vec<Separator> },
+}
+
+/// A compiled transform writing the metavariable `name`.
+#[derive(Clone)]
+struct Transform {
+	name:      String,
+	/// Metavariable read, without `$`/`$$$`.
+	source:    String,
+	multi:     bool,
+	operation: Operation,
+}
+
+/// Transforms of one template, sorted so each runs after its inputs.
+#[derive(Clone, Default, Deserialize)]
+#[serde(try_from = "HashMap<String, RawTransform>")]
+pub struct Transforms(Vec<Transform>);
+
+impl TryFrom<HashMap<String, RawTransform>> for Transforms {
+	type Error = String;
+
+	fn try_from(raw: HashMap<String, RawTransform>) -> std::result::Result<Self, String> {
+		let mut compiled = HashMap::with_capacity(raw.len());
+		for (name, transform) in raw {
+			let (source, operation) = match transform {
+				RawTransform { substring: Some(raw), replace: None, convert: None } => {
+					(raw.source, Operation::Substring { start: raw.start_char, end: raw.end_char })
+				},
+				RawTransform { substring: None, replace: Some(raw), convert: None } => {
+					let regex = Regex::new(&raw.replace)
+						.map_err(|err| format!("transform `{name}`: invalid regex: {err}"))?;
+					(raw.source, Operation::Replace { regex, by: raw.by })
+				},
+				RawTransform { substring: None, replace: None, convert: Some(raw) } => {
+					(raw.source, Operation::Convert {
+						case:       raw.to_case,
+						separators: raw.separated_by.unwrap_or_else(|| ALL_SEPARATORS.to_vec()),
+					})
+				},
+				_ => {
+					return Err(format!(
+						"transform `{name}` needs exactly one of `substring`, `replace` or `convert`"
+					));
+				},
+			};
+			let (multi, var) = match source.strip_prefix("$$$") {
+				Some(var) => (true, var),
+				None => (false, source.strip_prefix('$').unwrap_or_default()),
+			};
+			if !is_var_name(var) {
+				return Err(format!(
+					"transform `{name}`: source `{source}` must be a metavariable such as `$A` or \
+					 `$$$A`"
+				));
+			}
+			let source = var.to_string();
+			compiled.insert(name.clone(), Transform { name, source, multi, operation });
+		}
+
+		// Depth-first so a transform reading another one runs after it.
+		fn visit(
+			name: &str,
+			compiled: &HashMap<String, Transform>,
+			visiting: &mut Vec<String>,
+			done: &mut HashSet<String>,
+			order: &mut Vec<Transform>,
+		) -> std::result::Result<(), String> {
+			if done.contains(name) {
+				return Ok(());
+			}
+			if visiting.iter().any(|seen| seen == name) {
+				visiting.push(name.to_string());
+				return Err(format!("transform cycle: {}", visiting.join(" -> ")));
+			}
+			let transform = &compiled[name];
+			visiting.push(name.to_string());
+			if !transform.multi && compiled.contains_key(&transform.source) {
+				visit(&transform.source, compiled, visiting, done, order)?;
+			}
+			visiting.pop();
+			done.insert(name.to_string());
+			order.push(transform.clone());
+			Ok(())
+		}
+		let mut names: Vec<&String> = compiled.keys().collect();
+		names.sort();
+		let mut order = Vec::with_capacity(compiled.len());
+		let mut done = HashSet::new();
+		for name in names {
+			visit(name, &compiled, &mut Vec::new(), &mut done, &mut order)?;
+		}
+		Ok(Self(order))
+	}
+}
+
+/// Parse the `transform` option: a YAML or JSON mapping of name to transform.
+pub fn parse_transforms(text: &str) -> Result<Transforms> {
+	serde_yaml_ng::from_str::<Option<Transforms>>(text)
+		.map(Option::unwrap_or_default)
+		.map_err(|err| Error::from_reason(format!("Invalid transform: {err}")))
+}
+
+/// A rewrite template plus the transforms it may reference.
+#[derive(Clone)]
+pub struct Fixer {
+	template:   String,
+	transforms: Transforms,
+}
+
+impl Fixer {
+	pub fn new(template: impl Into<String>, transforms: Transforms) -> Self {
+		Self { template: template.into(), transforms }
+	}
+
+	/// Render the template for a match in `source`.
+	fn render<D: Doc>(&self, source: &str, match_start: usize, env: &MetaVarEnv<'_, D>) -> String {
+		let mut transformed: HashMap<&str, String> = HashMap::new();
+		for transform in &self.transforms.0 {
+			let input = if transform.multi {
+				capture_text(source, env, &transform.source, true)
+			} else {
+				transformed
+					.get(transform.source.as_str())
+					.cloned()
+					.or_else(|| capture_text(source, env, &transform.source, false))
+			};
+			if let Some(input) = input {
+				transformed.insert(&transform.name, transform.operation.apply(&input));
+			}
+		}
+
+		let mut rendered = String::with_capacity(self.template.len());
+		let mut rest = self.template.as_str();
+		while let Some(dollar) = rest.find('$') {
+			rendered.push_str(&rest[..dollar]);
+			let after = &rest[dollar..];
+			let multi = after.starts_with("$$$");
+			let name_start = if multi { 3 } else { 1 };
+			let name_len = after[name_start..]
+				.find(|ch: char| !(ch.is_ascii_uppercase() || ch.is_ascii_digit() || ch == '_'))
+				.unwrap_or(after.len() - name_start);
+			let name = &after[name_start..name_start + name_len];
+			if !is_var_name(name) {
+				rendered.push_str(&after[..name_start]);
+				rest = &after[name_start..];
+				continue;
+			}
+			let value = if multi {
+				capture_text(source, env, name, true)
+			} else {
+				transformed
+					.get(name)
+					.cloned()
+					.or_else(|| capture_text(source, env, name, false))
+			};
+			if let Some(value) = value {
+				let indent = line_indent(&rendered, rendered.len());
+				rendered.push_str(&reindent(&value, indent));
+			}
+			rest = &after[name_start + name_len..];
+		}
+		rendered.push_str(rest);
+
+		reindent(&rendered, line_indent(source, match_start))
+	}
+}
+
+impl<D: Doc<Source = String>> Replacer<D> for Fixer {
+	fn generate_replacement(&self, nm: &NodeMatch<'_, D>) -> Vec<u8> {
+		let source = nm.get_node().get_doc().get_source();
+		self
+			.render(source, nm.range().start, nm.get_env())
+			.into_bytes()
+	}
+}
+
+impl Operation {
+	fn apply(&self, input: &str) -> String {
+		match self {
+			Self::Substring { start, end } => {
+				let chars: Vec<char> = input.chars().collect();
+				let resolve = |index: i64| {
+					let len = chars.len() as i64;
+					let index = if index < 0 { len + index } else { index };
+					index.clamp(0, len) as usize
+				};
+				let start = start.map_or(0, resolve);
+				let end = end.map_or(chars.len(), resolve);
+				if start >= end {
+					String::new()
+				} else {
+					chars[start..end].iter().collect()
+				}
+			},
+			Self::Replace { regex, by } => regex.replace_all(input, by.as_str()).into_owned(),
+			Self::Convert { case, separators } => convert_case(input, *case, separators),
+		}
+	}
+}
+
+fn is_var_name(name: &str) -> bool {
+	name.starts_with(|ch: char| ch.is_ascii_uppercase() || ch == '_')
+		&& name
+			.chars()
+			.all(|ch| ch.is_ascii_uppercase() || ch.is_ascii_digit() || ch == '_')
+}
+
+/// Text of the capture `name`, de-indented relative to its first line.
+fn capture_text<D: Doc>(
+	source: &str,
+	env: &MetaVarEnv<'_, D>,
+	name: &str,
+	multi: bool,
+) -> Option<String> {
+	let range = if multi {
+		let nodes = env.get_multiple_matches(name);
+		let first = nodes.first()?;
+		let last = nodes.last()?;
+		first.range().start..last.range().end
+	} else {
+		env.get_match(name)?.range()
+	};
+	let text = source.get(range.clone())?;
+	Some(dedent(text, line_indent(source, range.start)))
+}
+
+/// Leading whitespace of the line containing `offset`.
+fn line_indent(text: &str, offset: usize) -> &str {
+	let line_start = text[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+	let line = &text[line_start..];
+	&line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Strip `indent` from every line but the first.
+fn dedent(text: &str, indent: &str) -> String {
+	if indent.is_empty() || !text.contains('\n') {
+		return text.to_string();
+	}
+	let mut lines = text.split('\n');
+	let mut out = lines.next().unwrap_or_default().to_string();
+	for line in lines {
+		out.push('\n');
+		let stripped = line.strip_prefix(indent).unwrap_or_else(|| {
+			let whitespace = line.len() - line.trim_start_matches([' ', '\t']).len();
+			&line[whitespace.min(indent.len())..]
+		});
+		out.push_str(stripped);
+	}
+	out
+}
+
+/// Prefix every non-empty line but the first with `indent`.
+fn reindent(text: &str, indent: &str) -> String {
+	if indent.is_empty() || !text.contains('\n') {
+		return text.to_string();
+	}
+	let mut lines = text.split('\n');
+	let mut out = lines.next().unwrap_or_default().to_string();
+	for line in lines {
+		out.push('\n');
+		if !line.is_empty() {
+			out.push_str(indent);
+		}
+		out.push_str(line);
+	}
+	out
+}
+
+fn convert_case(input: &str, case: Casing, separators: &[Separator]) -> String {
+	fn capitalize(word: &str) -> String {
+		let mut chars = word.chars();
+		chars.next().map_or_else(String::new, |first| {
+			first
+				.to_uppercase()
+				.chain(chars.flat_map(char::to_lowercase))
+				.collect()
+		})
+	}
+
+	let words = || split_words(input, separators);
+	match case {
+		Casing::LowerCase => input.to_lowercase(),
+		Casing::UpperCase => input.to_uppercase(),
+		Casing::Capitalize => {
+			let mut chars = input.chars();
+			chars
+				.next()
+				.map_or_else(String::new, |first| first.to_uppercase().chain(chars).collect())
+		},
+		Casing::CamelCase => words()
+			.iter()
+			.enumerate()
+			.map(|(index, word)| {
+				if index == 0 {
+					word.to_lowercase()
+				} else {
+					capitalize(word)
+				}
+			})
+			.collect(),
+		Casing::PascalCase => words().iter().map(|word| capitalize(word)).collect(),
+		Casing::SnakeCase => words().join("_").to_lowercase(),
+		Casing::KebabCase => words().join("-").to_lowercase(),
+	}
+}
+
+/// Split an identifier into words at the given separators. A case change
+/// splits before an uppercase letter that follows a lowercase letter or digit,
+/// and before the last capital of an acronym (`HTTPServer` -> `HTTP Server`).
+fn split_words<'a>(input: &'a str, separators: &[Separator]) -> Vec<&'a str> {
+	let separator_of = |ch: char| match ch {
+		'-' => Some(Separator::Dash),
+		'.' => Some(Separator::Dot),
+		' ' => Some(Separator::Space),
+		'/' => Some(Separator::Slash),
+		'_' => Some(Separator::Underscore),
+		_ => None,
+	};
+	let case_change = separators.contains(&Separator::CaseChange);
+
+	let mut words = Vec::new();
+	let mut start = 0;
+	let chars: Vec<(usize, char)> = input.char_indices().collect();
+	for (index, &(offset, ch)) in chars.iter().enumerate() {
+		if separator_of(ch).is_some_and(|separator| separators.contains(&separator)) {
+			if offset > start {
+				words.push(&input[start..offset]);
+			}
+			start = offset + ch.len_utf8();
+			continue;
+		}
+		if case_change && ch.is_uppercase() && offset > start {
+			let prev = chars[index - 1].1;
+			let next_is_lower = chars
+				.get(index + 1)
+				.is_some_and(|&(_, next)| next.is_lowercase());
+			if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_is_lower) {
+				words.push(&input[start..offset]);
+				start = offset;
+			}
+		}
+	}
+	if start < input.len() {
+		words.push(&input[start..]);
+	}
+	words
+}
+
+#[cfg(test)]
+mod tests {
+	use ast_grep_core::{Pattern, tree_sitter::LanguageExt};
+
+	use super::*;
+	use crate::language::SupportLang;
+
+	fn rewrite(source: &str, pattern: &str, template: &str, transforms: &str) -> String {
+		let lang = SupportLang::TypeScript;
+		let fixer =
+			Fixer::new(template, parse_transforms(transforms).expect("transforms should parse"));
+		let pattern = Pattern::try_new(pattern, lang).expect("pattern should compile");
+		let ast = lang.ast_grep(source);
+		let matched = ast.root().find(&pattern).expect("pattern should match");
+		String::from_utf8(matched.replace_by(&fixer).inserted_text).expect("utf-8 replacement")
+	}
+
+	#[test]
+	fn applies_transforms_in_dependency_order() {
+		let transforms = r"
+SNAKE: { convert: { source: $TRIMMED, toCase: snakeCase } }
+TRIMMED: { replace: { source: $NAME, replace: '^get', by: '' } }
+SHORT: { substring: { source: $NAME, startChar: 1, endChar: -1 } }
+";
+		assert_eq!(
+			rewrite("getUserID();", "$NAME()", "$SNAKE $SHORT", transforms),
+			"user_id etUserI"
+		);
+		assert_eq!(split_words("HTTPServer_v2", ALL_SEPARATORS), ["HTTP", "Server", "v2"]);
+		assert_eq!(convert_case("user-name", Casing::PascalCase, ALL_SEPARATORS), "UserName");
+		assert!(
+			parse_transforms(
+				"A: { replace: { source: $B, replace: x, by: y } }\nB: { substring: { source: $A } }"
+			)
+			.is_err()
+		);
+	}
+
+	#[test]
+	fn reindents_multi_captures_with_tabs() {
+		let source = "function f() {\n\tif (ok) {\n\t\ta();\n\t\tb();\n\t}\n}\n";
+		let replaced =
+			rewrite(source, "if ($C) { $$$BODY }", "if (!$C) {\n\treturn;\n}\n$$$BODY", "");
+		assert_eq!(replaced, "if (!ok) {\n\t\treturn;\n\t}\n\ta();\n\tb();");
+	}
+}
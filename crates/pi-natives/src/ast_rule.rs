@@ -0,0 +1,656 @@
+//! ast-grep rule documents for `astGrep` and `astEdit`.
+//!
+//! # Overview
+//! Rules follow the ast-grep YAML schema: a `rule` built from atomic keys
+//! (`pattern`, `kind`, `regex`), relational keys (`inside`, `has`, `follows`,
+//! `precedes`, each with optional `stopBy` and `field`), composite keys
+//! (`all`, `any`, `not`, `matches`), plus per-document `constraints` on
+//! metavariables and reusable `utils`. Keys in one rule object must all match.
+//! A `fix` may use metavariables derived by `transform` (see [`crate::ast_fix`]).
+//!
+//! Input is YAML (JSON is a subset); several rules are given as `---`
+//! separated documents or as a top-level list. Documents are parsed once and
+//! compiled per language, since patterns and kinds are grammar-specific.
+
+use std::{
+	borrow::Cow,
+	collections::{HashMap, HashSet},
+	sync::{Arc, OnceLock, Weak},
+};
+
+use ast_grep_core::{
+	Doc, MatchStrictness, Node,
+	matcher::{KindMatcher, Matcher, Pattern, RegexMatcher},
+	meta_var::MetaVarEnv,
+};
+use bit_set::BitSet;
+use napi::{Error, Result};
+use serde::Deserialize;
+
+use crate::{
+	ast::{parse_strictness, resolve_supported_lang},
+	ast_fix::{Fixer, Transforms},
+	language::SupportLang,
+};
+
+/// Document keys accepted for compatibility with ast-grep rule files but not
+/// used by search or rewrite.
+const IGNORED_KEYS: &[&str] =
+	&["message", "severity", "note", "url", "metadata", "files", "ignores", "labels"];
+
+/// One rule document.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawDocument {
+	id:          Option<String>,
+	language:    Option<String>,
+	rule:        RawRule,
+	#[serde(default)]
+	constraints: HashMap<String, RawRule>,
+	#[serde(default)]
+	utils:       HashMap<String, RawRule>,
+	fix:         Option<String>,
+	#[serde(default)]
+	transform:   Transforms,
+	#[serde(flatten)]
+	extra:       HashMap<String, serde_yaml_ng::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct RawRule {
+	pattern:  Option<RawPattern>,
+	kind:     Option<String>,
+	regex:    Option<String>,
+	inside:   Option<Box<Self>>,
+	has:      Option<Box<Self>>,
+	follows:  Option<Box<Self>>,
+	precedes: Option<Box<Self>>,
+	all:      Option<Vec<Self>>,
+	any:      Option<Vec<Self>>,
+	not:      Option<Box<Self>>,
+	matches:  Option<String>,
+	/// Only valid inside a relational rule.
+	stop_by:  Option<RawStopBy>,
+	/// Only valid inside `inside` and `has`.
+	field:    Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawPattern {
+	Source(String),
+	Contextual { context: String, selector: Option<String>, strictness: Option<String> },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawStopBy {
+	Named(String),
+	Rule(Box<RawRule>),
+}
+
+/// A parsed rule document, compiled on demand for each language.
+pub struct RuleDocument {
+	/// `id`, or `rule-<n>` (1-based) when absent.
+	pub id:       String,
+	/// Language the rule is restricted to.
+	pub language: Option<SupportLang>,
+	/// Rewrite template for `astEdit`.
+	pub fix:      Option<String>,
+	transform:    Transforms,
+	rule:         RawRule,
+	constraints:  HashMap<String, RawRule>,
+	utils:        HashMap<String, RawRule>,
+}
+
+/// Parse rule documents from YAML or JSON text.
+pub fn parse_rules(text: &str) -> Result<Vec<RuleDocument>> {
+	let mut raw = Vec::new();
+	for document in serde_yaml_ng::Deserializer::from_str(text) {
+		let value = serde_yaml_ng::Value::deserialize(document)
+			.map_err(|err| Error::from_reason(format!("Invalid rules: {err}")))?;
+		match value {
+			serde_yaml_ng::Value::Null => {},
+			serde_yaml_ng::Value::Sequence(items) => raw.extend(items),
+			value => raw.push(value),
+		}
+	}
+	if raw.is_empty() {
+		return Err(Error::from_reason("`rules` must contain at least one rule document"));
+	}
+
+	let mut documents = Vec::with_capacity(raw.len());
+	for (index, value) in raw.into_iter().enumerate() {
+		let label = format!("rule-{}", index + 1);
+		let parsed: RawDocument = serde_yaml_ng::from_value(value)
+			.map_err(|err| Error::from_reason(format!("Invalid rule {label}: {err}")))?;
+		let id = parsed.id.unwrap_or(label);
+		if let Some(key) = parsed
+			.extra
+			.keys()
+			.find(|key| !IGNORED_KEYS.contains(&key.as_str()))
+		{
+			return Err(Error::from_reason(format!("{id}: unsupported rule key `{key}`")));
+		}
+		let language = parsed
+			.language
+			.as_deref()
+			.map(resolve_supported_lang)
+			.transpose()
+			.map_err(|err| Error::from_reason(format!("{id}: {err}")))?;
+		check_util_references(&id, &parsed.rule, &parsed.constraints, &parsed.utils)?;
+		documents.push(RuleDocument {
+			id,
+			language,
+			fix: parsed.fix,
+			transform: parsed.transform,
+			rule: parsed.rule,
+			constraints: parsed.constraints,
+			utils: parsed.utils,
+		});
+	}
+	Ok(documents)
+}
+
+/// Reject `matches` references to unknown utils and cycles between utils,
+/// which would otherwise recurse forever while matching.
+fn check_util_references(
+	id: &str,
+	rule: &RawRule,
+	constraints: &HashMap<String, RawRule>,
+	utils: &HashMap<String, RawRule>,
+) -> Result<()> {
+	fn visit(rule: &RawRule, refs: &mut Vec<String>) {
+		refs.extend(rule.matches.iter().cloned());
+		let nested = [&rule.inside, &rule.has, &rule.follows, &rule.precedes, &rule.not];
+		for child in nested.into_iter().flatten() {
+			visit(child, refs);
+		}
+		for child in rule.all.iter().chain(&rule.any).flatten() {
+			visit(child, refs);
+		}
+		if let Some(RawStopBy::Rule(stop)) = &rule.stop_by {
+			visit(stop, refs);
+		}
+	}
+	fn references(rule: &RawRule) -> Vec<String> {
+		let mut refs = Vec::new();
+		visit(rule, &mut refs);
+		refs
+	}
+	fn find_cycle<'a>(
+		util: &'a str,
+		utils: &'a HashMap<String, RawRule>,
+		path: &mut Vec<&'a str>,
+		done: &mut HashSet<&'a str>,
+	) -> Option<String> {
+		if let Some(start) = path.iter().position(|seen| *seen == util) {
+			let mut cycle = path[start..].to_vec();
+			cycle.push(util);
+			return Some(cycle.join(" -> "));
+		}
+		if !done.insert(util) {
+			return None;
+		}
+		path.push(util);
+		for next in references(&utils[util]) {
+			if let Some((key, _)) = utils.get_key_value(&next)
+				&& let Some(cycle) = find_cycle(key, utils, path, done)
+			{
+				return Some(cycle);
+			}
+		}
+		path.pop();
+		None
+	}
+
+	let all_refs = std::iter::once(rule)
+		.chain(constraints.values())
+		.chain(utils.values())
+		.flat_map(references);
+	for reference in all_refs {
+		if !utils.contains_key(&reference) {
+			return Err(Error::from_reason(format!("{id}: unknown util rule `{reference}`")));
+		}
+	}
+	let mut done = HashSet::new();
+	for util in utils.keys() {
+		if let Some(cycle) = find_cycle(util, utils, &mut Vec::new(), &mut done) {
+			return Err(Error::from_reason(format!("{id}: util rules form a cycle: {cycle}")));
+		}
+	}
+	Ok(())
+}
+
+impl RuleDocument {
+	/// The `fix` template with this document's transforms.
+	pub fn fixer(&self) -> Option<Fixer> {
+		self
+			.fix
+			.as_ref()
+			.map(|fix| Fixer::new(fix.as_str(), self.transform.clone()))
+	}
+
+	/// Compile for `lang`. `strictness` applies to patterns that do not set
+	/// their own.
+	pub fn compile(&self, lang: SupportLang, strictness: &MatchStrictness) -> Result<RuleMatcher> {
+		let utils = Arc::new(OnceLock::new());
+		let compiler = Compiler { lang, strictness, utils: Arc::downgrade(&utils) };
+		let compiled_utils = self
+			.utils
+			.iter()
+			.map(|(name, rule)| Ok((name.clone(), compiler.rule(rule, false)?)))
+			.collect::<Result<HashMap<_, _>>>()?;
+		let _ = utils.set(compiled_utils);
+		let rule = compiler.rule(&self.rule, false)?;
+		let constraints = self
+			.constraints
+			.iter()
+			.map(|(name, rule)| {
+				let name = name.trim_start_matches('$').to_string();
+				Ok((name, compiler.rule(rule, false)?))
+			})
+			.collect::<Result<HashMap<_, _>>>()?;
+		Ok(RuleMatcher { rule, constraints, _utils: utils })
+	}
+}
+
+type Utils = OnceLock<HashMap<String, Rule>>;
+
+struct Compiler<'a> {
+	lang:       SupportLang,
+	strictness: &'a MatchStrictness,
+	utils:      Weak<Utils>,
+}
+
+impl Compiler<'_> {
+	fn rule(&self, raw: &RawRule, relational: bool) -> Result<Rule> {
+		if !relational && (raw.stop_by.is_some() || raw.field.is_some()) {
+			return Err(Error::from_reason(
+				"`stopBy` and `field` are only valid inside relational rules",
+			));
+		}
+		let mut parts = Vec::new();
+		if let Some(pattern) = &raw.pattern {
+			parts.push(Rule::Pattern(self.pattern(pattern)?));
+		}
+		if let Some(kind) = &raw.kind {
+			let matcher = KindMatcher::try_new(kind, self.lang)
+				.map_err(|err| Error::from_reason(format!("Invalid kind `{kind}`: {err}")))?;
+			parts.push(Rule::Kind(matcher));
+		}
+		if let Some(regex) = &raw.regex {
+			let matcher = RegexMatcher::try_new(regex)
+				.map_err(|err| Error::from_reason(format!("Invalid regex `{regex}`: {err}")))?;
+			parts.push(Rule::Regex(matcher));
+		}
+		let relations = [
+			(&raw.inside, RelationKind::Inside),
+			(&raw.has, RelationKind::Has),
+			(&raw.precedes, RelationKind::Precedes),
+			(&raw.follows, RelationKind::Follows),
+		];
+		for (relation, kind) in relations {
+			if let Some(relation) = relation {
+				parts.push(Rule::Relation(Box::new(self.relation(relation, kind)?)));
+			}
+		}
+		if let Some(all) = &raw.all {
+			parts.push(Rule::All(self.rules(all)?));
+		}
+		if let Some(any) = &raw.any {
+			parts.push(Rule::Any(self.rules(any)?));
+		}
+		if let Some(not) = &raw.not {
+			parts.push(Rule::Not(Box::new(self.rule(not, false)?)));
+		}
+		if let Some(id) = &raw.matches {
+			parts.push(Rule::Matches { id: id.clone(), utils: self.utils.clone() });
+		}
+
+		match parts.len() {
+			0 => Err(Error::from_reason(
+				"A rule needs at least one of pattern, kind, regex, inside, has, follows, precedes, \
+				 all, any, not or matches",
+			)),
+			1 => Ok(parts.remove(0)),
+			_ => Ok(Rule::All(parts)),
+		}
+	}
+
+	fn rules(&self, raw: &[RawRule]) -> Result<Vec<Rule>> {
+		raw.iter().map(|rule| self.rule(rule, false)).collect()
+	}
+
+	fn pattern(&self, raw: &RawPattern) -> Result<Pattern> {
+		let (compiled, strictness) = match raw {
+			RawPattern::Source(source) => (Pattern::try_new(source, self.lang), None),
+			RawPattern::Contextual { context, selector, strictness } => {
+				let compiled = match selector {
+					Some(selector) => Pattern::contextual(context, selector, self.lang),
+					None => Pattern::try_new(context, self.lang),
+				};
+				(compiled, strictness.as_deref())
+			},
+		};
+		let mut compiled =
+			compiled.map_err(|err| Error::from_reason(format!("Invalid pattern: {err}")))?;
+		compiled.strictness = match strictness {
+			Some(strictness) => parse_strictness(Some(strictness))?,
+			None => self.strictness.clone(),
+		};
+		Ok(compiled)
+	}
+
+	fn relation(&self, raw: &RawRule, kind: RelationKind) -> Result<Relation> {
+		if raw.field.is_some() && matches!(kind, RelationKind::Precedes | RelationKind::Follows) {
+			return Err(Error::from_reason("`field` is only valid in `inside` and `has`"));
+		}
+		let stop_by = match &raw.stop_by {
+			None => StopBy::Neighbor,
+			Some(RawStopBy::Named(name)) if name == "neighbor" => StopBy::Neighbor,
+			Some(RawStopBy::Named(name)) if name == "end" => StopBy::End,
+			Some(RawStopBy::Named(name)) => {
+				return Err(Error::from_reason(format!(
+					"Invalid stopBy `{name}` (expected neighbor, end or a rule)"
+				)));
+			},
+			Some(RawStopBy::Rule(rule)) => StopBy::Rule(self.rule(rule, false)?),
+		};
+		Ok(Relation { kind, rule: self.rule(raw, true)?, stop_by, field: raw.field.clone() })
+	}
+}
+
+/// A compiled rule node.
+enum Rule {
+	Pattern(Pattern),
+	Kind(KindMatcher),
+	Regex(RegexMatcher),
+	Relation(Box<Relation>),
+	All(Vec<Self>),
+	Any(Vec<Self>),
+	Not(Box<Self>),
+	Matches { id: String, utils: Weak<Utils> },
+}
+
+#[derive(Clone, Copy)]
+enum RelationKind {
+	Inside,
+	Has,
+	Precedes,
+	Follows,
+}
+
+enum StopBy {
+	/// Only the parent, direct children or adjacent sibling.
+	Neighbor,
+	/// Search to the root, the leaves or the first/last sibling.
+	End,
+	/// Search until a node matching the rule, which is still tested.
+	Rule(Rule),
+}
+
+struct Relation {
+	kind:    RelationKind,
+	/// The rule the related node must match (`stopBy`/`field` stripped).
+	rule:    Rule,
+	stop_by: StopBy,
+	field:   Option<String>,
+}
+
+/// Run `matcher` against `node`, writing captured metavariables back to `env`
+/// only on success.
+fn try_match<'tree, D: Doc>(
+	matcher: &impl Matcher,
+	node: Node<'tree, D>,
+	env: &mut Cow<'_, MetaVarEnv<'tree, D>>,
+) -> Option<Node<'tree, D>> {
+	let mut attempt = Cow::Borrowed(env.as_ref());
+	let matched = matcher.match_node_with_env(node, &mut attempt)?;
+	if let Cow::Owned(updated) = attempt {
+		*env = Cow::Owned(updated);
+	}
+	Some(matched)
+}
+
+fn is_field_child<D: Doc>(parent: &Node<'_, D>, field: &str, child: &Node<'_, D>) -> bool {
+	parent
+		.field_children(field)
+		.any(|candidate| candidate.node_id() == child.node_id())
+}
+
+impl Relation {
+	/// Whether `candidate` ends the search after being tested.
+	fn stops_at<D: Doc>(&self, candidate: &Node<'_, D>) -> bool {
+		match &self.stop_by {
+			StopBy::Neighbor => true,
+			StopBy::End => false,
+			StopBy::Rule(stop) => {
+				let mut env = Cow::Owned(MetaVarEnv::new());
+				stop
+					.match_node_with_env(candidate.clone(), &mut env)
+					.is_some()
+			},
+		}
+	}
+
+	fn matches<'tree, D: Doc>(
+		&self,
+		node: &Node<'tree, D>,
+		env: &mut Cow<'_, MetaVarEnv<'tree, D>>,
+	) -> bool {
+		match self.kind {
+			RelationKind::Inside => {
+				let mut child = node.clone();
+				for ancestor in node.ancestors() {
+					let in_field = self
+						.field
+						.as_deref()
+						.is_none_or(|field| is_field_child(&ancestor, field, &child));
+					if in_field && try_match(&self.rule, ancestor.clone(), env).is_some() {
+						return true;
+					}
+					if self.stops_at(&ancestor) {
+						return false;
+					}
+					child = ancestor;
+				}
+				false
+			},
+			RelationKind::Has => {
+				let children: Vec<_> = match self.field.as_deref() {
+					Some(field) => node.field_children(field).collect(),
+					None => node.children().collect(),
+				};
+				// Depth-first, in source order.
+				let mut stack: Vec<_> = children.into_iter().rev().collect();
+				while let Some(candidate) = stack.pop() {
+					if try_match(&self.rule, candidate.clone(), env).is_some() {
+						return true;
+					}
+					if !self.stops_at(&candidate) {
+						let len = stack.len();
+						stack.extend(candidate.children());
+						stack[len..].reverse();
+					}
+				}
+				false
+			},
+			RelationKind::Precedes => self.siblings(node.next_all(), env),
+			RelationKind::Follows => self.siblings(node.prev_all(), env),
+		}
+	}
+
+	fn siblings<'tree, D: Doc>(
+		&self,
+		siblings: impl Iterator<Item = Node<'tree, D>>,
+		env: &mut Cow<'_, MetaVarEnv<'tree, D>>,
+	) -> bool {
+		for sibling in siblings {
+			if try_match(&self.rule, sibling.clone(), env).is_some() {
+				return true;
+			}
+			if self.stops_at(&sibling) {
+				return false;
+			}
+		}
+		false
+	}
+}
+
+impl Matcher for Rule {
+	fn match_node_with_env<'tree, D: Doc>(
+		&self,
+		node: Node<'tree, D>,
+		env: &mut Cow<MetaVarEnv<'tree, D>>,
+	) -> Option<Node<'tree, D>> {
+		match self {
+			Self::Pattern(pattern) => pattern.match_node_with_env(node, env),
+			Self::Kind(kind) => kind.match_node_with_env(node, env),
+			Self::Regex(regex) => regex.match_node_with_env(node, env),
+			Self::Relation(relation) => relation.matches(&node, env).then_some(node),
+			Self::All(rules) => {
+				let mut attempt = Cow::Borrowed(env.as_ref());
+				for rule in rules {
+					rule.match_node_with_env(node.clone(), &mut attempt)?;
+				}
+				if let Cow::Owned(updated) = attempt {
+					*env = Cow::Owned(updated);
+				}
+				Some(node)
+			},
+			Self::Any(rules) => {
+				let matched = rules
+					.iter()
+					.any(|rule| try_match(rule, node.clone(), env).is_some());
+				matched.then_some(node)
+			},
+			Self::Not(rule) => {
+				let mut scratch = Cow::Borrowed(env.as_ref());
+				rule
+					.match_node_with_env(node.clone(), &mut scratch)
+					.is_none()
+					.then_some(node)
+			},
+			Self::Matches { id, utils } => {
+				let utils = utils.upgrade()?;
+				let rule = utils.get()?.get(id)?;
+				rule.match_node_with_env(node, env)
+			},
+		}
+	}
+
+	fn potential_kinds(&self) -> Option<BitSet> {
+		match self {
+			Self::Pattern(pattern) => pattern.potential_kinds(),
+			Self::Kind(kind) => kind.potential_kinds(),
+			// Any member's kinds bound the conjunction.
+			Self::All(rules) => rules.iter().find_map(Matcher::potential_kinds),
+			Self::Any(rules) => rules.iter().try_fold(BitSet::new(), |mut kinds, rule| {
+				kinds.union_with(&rule.potential_kinds()?);
+				Some(kinds)
+			}),
+			Self::Regex(_) | Self::Relation(_) | Self::Not(_) | Self::Matches { .. } => None,
+		}
+	}
+}
+
+/// A rule document compiled for one language.
+pub struct RuleMatcher {
+	rule:        Rule,
+	/// Keyed by metavariable name without the `$`.
+	constraints: HashMap<String, Rule>,
+	/// Keeps the utils alive for `matches` references, which hold weak links.
+	_utils:      Arc<Utils>,
+}
+
+impl Matcher for RuleMatcher {
+	fn match_node_with_env<'tree, D: Doc>(
+		&self,
+		node: Node<'tree, D>,
+		env: &mut Cow<MetaVarEnv<'tree, D>>,
+	) -> Option<Node<'tree, D>> {
+		let mut attempt = Cow::Borrowed(env.as_ref());
+		let matched = self.rule.match_node_with_env(node, &mut attempt)?;
+		if !self.constraints.is_empty() && !attempt.to_mut().match_constraints(&self.constraints) {
+			return None;
+		}
+		if let Cow::Owned(updated) = attempt {
+			*env = Cow::Owned(updated);
+		}
+		Some(matched)
+	}
+
+	fn potential_kinds(&self) -> Option<BitSet> {
+		self.rule.potential_kinds()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ast_grep_core::tree_sitter::LanguageExt;
+
+	use super::*;
+
+	fn find(rules: &str, lang: SupportLang, source: &str) -> Vec<String> {
+		let documents = parse_rules(rules).expect("rules should parse");
+		let ast = lang.ast_grep(source);
+		documents
+			.iter()
+			.flat_map(|document| {
+				let matcher = document
+					.compile(lang, &MatchStrictness::Smart)
+					.expect("rule should compile");
+				ast.root()
+					.find_all(&matcher)
+					.map(|matched| matched.text().into_owned())
+					.collect::<Vec<_>>()
+			})
+			.collect()
+	}
+
+	#[test]
+	fn matches_relational_and_composite_rules() {
+		let source = "try { foo(1); } catch (e) {}\nfoo(2);\nfunction f() { foo(3); }\n";
+		let outside_try = "rule:\n  pattern: foo($A)\n  not:\n    inside:\n      kind: \
+		                   try_statement\n      stopBy: end\n";
+		assert_eq!(find(outside_try, SupportLang::TypeScript, source), ["foo(2)", "foo(3)"]);
+
+		let json = r#"[{"id": "in-fn", "rule": {"pattern": "foo($A)", "inside": {"kind": "function_declaration", "stopBy": "end"}}},
+			{"id": "has", "rule": {"kind": "function_declaration", "has": {"pattern": "foo($$$)", "stopBy": "end"}}}]"#;
+		assert_eq!(find(json, SupportLang::TypeScript, source), [
+			"foo(3)",
+			"function f() { foo(3); }"
+		]);
+
+		let constrained = "rule:\n  any:\n    - pattern: foo($A)\n    - pattern: \
+		                   bar($A)\nconstraints:\n  A:\n    regex: ^[23]$\n";
+		assert_eq!(find(constrained, SupportLang::TypeScript, source), ["foo(2)", "foo(3)"]);
+
+		let multi = "id: one\nrule:\n  matches: call\nutils:\n  call:\n    pattern: \
+		             foo(1)\n---\nid: two\nrule:\n  kind: expression_statement\n  follows:\n    \
+		             kind: expression_statement\n";
+		assert_eq!(find(multi, SupportLang::TypeScript, "foo(1);\nfoo(2);\n"), ["foo(1)", "foo(2);"]);
+	}
+
+	#[test]
+	fn rejects_invalid_rules() {
+		assert!(parse_rules("").is_err());
+		assert!(parse_rules("rule:\n  patern: foo\n").is_err());
+		assert!(parse_rules("rule:\n  matches: missing\n").is_err());
+		assert!(
+			parse_rules("rule:\n  matches: a\nutils:\n  a:\n    matches: b\n  b:\n    matches: a\n")
+				.is_err()
+		);
+		assert!(parse_rules("rule:\n  pattern: foo\ntransform:\n  A: { rewrite: {} }\n").is_err());
+
+		let lang = SupportLang::TypeScript;
+		let compile =
+			|text: &str| parse_rules(text).unwrap()[0].compile(lang, &MatchStrictness::Smart);
+		assert!(compile("rule:\n  not:\n    pattern: foo\n").is_ok());
+		assert!(compile("rule:\n  kind: no_such_kind\n").is_err());
+		assert!(compile("rule:\n  pattern: foo\n  stopBy: end\n").is_err());
+		assert!(compile("rule: {}\n").is_err());
+	}
+}
@@ -0,0 +1,243 @@
+//! Line-based unified diffs for rewrite previews.
+//!
+//! # Overview
+//! [`unified_diff`] compares two texts line by line with Myers' algorithm and
+//! renders the edit script as a `diff -u` style patch with three lines of
+//! context, so a dry run can be reviewed or applied with `git apply`. Very
+//! dissimilar texts fall back to a single replace-everything hunk between
+//! their common leading and trailing lines.
+
+use std::fmt::Write;
+
+/// Lines of unchanged context around each hunk.
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+	Equal,
+	Delete,
+	Insert,
+}
+
+/// Render a unified diff from `before` to `after` for `path`. Returns an empty
+/// string when the texts are equal.
+pub fn unified_diff(path: &str, before: &str, after: &str) -> String {
+	let old: Vec<&str> = before.split_inclusive('\n').collect();
+	let new: Vec<&str> = after.split_inclusive('\n').collect();
+	let script = edit_script(&old, &new);
+	if script.iter().all(|&(op, ..)| op == Op::Equal) {
+		return String::new();
+	}
+
+	let mut out = format!("--- a/{path}\n+++ b/{path}\n");
+	let changed: Vec<usize> = script
+		.iter()
+		.enumerate()
+		.filter(|(_, (op, ..))| *op != Op::Equal)
+		.map(|(index, _)| index)
+		.collect();
+
+	let mut group_start = 0;
+	while group_start < changed.len() {
+		// Extend the hunk while the next change is within twice the context.
+		let mut group_end = group_start;
+		while group_end + 1 < changed.len()
+			&& changed[group_end + 1] - changed[group_end] <= 2 * CONTEXT_LINES + 1
+		{
+			group_end += 1;
+		}
+		let first = changed[group_start].saturating_sub(CONTEXT_LINES);
+		let last = (changed[group_end] + CONTEXT_LINES).min(script.len() - 1);
+		let hunk = &script[first..=last];
+
+		let (old_start, new_start) = (hunk[0].1, hunk[0].2);
+		let old_len = hunk.iter().filter(|(op, ..)| *op != Op::Insert).count();
+		let new_len = hunk.iter().filter(|(op, ..)| *op != Op::Delete).count();
+		let _ = writeln!(
+			out,
+			"@@ -{} +{} @@",
+			hunk_range(old_start, old_len),
+			hunk_range(new_start, new_len)
+		);
+		for &(op, old_index, new_index) in hunk {
+			let (marker, line) = match op {
+				Op::Equal => (' ', old[old_index]),
+				Op::Delete => ('-', old[old_index]),
+				Op::Insert => ('+', new[new_index]),
+			};
+			out.push(marker);
+			out.push_str(line);
+			if !line.ends_with('\n') {
+				out.push_str("\n\\ No newline at end of file\n");
+			}
+		}
+		group_start = group_end + 1;
+	}
+	out
+}
+
+/// `start,len` with a 1-based start; an empty range names the line before it.
+fn hunk_range(start: usize, len: usize) -> String {
+	match len {
+		0 => format!("{start},0"),
+		1 => format!("{}", start + 1),
+		_ => format!("{},{len}", start + 1),
+	}
+}
+
+/// Edit distance beyond which the changed region is rendered as one block of
+/// deletions followed by insertions. Bounds the search trace to
+/// `O(MAX_EDIT_DEPTH²)` entries however large the inputs are.
+const MAX_EDIT_DEPTH: usize = 1024;
+
+/// Shortest edit script as `(op, old_index, new_index)` triples; the index on
+/// the side an op does not consume is where that side's cursor stands.
+fn edit_script(old: &[&str], new: &[&str]) -> Vec<(Op, usize, usize)> {
+	let (n, m) = (old.len(), new.len());
+	let prefix = old
+		.iter()
+		.zip(new)
+		.take_while(|(old_line, new_line)| old_line == new_line)
+		.count();
+	let suffix = old[prefix..]
+		.iter()
+		.rev()
+		.zip(new[prefix..].iter().rev())
+		.take_while(|(old_line, new_line)| old_line == new_line)
+		.count();
+	let (old_end, new_end) = (n - suffix, m - suffix);
+
+	let mut script: Vec<_> = (0..prefix).map(|index| (Op::Equal, index, index)).collect();
+	if let Some(middle) = myers(&old[prefix..old_end], &new[prefix..new_end]) {
+		script.extend(
+			middle
+				.into_iter()
+				.map(|(op, x, y)| (op, x + prefix, y + prefix)),
+		);
+	} else {
+		script.extend((prefix..old_end).map(|x| (Op::Delete, x, prefix)));
+		script.extend((prefix..new_end).map(|y| (Op::Insert, old_end, y)));
+	}
+	script.extend((0..suffix).map(|index| (Op::Equal, old_end + index, new_end + index)));
+	script
+}
+
+/// Myers' shortest edit script, or `None` once the edit distance exceeds
+/// `MAX_EDIT_DEPTH`.
+fn myers(old: &[&str], new: &[&str]) -> Option<Vec<(Op, usize, usize)>> {
+	let (n, m) = (old.len(), new.len());
+	let max = n + m;
+	let offset = max as isize;
+	let mut frontier = vec![0usize; 2 * max + 2];
+	// Per depth, the frontier on diagonals `-depth..=depth` before that depth
+	// was explored; backtracking never reads further out.
+	let mut trace: Vec<Vec<usize>> = Vec::new();
+
+	'search: for depth in 0..=max as isize {
+		if depth as usize > MAX_EDIT_DEPTH {
+			return None;
+		}
+		trace.push(frontier[(offset - depth) as usize..=(offset + depth) as usize].to_vec());
+		for diagonal in (-depth..=depth).step_by(2) {
+			let index = (diagonal + offset) as usize;
+			let mut x = if diagonal == -depth
+				|| (diagonal != depth && frontier[index - 1] < frontier[index + 1])
+			{
+				frontier[index + 1]
+			} else {
+				frontier[index - 1] + 1
+			};
+			let mut y = (x as isize - diagonal) as usize;
+			let common = old[x.min(n)..]
+				.iter()
+				.zip(&new[y.min(m)..])
+				.take_while(|(old_line, new_line)| old_line == new_line)
+				.count();
+			x += common;
+			y += common;
+			frontier[index] = x;
+			if x >= n && y >= m {
+				break 'search;
+			}
+		}
+	}
+
+	// Walk the recorded frontiers back from the end to recover the path.
+	let mut script = Vec::with_capacity(max);
+	let (mut x, mut y) = (n, m);
+	for (depth, frontier) in trace.iter().enumerate().rev() {
+		let depth = depth as isize;
+		let diagonal = x as isize - y as isize;
+		let index = (diagonal + depth) as usize;
+		let prev_diagonal = if diagonal == -depth
+			|| (diagonal != depth && frontier[index - 1] < frontier[index + 1])
+		{
+			diagonal + 1
+		} else {
+			diagonal - 1
+		};
+		let prev_x = if depth == 0 {
+			0
+		} else {
+			frontier[(prev_diagonal + depth) as usize]
+		};
+		let prev_y = if depth == 0 {
+			0
+		} else {
+			(prev_x as isize - prev_diagonal) as usize
+		};
+		while x > prev_x && y > prev_y {
+			x -= 1;
+			y -= 1;
+			script.push((Op::Equal, x, y));
+		}
+		if depth > 0 {
+			if x == prev_x {
+				script.push((Op::Insert, x, prev_y));
+			} else {
+				script.push((Op::Delete, prev_x, y));
+			}
+		}
+		x = prev_x;
+		y = prev_y;
+	}
+	script.reverse();
+	Some(script)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn renders_hunks_with_context() {
+		let before = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+		let after = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk";
+		assert_eq!(
+			unified_diff("x.ts", before, after),
+			"--- a/x.ts\n+++ b/x.ts\n@@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n@@ -8,3 +8,4 @@\n h\n \
+			 i\n j\n+k\n\\ No newline at end of file\n"
+		);
+		assert_eq!(unified_diff("x.ts", before, before), "");
+		assert_eq!(unified_diff("x.ts", "", "a\n"), "--- a/x.ts\n+++ b/x.ts\n@@ -0,0 +1 @@\n+a\n");
+	}
+
+	#[test]
+	fn falls_back_to_one_block_past_the_depth_limit() {
+		let lines = MAX_EDIT_DEPTH + 1;
+		let numbered = |prefix: char| {
+			let mut text = String::from("keep\n");
+			for index in 0..lines {
+				let _ = writeln!(text, "{prefix}{index}");
+			}
+			text + "end\n"
+		};
+		let (before, after) = (numbered('a'), numbered('b'));
+		let diff = unified_diff("x.ts", &before, &after);
+		let header = format!("@@ -1,{} +1,{} @@\n keep\n-a0\n", lines + 2, lines + 2);
+		assert!(diff.starts_with(&format!("--- a/x.ts\n+++ b/x.ts\n{header}")), "{diff}");
+		// Every deletion precedes every insertion.
+		assert!(diff.contains(&format!("-a{}\n+b0\n", lines - 1)), "{diff}");
+		assert!(diff.ends_with(&format!("+b{}\n end\n", lines - 1)), "{diff}");
+	}
+}
@@ -2,6 +2,8 @@
 //!
 //! Searches for files and directories whose paths match a query string via
 //! subsequence scoring. Uses the shared [`fs_cache`] for directory scanning.
+//! Only paths are matched; file contents are never read, so there is no text
+//! encoding to detect here (content search is `grep`'s job).
 
 use std::path::Path;
 
@@ -22,7 +22,7 @@ use ignore::WalkBuilder;
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
 
-use crate::task;
+use crate::{parse_cache, task};
 
 // ═══════════════════════════════════════════════════════════════════════════
 // Public types (re-exported by glob for backward compatibility)
@@ -51,6 +51,8 @@ pub struct GlobMatch {
 	/// Modification time in milliseconds since Unix epoch (from
 	/// `symlink_metadata`).
 	pub mtime:     Option<f64>,
+	/// Size in bytes (from `symlink_metadata`).
+	pub size:      Option<f64>,
 }
 
 // ═══════════════════════════════════════════════════════════════════════════
@@ -186,7 +188,9 @@ pub fn should_skip_path(path: &Path, mentions_node_modules: bool) -> bool {
 	false
 }
 
-pub fn classify_file_type(path: &Path) -> Option<(FileType, Option<f64>)> {
+/// Classify `path` without following symlinks, returning its type, mtime (ms)
+/// and size.
+pub fn classify_file_type(path: &Path) -> Option<(FileType, Option<f64>, Option<f64>)> {
 	let metadata = std::fs::symlink_metadata(path).ok()?;
 	let file_type = metadata.file_type();
 	let mtime_ms = metadata
@@ -194,12 +198,13 @@ pub fn classify_file_type(path: &Path) -> Option<(FileType, Option<f64>)> {
 		.ok()
 		.and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
 		.map(|d| d.as_millis() as f64);
+	let size = Some(metadata.len() as f64);
 	if file_type.is_symlink() {
-		Some((FileType::Symlink, mtime_ms))
+		Some((FileType::Symlink, mtime_ms, size))
 	} else if file_type.is_dir() {
-		Some((FileType::Dir, mtime_ms))
+		Some((FileType::Dir, mtime_ms, size))
 	} else {
-		Some((FileType::File, mtime_ms))
+		Some((FileType::File, mtime_ms, size))
 	}
 }
 
@@ -267,11 +272,11 @@ fn collect_entries(
 			continue;
 		}
 
-		let Some((file_type, mtime)) = classify_file_type(path) else {
+		let Some((file_type, mtime, size)) = classify_file_type(path) else {
 			continue;
 		};
 
-		entries.push(GlobMatch { path: relative.into_owned(), file_type, mtime });
+		entries.push(GlobMatch { path: relative.into_owned(), file_type, mtime, size });
 	}
 
 	Ok(entries)
@@ -352,8 +357,10 @@ pub fn force_rescan(
 /// Invalidate cache entries whose root contains `target`.
 ///
 /// Removes any cache entry whose root is a prefix of (or equal to) `target`,
-/// because a file mutation under that root makes the scan stale.
+/// because a file mutation under that root makes the scan stale. Parsed trees
+/// at or under `target` are marked for re-parsing as well.
 pub fn invalidate_path(target: &Path) {
+	parse_cache::invalidate_path(target);
 	let keys_to_remove: Vec<CacheKey> = FS_CACHE
 		.iter()
 		.filter(|entry| target.starts_with(&entry.key().root))
@@ -364,15 +371,17 @@ pub fn invalidate_path(target: &Path) {
 	}
 }
 
-/// Clear the entire scan cache.
+/// Clear the entire scan cache, along with the parse cache.
 pub fn invalidate_all() {
 	FS_CACHE.clear();
+	parse_cache::invalidate_all();
 }
 
-/// Invalidate the filesystem scan cache.
+/// Invalidate the filesystem scan cache and the AST parse cache.
 ///
-/// When called with a path, removes entries for roots containing that path.
-/// When called without a path, clears the entire cache.
+/// When called with a path, removes entries for roots containing that path
+/// and marks parsed trees under it for re-parsing. When called without a
+/// path, clears both caches.
 ///
 /// Intended to be called after agent file mutations (write, edit, rename,
 /// delete).
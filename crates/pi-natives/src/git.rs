@@ -0,0 +1,439 @@
+//! Git plumbing for searching file contents at a revision or only in changed
+//! files.
+//!
+//! # Overview
+//! Shells out to the `git` executable (`ls-tree`, `cat-file --batch`, `diff`,
+//! `ls-files`) instead of linking a git library, so results follow the user's
+//! git version and configuration.
+//!
+//! Paths handed back are the caller's search path joined with the blob's path
+//! below it, so they compare equal to the paths a working-tree scan of the
+//! same search path produces.
+
+use std::{
+	collections::HashSet,
+	io::{self, BufRead, BufReader, Read, Write},
+	path::{Path, PathBuf},
+	process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+	sync::Arc,
+};
+
+use napi::bindgen_prelude::*;
+use parking_lot::Mutex;
+
+/// Which changes [`changed_under`] reports.
+pub enum ChangedSince {
+	/// Working tree (staged, unstaged and untracked) versus `HEAD`.
+	Head,
+	/// Working tree versus the index: unstaged and untracked changes.
+	Index,
+	/// Index versus `HEAD`: staged changes only.
+	Staged,
+	/// Working tree versus the merge base of `HEAD` and a branch (default: the
+	/// upstream, then `origin/HEAD`, `main`, `master`).
+	MergeBase(Option<String>),
+}
+
+impl ChangedSince {
+	/// Parse the `changed` option; `base` only applies to `mergeBase`.
+	pub fn parse(mode: Option<&str>, base: Option<String>) -> Result<Option<Self>> {
+		let since = match mode {
+			None => return Ok(None),
+			Some("head") => Self::Head,
+			Some("index") => Self::Index,
+			Some("staged") => Self::Staged,
+			Some("mergeBase") => Self::MergeBase(base),
+			Some(other) => {
+				return Err(Error::from_reason(format!(
+					"Invalid changed filter '{other}': expected head, index, staged or mergeBase"
+				)));
+			},
+		};
+		Ok(Some(since))
+	}
+}
+
+/// A regular file at a git revision.
+pub struct TreeEntry {
+	/// Search path joined with `relative`.
+	pub path:     PathBuf,
+	/// Path below the search path with `/` separators; empty when the search
+	/// path is the file itself.
+	pub relative: String,
+	/// Blob object id.
+	pub oid:      String,
+	/// Blob size in bytes.
+	pub size:     u64,
+}
+
+/// Files below a search path at one revision.
+pub struct Tree {
+	repo:        PathBuf,
+	pub entries: Vec<TreeEntry>,
+}
+
+fn spawn_error(err: &io::Error) -> Error {
+	Error::from_reason(format!("Failed to run git: {err}"))
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<Vec<u8>> {
+	let output = Command::new("git")
+		.arg("-C")
+		.arg(repo)
+		.args(args)
+		.stdin(Stdio::null())
+		.output()
+		.map_err(|err| spawn_error(&err))?;
+	if !output.status.success() {
+		let stderr = String::from_utf8_lossy(&output.stderr);
+		return Err(Error::from_reason(format!("git {} failed: {}", args[0], stderr.trim())));
+	}
+	Ok(output.stdout)
+}
+
+/// Reject revisions git would parse as options.
+fn check_rev(rev: &str) -> Result<()> {
+	if rev.is_empty() || rev.starts_with('-') {
+		return Err(Error::from_reason(format!("Invalid git revision '{rev}'")));
+	}
+	Ok(())
+}
+
+/// The repository containing `path`, and `path` relative to its root with `/`
+/// separators (empty for the root itself).
+fn locate(path: &Path) -> Result<(PathBuf, String)> {
+	// The path may only exist at the searched revision, so discover the
+	// repository from the nearest existing ancestor.
+	let mut existing = path;
+	let mut missing = Vec::new();
+	while !existing.exists() {
+		let (Some(name), Some(parent)) = (existing.file_name(), existing.parent()) else {
+			return Err(Error::from_reason(format!("Path not found: {}", path.display())));
+		};
+		missing.push(name);
+		existing = parent;
+	}
+	let existing = std::fs::canonicalize(existing)
+		.map_err(|err| Error::from_reason(format!("Path not found: {err}")))?;
+	let dir = if existing.is_dir() {
+		existing.as_path()
+	} else {
+		existing.parent().unwrap_or(&existing)
+	};
+	let toplevel = git(dir, &["rev-parse", "--show-toplevel"])?;
+	let toplevel = PathBuf::from(String::from_utf8_lossy(&toplevel).trim_end());
+	let repo = std::fs::canonicalize(&toplevel).unwrap_or(toplevel);
+
+	let mut full = existing.clone();
+	full.extend(missing.iter().rev());
+	let relative = full.strip_prefix(&repo).map_err(|_| {
+		Error::from_reason(format!("{} is outside {}", path.display(), repo.display()))
+	})?;
+	let relative = relative.to_string_lossy().replace('\\', "/");
+	Ok((repo, relative))
+}
+
+/// `name` (relative to the repository root) relative to `prefix`, or `None`
+/// when it is not below it.
+fn strip_prefix<'a>(name: &'a str, prefix: &str) -> Option<&'a str> {
+	if prefix.is_empty() || name == prefix {
+		return Some(if prefix.is_empty() { name } else { "" });
+	}
+	name.strip_prefix(prefix)?.strip_prefix('/')
+}
+
+fn join(path: &Path, relative: &str) -> PathBuf {
+	if relative.is_empty() {
+		path.to_path_buf()
+	} else {
+		path.join(relative)
+	}
+}
+
+/// Regular files below `path` at `rev`. Symlinks and submodules are skipped.
+///
+/// # Errors
+/// Returns an error when `path` is not inside a repository, the revision does
+/// not resolve, or nothing exists at `path` in that revision.
+pub fn tree_under(path: &Path, rev: &str) -> Result<Tree> {
+	check_rev(rev)?;
+	let (repo, prefix) = locate(path)?;
+	let mut args = vec!["ls-tree", "-r", "-l", "-z", "--full-tree", rev];
+	if !prefix.is_empty() {
+		args.extend(["--", prefix.as_str()]);
+	}
+	let output = git(&repo, &args)?;
+
+	let mut entries = Vec::new();
+	for record in output.split(|&byte| byte == 0) {
+		// `<mode> <type> <oid> <size>\t<path>`
+		let Some(tab) = record.iter().position(|&byte| byte == b'\t') else {
+			continue;
+		};
+		let meta = String::from_utf8_lossy(&record[..tab]);
+		let name = String::from_utf8_lossy(&record[tab + 1..]);
+		let [mode, kind, oid, size] = meta.split_ascii_whitespace().collect::<Vec<_>>()[..] else {
+			continue;
+		};
+		if kind != "blob" || mode == "120000" {
+			continue;
+		}
+		let Some(relative) = strip_prefix(&name, &prefix) else {
+			continue;
+		};
+		entries.push(TreeEntry {
+			path:     join(path, relative),
+			relative: relative.to_string(),
+			oid:      oid.to_string(),
+			size:     size.parse().unwrap_or(0),
+		});
+	}
+	if entries.is_empty() && !prefix.is_empty() {
+		return Err(Error::from_reason(format!("Path '{prefix}' not found at revision '{rev}'")));
+	}
+	Ok(Tree { repo, entries })
+}
+
+impl Tree {
+	/// A pool of `git cat-file --batch` processes serving this tree's blobs.
+	/// Processes start on first use.
+	pub fn blob_pool(&self) -> Arc<BlobPool> {
+		Arc::new(BlobPool { repo: self.repo.clone(), idle: Mutex::new(Vec::new()) })
+	}
+}
+
+impl TreeEntry {
+	/// This entry's blob, read through `pool` when needed.
+	pub fn blob(&self, pool: &Arc<BlobPool>) -> Blob {
+		Blob { size: self.size, oid: self.oid.clone(), pool: Arc::clone(pool) }
+	}
+}
+
+/// Idle `git cat-file --batch` processes for one repository. Each read checks
+/// one out, so parallel readers never wait on each other's round trips; the
+/// pool grows to the number of concurrent readers.
+pub struct BlobPool {
+	repo: PathBuf,
+	idle: Mutex<Vec<BlobReader>>,
+}
+
+impl BlobPool {
+	fn read(&self, oid: &str) -> io::Result<Vec<u8>> {
+		let idle = self.idle.lock().pop();
+		let mut reader = match idle {
+			Some(reader) => reader,
+			None => BlobReader::spawn(&self.repo)?,
+		};
+		let data = reader.read(oid)?;
+		// A reader that failed mid-read is dropped (and reaped) instead.
+		self.idle.lock().push(reader);
+		Ok(data)
+	}
+}
+
+/// A running `git cat-file --batch` that answers one blob at a time, so only
+/// the blobs being searched are held in memory. The process is killed and
+/// reaped when the reader is dropped.
+struct BlobReader {
+	child:  Child,
+	stdin:  ChildStdin,
+	stdout: BufReader<ChildStdout>,
+	header: String,
+}
+
+impl BlobReader {
+	fn spawn(repo: &Path) -> io::Result<Self> {
+		let mut child = Command::new("git")
+			.arg("-C")
+			.arg(repo)
+			.args(["cat-file", "--batch"])
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::null())
+			.spawn()?;
+		let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+			let _ = child.kill();
+			let _ = child.wait();
+			return Err(io::Error::other("Failed to open git cat-file pipes"));
+		};
+		Ok(Self { child, stdin, stdout: BufReader::new(stdout), header: String::new() })
+	}
+
+	fn read(&mut self, oid: &str) -> io::Result<Vec<u8>> {
+		// Without `--buffer`, git flushes each object as soon as it is asked for.
+		writeln!(self.stdin, "{oid}")?;
+		self.stdin.flush()?;
+		self.header.clear();
+		self.stdout.read_line(&mut self.header)?;
+		// `<oid> <type> <size>` or `<oid> missing`
+		let header = self.header.trim_end();
+		let size = header
+			.rsplit(' ')
+			.next()
+			.and_then(|size| size.parse::<usize>().ok())
+			.ok_or_else(|| io::Error::other(format!("git cat-file: {header}")))?;
+		let mut data = vec![0; size + 1];
+		self.stdout.read_exact(&mut data)?;
+		data.pop();
+		Ok(data)
+	}
+}
+
+impl Drop for BlobReader {
+	fn drop(&mut self) {
+		let _ = self.child.kill();
+		let _ = self.child.wait();
+	}
+}
+
+/// A blob at a revision whose contents are read on demand.
+pub struct Blob {
+	pub size: u64,
+	oid:      String,
+	pool:     Arc<BlobPool>,
+}
+
+impl Blob {
+	/// Read the blob's contents.
+	///
+	/// # Errors
+	/// Returns an error when git cannot be run, the blob is missing or git
+	/// stopped answering.
+	pub fn read(&self) -> io::Result<Vec<u8>> {
+		self.pool.read(&self.oid)
+	}
+}
+
+/// `HEAD`'s merge base with `branch`, or with the first of the default
+/// branches that resolves.
+fn merge_base(repo: &Path, branch: Option<&str>) -> Result<String> {
+	if let Some(branch) = branch {
+		check_rev(branch)?;
+		let output = git(repo, &["merge-base", "HEAD", branch])?;
+		return Ok(String::from_utf8_lossy(&output).trim().to_string());
+	}
+	["@{upstream}", "origin/HEAD", "main", "master"]
+		.into_iter()
+		.find_map(|branch| git(repo, &["merge-base", "HEAD", branch]).ok())
+		.map(|output| String::from_utf8_lossy(&output).trim().to_string())
+		.ok_or_else(|| Error::from_reason("No merge base found; pass `changedBase`"))
+}
+
+/// Files below `path` changed according to `since`, joined onto `path`.
+/// Deleted files are included; they simply match nothing in a working-tree
+/// scan.
+///
+/// # Errors
+/// Returns an error when `path` is not inside a repository or git fails.
+pub fn changed_under(path: &Path, since: &ChangedSince) -> Result<HashSet<PathBuf>> {
+	let (repo, prefix) = locate(path)?;
+	let pathspec = format!(":(literal){prefix}");
+	let base;
+	let mut diff = vec!["diff", "--name-only", "-z", "--no-renames"];
+	let untracked = match since {
+		ChangedSince::Head => {
+			diff.push("HEAD");
+			true
+		},
+		ChangedSince::Index => true,
+		ChangedSince::Staged => {
+			diff.push("--cached");
+			false
+		},
+		ChangedSince::MergeBase(branch) => {
+			base = merge_base(&repo, branch.as_deref())?;
+			diff.push(&base);
+			true
+		},
+	};
+	let mut untracked_args = vec!["ls-files", "--others", "--exclude-standard", "-z"];
+	if !prefix.is_empty() {
+		diff.extend(["--", pathspec.as_str()]);
+		untracked_args.extend(["--", pathspec.as_str()]);
+	}
+
+	let mut names = git(&repo, &diff)?;
+	if untracked {
+		names.push(0);
+		names.extend(git(&repo, &untracked_args)?);
+	}
+	Ok(names
+		.split(|&byte| byte == 0)
+		.filter(|name| !name.is_empty())
+		.filter_map(|name| {
+			let name = String::from_utf8_lossy(name);
+			strip_prefix(&name, &prefix).map(|relative| join(path, relative))
+		})
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::TempDir;
+
+	fn run(repo: &Path, args: &[&str]) {
+		let status = Command::new("git")
+			.arg("-C")
+			.arg(repo)
+			.args([
+				"-c",
+				"user.name=test",
+				"-c",
+				"user.email=test@example.com",
+				"-c",
+				"commit.gpgsign=false",
+			])
+			.args(args)
+			.stdout(Stdio::null())
+			.stderr(Stdio::null())
+			.status()
+			.expect("git should run");
+		assert!(status.success(), "git {args:?} failed");
+	}
+
+	#[test]
+	fn reads_revisions_and_changes() {
+		let dir = TempDir::new("git");
+		let repo = &dir.root;
+		run(repo, &["init", "-q"]);
+		dir.write("src/a.txt", "old\n");
+		dir.write("b.txt", "keep\n");
+		run(repo, &["add", "."]);
+		run(repo, &["commit", "-q", "-m", "init"]);
+
+		dir.write("src/a.txt", "new\n");
+		dir.write("src/c.txt", "untracked\n");
+
+		let src = repo.join("src");
+		let tree = tree_under(&src, "HEAD").expect("HEAD should list");
+		let relative: Vec<_> = tree
+			.entries
+			.iter()
+			.map(|entry| entry.relative.as_str())
+			.collect();
+		assert_eq!(relative, ["a.txt"]);
+		assert_eq!(tree.entries[0].path, src.join("a.txt"));
+		let pool = tree.blob_pool();
+		let blob = tree.entries[0].blob(&pool);
+		assert_eq!(blob.size, 4);
+		let reads = std::thread::scope(|scope| {
+			let handles = [(); 4].map(|()| scope.spawn(|| blob.read()));
+			handles.map(|handle| handle.join().expect("reader thread should not panic"))
+		});
+		for data in reads {
+			assert_eq!(data.expect("blob should be readable"), b"old\n");
+		}
+		let idle = pool.idle.lock().len();
+		assert!((1..=4).contains(&idle), "{idle} idle readers");
+		let missing = Blob { size: 0, oid: "0".repeat(40), pool: Arc::clone(&pool) };
+		assert!(missing.read().is_err());
+		assert_eq!(blob.read().expect("blob should be readable"), b"old\n");
+
+		let changed = changed_under(&src, &ChangedSince::Head).expect("diff should run");
+		assert_eq!(changed, HashSet::from([src.join("a.txt"), src.join("c.txt")]));
+		let staged = changed_under(repo, &ChangedSince::Staged).expect("diff should run");
+		assert!(staged.is_empty());
+		assert!(tree_under(&src, "--output=x").is_err());
+	}
+}
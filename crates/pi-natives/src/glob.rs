@@ -16,7 +16,6 @@
 
 use std::path::Path;
 
-use globset::GlobSet;
 use napi::{
 	bindgen_prelude::*,
 	threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode},
@@ -25,13 +24,19 @@ use napi_derive::napi;
 
 // Re-export entry types so existing `glob::FileType` / `glob::GlobMatch` paths still work.
 pub use crate::fs_cache::{FileType, GlobMatch};
-use crate::{fs_cache, glob_util, task};
+use crate::{
+	fs_cache,
+	glob_util::{self, GlobFilter},
+	task,
+};
 
 /// Input options for `glob`, including traversal, filtering, and cancellation.
 #[napi(object)]
 pub struct GlobOptions<'env> {
-	/// Glob pattern to match (e.g., "*.ts").
-	pub pattern:              String,
+	/// Glob pattern(s) to match (e.g., "*.ts"); `!`-prefixed entries exclude.
+	pub pattern:              Either<String, Vec<String>>,
+	/// Globs to exclude (e.g., "dist/**").
+	pub exclude:              Option<Vec<String>>,
 	/// Directory to search.
 	pub path:                 String,
 	/// Filter by file type: "file", "dir", or "symlink". Symlinks are
@@ -74,16 +79,17 @@ pub struct GlobResult {
 
 /// Internal runtime config for a single glob execution.
 struct GlobConfig {
-	root:                  std::path::PathBuf,
-	pattern:               String,
-	recursive:             bool,
-	include_hidden:        bool,
-	file_type_filter:      Option<FileType>,
-	max_results:           usize,
-	use_gitignore:         bool,
-	mentions_node_modules: bool,
-	sort_by_mtime:         bool,
-	use_cache:             bool,
+	root:                 std::path::PathBuf,
+	pattern:              Either<String, Vec<String>>,
+	exclude:              Option<Vec<String>>,
+	recursive:            bool,
+	include_hidden:       bool,
+	file_type_filter:     Option<FileType>,
+	max_results:          usize,
+	use_gitignore:        bool,
+	include_node_modules: Option<bool>,
+	sort_by_mtime:        bool,
+	use_cache:            bool,
 }
 
 fn resolve_symlink_target_type(root: &Path, relative_path: &str) -> Option<FileType> {
@@ -124,7 +130,7 @@ fn apply_file_type_filter(entry: &GlobMatch, config: &GlobConfig) -> Option<File
 /// Filter and collect matching entries from a pre-scanned list.
 fn filter_entries(
 	entries: &[GlobMatch],
-	glob_set: &GlobSet,
+	glob_filter: &GlobFilter,
 	config: &GlobConfig,
 	on_match: Option<&ThreadsafeFunction<GlobMatch>>,
 	ct: &task::CancelToken,
@@ -133,14 +139,17 @@ fn filter_entries(
 	if config.max_results == 0 {
 		return Ok(matches);
 	}
+	let mentions_node_modules = config
+		.include_node_modules
+		.unwrap_or_else(|| glob_filter.includes_text("node_modules"));
 
 	for entry in entries {
 		ct.heartbeat()?;
-		if fs_cache::should_skip_path(Path::new(&entry.path), config.mentions_node_modules) {
+		if fs_cache::should_skip_path(Path::new(&entry.path), mentions_node_modules) {
 			// Apply post-scan node_modules policy before glob matching.
 			continue;
 		}
-		if !glob_set.is_match(&entry.path) {
+		if !glob_filter.is_match(&entry.path) {
 			continue;
 		}
 		let Some(effective_file_type) = apply_file_type_filter(entry, config) else {
@@ -168,7 +177,13 @@ fn run_glob(
 	on_match: Option<&ThreadsafeFunction<GlobMatch>>,
 	ct: task::CancelToken,
 ) -> Result<GlobResult> {
-	let glob_set = glob_util::compile_glob(&config.pattern, config.recursive)?;
+	// An empty pattern list matches everything, like "*".
+	let glob_filter = glob_util::compile_glob_filter(
+		Some(&config.pattern),
+		config.exclude.as_deref(),
+		config.recursive,
+	)?
+	.unwrap_or_default();
 	if config.max_results == 0 {
 		return Ok(GlobResult { matches: Vec::new(), total_matches: 0 });
 	}
@@ -176,7 +191,7 @@ fn run_glob(
 	let mut matches = if config.use_cache {
 		let scan =
 			fs_cache::get_or_scan(&config.root, config.include_hidden, config.use_gitignore, &ct)?;
-		let mut matches = filter_entries(&scan.entries, &glob_set, &config, on_match, &ct)?;
+		let mut matches = filter_entries(&scan.entries, &glob_filter, &config, on_match, &ct)?;
 		// Empty-result recheck: if we got zero matches from a cached scan that's old
 		// enough, force a rescan and try once more before returning empty.
 		if matches.is_empty() && scan.cache_age_ms >= fs_cache::empty_recheck_ms() {
@@ -187,7 +202,7 @@ fn run_glob(
 				true,
 				&ct,
 			)?;
-			matches = filter_entries(&fresh, &glob_set, &config, on_match, &ct)?;
+			matches = filter_entries(&fresh, &glob_filter, &config, on_match, &ct)?;
 		}
 		matches
 	} else {
@@ -198,7 +213,7 @@ fn run_glob(
 			false,
 			&ct,
 		)?;
-		filter_entries(&fresh, &glob_set, &config, on_match, &ct)?
+		filter_entries(&fresh, &glob_filter, &config, on_match, &ct)?
 	};
 
 	if config.sort_by_mtime {
@@ -237,6 +252,7 @@ pub fn glob(
 ) -> task::Async<GlobResult> {
 	let GlobOptions {
 		pattern,
+		exclude,
 		path,
 		file_type,
 		recursive,
@@ -250,10 +266,6 @@ pub fn glob(
 		signal,
 	} = options;
 
-	let pattern = pattern.trim();
-	let pattern = if pattern.is_empty() { "*" } else { pattern };
-	let pattern = pattern.to_string();
-
 	let ct = task::CancelToken::new(timeout_ms, signal);
 
 	task::blocking("glob", ct, move |ct| {
@@ -265,11 +277,11 @@ pub fn glob(
 				recursive: recursive.unwrap_or(true),
 				max_results: max_results.map_or(usize::MAX, |value| value as usize),
 				use_gitignore: gitignore.unwrap_or(true),
-				mentions_node_modules: include_node_modules
-					.unwrap_or_else(|| pattern.contains("node_modules")),
+				include_node_modules,
 				sort_by_mtime: sort_by_mtime.unwrap_or(false),
 				use_cache: cache.unwrap_or(false),
 				pattern,
+				exclude,
 			},
 			on_match.as_ref(),
 			ct,
@@ -1,5 +1,7 @@
-//! Shared glob-pattern helpers used by both [`crate::glob`] and
-//! [`crate::grep`].
+//! Shared glob-pattern helpers used by [`crate::glob`], [`crate::grep`] and
+//! [`crate::ast`].
+
+use std::path::Path;
 
 use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
 use napi::bindgen_prelude::*;
@@ -21,16 +23,7 @@ pub fn build_glob_pattern(glob: &str, recursive: bool) -> String {
 /// When `recursive` is true, simple patterns (no path separators, no leading
 /// `**`) are automatically prefixed with `**/`.
 pub fn compile_glob(glob: &str, recursive: bool) -> Result<GlobSet> {
-	let mut builder = GlobSetBuilder::new();
-	let pattern = build_glob_pattern(glob, recursive);
-	let glob = GlobBuilder::new(&pattern)
-		.literal_separator(true)
-		.build()
-		.map_err(|err| Error::from_reason(format!("Invalid glob pattern: {err}")))?;
-	builder.add(glob);
-	builder
-		.build()
-		.map_err(|err| Error::from_reason(format!("Failed to build glob matcher: {err}")))
+	compile_set(&[glob], recursive).map(Option::unwrap_or_default)
 }
 
 /// Like [`compile_glob`], but accepts an `Option<&str>` — returns `Ok(None)`
@@ -42,6 +35,91 @@ pub fn try_compile_glob(glob: Option<&str>, recursive: bool) -> Result<Option<Gl
 	compile_glob(glob, recursive).map(Some)
 }
 
+/// Include/exclude glob filter compiled from a list of patterns.
+///
+/// A path matches when it matches at least one include pattern (or there are
+/// none) and no exclude pattern. The default filter matches everything.
+#[derive(Default)]
+pub struct GlobFilter {
+	include:  Option<GlobSet>,
+	exclude:  Option<GlobSet>,
+	patterns: Vec<String>,
+}
+
+impl GlobFilter {
+	/// Check a (relative) path against the include and exclude sets.
+	pub fn is_match(&self, path: impl AsRef<Path>) -> bool {
+		let path = path.as_ref();
+		if self.exclude.as_ref().is_some_and(|set| set.is_match(path)) {
+			return false;
+		}
+		self.include.as_ref().is_none_or(|set| set.is_match(path))
+	}
+
+	/// Whether any include pattern mentions `needle` (e.g. `node_modules`).
+	pub fn includes_text(&self, needle: &str) -> bool {
+		self.patterns.iter().any(|pattern| pattern.contains(needle))
+	}
+}
+
+fn compile_set(patterns: &[&str], recursive: bool) -> Result<Option<GlobSet>> {
+	if patterns.is_empty() {
+		return Ok(None);
+	}
+	let mut builder = GlobSetBuilder::new();
+	for pattern in patterns {
+		let pattern = build_glob_pattern(pattern, recursive);
+		let glob = GlobBuilder::new(&pattern)
+			.literal_separator(true)
+			.build()
+			.map_err(|err| Error::from_reason(format!("Invalid glob pattern: {err}")))?;
+		builder.add(glob);
+	}
+	builder
+		.build()
+		.map(Some)
+		.map_err(|err| Error::from_reason(format!("Failed to build glob matcher: {err}")))
+}
+
+/// Compile a glob list into a [`GlobFilter`].
+///
+/// `globs` is one pattern or a list; entries prefixed with `!` are excluded,
+/// as is everything in `exclude`. Blank entries are ignored, and `Ok(None)` is
+/// returned when nothing remains. `recursive` is applied to every pattern as in
+/// [`compile_glob`].
+pub fn compile_glob_filter(
+	globs: Option<&Either<String, Vec<String>>>,
+	exclude: Option<&[String]>,
+	recursive: bool,
+) -> Result<Option<GlobFilter>> {
+	let listed: Vec<&str> = match globs {
+		None => Vec::new(),
+		Some(Either::A(glob)) => vec![glob.as_str()],
+		Some(Either::B(globs)) => globs.iter().map(String::as_str).collect(),
+	};
+
+	let mut include = Vec::new();
+	let mut excluded = Vec::new();
+	for glob in listed.into_iter().map(str::trim) {
+		match glob.strip_prefix('!') {
+			Some(negated) => excluded.push(negated.trim()),
+			None => include.push(glob),
+		}
+	}
+	excluded.extend(exclude.into_iter().flatten().map(|glob| glob.trim()));
+	include.retain(|glob| !glob.is_empty());
+	excluded.retain(|glob| !glob.is_empty());
+	if include.is_empty() && excluded.is_empty() {
+		return Ok(None);
+	}
+
+	Ok(Some(GlobFilter {
+		include:  compile_set(&include, recursive)?,
+		exclude:  compile_set(&excluded, recursive)?,
+		patterns: include.into_iter().map(str::to_string).collect(),
+	}))
+}
+
 /// Close unclosed `{` alternation groups in a glob pattern.
 ///
 /// LLMs occasionally produce patterns like `*.{ts,js` without the closing `}`.
@@ -113,4 +191,38 @@ mod tests {
 	fn compile_glob_fixes_unclosed_brace() {
 		assert!(compile_glob("*.{ts,tsx,js", true).is_ok());
 	}
+
+	#[test]
+	fn glob_filter_applies_negations_and_excludes() {
+		let globs = Either::B(vec!["*.ts".to_string(), "!*.test.ts".to_string()]);
+		let exclude = vec!["generated/**".to_string()];
+		let filter = compile_glob_filter(Some(&globs), Some(&exclude), true)
+			.unwrap()
+			.unwrap();
+		assert!(filter.is_match("src/app.ts"));
+		assert!(!filter.is_match("src/app.test.ts"));
+		assert!(!filter.is_match("generated/api.ts"));
+		assert!(!filter.is_match("src/app.js"));
+	}
+
+	#[test]
+	fn glob_filter_with_only_excludes_matches_everything_else() {
+		let globs = Either::A("!**/node_modules/**".to_string());
+		let filter = compile_glob_filter(Some(&globs), None, true)
+			.unwrap()
+			.unwrap();
+		assert!(filter.is_match("src/a.rs"));
+		assert!(!filter.is_match("pkg/node_modules/x/index.js"));
+		assert!(!filter.includes_text("node_modules"));
+	}
+
+	#[test]
+	fn glob_filter_ignores_blank_entries() {
+		let globs = Either::B(vec![" ".to_string(), String::new()]);
+		assert!(
+			compile_glob_filter(Some(&globs), None, true)
+				.unwrap()
+				.is_none()
+		);
+	}
 }
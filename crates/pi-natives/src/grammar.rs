@@ -0,0 +1,258 @@
+//! Tree-sitter grammars available to the AST APIs.
+//!
+//! Built-in grammars are compiled into the addon; more can be loaded at
+//! runtime with `registerGrammar()` from a shared library built by
+//! `tree-sitter build`. A registered grammar is usable wherever a `lang` is
+//! accepted (`astGrep`, `astEdit`, `outline`, `treeSitterQuery`,
+//! `validateSyntax`) and is inferred for files matching its extensions or file
+//! names, ahead of built-ins. WebAssembly grammars are not supported, as no
+//! wasm runtime is embedded.
+
+use std::path::Path;
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+
+use crate::{
+	ast::LANG_ALIASES,
+	language::{self, DynamicLang, GrammarSpec, SupportLang},
+};
+
+#[napi(object)]
+pub struct GrammarDefinition {
+	/// Language name used as `lang`; lowercase letters, digits, `-` and `_`.
+	pub name:         String,
+	/// Shared library exporting the grammar (`.so`, `.dylib` or `.dll`).
+	pub path:         String,
+	/// Exported language function; defaults to `tree_sitter_<name>` with `-`
+	/// replaced by `_`.
+	pub symbol:       Option<String>,
+	/// Other names accepted as `lang`.
+	pub aliases:      Option<Vec<String>>,
+	/// File extensions inferred as this language, with or without the dot.
+	pub extensions:   Option<Vec<String>>,
+	/// Exact file names inferred as this language, e.g. `Justfile`.
+	#[napi(js_name = "fileNames")]
+	pub file_names:   Option<Vec<String>>,
+	/// Character substituted for `$` in patterns, for grammars whose
+	/// identifiers cannot contain `$`.
+	#[napi(js_name = "expandoChar")]
+	pub expando_char: Option<String>,
+}
+
+#[napi(object)]
+pub struct GrammarInfo {
+	pub name:       String,
+	pub aliases:    Vec<String>,
+	pub extensions: Vec<String>,
+	#[napi(js_name = "fileNames")]
+	pub file_names: Vec<String>,
+	/// False for grammars added with `registerGrammar()`.
+	pub builtin:    bool,
+}
+
+fn is_valid_name(name: &str) -> bool {
+	!name.is_empty()
+		&& name.bytes().all(|byte| {
+			byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-' || byte == b'_'
+		})
+}
+
+/// Validate `definition` and turn it into a registry entry.
+fn grammar_spec(definition: GrammarDefinition) -> Result<GrammarSpec> {
+	let GrammarDefinition { name, aliases, extensions, file_names, expando_char, .. } = definition;
+	let name = name.trim().to_lowercase();
+	let aliases = aliases
+		.unwrap_or_default()
+		.into_iter()
+		.map(|alias| alias.trim().to_lowercase())
+		.collect::<Vec<_>>();
+	for label in std::iter::once(&name).chain(&aliases) {
+		if !is_valid_name(label) {
+			return Err(Error::from_reason(format!("Invalid grammar name '{label}'")));
+		}
+		if LANG_ALIASES.contains_key(label.as_str()) {
+			return Err(Error::from_reason(format!("'{label}' is a built-in language name")));
+		}
+	}
+
+	let expando = match expando_char.as_deref().map(|value| {
+		let mut chars = value.chars();
+		(chars.next(), chars.next())
+	}) {
+		None => None,
+		Some((Some(expando), None)) => Some(expando),
+		Some(_) => {
+			return Err(Error::from_reason("`expandoChar` must be a single character"));
+		},
+	};
+
+	Ok(GrammarSpec {
+		name,
+		aliases,
+		extensions: extensions
+			.unwrap_or_default()
+			.iter()
+			.map(|ext| ext.trim().trim_start_matches('.').to_string())
+			.filter(|ext| !ext.is_empty())
+			.collect(),
+		file_names: file_names.unwrap_or_default(),
+		expando,
+	})
+}
+
+fn registered_info(lang: DynamicLang) -> GrammarInfo {
+	GrammarInfo {
+		name:       lang.name().to_string(),
+		aliases:    lang.aliases(),
+		extensions: lang.extensions(),
+		file_names: lang.file_names(),
+		builtin:    false,
+	}
+}
+
+/// Load a tree-sitter grammar from a shared library and register it for the
+/// AST APIs. Registered grammars stay loaded until the process exits.
+///
+/// Loading runs code from the library, so only register grammars you trust.
+///
+/// # Errors
+/// Returns an error for invalid or taken names, when the library or its
+/// language function cannot be loaded, or when its ABI is incompatible.
+#[napi(js_name = "registerGrammar")]
+pub fn register_grammar(definition: GrammarDefinition) -> Result<GrammarInfo> {
+	let path = definition.path.clone();
+	let symbol = definition.symbol.clone();
+	let spec = grammar_spec(definition)?;
+	let lang = language::dynamic::register_library(spec, Path::new(&path), symbol.as_deref())
+		.map_err(Error::from_reason)?;
+	Ok(registered_info(lang))
+}
+
+/// List every grammar usable by the AST APIs: built-ins by name followed by
+/// registered grammars in registration order.
+#[napi(js_name = "getGrammars")]
+pub fn get_grammars() -> Vec<GrammarInfo> {
+	let mut grammars = SupportLang::all_langs()
+		.iter()
+		.map(|&lang| {
+			let name = lang.canonical_name();
+			let mut aliases = LANG_ALIASES
+				.entries()
+				.filter(|&(alias, &target)| target == lang && *alias != name)
+				.map(|(alias, _)| (*alias).to_string())
+				.collect::<Vec<_>>();
+			aliases.sort_unstable();
+			let file_names = match lang {
+				SupportLang::Make => vec!["Makefile", "makefile", "GNUmakefile"],
+				_ => Vec::new(),
+			};
+			GrammarInfo {
+				name: name.to_string(),
+				aliases,
+				extensions: language::extensions(lang)
+					.iter()
+					.map(ToString::to_string)
+					.collect(),
+				file_names: file_names.into_iter().map(ToString::to_string).collect(),
+				builtin: true,
+			}
+		})
+		.collect::<Vec<_>>();
+	grammars.sort_by(|a, b| a.name.cmp(&b.name));
+	grammars.extend(DynamicLang::all().into_iter().map(registered_info));
+	grammars
+}
+
+#[cfg(test)]
+mod tests {
+	use ast_grep_core::{Language, tree_sitter::LanguageExt};
+
+	use super::*;
+	use crate::{ast::resolve_supported_lang, language::dynamic};
+
+	fn spec(name: &str, extension: &str) -> GrammarSpec {
+		GrammarSpec {
+			name:       name.to_string(),
+			aliases:    vec![format!("{name}-alias")],
+			extensions: vec![extension.to_string()],
+			file_names: Vec::new(),
+			expando:    None,
+		}
+	}
+
+	#[test]
+	fn registered_grammars_resolve_and_match() {
+		let Ok(lang) =
+			dynamic::register(spec("jsonx", "jsonx"), tree_sitter_json::LANGUAGE.into(), None)
+		else {
+			panic!("grammar should register");
+		};
+		assert_eq!(lang.name(), "jsonx");
+
+		let Ok(resolved) = resolve_supported_lang("JSONX-alias") else {
+			panic!("registered alias should resolve");
+		};
+		assert_eq!(resolved, SupportLang::Dynamic(lang));
+		assert_eq!(resolved.canonical_name(), "jsonx");
+		assert_eq!(SupportLang::from_path("data/config.jsonx"), Some(resolved));
+
+		let root = resolved.ast_grep(r#"{"name": "pi", "tags": ["pi"]}"#);
+		let found = root.root().find_all(r#""pi""#).collect::<Vec<_>>();
+		assert_eq!(found.len(), 2);
+		assert_eq!(found[1].kind(), "string");
+
+		assert!(
+			get_grammars()
+				.iter()
+				.any(|info| info.name == "jsonx" && !info.builtin)
+		);
+		let Err(err) =
+			dynamic::register(spec("jsonx", "jx"), tree_sitter_json::LANGUAGE.into(), None)
+		else {
+			panic!("duplicate names should be rejected");
+		};
+		assert!(err.contains("already registered"), "{err}");
+	}
+
+	#[test]
+	fn rejects_invalid_definitions() {
+		let definition = |name: &str, path: &str, expando: Option<&str>| GrammarDefinition {
+			name:         name.to_string(),
+			path:         path.to_string(),
+			symbol:       None,
+			aliases:      None,
+			extensions:   Some(vec![".foo".to_string()]),
+			file_names:   None,
+			expando_char: expando.map(str::to_string),
+		};
+		let reason = |definition| match register_grammar(definition) {
+			Ok(_) => panic!("registration should fail"),
+			Err(err) => err.reason.clone(),
+		};
+		assert!(reason(definition("rust", "/x.so", None)).contains("built-in"));
+		assert!(reason(definition("my lang", "/x.so", None)).contains("Invalid grammar name"));
+		assert!(reason(definition("foo", "/x.so", Some("ab"))).contains("single character"));
+		assert!(
+			reason(definition("foo", "/nonexistent/libfoo.so", None))
+				.contains("Failed to load grammar library")
+		);
+
+		let Ok(spec) = grammar_spec(definition("Foo", "/x.so", Some("µ"))) else {
+			panic!("definition should be valid");
+		};
+		assert_eq!((spec.name.as_str(), spec.expando), ("foo", Some('µ')));
+		assert_eq!(spec.extensions, ["foo"]);
+	}
+
+	#[test]
+	fn lists_builtin_grammars() {
+		let grammars = get_grammars();
+		let Some(typescript) = grammars.iter().find(|info| info.name == "typescript") else {
+			panic!("typescript should be listed");
+		};
+		assert!(typescript.builtin);
+		assert!(typescript.aliases.iter().any(|alias| alias == "ts"));
+		assert!(typescript.extensions.iter().any(|ext| ext == "ts"));
+	}
+}
@@ -1,24 +1,31 @@
 //! Ripgrep-backed search exported via N-API.
 //!
-//! Provides two layers:
+//! Provides three layers:
 //! - `search()` for in-memory content search.
 //! - `grep()` for filesystem search with glob/type filtering.
+//! - `grepReplace()` for regex search-and-replace over the same file set.
 //!
 //! The filesystem search matches the previous JS wrapper behavior, including
 //! global offsets, optional match limits, and per-file match summaries.
 
 use std::{
 	borrow::Cow,
+	cell::OnceCell,
+	collections::{HashMap, HashSet},
 	fs::File,
-	io::{self, Cursor, Read},
+	hash::{Hash, Hasher},
+	io::{self, Cursor, Read, Seek},
+	ops::Range,
 	path::{Path, PathBuf},
+	time::{Duration, Instant},
 };
 
-use globset::GlobSet;
-use grep_matcher::Matcher;
-use grep_regex::RegexMatcherBuilder;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use grep_matcher::{Captures, Matcher};
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
 use grep_searcher::{
-	BinaryDetection, Searcher, SearcherBuilder, Sink, SinkContext, SinkContextKind, SinkMatch,
+	BinaryDetection, Encoding, MmapChoice, Searcher, SearcherBuilder, Sink, SinkContext,
+	SinkContextKind, SinkFinish, SinkMatch,
 };
 use napi::{
 	JsString,
@@ -26,17 +33,44 @@ use napi::{
 	threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode},
 };
 use napi_derive::napi;
+use parking_lot::Mutex;
 use rayon::prelude::*;
+use serde_json::{Value, json};
 use smallvec::SmallVec;
 
-use crate::{fs_cache, glob_util, task};
+use crate::{
+	fs_cache, git,
+	glob_util::{self, GlobFilter},
+	grep_index,
+	grep_syntax::{SyntaxFilter, SyntaxMap},
+	task,
+	type_filter::{TypeFilter, resolve_type_filter},
+};
 
-const MAX_FILE_BYTES: u64 = 4 * 1024 * 1024;
+/// Default size cap for files searched by `grep` (override with `maxFileSize`).
+const DEFAULT_MAX_FILE_BYTES: u64 = 256 * 1024 * 1024;
+/// Files at least this large are memory-mapped instead of streamed.
+const MMAP_THRESHOLD_BYTES: u64 = 4 * 1024 * 1024;
+/// `grepReplace` rewrites whole files in memory, so it keeps a tighter cap.
+const MAX_REPLACE_FILE_BYTES: u64 = 4 * 1024 * 1024;
+/// Leading bytes inspected when guessing a file's text encoding.
+pub(crate) const ENCODING_SNIFF_BYTES: u64 = 8 * 1024;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum OutputMode {
 	Content,
 	Count,
+	/// Content matches plus `rg --json` records.
+	Json,
+	/// Content matches merged with their context into hunks.
+	Hunks,
+}
+
+impl OutputMode {
+	/// Whether matched lines (and their context) are collected.
+	const fn collects_lines(self) -> bool {
+		matches!(self, Self::Content | Self::Json | Self::Hunks)
+	}
 }
 
 /// Options for searching file content.
@@ -49,6 +83,15 @@ pub struct SearchOptions {
 	pub ignore_case:    Option<bool>,
 	/// Enable multiline matching.
 	pub multiline:      Option<bool>,
+	/// Treat the pattern as a literal string instead of a regex.
+	#[napi(js_name = "fixedStrings")]
+	pub fixed_strings:  Option<bool>,
+	/// Only match at word boundaries.
+	#[napi(js_name = "wordRegexp")]
+	pub word_regexp:    Option<bool>,
+	/// Case-insensitive unless the pattern contains an uppercase letter.
+	#[napi(js_name = "smartCase")]
+	pub smart_case:     Option<bool>,
 	/// Maximum number of matches to return.
 	#[napi(js_name = "maxCount")]
 	pub max_count:      Option<u32>,
@@ -67,31 +110,74 @@ pub struct SearchOptions {
 	pub max_columns:    Option<u32>,
 	/// Output mode (content or count).
 	pub mode:           Option<String>,
+	/// Binary content handling: `skip` (default), `summary` or `text`.
+	pub binary:         Option<String>,
+}
+
+/// Options for [`has_match`].
+#[napi(object)]
+#[derive(Default)]
+pub struct HasMatchOptions {
+	/// Case-insensitive search.
+	#[napi(js_name = "ignoreCase")]
+	pub ignore_case:   Option<bool>,
+	/// Enable multiline matching.
+	pub multiline:     Option<bool>,
+	/// Treat the pattern as a literal string instead of a regex.
+	#[napi(js_name = "fixedStrings")]
+	pub fixed_strings: Option<bool>,
+	/// Only match at word boundaries.
+	#[napi(js_name = "wordRegexp")]
+	pub word_regexp:   Option<bool>,
+	/// Case-insensitive unless the pattern contains an uppercase letter.
+	#[napi(js_name = "smartCase")]
+	pub smart_case:    Option<bool>,
 }
 
 /// Options for searching files on disk.
 #[napi(object)]
 pub struct GrepOptions<'env> {
-	/// Regex pattern to search for.
-	pub pattern:        String,
+	/// Regex pattern to search for (mutually exclusive with `patterns`). The
+	/// TypeScript `GrepOptions` union requires one of the two; it is optional
+	/// here only because a napi object cannot express that.
+	pub pattern:        Option<String>,
+	/// Several named patterns searched in one pass.
+	pub patterns:       Option<Vec<GrepPattern>>,
+	/// File-level boolean query over `patterns` ids.
+	#[napi(js_name = "fileQuery")]
+	pub file_query:     Option<GrepFileQuery>,
 	/// Directory or file to search.
 	pub path:           String,
-	/// Glob filter for filenames (e.g., "*.ts").
-	pub glob:           Option<String>,
-	/// Filter by file type (e.g., "js", "py", "rust").
+	/// Glob filter(s) for filenames; `!`-prefixed entries exclude (e.g., "*.ts").
+	pub glob:           Option<Either<String, Vec<String>>>,
+	/// Globs to exclude (e.g., "generated/**").
+	pub exclude:        Option<Vec<String>>,
+	/// File types to include, or exclude with a `!` prefix (e.g. `"ts,!test"`).
 	#[napi(js_name = "type")]
-	pub type_filter:    Option<String>,
+	pub type_filter:    Option<Either<String, Vec<String>>>,
 	/// Case-insensitive search.
 	#[napi(js_name = "ignoreCase")]
 	pub ignore_case:    Option<bool>,
 	/// Enable multiline matching.
 	pub multiline:      Option<bool>,
+	/// Treat the pattern as a literal string instead of a regex.
+	#[napi(js_name = "fixedStrings")]
+	pub fixed_strings:  Option<bool>,
+	/// Only match at word boundaries.
+	#[napi(js_name = "wordRegexp")]
+	pub word_regexp:    Option<bool>,
+	/// Case-insensitive unless the pattern contains an uppercase letter.
+	#[napi(js_name = "smartCase")]
+	pub smart_case:     Option<bool>,
 	/// Include hidden files (default: true).
 	pub hidden:         Option<bool>,
 	/// Respect .gitignore files (default: true).
 	pub gitignore:      Option<bool>,
 	/// Enable shared filesystem scan cache (default: false).
 	pub cache:          Option<bool>,
+	/// Skip files larger than this many bytes (default: 256 MiB; 0 disables).
+	#[napi(js_name = "maxFileSize")]
+	pub max_file_size:  Option<f64>,
 	/// Maximum number of matches to return.
 	#[napi(js_name = "maxCount")]
 	pub max_count:      Option<u32>,
@@ -108,8 +194,28 @@ pub struct GrepOptions<'env> {
 	/// Truncate lines longer than this (characters).
 	#[napi(js_name = "maxColumns")]
 	pub max_columns:    Option<u32>,
-	/// Output mode (content, filesWithMatches, or count).
+	/// Output mode (content, filesWithMatches, count, json, or hunks). Hunks
+	/// are only returned in the result; passing `onMatch` with them is an
+	/// error.
 	pub mode:           Option<String>,
+	/// Binary file handling: `skip` (default), `summary` or `text`.
+	pub binary:         Option<String>,
+	/// Only report hits in these syntax classes: `code`, `comment` or
+	/// `string`, or negated with `!`. Files without a supported grammar are
+	/// searched but never match.
+	pub syntax:         Option<Either<String, Vec<String>>>,
+	/// Prune files with the persistent trigram index for `path`, creating it
+	/// on first use (default: false). Ignored with `rev`.
+	pub index:          Option<bool>,
+	/// Search file contents at this git revision instead of the working tree.
+	pub rev:            Option<String>,
+	/// Only search files changed versus `head`, `index`, `staged` (index
+	/// versus HEAD) or `mergeBase`.
+	pub changed:        Option<String>,
+	/// Branch for `changed: "mergeBase"` (default: upstream, then
+	/// `origin/HEAD`, `main`, `master`).
+	#[napi(js_name = "changedBase")]
+	pub changed_base:   Option<String>,
 	/// Abort signal for cancelling the operation.
 	pub signal:         Option<Unknown<'env>>,
 	/// Timeout in milliseconds for the operation.
@@ -117,6 +223,42 @@ pub struct GrepOptions<'env> {
 	pub timeout_ms:     Option<u32>,
 }
 
+/// A named pattern for multi-pattern grep.
+#[napi(object)]
+pub struct GrepPattern {
+	/// Identifier reported on every hit produced by this pattern.
+	pub id:      String,
+	/// Regex pattern to search for.
+	pub pattern: String,
+}
+
+/// Proximity constraint: `first` must match within `lines` lines of `second`.
+#[napi(object)]
+pub struct GrepNearQuery {
+	/// Pattern id of the first pattern.
+	pub first:  String,
+	/// Pattern id of the second pattern.
+	pub second: String,
+	/// Maximum distance in lines between the two hits.
+	pub lines:  u32,
+}
+
+/// File-level boolean composition over pattern ids.
+///
+/// A file is reported only when every clause holds; without a query any file
+/// where at least one pattern matches is reported.
+#[napi(object)]
+pub struct GrepFileQuery {
+	/// Pattern ids that must all match somewhere in the file.
+	pub all:  Option<Vec<String>>,
+	/// Pattern ids of which at least one must match.
+	pub any:  Option<Vec<String>>,
+	/// Pattern ids that must not match anywhere in the file.
+	pub none: Option<Vec<String>>,
+	/// Proximity constraints between pairs of patterns.
+	pub near: Option<Vec<GrepNearQuery>>,
+}
+
 /// A context line (before or after a match).
 #[derive(Clone)]
 #[napi(object)]
@@ -127,6 +269,41 @@ pub struct ContextLine {
 	pub line:        String,
 }
 
+/// A single regex hit within a matched line (or multiline block).
+///
+/// Offsets are relative to the reported `line` text. Line numbers and columns
+/// are absolute and 1-based; columns count UTF-16 code units so they line up
+/// with JS string indices.
+#[derive(Clone)]
+#[napi(object)]
+pub struct Submatch {
+	/// Matched text.
+	pub text:            String,
+	/// UTF-16 offset of the match start within `line`.
+	pub start:           u32,
+	/// UTF-16 offset of the match end (exclusive) within `line`.
+	pub end:             u32,
+	/// Byte offset of the match start within `line`.
+	#[napi(js_name = "byteStart")]
+	pub byte_start:      u32,
+	/// Byte offset of the match end (exclusive) within `line`.
+	#[napi(js_name = "byteEnd")]
+	pub byte_end:        u32,
+	/// 1-indexed line number where the match starts.
+	#[napi(js_name = "lineNumber")]
+	pub line_number:     u32,
+	/// 1-indexed column where the match starts.
+	pub column:          u32,
+	/// 1-indexed line number where the match ends.
+	#[napi(js_name = "endLineNumber")]
+	pub end_line_number: u32,
+	/// 1-indexed column just past the end of the match.
+	#[napi(js_name = "endColumn")]
+	pub end_column:      u32,
+	/// Named capture groups that participated in the match.
+	pub groups:          Option<HashMap<String, String>>,
+}
+
 /// A single match in the content.
 #[napi(object)]
 pub struct Match {
@@ -143,6 +320,8 @@ pub struct Match {
 	pub context_after:  Option<Vec<ContextLine>>,
 	/// Whether the line was truncated.
 	pub truncated:      Option<bool>,
+	/// Individual regex hits within the line.
+	pub submatches:     Option<Vec<Submatch>>,
 }
 
 /// Result of searching content.
@@ -158,6 +337,10 @@ pub struct SearchResult {
 	pub limit_reached: bool,
 	/// Error message, if any.
 	pub error:         Option<String>,
+	/// Detected text encoding of the content (e.g. `UTF-8`, `UTF-16LE`).
+	pub encoding:      Option<String>,
+	/// Whether binary data was found in the content.
+	pub binary:        Option<bool>,
 }
 
 /// A single match in a grep result.
@@ -179,9 +362,20 @@ pub struct GrepMatch {
 	pub context_after:  Option<Vec<ContextLine>>,
 	/// Whether the line was truncated.
 	pub truncated:      Option<bool>,
+	/// Individual regex hits within the line (content mode only).
+	pub submatches:     Option<Vec<Submatch>>,
+	/// Id of the pattern that produced this hit (multi-pattern searches only).
+	#[napi(js_name = "patternId")]
+	pub pattern_id:     Option<String>,
 	/// Per-file match count (count mode only).
 	#[napi(js_name = "matchCount")]
 	pub match_count:    Option<u32>,
+	/// Detected source encoding when the file is not UTF-8.
+	pub encoding:       Option<String>,
+	/// Set on the "binary file matches" entry emitted in `summary` binary mode.
+	pub binary:         Option<bool>,
+	/// One `rg --json` record (streamed to `onMatch` in `json` mode only).
+	pub json:           Option<String>,
 }
 
 /// Result of searching files.
@@ -201,30 +395,169 @@ pub struct GrepResult {
 	/// Whether the limit/offset stopped the search early.
 	#[napi(js_name = "limitReached")]
 	pub limit_reached:      Option<bool>,
+	/// Files that were not searched, with the reason.
+	pub skipped:            Option<Vec<GrepSkippedFile>>,
+	/// `rg --json` output as JSON Lines (`json` mode only).
+	pub json:               Option<String>,
+	/// Matches merged with their context into contiguous blocks (`hunks` mode
+	/// only; `matches` is empty then).
+	pub hunks:              Option<Vec<GrepHunk>>,
+}
+
+/// A line of a [`GrepHunk`].
+#[napi(object)]
+pub struct GrepHunkLine {
+	/// 1-indexed line number (where a multiline match starts).
+	#[napi(js_name = "lineNumber")]
+	pub line_number: u32,
+	/// Line content (trimmed line ending).
+	pub line:        String,
+	/// `match` or `context`.
+	pub kind:        String,
+	/// Whether the line was truncated.
+	pub truncated:   Option<bool>,
+	/// Individual regex hits within a match line.
+	pub submatches:  Option<Vec<Submatch>>,
+	/// Ids of the patterns matching this line (multi-pattern searches only).
+	#[napi(js_name = "patternIds")]
+	pub pattern_ids: Option<Vec<String>>,
 }
 
-enum TypeFilter {
-	Known { exts: &'static [&'static str], names: &'static [&'static str] },
-	Custom(String),
+/// Matches and their context merged into one contiguous block, like a
+/// `--`-separated block of `rg -C` output.
+#[napi(object)]
+pub struct GrepHunk {
+	/// File path (relative for directory searches).
+	pub path:       String,
+	/// First line of the hunk.
+	#[napi(js_name = "startLine")]
+	pub start_line: u32,
+	/// Last line of the hunk.
+	#[napi(js_name = "endLine")]
+	pub end_line:   u32,
+	/// Match and context lines in line order, each line at most once.
+	pub lines:      Vec<GrepHunkLine>,
+	/// Detected source encoding when the file is not UTF-8.
+	pub encoding:   Option<String>,
 }
 
-impl TypeFilter {
-	fn match_ext(&self, ext: &str) -> bool {
-		match self {
-			Self::Known { exts, .. } => exts.iter().any(|e| ext.eq_ignore_ascii_case(e)),
-			Self::Custom(custom_ext) => ext.eq_ignore_ascii_case(custom_ext),
-		}
-	}
+/// A file `grep` did not search.
+#[napi(object)]
+pub struct GrepSkippedFile {
+	/// File path (relative for directory searches).
+	pub path:    String,
+	/// `tooLarge`, `binary`, `encoding` (`grepReplace` only), `permissionDenied`
+	/// or `ioError`.
+	pub reason:  String,
+	/// Human-readable detail (size, offending offset or OS error).
+	pub message: Option<String>,
+}
 
-	fn match_name(&self, name: &str) -> bool {
-		match self {
-			Self::Known { names, .. } => names.iter().any(|n| name.eq_ignore_ascii_case(n)),
-			Self::Custom(ext) => ext.eq_ignore_ascii_case(name),
-		}
-	}
+/// Options for regex search-and-replace across files.
+#[napi(object)]
+pub struct GrepReplaceOptions<'env> {
+	/// Regex pattern to search for.
+	pub pattern:       String,
+	/// Replacement template (`$1`, `${name}`, `$$` for a literal `$`).
+	pub replacement:   String,
+	/// Directory or file to search.
+	pub path:          String,
+	/// Glob filter(s) for filenames; `!`-prefixed entries exclude (e.g., "*.ts").
+	pub glob:          Option<Either<String, Vec<String>>>,
+	/// Globs to exclude (e.g., "generated/**").
+	pub exclude:       Option<Vec<String>>,
+	/// File types to include, or exclude with a `!` prefix (e.g. `"ts,!test"`).
+	#[napi(js_name = "type")]
+	pub type_filter:   Option<Either<String, Vec<String>>>,
+	/// Case-insensitive search.
+	#[napi(js_name = "ignoreCase")]
+	pub ignore_case:   Option<bool>,
+	/// Allow matches to span multiple lines.
+	pub multiline:     Option<bool>,
+	/// Treat the pattern as a literal string instead of a regex.
+	#[napi(js_name = "fixedStrings")]
+	pub fixed_strings: Option<bool>,
+	/// Only match at word boundaries.
+	#[napi(js_name = "wordRegexp")]
+	pub word_regexp:   Option<bool>,
+	/// Case-insensitive unless the pattern contains an uppercase letter.
+	#[napi(js_name = "smartCase")]
+	pub smart_case:    Option<bool>,
+	/// Include hidden files (default: true).
+	pub hidden:        Option<bool>,
+	/// Respect .gitignore files (default: true).
+	pub gitignore:     Option<bool>,
+	/// Enable shared filesystem scan cache (default: false).
+	pub cache:         Option<bool>,
+	/// Compute changes without writing files (default: true).
+	#[napi(js_name = "dryRun")]
+	pub dry_run:       Option<bool>,
+	/// Abort signal for cancelling the operation.
+	pub signal:        Option<Unknown<'env>>,
+	/// Timeout in milliseconds for the operation.
+	#[napi(js_name = "timeoutMs")]
+	pub timeout_ms:    Option<u32>,
+}
+
+/// A contiguous block of lines changed by a replacement.
+#[derive(Clone)]
+#[napi(object)]
+pub struct GrepReplaceChange {
+	/// 1-indexed line number of the first affected line.
+	#[napi(js_name = "lineNumber")]
+	pub line_number:  u32,
+	/// Original line(s), without the trailing line ending.
+	pub before:       String,
+	/// Rewritten line(s), without the trailing line ending.
+	pub after:        String,
+	/// Number of regex replacements inside this block.
+	pub replacements: u32,
+}
+
+/// Replacements computed (and optionally applied) for a single file.
+#[napi(object)]
+pub struct GrepReplaceFile {
+	/// File path (relative for directory searches).
+	pub path:    String,
+	/// Number of regex replacements in the file.
+	pub count:   u32,
+	/// Changed line blocks in file order.
+	pub changes: Vec<GrepReplaceChange>,
+}
+
+/// Result of a search-and-replace run.
+#[napi(object)]
+pub struct GrepReplaceResult {
+	/// Files with at least one replacement, sorted by path.
+	pub files:              Vec<GrepReplaceFile>,
+	/// Total replacements across all files.
+	#[napi(js_name = "totalReplacements")]
+	pub total_replacements: u32,
+	/// Number of files with at least one replacement.
+	#[napi(js_name = "filesTouched")]
+	pub files_touched:      u32,
+	/// Number of files searched.
+	#[napi(js_name = "filesSearched")]
+	pub files_searched:     u32,
+	/// Whether changes were written to disk.
+	pub applied:            bool,
+	/// Per-file read/write failures.
+	pub errors:             Option<Vec<String>>,
+	/// Files left untouched because they are too large, binary or UTF-16.
+	pub skipped:            Option<Vec<GrepSkippedFile>>,
+}
+
+/// Compiled search regex plus the named capture groups it declares.
+struct CompiledMatcher {
+	regex:    RegexMatcher,
+	groups:   Vec<(String, usize)>,
+	/// Matchers of a multi-pattern set `regex` is the union of; every line it
+	/// hits is reported once per pattern hitting it.
+	patterns: Vec<Self>,
 }
 
-struct MatchCollector {
+struct MatchCollector<'m> {
+	matcher:         &'m CompiledMatcher,
 	matches:         Vec<CollectedMatch>,
 	match_count:     u64,
 	collected_count: u64,
@@ -232,45 +565,106 @@ struct MatchCollector {
 	offset:          u64,
 	skipped:         u64,
 	limit_reached:   bool,
-	context_before:  SmallVec<[ContextLine; 8]>,
+	context_before:  SmallVec<[CollectedContext; 8]>,
 	max_columns:     Option<usize>,
 	collect_matches: bool,
+	/// Keep the raw bytes of reported lines, for `rg --json` records.
+	keep_raw:        bool,
+	binary_offset:   Option<u64>,
+	/// Syntax classes to keep and the parsed file they are looked up in.
+	syntax:          Option<(SyntaxFilter, &'m SyntaxMap)>,
+	/// The last matched line was dropped by `syntax`, so its after-context is
+	/// not attached to the previous match.
+	dropped_last:    bool,
 }
 
 struct CollectedMatch {
 	line_number:    u64,
+	/// Absolute byte offset of the first matched line.
+	offset:         u64,
 	line:           String,
-	context_before: SmallVec<[ContextLine; 8]>,
-	context_after:  SmallVec<[ContextLine; 8]>,
+	context_before: SmallVec<[CollectedContext; 8]>,
+	context_after:  SmallVec<[CollectedContext; 8]>,
 	truncated:      bool,
+	submatches:     Vec<Submatch>,
+	binary:         bool,
+	raw:            Option<RawMatch>,
+	/// Index of the pattern that hit, in multi-pattern searches.
+	pattern:        Option<usize>,
+}
+
+/// A matched block exactly as searched (line terminators included), kept
+/// for `rg --json` records.
+struct RawMatch {
+	bytes:      Vec<u8>,
+	/// Byte range of each reported hit within `bytes`.
+	submatches: Vec<Range<usize>>,
+}
+
+/// Per-file search facts reported in `rg --json` `end` records.
+#[derive(Clone, Copy, Default)]
+struct SearchStats {
+	binary_offset:  Option<u64>,
+	bytes_searched: u64,
+	elapsed:        Duration,
+}
+
+/// A context line and its absolute byte offset.
+#[derive(Clone)]
+struct CollectedContext {
+	line:   ContextLine,
+	offset: u64,
+	/// The line exactly as searched, kept for `rg --json` records.
+	raw:    Option<Vec<u8>>,
 }
 
 struct SearchResultInternal {
-	matches:       Vec<CollectedMatch>,
-	match_count:   u64,
-	collected:     u64,
-	limit_reached: bool,
+	matches:        Vec<CollectedMatch>,
+	match_count:    u64,
+	collected:      u64,
+	limit_reached:  bool,
+	binary_offset:  Option<u64>,
+	encoding:       TextEncoding,
+	bytes_searched: u64,
+	elapsed:        Duration,
+}
+
+impl SearchResultInternal {
+	const fn stats(&self) -> SearchStats {
+		SearchStats {
+			binary_offset:  self.binary_offset,
+			bytes_searched: self.bytes_searched,
+			elapsed:        self.elapsed,
+		}
+	}
 }
 
 struct FileEntry {
 	path:          PathBuf,
 	relative_path: String,
+	/// Contents at a git revision, searched instead of `path`.
+	blob:          Option<git::Blob>,
 }
 
 struct FileSearchResult {
 	relative_path: String,
-	matches:       Vec<CollectedMatch>,
-	match_count:   u64,
+	search:        SearchResultInternal,
+	/// `rg --json` records, already streamed to the callback.
+	records:       Option<FileRecords>,
 }
 
-impl MatchCollector {
+impl<'m> MatchCollector<'m> {
 	fn new(
+		matcher: &'m CompiledMatcher,
 		max_count: Option<u64>,
 		offset: u64,
 		max_columns: Option<usize>,
 		collect_matches: bool,
+		keep_raw: bool,
+		syntax: Option<(SyntaxFilter, &'m SyntaxMap)>,
 	) -> Self {
 		Self {
+			matcher,
 			matches: Vec::new(),
 			match_count: 0,
 			collected_count: 0,
@@ -281,18 +675,115 @@ impl MatchCollector {
 			context_before: SmallVec::new(),
 			max_columns,
 			collect_matches,
+			keep_raw,
+			binary_offset: None,
+			syntax,
+			dropped_last: false,
+		}
+	}
+
+	fn into_result(self) -> SearchResultInternal {
+		SearchResultInternal {
+			matches:        self.matches,
+			match_count:    self.match_count,
+			collected:      self.collected_count,
+			limit_reached:  self.limit_reached,
+			binary_offset:  self.binary_offset,
+			encoding:       TextEncoding::Utf8,
+			bytes_searched: 0,
+			elapsed:        Duration::ZERO,
+		}
+	}
+
+	/// Whether a hit starting at `offset` (absolute) is in a kept syntax class.
+	fn keeps_offset(&self, offset: u64) -> bool {
+		self
+			.syntax
+			.is_none_or(|(filter, map)| filter.allows(map.kind_at(offset as usize)))
+	}
+
+	/// Whether any regex hit in `mat` is in a kept syntax class.
+	fn keeps_match(&self, mat: &SinkMatch<'_>) -> bool {
+		if self.syntax.is_none() {
+			return true;
+		}
+		let base = mat.absolute_byte_offset();
+		let mut keep = false;
+		let _ = self.matcher.regex.find_iter(mat.bytes(), |range| {
+			keep = self.keeps_offset(base + range.start() as u64);
+			!keep
+		});
+		keep
+	}
+
+	/// Byte ranges within `mat` of the hits of `matcher` in a kept syntax
+	/// class.
+	fn kept_spans(&self, matcher: &CompiledMatcher, mat: &SinkMatch<'_>) -> Vec<Range<usize>> {
+		let base = mat.absolute_byte_offset();
+		let mut spans = Vec::new();
+		let _ = matcher.regex.find_iter(mat.bytes(), |range| {
+			if self.keeps_offset(base + range.start() as u64) {
+				spans.push(range.start()..range.end());
+			}
+			true
+		});
+		spans
+	}
+
+	/// Record a line hit by the union of a pattern set once per pattern hitting
+	/// it, each with the line's context. Offset and limit are applied by the
+	/// caller over the merged hits.
+	fn matched_patterns(&mut self, mat: &SinkMatch<'_>) {
+		let matcher = self.matcher;
+		let base = mat.absolute_byte_offset();
+		let line_number = mat.line_number().unwrap_or(0);
+		let raw_line = bytes_to_trimmed_string(mat.bytes());
+		let (line, kept) = self.truncate_line(&raw_line);
+		let visible_len = kept.unwrap_or(line.len());
+		let context_before = std::mem::take(&mut self.context_before);
+		let first = self.matches.len();
+		for (index, pattern) in matcher.patterns.iter().enumerate() {
+			let spans = self.kept_spans(pattern, mat);
+			if spans.is_empty() {
+				continue;
+			}
+			let submatches =
+				collect_submatches(pattern, mat.bytes(), line_number, visible_len, |start| {
+					self.keeps_offset(base + start as u64)
+				});
+			let raw = self
+				.keep_raw
+				.then(|| RawMatch { bytes: mat.bytes().to_vec(), submatches: spans });
+			self.matches.push(CollectedMatch {
+				line_number,
+				offset: base,
+				line: line.clone(),
+				context_before: context_before.clone(),
+				context_after: SmallVec::new(),
+				truncated: kept.is_some(),
+				submatches,
+				binary: false,
+				raw,
+				pattern: Some(index),
+			});
 		}
+		let hits = (self.matches.len() - first) as u64;
+		self.dropped_last = hits == 0;
+		self.match_count += hits;
+		self.collected_count += hits;
 	}
 
-	fn truncate_line(&self, line: &str) -> (String, bool) {
+	/// Cut `line` to `max_columns`, returning the line and, when cut, the byte
+	/// length kept before the `...` marker.
+	fn truncate_line(&self, line: &str) -> (String, Option<usize>) {
 		match self.max_columns {
 			Some(max) if line.len() > max => {
 				let cut = max.saturating_sub(3);
 				let boundary = line.floor_char_boundary(cut);
 				let truncated = format!("{}...", &line[..boundary]);
-				(truncated, true)
+				(truncated, Some(boundary))
 			},
-			_ => (line.to_string(), false),
+			_ => (line.to_string(), None),
 		}
 	}
 }
@@ -304,7 +795,146 @@ fn bytes_to_trimmed_string(bytes: &[u8]) -> String {
 	}
 }
 
-impl Sink for MatchCollector {
+fn utf16_len(bytes: &[u8]) -> usize {
+	String::from_utf8_lossy(bytes).encode_utf16().count()
+}
+
+/// Byte offset in `String::from_utf8_lossy(bytes)` of raw byte `offset`.
+///
+/// Each invalid sequence becomes one U+FFFD (3 bytes), so offsets past one
+/// shift; an offset inside an invalid sequence maps to its replacement.
+fn lossy_offset(bytes: &[u8], offset: usize) -> usize {
+	let (mut raw, mut mapped) = (0, 0);
+	for chunk in bytes.utf8_chunks() {
+		let valid = chunk.valid().len();
+		if offset <= raw + valid {
+			return mapped + offset - raw;
+		}
+		raw += valid;
+		mapped += valid;
+		let invalid = chunk.invalid().len();
+		if invalid > 0 {
+			if offset < raw + invalid {
+				return mapped;
+			}
+			raw += invalid;
+			mapped += char::REPLACEMENT_CHARACTER.len_utf8();
+		}
+	}
+	mapped
+}
+
+/// Resolve a byte offset inside a (possibly multiline) match block to a
+/// 0-based line delta and a 1-based UTF-16 column.
+fn locate_offset(bytes: &[u8], offset: usize) -> (u64, u32) {
+	let prefix = &bytes[..offset];
+	let line_delta = prefix.split(|&b| b == b'\n').count() as u64 - 1;
+	let line_start = prefix
+		.iter()
+		.rposition(|&b| b == b'\n')
+		.map_or(0, |idx| idx + 1);
+	(line_delta, crate::utils::clamp_u32(utf16_len(&prefix[line_start..]) as u64 + 1))
+}
+
+/// Collect every regex hit inside a matched block.
+///
+/// Offsets index into the reported line, which is `bytes` decoded lossily.
+/// `visible_len` is the byte length of that line after trimming and
+/// truncation; hits starting past it are dropped and hits crossing it are
+/// clipped. Hits whose raw start offset fails `keep` are left out.
+fn collect_submatches(
+	matcher: &CompiledMatcher,
+	bytes: &[u8],
+	line_number: u64,
+	visible_len: usize,
+	keep: impl Fn(usize) -> bool,
+) -> Vec<Submatch> {
+	let mut submatches = Vec::new();
+	let Ok(mut caps) = matcher.regex.new_captures() else {
+		return submatches;
+	};
+	let line = String::from_utf8_lossy(bytes);
+	let _ = matcher.regex.captures_iter(bytes, &mut caps, |caps| {
+		let Some(range) = caps.get(0) else {
+			return true;
+		};
+		if !keep(range.start()) {
+			return true;
+		}
+		let start = lossy_offset(bytes, range.start());
+		if start > visible_len || (start == visible_len && !range.is_empty()) {
+			return false;
+		}
+		let start = line.floor_char_boundary(start);
+		let end = line.floor_char_boundary(lossy_offset(bytes, range.end()).min(visible_len));
+		let (start_delta, column) = locate_offset(bytes, range.start());
+		let (end_delta, end_column) = locate_offset(bytes, range.end());
+		let groups = if matcher.groups.is_empty() {
+			None
+		} else {
+			let groups: HashMap<String, String> = matcher
+				.groups
+				.iter()
+				.filter_map(|(name, index)| {
+					let group = caps.get(*index)?;
+					let text = String::from_utf8_lossy(&bytes[group.start()..group.end()]);
+					Some((name.clone(), text.into_owned()))
+				})
+				.collect();
+			(!groups.is_empty()).then_some(groups)
+		};
+		let start_utf16 = line[..start].encode_utf16().count();
+		let end_utf16 = start_utf16 + line[start..end].encode_utf16().count();
+		submatches.push(Submatch {
+			text: line[start..end].to_string(),
+			start: crate::utils::clamp_u32(start_utf16 as u64),
+			end: crate::utils::clamp_u32(end_utf16 as u64),
+			byte_start: crate::utils::clamp_u32(start as u64),
+			byte_end: crate::utils::clamp_u32(end as u64),
+			line_number: crate::utils::clamp_u32(line_number + start_delta),
+			column,
+			end_line_number: crate::utils::clamp_u32(line_number + end_delta),
+			end_column,
+			groups,
+		});
+		true
+	});
+	submatches
+}
+
+/// Extract `(?P<name>...)` / `(?<name>...)` group names from a regex and
+/// resolve their capture indices against the compiled matcher.
+fn named_groups(pattern: &str, regex: &RegexMatcher) -> Vec<(String, usize)> {
+	let bytes = pattern.as_bytes();
+	let mut groups = Vec::new();
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'\\' {
+			i += 2;
+			continue;
+		}
+		if bytes[i] == b'('
+			&& bytes.get(i + 1) == Some(&b'?')
+			&& let Some(name_start) = match (bytes.get(i + 2), bytes.get(i + 3)) {
+				(Some(b'P'), Some(b'<')) => Some(i + 4),
+				(Some(b'<'), Some(next)) if !matches!(next, b'=' | b'!') => Some(i + 3),
+				_ => None,
+			}
+			&& let Some(len) = bytes[name_start..].iter().position(|&b| b == b'>')
+		{
+			let name = &pattern[name_start..name_start + len];
+			if let Some(index) = regex.capture_index(name) {
+				groups.push((name.to_string(), index));
+			}
+			i = name_start + len + 1;
+			continue;
+		}
+		i += 1;
+	}
+	groups
+}
+
+impl Sink for MatchCollector<'_> {
 	type Error = io::Error;
 
 	fn matched(
@@ -312,6 +942,15 @@ impl Sink for MatchCollector {
 		_searcher: &Searcher,
 		mat: &SinkMatch<'_>,
 	) -> std::result::Result<bool, Self::Error> {
+		if !self.matcher.patterns.is_empty() {
+			self.matched_patterns(mat);
+			return Ok(true);
+		}
+		self.dropped_last = !self.keeps_match(mat);
+		if self.dropped_last {
+			self.context_before.clear();
+			return Ok(true);
+		}
 		self.match_count += 1;
 
 		// If we already hit the limit, stop now (after-context for previous match was
@@ -328,15 +967,31 @@ impl Sink for MatchCollector {
 
 		if self.collect_matches {
 			let raw_line = bytes_to_trimmed_string(mat.bytes());
-			let (line, truncated) = self.truncate_line(&raw_line);
+			let (line, kept) = self.truncate_line(&raw_line);
+			let truncated = kept.is_some();
 			let line_number = mat.line_number().unwrap_or(0);
+			let visible_len = kept.unwrap_or(line.len());
+			let base = mat.absolute_byte_offset();
+			let submatches =
+				collect_submatches(self.matcher, mat.bytes(), line_number, visible_len, |start| {
+					self.keeps_offset(base + start as u64)
+				});
+			let raw = self.keep_raw.then(|| RawMatch {
+				bytes:      mat.bytes().to_vec(),
+				submatches: self.kept_spans(self.matcher, mat),
+			});
 
 			self.matches.push(CollectedMatch {
 				line_number,
+				offset: mat.absolute_byte_offset(),
 				line,
 				context_before: std::mem::take(&mut self.context_before),
 				context_after: SmallVec::new(),
 				truncated,
+				submatches,
+				binary: false,
+				raw,
+				pattern: None,
 			});
 		} else {
 			self.context_before.clear();
@@ -366,18 +1021,25 @@ impl Sink for MatchCollector {
 		let raw_line = bytes_to_trimmed_string(ctx.bytes());
 		let (line, _) = self.truncate_line(&raw_line);
 		let line_number = ctx.line_number().unwrap_or(0);
+		let context = CollectedContext {
+			line:   ContextLine { line_number: crate::utils::clamp_u32(line_number), line },
+			offset: ctx.absolute_byte_offset(),
+			raw:    self.keep_raw.then(|| ctx.bytes().to_vec()),
+		};
 
 		match ctx.kind() {
-			SinkContextKind::Before => {
-				self
-					.context_before
-					.push(ContextLine { line_number: crate::utils::clamp_u32(line_number), line });
-			},
+			SinkContextKind::Before => self.context_before.push(context),
+			SinkContextKind::After if self.dropped_last => {},
 			SinkContextKind::After => {
-				if let Some(last_match) = self.matches.last_mut() {
-					last_match
-						.context_after
-						.push(ContextLine { line_number: crate::utils::clamp_u32(line_number), line });
+				// Every pattern hitting the last matched line shares its context.
+				let last = self.matches.last().map(|matched| matched.offset);
+				for matched in self
+					.matches
+					.iter_mut()
+					.rev()
+					.take_while(|matched| Some(matched.offset) == last)
+				{
+					matched.context_after.push(context.clone());
 				}
 			},
 			SinkContextKind::Other => {},
@@ -385,11 +1047,22 @@ impl Sink for MatchCollector {
 
 		Ok(true)
 	}
+
+	fn finish(
+		&mut self,
+		_searcher: &Searcher,
+		finish: &SinkFinish,
+	) -> std::result::Result<(), Self::Error> {
+		self.binary_offset = finish.binary_byte_offset();
+		Ok(())
+	}
 }
 
 fn parse_output_mode(mode: Option<&str>) -> OutputMode {
 	match mode {
 		Some("count" | "filesWithMatches") => OutputMode::Count,
+		Some("json") => OutputMode::Json,
+		Some("hunks") => OutputMode::Hunks,
 		_ => OutputMode::Content,
 	}
 }
@@ -404,63 +1077,6 @@ fn resolve_search_path(path: &str) -> Result<PathBuf> {
 	Ok(cwd.join(candidate))
 }
 
-fn resolve_type_filter(type_name: Option<&str>) -> Option<TypeFilter> {
-	let normalized = type_name
-		.map(str::trim)
-		.filter(|value| !value.is_empty())
-		.map(|value| value.trim_start_matches('.').to_lowercase())?;
-
-	let (exts, names): (&[&str], &[&str]) = match normalized.as_str() {
-		"js" | "javascript" => (&["js", "jsx", "mjs", "cjs"], &[]),
-		"ts" | "typescript" => (&["ts", "tsx", "mts", "cts"], &[]),
-		"json" => (&["json", "jsonc", "json5"], &[]),
-		"yaml" | "yml" => (&["yaml", "yml"], &[]),
-		"toml" => (&["toml"], &[]),
-		"md" | "markdown" => (&["md", "markdown", "mdx"], &[]),
-		"py" | "python" => (&["py", "pyi"], &[]),
-		"rs" | "rust" => (&["rs"], &[]),
-		"go" => (&["go"], &[]),
-		"java" => (&["java"], &[]),
-		"kt" | "kotlin" => (&["kt", "kts"], &[]),
-		"c" => (&["c", "h"], &[]),
-		"cpp" | "cxx" => (&["cpp", "cc", "cxx", "hpp", "hxx", "hh"], &[]),
-		"cs" | "csharp" => (&["cs", "csx"], &[]),
-		"php" => (&["php", "phtml"], &[]),
-		"rb" | "ruby" => (&["rb", "rake", "gemspec"], &[]),
-		"sh" | "bash" => (&["sh", "bash", "zsh"], &[]),
-		"zsh" => (&["zsh"], &[]),
-		"fish" => (&["fish"], &[]),
-		"html" => (&["html", "htm"], &[]),
-		"css" => (&["css"], &[]),
-		"scss" => (&["scss"], &[]),
-		"sass" => (&["sass"], &[]),
-		"less" => (&["less"], &[]),
-		"xml" => (&["xml"], &[]),
-		"docker" | "dockerfile" => (&[], &["dockerfile"]),
-		"make" | "makefile" => (&[], &["makefile"]),
-		_ => {
-			return Some(TypeFilter::Custom(normalized));
-		},
-	};
-
-	Some(TypeFilter::Known { exts, names })
-}
-
-fn matches_type_filter(path: &Path, filter: &TypeFilter) -> bool {
-	let base_name = path
-		.file_name()
-		.and_then(|name| name.to_str())
-		.unwrap_or("");
-	if filter.match_name(base_name) {
-		return true;
-	}
-	let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
-	if ext.is_empty() {
-		return false;
-	}
-	filter.match_ext(ext)
-}
-
 fn resolve_context(
 	context: Option<u32>,
 	context_before: Option<u32>,
@@ -474,13 +1090,29 @@ fn resolve_context(
 	}
 }
 
-fn build_searcher(before_context: u32, after_context: u32) -> Searcher {
-	SearcherBuilder::new()
-		.binary_detection(BinaryDetection::quit(b'\x00'))
+fn build_searcher(
+	before_context: u32,
+	after_context: u32,
+	multiline: bool,
+	binary: BinaryMode,
+	encoding: TextEncoding,
+	mmap: bool,
+) -> Searcher {
+	let mut builder = SearcherBuilder::new();
+	builder
+		.binary_detection(binary.detection())
+		.encoding(encoding.searcher_encoding())
 		.line_number(true)
+		.multi_line(multiline)
 		.before_context(before_context as usize)
-		.after_context(after_context as usize)
-		.build()
+		.after_context(after_context as usize);
+	if mmap {
+		// SAFETY: Mapped files are only read for the duration of one search. A
+		// concurrent truncation can at worst surface as a read error/SIGBUS, the
+		// same trade-off ripgrep makes for large files.
+		builder.memory_map(unsafe { MmapChoice::auto() });
+	}
+	builder.build()
 }
 
 #[derive(Clone, Copy)]
@@ -491,79 +1123,522 @@ struct SearchParams {
 	mode:           OutputMode,
 	max_count:      Option<u64>,
 	offset:         u64,
+	multiline:      bool,
+	binary:         BinaryMode,
+	/// Only count hits in these syntax classes (see [`crate::grep_syntax`]).
+	syntax:         Option<SyntaxFilter>,
+}
+
+/// How binary data (a NUL byte after transcoding) is handled, mirroring
+/// ripgrep's default, `--binary` and `--text` behaviours.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BinaryMode {
+	/// Stop at the first NUL byte and drop the file's results.
+	Skip,
+	/// Keep searching, but report a single "binary file matches" entry.
+	Summary,
+	/// Search binary data as if it were text.
+	Text,
+}
+
+impl BinaryMode {
+	fn parse(mode: Option<&str>) -> Self {
+		match mode {
+			Some("summary") => Self::Summary,
+			Some("text") => Self::Text,
+			_ => Self::Skip,
+		}
+	}
+
+	fn detection(self) -> BinaryDetection {
+		match self {
+			Self::Skip => BinaryDetection::quit(b'\x00'),
+			Self::Summary => BinaryDetection::convert(b'\x00'),
+			Self::Text => BinaryDetection::none(),
+		}
+	}
+}
+
+/// Text encoding detected from a byte-order mark or the leading bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TextEncoding {
+	Utf8,
+	Utf16Le,
+	Utf16Be,
+	/// Latin-1, decoded as its `windows-1252` superset.
+	Latin1,
+}
+
+impl TextEncoding {
+	const fn label(self) -> &'static str {
+		match self {
+			Self::Utf8 => "UTF-8",
+			Self::Utf16Le => "UTF-16LE",
+			Self::Utf16Be => "UTF-16BE",
+			Self::Latin1 => "windows-1252",
+		}
+	}
+
+	/// Guess the encoding from a BOM, the NUL layout of UTF-16 text, or
+	/// invalid UTF-8 sequences outnumbering valid multibyte ones.
+	pub(crate) fn detect(prefix: &[u8]) -> Self {
+		match prefix {
+			[0xef, 0xbb, 0xbf, ..] => return Self::Utf8,
+			[0xff, 0xfe, ..] => return Self::Utf16Le,
+			[0xfe, 0xff, ..] => return Self::Utf16Be,
+			_ => {},
+		}
+		if let Some(utf16) = Self::guess_utf16(prefix) {
+			return utf16;
+		}
+		if prefix.contains(&0) {
+			return Self::Utf8;
+		}
+		// A few stray bytes in otherwise valid UTF-8 stay UTF-8 (decoded
+		// lossily); Latin-1 needs invalid sequences to be the norm.
+		let (mut multibyte, mut invalid) = (0, 0);
+		let mut chunks = prefix.utf8_chunks().peekable();
+		while let Some(chunk) = chunks.next() {
+			multibyte += chunk.valid().chars().filter(|ch| !ch.is_ascii()).count();
+			// A sequence cut off by the end of the prefix is not evidence.
+			if !chunk.invalid().is_empty() && chunks.peek().is_some() {
+				invalid += 1;
+			}
+		}
+		if invalid > multibyte {
+			Self::Latin1
+		} else {
+			Self::Utf8
+		}
+	}
+
+	/// Mostly-ASCII UTF-16 has a NUL in every other byte and none in between.
+	fn guess_utf16(prefix: &[u8]) -> Option<Self> {
+		let units = prefix.len() / 2;
+		if units < 2 {
+			return None;
+		}
+		let (mut even, mut odd) = (0, 0);
+		for [first, second] in prefix.as_chunks::<2>().0 {
+			even += usize::from(*first == 0);
+			odd += usize::from(*second == 0);
+		}
+		if even == 0 && odd * 4 >= units * 3 {
+			Some(Self::Utf16Le)
+		} else if odd == 0 && even * 4 >= units * 3 {
+			Some(Self::Utf16Be)
+		} else {
+			None
+		}
+	}
+
+	/// Transcoding setting for the searcher; UTF-8 is searched as-is (a UTF-8
+	/// BOM is still stripped by BOM sniffing).
+	fn searcher_encoding(self) -> Option<Encoding> {
+		match self {
+			Self::Utf8 => None,
+			other => Encoding::new(other.label()).ok(),
+		}
+	}
+
+	/// Label reported on grep hits: `None` for plain UTF-8.
+	fn reported(self) -> Option<String> {
+		(self != Self::Utf8).then(|| self.label().to_string())
+	}
+}
+
+/// Read the leading bytes of `file` to detect its encoding, then rewind.
+fn sniff_encoding(file: &mut File) -> io::Result<TextEncoding> {
+	let mut prefix = Vec::with_capacity(ENCODING_SNIFF_BYTES as usize);
+	(&*file)
+		.take(ENCODING_SNIFF_BYTES)
+		.read_to_end(&mut prefix)?;
+	file.rewind()?;
+	Ok(TextEncoding::detect(&prefix))
 }
 
 fn run_search(
-	matcher: &grep_regex::RegexMatcher,
+	matcher: &CompiledMatcher,
 	content: &[u8],
 	params: SearchParams,
 ) -> io::Result<SearchResultInternal> {
-	run_search_reader(matcher, Cursor::new(content), params)
+	let encoding =
+		TextEncoding::detect(&content[..content.len().min(ENCODING_SNIFF_BYTES as usize)]);
+	let mut search = run_search_reader(matcher, Cursor::new(content), encoding, params)?;
+	search.encoding = encoding;
+	Ok(search)
+}
+
+fn searcher_for(params: SearchParams, encoding: TextEncoding, mmap: bool) -> Searcher {
+	let (before, after) = if params.mode.collects_lines() {
+		(params.context_before, params.context_after)
+	} else {
+		(0, 0)
+	};
+	build_searcher(before, after, params.multiline, params.binary, encoding, mmap)
+}
+
+fn collector_for<'m>(
+	matcher: &'m CompiledMatcher,
+	params: SearchParams,
+	syntax: Option<&'m SyntaxMap>,
+) -> MatchCollector<'m> {
+	MatchCollector::new(
+		matcher,
+		params.max_count,
+		params.offset,
+		params.max_columns.map(|v| v as usize),
+		params.mode.collects_lines(),
+		params.mode == OutputMode::Json,
+		params.syntax.zip(syntax),
+	)
 }
 
 /// Stream-based search that reads directly from a `Read` without buffering.
 fn run_search_reader<R: Read>(
-	matcher: &grep_regex::RegexMatcher,
+	matcher: &CompiledMatcher,
 	reader: R,
+	encoding: TextEncoding,
 	params: SearchParams,
 ) -> io::Result<SearchResultInternal> {
-	let mut searcher = build_searcher(
-		if params.mode == OutputMode::Content {
-			params.context_before
-		} else {
-			0
-		},
-		if params.mode == OutputMode::Content {
-			params.context_after
-		} else {
-			0
-		},
-	);
-	let mut collector = MatchCollector::new(
-		params.max_count,
-		params.offset,
-		params.max_columns.map(|v| v as usize),
-		params.mode == OutputMode::Content,
-	);
-	searcher.search_reader(matcher, reader, &mut collector)?;
-	Ok(SearchResultInternal {
-		matches:       collector.matches,
-		match_count:   collector.match_count,
-		collected:     collector.collected_count,
-		limit_reached: collector.limit_reached,
-	})
+	let mut searcher = searcher_for(params, encoding, false);
+	let mut collector = collector_for(matcher, params, None);
+	searcher.search_reader(&matcher.regex, reader, &mut collector)?;
+	Ok(collector.into_result())
 }
 
-fn to_public_match(matched: CollectedMatch) -> Match {
-	let context_before = if matched.context_before.is_empty() {
-		None
-	} else {
-		Some(matched.context_before.into_vec())
-	};
-	let context_after = if matched.context_after.is_empty() {
-		None
-	} else {
-		Some(matched.context_after.into_vec())
-	};
-	Match {
-		line_number: crate::utils::clamp_u32(matched.line_number),
-		line: matched.line,
-		context_before,
-		context_after,
-		truncated: if matched.truncated { Some(true) } else { None },
-	}
+/// Search a blob held in memory, classifying hits with `syntax` when given.
+fn run_search_slice(
+	matcher: &CompiledMatcher,
+	content: &[u8],
+	encoding: TextEncoding,
+	params: SearchParams,
+	syntax: Option<&SyntaxMap>,
+) -> io::Result<SearchResultInternal> {
+	let started = Instant::now();
+	let mut searcher = searcher_for(params, encoding, false);
+	let mut collector = collector_for(matcher, params, syntax);
+	searcher.search_slice(&matcher.regex, content, &mut collector)?;
+	let mut search = collector.into_result();
+	search.encoding = encoding;
+	search.bytes_searched = content.len() as u64;
+	search.elapsed = started.elapsed();
+	Ok(search)
 }
 
-fn to_grep_match(path: &str, matched: CollectedMatch) -> GrepMatch {
+/// Search an open file, memory-mapping it when it is large enough to benefit.
+fn run_search_file(
+	matcher: &CompiledMatcher,
+	file: &File,
+	len: u64,
+	encoding: TextEncoding,
+	params: SearchParams,
+) -> io::Result<SearchResultInternal> {
+	let started = Instant::now();
+	let mut searcher = searcher_for(params, encoding, len >= MMAP_THRESHOLD_BYTES);
+	let mut collector = collector_for(matcher, params, None);
+	searcher.search_file(&matcher.regex, file, &mut collector)?;
+	let mut search = collector.into_result();
+	search.encoding = encoding;
+	search.bytes_searched = len;
+	search.elapsed = started.elapsed();
+	Ok(search)
+}
+
+/// Apply `mode` to a finished search. `skip` keeps the hits found before the
+/// first NUL, where the search stopped; `Err` carries the NUL offset when
+/// there were none. `summary` collapses hits into a single "binary file
+/// matches" entry.
+fn apply_binary_mode(
+	mut search: SearchResultInternal,
+	mode: BinaryMode,
+) -> std::result::Result<SearchResultInternal, u64> {
+	let Some(offset) = search.binary_offset else {
+		return Ok(search);
+	};
+	match mode {
+		BinaryMode::Skip if search.match_count == 0 => Err(offset),
+		BinaryMode::Summary if !search.matches.is_empty() => {
+			search.matches = vec![CollectedMatch {
+				line_number: 0,
+				offset,
+				line: format!("binary file matches (found \"\\0\" byte around offset {offset})"),
+				context_before: SmallVec::new(),
+				context_after: SmallVec::new(),
+				truncated: false,
+				submatches: Vec::new(),
+				binary: true,
+				raw: None,
+				pattern: None,
+			}];
+			search.collected = 1;
+			Ok(search)
+		},
+		BinaryMode::Skip | BinaryMode::Summary | BinaryMode::Text => Ok(search),
+	}
+}
+
+/// Why a file was left out of a `grep` search.
+#[derive(Clone, Copy)]
+enum SkipReason {
+	TooLarge,
+	Binary,
+	/// Text in an encoding that is not rewritten in place.
+	Encoding,
+	PermissionDenied,
+	Io,
+}
+
+impl SkipReason {
+	const fn as_str(self) -> &'static str {
+		match self {
+			Self::TooLarge => "tooLarge",
+			Self::Binary => "binary",
+			Self::Encoding => "encoding",
+			Self::PermissionDenied => "permissionDenied",
+			Self::Io => "ioError",
+		}
+	}
+}
+
+fn skipped_file(entry: &FileEntry, reason: SkipReason, message: String) -> GrepSkippedFile {
+	GrepSkippedFile {
+		path:    entry.relative_path.clone(),
+		reason:  reason.as_str().to_string(),
+		message: Some(message),
+	}
+}
+
+fn io_skip(entry: &FileEntry, err: &io::Error) -> GrepSkippedFile {
+	let reason = if err.kind() == io::ErrorKind::PermissionDenied {
+		SkipReason::PermissionDenied
+	} else {
+		SkipReason::Io
+	};
+	skipped_file(entry, reason, err.to_string())
+}
+
+/// Contents of a [`FileEntry`] opened for searching.
+enum OpenEntry<'a> {
+	File(File),
+	Blob(Cow<'a, [u8]>),
+	/// Whole content loaded for a `syntax` search. The grammar for `path` only
+	/// runs once the regex hits somewhere in `data`; `syntax` holds `None` when
+	/// no grammar applies, which leaves nothing to match.
+	Loaded {
+		data:   Cow<'a, [u8]>,
+		path:   &'a Path,
+		syntax: OnceCell<Option<SyntaxMap>>,
+	},
+}
+
+impl<'a> OpenEntry<'a> {
+	fn sniff_encoding(&mut self) -> io::Result<TextEncoding> {
+		match self {
+			Self::File(file) => sniff_encoding(file),
+			Self::Blob(data) => {
+				Ok(TextEncoding::detect(&data[..data.len().min(ENCODING_SNIFF_BYTES as usize)]))
+			},
+			Self::Loaded { data, .. } => {
+				Ok(TextEncoding::detect(&data[..data.len().min(ENCODING_SNIFF_BYTES as usize)]))
+			},
+		}
+	}
+
+	/// Load the whole content so it can be classified with the grammar for
+	/// `path`.
+	fn load(self, path: &'a Path) -> io::Result<Self> {
+		let data = match self {
+			Self::File(mut file) => {
+				let mut data = Vec::new();
+				file.read_to_end(&mut data)?;
+				Cow::Owned(data)
+			},
+			Self::Blob(data) => data,
+			loaded @ Self::Loaded { .. } => return Ok(loaded),
+		};
+		Ok(Self::Loaded { data, path, syntax: OnceCell::new() })
+	}
+
+	/// Read a file into memory so several searches share one read.
+	fn buffer(self) -> io::Result<Self> {
+		match self {
+			Self::File(mut file) => {
+				let mut data = Vec::new();
+				file.read_to_end(&mut data)?;
+				Ok(Self::Blob(Cow::Owned(data)))
+			},
+			other => Ok(other),
+		}
+	}
+
+	/// Search the whole content; files are rewound first so this can run once
+	/// per pattern.
+	fn search(
+		&mut self,
+		matcher: &CompiledMatcher,
+		len: u64,
+		encoding: TextEncoding,
+		params: SearchParams,
+	) -> io::Result<SearchResultInternal> {
+		match self {
+			Self::File(file) => {
+				file.rewind()?;
+				run_search_file(matcher, file, len, encoding, params)
+			},
+			Self::Blob(data) => run_search_slice(matcher, data, encoding, params, None),
+			Self::Loaded { data, path, syntax } => {
+				// Byte offsets only line up with the tree for UTF-8 text, so other
+				// encodings are left unclassified.
+				let hit =
+					encoding == TextEncoding::Utf8 && matcher.regex.is_match(data).unwrap_or(true);
+				let syntax = if hit {
+					syntax.get_or_init(|| SyntaxMap::parse(path, data)).as_ref()
+				} else {
+					None
+				};
+				if let Some(syntax) = syntax {
+					return run_search_slice(matcher, data, encoding, params, Some(syntax));
+				}
+				let mut search = collector_for(matcher, params, None).into_result();
+				search.encoding = encoding;
+				search.bytes_searched = data.len() as u64;
+				Ok(search)
+			},
+		}
+	}
+}
+
+/// Open `entry` for searching, enforcing the size cap. Returns the content,
+/// its length and its encoding; content is loaded whole when `params`
+/// filters by syntax.
+fn open_entry(
+	entry: &FileEntry,
+	max_file_bytes: Option<u64>,
+	params: SearchParams,
+) -> std::result::Result<(OpenEntry<'_>, u64, TextEncoding), GrepSkippedFile> {
+	let within_cap = |len: u64| match max_file_bytes {
+		Some(max) if len > max => Err(skipped_file(
+			entry,
+			SkipReason::TooLarge,
+			format!("{len} bytes exceeds the {max} byte limit"),
+		)),
+		_ => Ok(()),
+	};
+	// Oversized blobs are reported as skipped without being read.
+	let (mut opened, len) = if let Some(blob) = &entry.blob {
+		within_cap(blob.size)?;
+		let data = blob.read().map_err(|err| io_skip(entry, &err))?;
+		(OpenEntry::Blob(Cow::Owned(data)), blob.size)
+	} else {
+		let file = File::open(&entry.path).map_err(|err| io_skip(entry, &err))?;
+		let len = file.metadata().map_err(|err| io_skip(entry, &err))?.len();
+		within_cap(len)?;
+		(OpenEntry::File(file), len)
+	};
+	let encoding = opened
+		.sniff_encoding()
+		.map_err(|err| io_skip(entry, &err))?;
+	if params.syntax.is_some() {
+		opened = opened
+			.load(&entry.path)
+			.map_err(|err| io_skip(entry, &err))?;
+	}
+	Ok((opened, len, encoding))
+}
+
+/// Apply the binary mode to a file's results, reporting it as skipped when
+/// binary files are skipped.
+fn check_binary(
+	entry: &FileEntry,
+	search: SearchResultInternal,
+	mode: BinaryMode,
+) -> std::result::Result<SearchResultInternal, GrepSkippedFile> {
+	apply_binary_mode(search, mode).map_err(|offset| {
+		skipped_file(entry, SkipReason::Binary, format!("NUL byte at offset {offset}"))
+	})
+}
+
+/// Search one file from the scanned set, reporting why it was skipped when it
+/// could not be searched.
+fn search_entry(
+	entry: &FileEntry,
+	matcher: &CompiledMatcher,
+	params: SearchParams,
+	max_file_bytes: Option<u64>,
+) -> std::result::Result<SearchResultInternal, GrepSkippedFile> {
+	let (mut opened, len, encoding) = open_entry(entry, max_file_bytes, params)?;
+	let search = opened
+		.search(matcher, len, encoding, params)
+		.map_err(|err| io_skip(entry, &err))?;
+	check_binary(entry, search, params.binary)
+}
+
+/// Resolve the `maxFileSize` option: unset uses the default, `0` or negative
+/// disables the cap.
+fn resolve_max_file_bytes(max_file_size: Option<f64>) -> Option<u64> {
+	match max_file_size {
+		None => Some(DEFAULT_MAX_FILE_BYTES),
+		Some(value) if value <= 0.0 => None,
+		Some(value) => Some(value as u64),
+	}
+}
+
+fn to_public_match(matched: CollectedMatch) -> Match {
+	let context_before = if matched.context_before.is_empty() {
+		None
+	} else {
+		Some(
+			matched
+				.context_before
+				.into_iter()
+				.map(|context| context.line)
+				.collect(),
+		)
+	};
+	let context_after = if matched.context_after.is_empty() {
+		None
+	} else {
+		Some(
+			matched
+				.context_after
+				.into_iter()
+				.map(|context| context.line)
+				.collect(),
+		)
+	};
+	Match {
+		line_number: crate::utils::clamp_u32(matched.line_number),
+		line: matched.line,
+		context_before,
+		context_after,
+		truncated: if matched.truncated { Some(true) } else { None },
+		submatches: (!matched.submatches.is_empty()).then_some(matched.submatches),
+	}
+}
+
+fn to_grep_match(path: &str, matched: CollectedMatch, encoding: TextEncoding) -> GrepMatch {
 	let context_before = if matched.context_before.is_empty() {
 		None
 	} else {
-		Some(matched.context_before.into_vec())
+		Some(
+			matched
+				.context_before
+				.into_iter()
+				.map(|context| context.line)
+				.collect(),
+		)
 	};
 	let context_after = if matched.context_after.is_empty() {
 		None
 	} else {
-		Some(matched.context_after.into_vec())
+		Some(
+			matched
+				.context_after
+				.into_iter()
+				.map(|context| context.line)
+				.collect(),
+		)
 	};
 	GrepMatch {
 		path: path.to_string(),
@@ -572,25 +1647,327 @@ fn to_grep_match(path: &str, matched: CollectedMatch) -> GrepMatch {
 		context_before,
 		context_after,
 		truncated: if matched.truncated { Some(true) } else { None },
+		submatches: (!matched.submatches.is_empty()).then_some(matched.submatches),
+		pattern_id: None,
 		match_count: None,
+		encoding: encoding.reported(),
+		binary: matched.binary.then_some(true),
+		json: None,
 	}
 }
 
 const fn empty_search_result(error: Option<String>) -> SearchResult {
-	SearchResult { matches: Vec::new(), match_count: 0, limit_reached: false, error }
+	SearchResult {
+		matches: Vec::new(),
+		match_count: 0,
+		limit_reached: false,
+		error,
+		encoding: None,
+		binary: None,
+	}
+}
+
+/// Counters reported in `end` (per file) and `summary` records.
+#[derive(Clone, Copy, Default)]
+struct JsonStats {
+	elapsed:             Duration,
+	searches:            u64,
+	searches_with_match: u64,
+	bytes_searched:      u64,
+	bytes_printed:       u64,
+	matched_lines:       u64,
+	matches:             u64,
+}
+
+impl JsonStats {
+	fn add(&mut self, other: &Self) {
+		self.elapsed += other.elapsed;
+		self.searches += other.searches;
+		self.searches_with_match += other.searches_with_match;
+		self.bytes_searched += other.bytes_searched;
+		self.bytes_printed += other.bytes_printed;
+		self.matched_lines += other.matched_lines;
+		self.matches += other.matches;
+	}
+
+	fn to_json(self) -> Value {
+		json!({
+			"elapsed": json_duration(self.elapsed),
+			"searches": self.searches,
+			"searches_with_match": self.searches_with_match,
+			"bytes_searched": self.bytes_searched,
+			"bytes_printed": self.bytes_printed,
+			"matched_lines": self.matched_lines,
+			"matches": self.matches,
+		})
+	}
+}
+
+fn json_duration(elapsed: Duration) -> Value {
+	json!({
+		"secs": elapsed.as_secs(),
+		"nanos": elapsed.subsec_nanos(),
+		"human": format!("{:.6}s", elapsed.as_secs_f64()),
+	})
+}
+
+/// `{"text": ...}` for UTF-8 data and `{"bytes": <base64>}` otherwise, as in
+/// `rg --json`.
+fn json_data(bytes: &[u8]) -> Value {
+	match std::str::from_utf8(bytes) {
+		Ok(text) => json!({ "text": text }),
+		Err(_) => json!({ "bytes": BASE64_STANDARD.encode(bytes) }),
+	}
+}
+
+/// Line record (`match` or `context`) in the `rg --json` schema. `lines` are
+/// the raw bytes searched, line terminators included.
+fn json_line_record(
+	kind: &str,
+	path: &str,
+	line_number: u64,
+	offset: u64,
+	lines: &[u8],
+	submatches: &[Range<usize>],
+) -> Value {
+	let submatches: Vec<Value> = submatches
+		.iter()
+		.map(|range| {
+			json!({
+				"match": json_data(&lines[range.clone()]),
+				"start": range.start,
+				"end": range.end,
+			})
+		})
+		.collect();
+	json!({
+		"type": kind,
+		"data": {
+			"path": { "text": path },
+			"lines": json_data(lines),
+			"line_number": line_number,
+			"absolute_offset": offset,
+			"submatches": submatches,
+		},
+	})
+}
+
+/// One serialized `rg --json` record and the line it reports.
+struct JsonRecord {
+	line_number: u64,
+	line:        String,
+	text:        String,
+}
+
+impl JsonRecord {
+	fn new(line_number: u64, line: &str, record: &Value) -> Self {
+		Self { line_number, line: line.to_string(), text: record.to_string() }
+	}
+
+	/// Size in bytes, newline included.
+	const fn len(&self) -> u64 {
+		self.text.len() as u64 + 1
+	}
+
+	/// Stream the record through the `json` field of a [`GrepMatch`].
+	fn send(&self, path: &str, on_match: &ThreadsafeFunction<GrepMatch>) {
+		let record = GrepMatch {
+			path:           path.to_string(),
+			line_number:    crate::utils::clamp_u32(self.line_number),
+			line:           self.line.clone(),
+			context_before: None,
+			context_after:  None,
+			truncated:      None,
+			submatches:     None,
+			pattern_id:     None,
+			match_count:    None,
+			encoding:       None,
+			binary:         None,
+			json:           Some(self.text.clone()),
+		};
+		on_match.call(Ok(record), ThreadsafeFunctionCallMode::NonBlocking);
+	}
+}
+
+/// The records of one searched file and the counters they add.
+struct FileRecords {
+	path:    String,
+	records: Vec<JsonRecord>,
+	stats:   JsonStats,
+}
+
+impl FileRecords {
+	/// Render the records for one searched file. Files without reportable
+	/// matches only count towards the summary, as in ripgrep.
+	fn render<'m>(
+		path: &str,
+		matches: impl IntoIterator<Item = &'m CollectedMatch>,
+		search: SearchStats,
+	) -> Self {
+		let mut file = Self {
+			path:    path.to_string(),
+			records: Vec::new(),
+			stats:   JsonStats {
+				elapsed: search.elapsed,
+				searches: 1,
+				bytes_searched: search.bytes_searched,
+				..JsonStats::default()
+			},
+		};
+		// The binary summary entry is reported via `binary_offset` instead.
+		let mut matches = matches
+			.into_iter()
+			.filter(|matched| !matched.binary)
+			.peekable();
+		if matches.peek().is_none() {
+			return file;
+		}
+		file.stats.searches_with_match = 1;
+		file.push(0, "", &json!({ "type": "begin", "data": { "path": { "text": path } } }));
+		for matched in matches {
+			for context in &matched.context_before {
+				file.push_context(context);
+			}
+			let fallback;
+			let (lines, spans) = if let Some(raw) = &matched.raw {
+				(raw.bytes.as_slice(), raw.submatches.as_slice())
+			} else {
+				fallback = matched
+					.submatches
+					.iter()
+					.map(|submatch| submatch.byte_start as usize..submatch.byte_end as usize)
+					.collect::<Vec<_>>();
+				(matched.line.as_bytes(), fallback.as_slice())
+			};
+			let record =
+				json_line_record("match", path, matched.line_number, matched.offset, lines, spans);
+			file.push(matched.line_number, &matched.line, &record);
+			file.stats.matched_lines += matched.line.lines().count().max(1) as u64;
+			file.stats.matches += spans.len().max(1) as u64;
+			for context in &matched.context_after {
+				file.push_context(context);
+			}
+		}
+		let end_stats = file.stats;
+		file.push(
+			0,
+			"",
+			&json!({
+				"type": "end",
+				"data": {
+					"path": { "text": path },
+					"binary_offset": search.binary_offset,
+					"stats": end_stats.to_json(),
+				},
+			}),
+		);
+		file
+	}
+
+	fn push(&mut self, line_number: u64, line: &str, record: &Value) {
+		let record = JsonRecord::new(line_number, line, record);
+		self.stats.bytes_printed += record.len();
+		self.records.push(record);
+	}
+
+	fn push_context(&mut self, context: &CollectedContext) {
+		let line_number = u64::from(context.line.line_number);
+		let lines = context
+			.raw
+			.as_deref()
+			.unwrap_or(context.line.line.as_bytes());
+		let record = json_line_record("context", &self.path, line_number, context.offset, lines, &[]);
+		self.push(line_number, &context.line.line, &record);
+	}
+
+	/// Stream every record to `on_match`.
+	fn send(&self, on_match: &ThreadsafeFunction<GrepMatch>) {
+		for record in &self.records {
+			record.send(&self.path, on_match);
+		}
+	}
+}
+
+/// Writer for `rg --json` output: `begin`, `context`/`match` and `end` records
+/// for every file with a match, then one `summary` record.
+///
+/// Records are buffered into one JSON Lines string and, when a callback is
+/// given, streamed one at a time through the `json` field of a [`GrepMatch`].
+struct JsonLines<'a> {
+	output:   String,
+	on_match: Option<&'a ThreadsafeFunction<GrepMatch>>,
+	started:  Instant,
+	stats:    JsonStats,
+}
+
+impl<'a> JsonLines<'a> {
+	fn new(on_match: Option<&'a ThreadsafeFunction<GrepMatch>>) -> Self {
+		Self { output: String::new(), on_match, started: Instant::now(), stats: JsonStats::default() }
+	}
+
+	/// Emit the records for one searched file.
+	fn file<'m>(
+		&mut self,
+		path: &str,
+		matches: impl IntoIterator<Item = &'m CollectedMatch>,
+		search: SearchStats,
+	) {
+		let file = FileRecords::render(path, matches, search);
+		if let Some(on_match) = self.on_match {
+			file.send(on_match);
+		}
+		self.append(file);
+	}
+
+	/// Buffer records that were already streamed, e.g. by a search worker.
+	fn append(&mut self, file: FileRecords) {
+		for record in file.records {
+			self.output.push_str(&record.text);
+			self.output.push('\n');
+		}
+		self.stats.add(&file.stats);
+	}
+
+	/// Emit the `summary` record and return the buffered output.
+	fn finish(mut self) -> String {
+		let summary = JsonRecord::new(
+			0,
+			"",
+			&json!({
+				"type": "summary",
+				"data": {
+					"elapsed_total": json_duration(self.started.elapsed()),
+					"stats": self.stats.to_json(),
+				},
+			}),
+		);
+		if let Some(on_match) = self.on_match {
+			summary.send("", on_match);
+		}
+		self.output.push_str(&summary.text);
+		self.output.push('\n');
+		self.output
+	}
 }
 
 /// Internal configuration for grep, extracted from options.
 struct GrepConfig {
-	pattern:        String,
+	pattern:        Option<String>,
+	patterns:       Option<Vec<GrepPattern>>,
+	file_query:     Option<GrepFileQuery>,
 	path:           String,
-	glob:           Option<String>,
-	type_filter:    Option<String>,
+	glob:           Option<Either<String, Vec<String>>>,
+	exclude:        Option<Vec<String>>,
+	type_filter:    Option<Either<String, Vec<String>>>,
 	ignore_case:    Option<bool>,
 	multiline:      Option<bool>,
+	fixed_strings:  Option<bool>,
+	word_regexp:    Option<bool>,
+	smart_case:     Option<bool>,
 	hidden:         Option<bool>,
 	gitignore:      Option<bool>,
 	cache:          Option<bool>,
+	max_file_size:  Option<f64>,
 	max_count:      Option<u32>,
 	offset:         Option<u32>,
 	context_before: Option<u32>,
@@ -598,39 +1975,150 @@ struct GrepConfig {
 	context:        Option<u32>,
 	max_columns:    Option<u32>,
 	mode:           Option<String>,
+	binary:         Option<String>,
+	syntax:         Option<Either<String, Vec<String>>>,
+	index:          Option<bool>,
+	rev:            Option<String>,
+	changed:        Option<String>,
+	changed_base:   Option<String>,
+}
+
+/// Filters applied to scanned entries before they are searched.
+#[derive(Clone, Copy)]
+struct EntryFilters<'a> {
+	glob:    Option<&'a GlobFilter>,
+	types:   Option<&'a TypeFilter>,
+	/// Prune files through the persistent trigram index.
+	index:   Option<&'a grep_index::Query>,
+	/// Only files in this set (see [`git::changed_under`]).
+	changed: Option<&'a HashSet<PathBuf>>,
 }
 
 fn collect_files(
 	root: &Path,
 	scanned_entries: &[fs_cache::GlobMatch],
-	glob_set: Option<&GlobSet>,
-	type_filter: Option<&TypeFilter>,
-) -> Vec<FileEntry> {
+	filters: EntryFilters<'_>,
+	ct: &task::CancelToken,
+) -> Result<Vec<FileEntry>> {
+	let candidates = match filters.index {
+		Some(query) => grep_index::candidate_paths(root, scanned_entries, query, ct)?,
+		None => None,
+	};
 	let mut entries = Vec::new();
 	for entry in scanned_entries {
 		if entry.file_type != fs_cache::FileType::File {
 			continue;
 		}
-		if let Some(glob_set) = glob_set
-			&& !glob_set.is_match(Path::new(&entry.path))
+		if let Some(glob_filter) = filters.glob
+			&& !glob_filter.is_match(&entry.path)
+		{
+			continue;
+		}
+		if let Some(candidates) = &candidates
+			&& !candidates.contains(&entry.path)
 		{
 			continue;
 		}
 		let path = root.join(&entry.path);
-		if let Some(filter) = type_filter
-			&& !matches_type_filter(&path, filter)
+		if let Some(changed) = filters.changed
+			&& !changed.contains(&path)
+		{
+			continue;
+		}
+		if let Some(filter) = filters.types
+			&& !filter.matches(&path)
+		{
+			continue;
+		}
+		entries.push(FileEntry { path, relative_path: entry.path.clone(), blob: None });
+	}
+	Ok(entries)
+}
+
+/// Scan a directory (optionally through the shared scan cache) and return the
+/// files that pass `filters`.
+fn scan_entries(
+	root: &Path,
+	include_hidden: bool,
+	use_gitignore: bool,
+	use_cache: bool,
+	filters: EntryFilters<'_>,
+	ct: &task::CancelToken,
+) -> Result<Vec<FileEntry>> {
+	if !use_cache {
+		let fresh = fs_cache::force_rescan(root, include_hidden, use_gitignore, false, ct)?;
+		return collect_files(root, &fresh, filters, ct);
+	}
+	let scan = fs_cache::get_or_scan(root, include_hidden, use_gitignore, ct)?;
+	let mut entries = collect_files(root, &scan.entries, filters, ct)?;
+	if entries.is_empty() && scan.cache_age_ms >= fs_cache::empty_recheck_ms() {
+		let fresh = fs_cache::force_rescan(root, include_hidden, use_gitignore, true, ct)?;
+		entries = collect_files(root, &fresh, filters, ct)?;
+	}
+	Ok(entries)
+}
+
+/// Files below `search_path` at git revision `rev` that pass `filters`.
+/// Their blobs are read through a pool of `git cat-file --batch` processes as
+/// each file is searched. Like a working-tree scan, VCS and `node_modules`
+/// directories are skipped, and so are hidden paths unless `include_hidden`
+/// is set.
+fn revision_entries(
+	search_path: &Path,
+	rev: &str,
+	include_hidden: bool,
+	filters: EntryFilters<'_>,
+	ct: &task::CancelToken,
+) -> Result<Vec<FileEntry>> {
+	let tree = git::tree_under(search_path, rev)?;
+	let mentions_node_modules = filters
+		.glob
+		.is_some_and(|filter| filter.includes_text("node_modules"));
+	let pool = tree.blob_pool();
+	let mut entries = Vec::new();
+	for item in &tree.entries {
+		ct.heartbeat()?;
+		// Like a working-tree search, a file search path reports its own path
+		// and ignores the glob filter.
+		let relative_path = if item.relative.is_empty() {
+			search_path.to_string_lossy().into_owned()
+		} else {
+			if fs_cache::should_skip_path(Path::new(&item.relative), mentions_node_modules) {
+				continue;
+			}
+			if !include_hidden && item.relative.split('/').any(|part| part.starts_with('.')) {
+				continue;
+			}
+			if let Some(glob_filter) = filters.glob
+				&& !glob_filter.is_match(&item.relative)
+			{
+				continue;
+			}
+			item.relative.clone()
+		};
+		if let Some(changed) = filters.changed
+			&& !changed.contains(&item.path)
+		{
+			continue;
+		}
+		if let Some(filter) = filters.types
+			&& !filter.matches(&item.path)
 		{
 			continue;
 		}
-		entries.push(FileEntry { path, relative_path: entry.path.clone() });
+		entries.push(FileEntry {
+			path: item.path.clone(),
+			relative_path,
+			blob: Some(item.blob(&pool)),
+		});
 	}
-	entries
+	Ok(entries)
 }
 
 /// Check if `bytes[start]` (which must be `b'{'`) begins a valid repetition
 /// quantifier: `{N}`, `{N,}`, or `{N,M}` where N and M are decimal digits.
 /// Returns the byte index of the closing `}` if valid.
-fn find_valid_repetition(bytes: &[u8], start: usize) -> Option<usize> {
+const fn find_valid_repetition(bytes: &[u8], start: usize) -> Option<usize> {
 	let len = bytes.len();
 	let mut i = start + 1;
 	// Must start with at least one digit.
@@ -663,160 +2151,1499 @@ fn find_valid_repetition(bytes: &[u8], start: usize) -> Option<usize> {
 	None
 }
 
-fn find_braced_escape_end(bytes: &[u8], start: usize) -> Option<usize> {
-	let mut i = start + 1;
-	while i < bytes.len() {
-		if bytes[i] == b'}' {
-			return Some(i);
-		}
-		i += 1;
+const fn find_braced_escape_end(bytes: &[u8], start: usize) -> Option<usize> {
+	let mut i = start + 1;
+	while i < bytes.len() {
+		if bytes[i] == b'}' {
+			return Some(i);
+		}
+		i += 1;
+	}
+	None
+}
+
+/// Escape `{` and `}` that don't form valid repetition quantifiers.
+///
+/// Patterns like `${platform}` or `a{b}` contain braces the regex engine
+/// rejects as malformed repetitions.  Since such braces can never be valid
+/// regex syntax, turning them into `\{` / `\}` is semantics-preserving
+/// and avoids confusing error messages for callers who pass literal text
+/// fragments (e.g. JS template strings).
+fn sanitize_braces(pattern: &str) -> Cow<'_, str> {
+	let bytes = pattern.as_bytes();
+	if !bytes.contains(&b'{') && !bytes.contains(&b'}') {
+		return Cow::Borrowed(pattern);
+	}
+
+	let len = bytes.len();
+	let mut result = String::with_capacity(len + 8);
+	let mut modified = false;
+	let mut i = 0;
+
+	while i < len {
+		// Pass escaped characters through unchanged.
+		if bytes[i] == b'\\' && i + 1 < len {
+			result.push('\\');
+			i += 1;
+			let ch = pattern[i..].chars().next().unwrap();
+			result.push(ch);
+			i += ch.len_utf8();
+			if matches!(ch, 'p' | 'P' | 'x' | 'u') && i < len && bytes[i] == b'{' {
+				if let Some(end) = find_braced_escape_end(bytes, i) {
+					result.push_str(&pattern[i..=end]);
+					i = end + 1;
+				} else {
+					result.push_str(&pattern[i..]);
+					i = len;
+				}
+			}
+			continue;
+		}
+
+		if bytes[i] == b'{' {
+			if let Some(end) = find_valid_repetition(bytes, i) {
+				result.push_str(&pattern[i..=end]);
+				i = end + 1;
+				continue;
+			}
+			result.push_str("\\{");
+			i += 1;
+			modified = true;
+			continue;
+		}
+
+		if bytes[i] == b'}' {
+			result.push_str("\\}");
+			i += 1;
+			modified = true;
+			continue;
+		}
+
+		let ch = pattern[i..].chars().next().unwrap();
+		result.push(ch);
+		i += ch.len_utf8();
+	}
+
+	if modified {
+		Cow::Owned(result)
+	} else {
+		Cow::Borrowed(pattern)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::TempDir;
+
+	impl Default for SearchParams {
+		fn default() -> Self {
+			Self {
+				context_before: 0,
+				context_after:  0,
+				max_columns:    None,
+				mode:           OutputMode::Content,
+				max_count:      None,
+				offset:         0,
+				multiline:      false,
+				binary:         BinaryMode::Skip,
+				syntax:         None,
+			}
+		}
+	}
+
+	#[test]
+	fn preserves_unicode_property_escapes() {
+		assert_eq!(sanitize_braces(r"\p{Greek}").as_ref(), r"\p{Greek}");
+	}
+
+	#[test]
+	fn preserves_hex_brace_escapes() {
+		assert_eq!(sanitize_braces(r"\x{41}").as_ref(), r"\x{41}");
+	}
+
+	#[test]
+	fn preserves_malformed_braced_escapes() {
+		assert_eq!(sanitize_braces(r"\p{Greek").as_ref(), r"\p{Greek");
+	}
+
+	#[test]
+	fn escapes_non_quantifier_braces() {
+		assert_eq!(sanitize_braces("${platform}").as_ref(), "$\\{platform\\}");
+	}
+
+	#[test]
+	fn preserves_valid_quantifiers() {
+		assert_eq!(sanitize_braces("a{2,4}").as_ref(), "a{2,4}");
+	}
+
+	#[test]
+	fn reports_submatch_spans_in_utf16_and_bytes() {
+		let matcher = build_matcher("b+", MatchFlags::default()).unwrap();
+		let line = "é bb b\n".as_bytes();
+		let spans = collect_submatches(&matcher, line, 7, line.len() - 1, |_| true);
+		assert_eq!(spans.len(), 2);
+		assert_eq!((spans[0].byte_start, spans[0].byte_end), (3, 5));
+		assert_eq!((spans[0].start, spans[0].end), (2, 4));
+		assert_eq!((spans[0].line_number, spans[0].column, spans[0].end_column), (7, 3, 5));
+		assert_eq!(spans[1].text, "b");
+	}
+
+	#[test]
+	fn reports_named_groups() {
+		let matcher = build_matcher(r"(?P<key>\w+)=(?<value>\d+)", MatchFlags::default()).unwrap();
+		let spans = collect_submatches(&matcher, b"a=1 b=x", 1, 7, |_| true);
+		assert_eq!(spans.len(), 1);
+		let groups = spans[0].groups.as_ref().unwrap();
+		assert_eq!(groups.get("key").map(String::as_str), Some("a"));
+		assert_eq!(groups.get("value").map(String::as_str), Some("1"));
+	}
+
+	#[test]
+	fn tracks_lines_across_multiline_matches() {
+		let matcher =
+			build_matcher(r"foo\nba", MatchFlags { multiline: true, ..Default::default() }).unwrap();
+		let block = b"x foo\nbar\n";
+		let spans = collect_submatches(&matcher, block, 10, block.len() - 1, |_| true);
+		assert_eq!(spans.len(), 1);
+		assert_eq!((spans[0].line_number, spans[0].column), (10, 3));
+		assert_eq!((spans[0].end_line_number, spans[0].end_column), (11, 3));
+	}
+
+	#[test]
+	fn replaces_with_capture_templates() {
+		let matcher = build_replace_matcher(r"(\w+)\((?<arg>\d)\)", MatchFlags::default()).unwrap();
+		let content = b"a\nfoo(1) + bar(2)\nb\n";
+		let replacements = find_replacements(&matcher, content, b"${arg}_$1").unwrap();
+		let (output, changes) = apply_replacements(content, &replacements);
+		assert_eq!(output, b"a\n1_foo + 2_bar\nb\n");
+		assert_eq!(changes.len(), 1);
+		assert_eq!(changes[0].line_number, 2);
+		assert_eq!(changes[0].before, "foo(1) + bar(2)");
+		assert_eq!(changes[0].after, "1_foo + 2_bar");
+		assert_eq!(changes[0].replacements, 2);
+	}
+
+	#[test]
+	fn replace_honors_grep_match_flags() {
+		let content = b"a.b(x) axb(x) a.bc(x)\n";
+		let literal = MatchFlags { fixed_strings: true, ..MatchFlags::default() };
+		let matcher = build_replace_matcher("a.b(", literal).unwrap();
+		let (output, _) =
+			apply_replacements(content, &find_replacements(&matcher, content, b"$$(").unwrap());
+		assert_eq!(output, b"$(x) axb(x) a.bc(x)\n");
+
+		let word = MatchFlags { word: true, smart_case: true, ..MatchFlags::default() };
+		let matcher = build_replace_matcher("a.b", word).unwrap();
+		let replacements = find_replacements(&matcher, b"A.B a.bc axb\n", b"z").unwrap();
+		let spans: Vec<_> = replacements
+			.iter()
+			.map(|hit| (hit.start, hit.end))
+			.collect();
+		assert_eq!(spans, [(0, 3), (9, 12)]);
+	}
+
+	#[test]
+	fn replace_does_not_cross_lines_without_multiline() {
+		let matcher = build_replace_matcher(r"a\s+b", MatchFlags::default()).unwrap();
+		let replacements = find_replacements(&matcher, b"a\nb a b\n", b"x").unwrap();
+		assert_eq!(replacements.len(), 1);
+		assert_eq!(replacements[0].start, 4);
+	}
+
+	#[test]
+	fn multiline_replacement_groups_spanned_lines() {
+		let matcher = build_replace_matcher(r"one\ntwo", MatchFlags {
+			multiline: true,
+			..MatchFlags::default()
+		})
+		.unwrap();
+		let content = b"zero\none\ntwo\nthree\n";
+		let replacements = find_replacements(&matcher, content, b"1-2").unwrap();
+		let (output, changes) = apply_replacements(content, &replacements);
+		assert_eq!(output, b"zero\n1-2\nthree\n");
+		assert_eq!(changes[0].line_number, 2);
+		assert_eq!(changes[0].before, "one\ntwo");
+		assert_eq!(changes[0].after, "1-2");
+	}
+
+	#[test]
+	fn reports_files_replace_leaves_alone() {
+		let dir = TempDir::new("reports-files-replace-leaves-alone");
+		let matcher = build_replace_matcher("old", MatchFlags::default()).unwrap();
+		let skip_reason = |entry: &FileEntry| {
+			let FileReplaceOutcome::Skipped(skip) = plan_replace(entry, &matcher, b"new") else {
+				panic!("{} should be skipped", entry.relative_path);
+			};
+			skip.reason
+		};
+		assert_eq!(skip_reason(&temp_entry(&dir, "replace.bin", b"old\0")), "binary");
+		assert_eq!(skip_reason(&temp_entry(&dir, "replace.rc", b"\xff\xfeo\0l\0d\0")), "encoding");
+		let large = vec![b'x'; MAX_REPLACE_FILE_BYTES as usize + 1];
+		assert_eq!(skip_reason(&temp_entry(&dir, "replace-large.txt", &large)), "tooLarge");
+	}
+
+	#[test]
+	fn writes_only_files_unchanged_since_planned() {
+		let dir = TempDir::new("writes-only-files-unchanged-since-planned");
+		let matcher = build_replace_matcher("old", MatchFlags::default()).unwrap();
+		let entry = temp_entry(&dir, "replace.txt", b"old\n");
+		let FileReplaceOutcome::Changed(planned) = plan_replace(&entry, &matcher, b"new") else {
+			panic!("replacement should be planned");
+		};
+		std::fs::write(&entry.path, b"old\nedited\n").unwrap();
+		assert!(write_planned(&planned).is_err());
+		assert_eq!(std::fs::read(&entry.path).unwrap(), b"old\nedited\n");
+
+		std::fs::write(&entry.path, b"old\n").unwrap();
+		write_planned(&planned).unwrap();
+		assert_eq!(std::fs::read(&entry.path).unwrap(), b"new\n");
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn rewrites_the_target_of_a_symlink() {
+		let dir = TempDir::new("rewrites-the-target-of-a-symlink");
+		let target = temp_entry(&dir, "replace-target.txt", b"old\n");
+		let link = target.path.with_extension("link");
+		std::os::unix::fs::symlink(&target.path, &link).unwrap();
+		write_atomic(&link, b"new\n").unwrap();
+		assert!(std::fs::symlink_metadata(&link).unwrap().is_symlink());
+		assert_eq!(std::fs::read(&target.path).unwrap(), b"new\n");
+	}
+
+	#[test]
+	fn file_query_combines_pattern_hits() {
+		let query =
+			FileQuery { all: vec![0], none: vec![2], near: vec![(0, 1, 2)], ..Default::default() };
+		assert!(query.accepts(&[vec![1, 10], vec![12], vec![]]));
+		assert!(!query.accepts(&[vec![1], vec![12], vec![]]));
+		assert!(!query.accepts(&[vec![10], vec![12], vec![3]]));
+		assert!(!query.accepts(&[vec![], vec![12], vec![]]));
+	}
+
+	#[test]
+	fn rejects_unknown_query_ids() {
+		let patterns = vec![GrepPattern { id: "a".into(), pattern: "x".into() }];
+		let query =
+			GrepFileQuery { all: Some(vec!["b".into()]), any: None, none: None, near: None };
+		assert!(build_pattern_set(patterns, Some(query), MatchFlags::default()).is_err());
+	}
+
+	fn hunk_match(path: &str, line_number: u32, before: &[u32], after: &[u32]) -> GrepMatch {
+		let context = |lines: &[u32]| {
+			Some(
+				lines
+					.iter()
+					.map(|&line_number| ContextLine { line_number, line: format!("c{line_number}") })
+					.collect(),
+			)
+		};
+		GrepMatch {
+			path: path.to_string(),
+			line_number,
+			line: format!("m{line_number}"),
+			context_before: context(before),
+			context_after: context(after),
+			truncated: None,
+			submatches: None,
+			pattern_id: None,
+			match_count: None,
+			encoding: None,
+			binary: None,
+			json: None,
+		}
+	}
+
+	#[test]
+	fn merges_adjacent_context_into_hunks() {
+		let hunks = build_hunks(vec![
+			hunk_match("a", 3, &[2], &[4]),
+			hunk_match("a", 5, &[4], &[6]),
+			hunk_match("a", 8, &[7], &[9]),
+			hunk_match("a", 20, &[19], &[]),
+			hunk_match("b", 1, &[], &[2]),
+		]);
+		let shape: Vec<_> = hunks
+			.iter()
+			.map(|hunk| (hunk.path.as_str(), hunk.start_line, hunk.end_line))
+			.collect();
+		assert_eq!(shape, [("a", 2, 9), ("a", 19, 20), ("b", 1, 2)]);
+		let lines: Vec<_> = hunks[0]
+			.lines
+			.iter()
+			.map(|line| (line.line_number, line.kind.as_str()))
+			.collect();
+		assert_eq!(lines, [
+			(2, "context"),
+			(3, "match"),
+			(4, "context"),
+			(5, "match"),
+			(6, "context"),
+			(7, "context"),
+			(8, "match"),
+			(9, "context"),
+		]);
+	}
+
+	#[test]
+	fn maps_submatches_onto_lossy_and_trimmed_lines() {
+		let matcher = build_matcher("foo.*", MatchFlags::default()).unwrap();
+		// Each invalid byte is reported as a 3-byte U+FFFD.
+		let line = b"\xff\xfe foo bar\r\n";
+		let reported = bytes_to_trimmed_string(line);
+		let spans = collect_submatches(&matcher, line, 1, reported.len(), |_| true);
+		assert_eq!(spans.len(), 1);
+		let (start, end) = (spans[0].byte_start as usize, spans[0].byte_end as usize);
+		assert_eq!(&reported[start..end], "foo bar");
+		assert_eq!(spans[0].text, "foo bar");
+		assert_eq!((spans[0].start, spans[0].end), (3, 10));
+	}
+
+	#[test]
+	fn drops_submatches_past_truncation() {
+		let matcher = build_matcher("z", MatchFlags::default()).unwrap();
+		let spans = collect_submatches(&matcher, b"aaaz", 1, 2, |_| true);
+		assert!(spans.is_empty());
+	}
+
+	fn temp_entry(dir: &TempDir, name: &str, content: &[u8]) -> FileEntry {
+		FileEntry {
+			path:          dir.write(name, content),
+			relative_path: name.to_string(),
+			blob:          None,
+		}
+	}
+
+	#[test]
+	fn attributes_pattern_set_hits_per_pattern() {
+		let dir = TempDir::new("attributes-pattern-set-hits-per-pattern");
+		let patterns = vec![GrepPattern { id: "foo".into(), pattern: "foo".into() }, GrepPattern {
+			id:      "bar".into(),
+			pattern: "Bar".into(),
+		}];
+		let flags = MatchFlags { smart_case: true, ..MatchFlags::default() };
+		let Ok(set) = build_pattern_set(patterns, None, flags) else {
+			panic!("patterns should compile");
+		};
+		let mut params = SearchParams::default();
+		let entry = temp_entry(&dir, "set.txt", b"intro\nfoo bar\nFOO Bar\nbar\n");
+		let hits = |params| {
+			let Ok(result) = search_file_multi(&entry, &set, params, None) else {
+				panic!("file should be searched");
+			};
+			result
+				.matches
+				.iter()
+				.map(|matched| (matched.line_number, matched.pattern))
+				.collect::<Vec<_>>()
+		};
+
+		// Smart case is settled per pattern: `foo` ignores case, `Bar` does not,
+		// and the lowercase `bar` lines only hit through the union.
+		let expected = vec![(2, Some(0)), (3, Some(0)), (3, Some(1))];
+		assert_eq!(hits(params), expected);
+		params.multiline = true;
+		assert_eq!(hits(params), expected);
+	}
+
+	#[test]
+	fn searches_large_files_and_reports_skips() {
+		let dir = TempDir::new("searches-large-files-and-reports-skips");
+		let matcher = build_matcher("needle", MatchFlags::default()).unwrap();
+		let params = SearchParams::default();
+
+		let mut big = vec![b'x'; (MMAP_THRESHOLD_BYTES + 1) as usize];
+		big.extend_from_slice(b"\nneedle\n");
+		let large = temp_entry(&dir, "large.txt", &big);
+		let Ok(search) = search_entry(&large, &matcher, params, None) else {
+			panic!("large file should be searched");
+		};
+		assert_eq!(search.match_count, 1);
+		let skip = search_entry(&large, &matcher, params, Some(1024))
+			.err()
+			.unwrap();
+		assert_eq!(skip.reason, "tooLarge");
+
+		let binary = temp_entry(&dir, "binary.bin", b"\0\x01\x02needle");
+		let skip = search_entry(&binary, &matcher, params, None).err().unwrap();
+		assert_eq!(skip.reason, "binary");
+		// Hits in the buffers searched before the first NUL are still reported.
+		let mut late_nul = b"needle\n".to_vec();
+		late_nul.extend(b"filler\n".repeat(32 * 1024));
+		late_nul.extend(b"\0needle\n");
+		std::fs::write(&binary.path, &late_nul).unwrap();
+		let Ok(search) = search_entry(&binary, &matcher, params, None) else {
+			panic!("hits before the NUL should be kept");
+		};
+		assert_eq!(search.matches.len(), 1);
+
+		let missing = FileEntry {
+			path:          binary.path.with_extension("gone"),
+			relative_path: "gone".into(),
+			blob:          None,
+		};
+		let skip = search_entry(&missing, &matcher, params, None)
+			.err()
+			.unwrap();
+		assert_eq!(skip.reason, "ioError");
+	}
+
+	#[test]
+	fn filters_hits_by_syntax_class() {
+		let dir = TempDir::new("filters-hits-by-syntax-class");
+		let matcher = build_matcher("user", MatchFlags::default()).unwrap();
+		let params = |spec: &str| SearchParams {
+			offset: 1,
+			syntax: SyntaxFilter::parse(Some(&Either::A(spec.to_string()))).unwrap(),
+			..SearchParams::default()
+		};
+		let source = b"// user one\nfn user() {}\n/* user two */ let x = \"user\"; user();\n";
+		let entry = temp_entry(&dir, "syntax.rs", source);
+
+		let Ok(comments) = search_entry(&entry, &matcher, params("comment"), None) else {
+			panic!("source should be searched");
+		};
+		assert_eq!(comments.match_count, 2);
+		assert_eq!(comments.matches.len(), 1);
+		assert_eq!(comments.matches[0].line_number, 3);
+		assert_eq!(comments.matches[0].submatches.len(), 1);
+
+		let Ok(code) = search_entry(&entry, &matcher, params("code"), None) else {
+			panic!("source should be searched");
+		};
+		assert_eq!(code.match_count, 2);
+		assert_eq!(code.matches[0].line_number, 3);
+		assert_eq!(code.matches[0].submatches[0].byte_start, 31);
+
+		let text = temp_entry(&dir, "syntax.txt", b"user\n");
+		let Ok(search) = search_entry(&text, &matcher, params("code"), None) else {
+			panic!("source should be searched");
+		};
+		assert_eq!(search.match_count, 0);
+
+		// The grammar only runs for files the regex hits.
+		let Ok((mut opened, len, encoding)) = open_entry(&entry, None, params("code")) else {
+			panic!("source should open");
+		};
+		let missing = build_matcher("nowhere", MatchFlags::default()).unwrap();
+		let Ok(search) = opened.search(&missing, len, encoding, params("code")) else {
+			panic!("source should be searched");
+		};
+		assert_eq!(search.match_count, 0);
+		let OpenEntry::Loaded { syntax, .. } = &opened else {
+			panic!("syntax searches load the whole file");
+		};
+		assert!(syntax.get().is_none());
+		let Ok(search) = opened.search(&matcher, len, encoding, params("code")) else {
+			panic!("source should be searched");
+		};
+		assert_eq!(search.match_count, 2);
+		let OpenEntry::Loaded { syntax, .. } = &opened else {
+			panic!("syntax searches load the whole file");
+		};
+		assert!(syntax.get().is_some_and(Option::is_some));
+	}
+
+	#[test]
+	fn json_output_follows_rg_schema() {
+		let dir = TempDir::new("json-output-follows-rg-schema");
+		let matcher = build_matcher("b+", MatchFlags::default()).unwrap();
+		let params = SearchParams {
+			context_before: 1,
+			context_after: 1,
+			mode: OutputMode::Json,
+			..SearchParams::default()
+		};
+		let entries =
+			[temp_entry(&dir, "json.txt", b"a\nbb\nc\n"), temp_entry(&dir, "none.txt", b"a\n")];
+		let mut json = JsonLines::new(None);
+		let (matches, ..) =
+			run_sequential_search(&entries, &matcher, params, None, &mut Vec::new(), Some(&mut json));
+		assert_eq!(matches.len(), 1);
+
+		let records: Vec<Value> = json
+			.finish()
+			.lines()
+			.map(|line| serde_json::from_str(line).unwrap())
+			.collect();
+		let kinds: Vec<_> = records
+			.iter()
+			.map(|record| record["type"].as_str().unwrap())
+			.collect();
+		assert_eq!(kinds, ["begin", "context", "match", "context", "end", "summary"]);
+		let matched = &records[2]["data"];
+		assert_eq!(matched["path"]["text"], "json.txt");
+		assert_eq!(matched["lines"]["text"], "bb\n");
+		assert_eq!(matched["line_number"], 2);
+		assert_eq!(matched["absolute_offset"], 2);
+		assert_eq!(
+			matched["submatches"],
+			json!([{ "match": { "text": "bb" }, "start": 0, "end": 2 }])
+		);
+		assert_eq!(records[3]["data"]["absolute_offset"], 5);
+		let stats = &records[5]["data"]["stats"];
+		assert_eq!(stats["searches"], 2);
+		assert_eq!(stats["searches_with_match"], 1);
+		assert_eq!(stats["matches"], 1);
+
+		// Lines are reported as searched: `\r` and trailing blanks kept, no
+		// newline added, and base64 bytes for invalid UTF-8 past the sniffed
+		// prefix. The parallel path renders the same records.
+		let mut content = b"a\n".repeat(ENCODING_SNIFF_BYTES as usize);
+		content.extend(b"x\xff bb  \r\nc");
+		let raw = [temp_entry(&dir, "raw.txt", &content)];
+		let mut json = JsonLines::new(None);
+		let (results, _) = run_parallel_search(&raw, &matcher, params, None, Some(&json));
+		for result in results {
+			json.append(result.records.expect("json mode renders records"));
+		}
+		let records: Vec<Value> = json
+			.finish()
+			.lines()
+			.map(|line| serde_json::from_str(line).unwrap())
+			.collect();
+		let matched = &records[2]["data"];
+		assert_eq!(matched["lines"], json!({ "bytes": "eP8gYmIgIA0K" }));
+		assert_eq!(
+			matched["submatches"],
+			json!([{ "match": { "text": "bb" }, "start": 3, "end": 5 }])
+		);
+		assert_eq!(records[3]["data"]["lines"], json!({ "text": "c" }));
+	}
+
+	fn utf16le(text: &str) -> Vec<u8> {
+		text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+	}
+
+	#[test]
+	fn detects_text_encodings() {
+		assert_eq!(TextEncoding::detect(b"plain ascii\n"), TextEncoding::Utf8);
+		assert_eq!(TextEncoding::detect("caf\u{e9}".as_bytes()), TextEncoding::Utf8);
+		assert_eq!(TextEncoding::detect(b"caf\xe9 au lait"), TextEncoding::Latin1);
+		assert_eq!(
+			TextEncoding::detect("caf\u{e9} na\u{ef}ve \u{2014} r\u{e9}sum\u{e9}\n".as_bytes()),
+			TextEncoding::Utf8
+		);
+		let mut stray = "caf\u{e9} na\u{ef}ve\n".as_bytes().to_vec();
+		stray.extend_from_slice(b"stray \x80 byte\n");
+		assert_eq!(TextEncoding::detect(&stray), TextEncoding::Utf8);
+		assert_eq!(TextEncoding::detect(b"\xff\xfeh\0i\0"), TextEncoding::Utf16Le);
+		assert_eq!(TextEncoding::detect(&utf16le("IDS_TITLE \"Hello\"")), TextEncoding::Utf16Le);
+		assert_eq!(TextEncoding::detect(b"\0h\0i\0!"), TextEncoding::Utf16Be);
+		assert_eq!(TextEncoding::detect(b"ELF\0\0\x01\x02"), TextEncoding::Utf8);
+	}
+
+	#[test]
+	fn transcodes_before_matching() {
+		let matcher = build_matcher("Hello", MatchFlags::default()).unwrap();
+		let params = SearchParams::default();
+		let search = run_search(&matcher, &utf16le("x\nsay Hello\n"), params).unwrap();
+		assert_eq!(search.encoding, TextEncoding::Utf16Le);
+		assert!(search.binary_offset.is_none());
+		assert_eq!(search.matches[0].line, "say Hello");
+
+		let matcher = build_matcher("caf.", MatchFlags::default()).unwrap();
+		let search = run_search(&matcher, b"un caf\xe9\n", params).unwrap();
+		assert_eq!(search.matches[0].line, "un caf\u{e9}");
+	}
+
+	#[test]
+	fn binary_modes_follow_ripgrep() {
+		let matcher = build_matcher("needle", MatchFlags::default()).unwrap();
+		let content = b"needle\n\0\x01\nneedle\n";
+		let params = |binary| SearchParams { binary, ..SearchParams::default() };
+
+		let skip = run_search(&matcher, content, params(BinaryMode::Skip)).unwrap();
+		assert!(apply_binary_mode(skip, BinaryMode::Skip).is_err());
+		// The search stops at the first buffer holding a NUL; hits in earlier
+		// buffers are kept.
+		let mut late_nul = b"needle\n".to_vec();
+		late_nul.extend(b"filler\n".repeat(32 * 1024));
+		late_nul.extend(b"\0needle\n");
+		let skip = run_search(&matcher, &late_nul, params(BinaryMode::Skip)).unwrap();
+		assert!(skip.binary_offset.is_some());
+		let Ok(skip) = apply_binary_mode(skip, BinaryMode::Skip) else {
+			panic!("skip mode should keep hits before the NUL");
+		};
+		assert_eq!(skip.matches.len(), 1);
+
+		let summary = run_search(&matcher, content, params(BinaryMode::Summary)).unwrap();
+		assert_eq!(summary.match_count, 2);
+		let Ok(summary) = apply_binary_mode(summary, BinaryMode::Summary) else {
+			panic!("summary mode should keep the file");
+		};
+		assert_eq!(summary.matches.len(), 1);
+		assert!(summary.matches[0].binary);
+
+		let text = run_search(&matcher, content, params(BinaryMode::Text)).unwrap();
+		assert!(text.binary_offset.is_none());
+		assert_eq!(text.matches.len(), 2);
+	}
+
+	#[test]
+	fn fixed_strings_match_metacharacters_literally() {
+		let flags = MatchFlags { fixed_strings: true, ..Default::default() };
+		let matcher = build_matcher("foo(a[0]", flags).unwrap();
+		assert!(matcher.regex.is_match(b"x = foo(a[0], 1)").unwrap());
+		assert!(!matcher.regex.is_match(b"x = fooa0").unwrap());
+	}
+
+	#[test]
+	fn word_regexp_requires_boundaries() {
+		let flags = MatchFlags { word: true, ..Default::default() };
+		let matcher = build_matcher("log", flags).unwrap();
+		assert!(matcher.regex.is_match(b"call log(x)").unwrap());
+		assert!(!matcher.regex.is_match(b"catalog").unwrap());
+	}
+
+	#[test]
+	fn smart_case_depends_on_uppercase() {
+		let flags = MatchFlags { smart_case: true, ..Default::default() };
+		assert!(
+			build_matcher("foo", flags)
+				.unwrap()
+				.regex
+				.is_match(b"FOO")
+				.unwrap()
+		);
+		assert!(
+			!build_matcher("Foo", flags)
+				.unwrap()
+				.regex
+				.is_match(b"FOO")
+				.unwrap()
+		);
+		let forced = MatchFlags { ignore_case: true, ..flags };
+		assert!(
+			build_matcher("Foo", forced)
+				.unwrap()
+				.regex
+				.is_match(b"FOO")
+				.unwrap()
+		);
+	}
+
+	#[test]
+	fn reads_revision_blobs_lazily_and_skips_hidden_paths() {
+		let dir = TempDir::new("grep-rev");
+		let repo = &dir.root;
+		dir.write("src/a.txt", "needle\n");
+		dir.write("src/big.txt", "needle needle\n");
+		dir.write(".config/b.txt", "needle\n");
+		dir.write("node_modules/dep/c.txt", "needle\n");
+		let git = |args: &[&str]| {
+			let status = std::process::Command::new("git")
+				.arg("-C")
+				.arg(repo)
+				.args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+				.args(["-c", "commit.gpgsign=false"])
+				.args(args)
+				.stdout(std::process::Stdio::null())
+				.stderr(std::process::Stdio::null())
+				.status()
+				.expect("git should run");
+			assert!(status.success(), "git {args:?} failed");
+		};
+		git(&["init", "-q"]);
+		git(&["add", "-f", "."]);
+		git(&["commit", "-q", "-m", "init"]);
+
+		let filters = EntryFilters { glob: None, types: None, index: None, changed: None };
+		let ct = task::CancelToken::default();
+		let relative = |entries: &[FileEntry]| {
+			entries
+				.iter()
+				.map(|entry| entry.relative_path.clone())
+				.collect::<Vec<_>>()
+		};
+		let Ok(visible) = revision_entries(repo, "HEAD", false, filters, &ct) else {
+			panic!("HEAD should list");
+		};
+		assert_eq!(relative(&visible), ["src/a.txt", "src/big.txt"]);
+		let Ok(hidden) = revision_entries(repo, "HEAD", true, filters, &ct) else {
+			panic!("HEAD should list");
+		};
+		assert_eq!(relative(&hidden), [".config/b.txt", "src/a.txt", "src/big.txt"]);
+
+		let params = SearchParams::default();
+		let Ok((OpenEntry::Blob(data), len, _)) = open_entry(&visible[0], Some(10), params) else {
+			panic!("small blob should be read");
+		};
+		assert_eq!((data.as_ref(), len), (&b"needle\n"[..], 7));
+		let Err(skipped) = open_entry(&visible[1], Some(10), params) else {
+			panic!("large blob should be skipped");
+		};
+		assert_eq!(skipped.reason, "tooLarge");
+	}
+}
+
+/// Pattern interpretation flags shared by `search`, `grep` and `hasMatch`.
+///
+/// Semantics follow ripgrep: `ignore_case` wins over `smart_case`, and
+/// `word` wraps the pattern so it only matches between word boundaries.
+#[derive(Clone, Copy, Default)]
+struct MatchFlags {
+	ignore_case:   bool,
+	smart_case:    bool,
+	multiline:     bool,
+	fixed_strings: bool,
+	word:          bool,
+}
+
+impl MatchFlags {
+	fn resolve(
+		ignore_case: Option<bool>,
+		smart_case: Option<bool>,
+		multiline: Option<bool>,
+		fixed_strings: Option<bool>,
+		word_regexp: Option<bool>,
+	) -> Self {
+		Self {
+			ignore_case:   ignore_case.unwrap_or(false),
+			smart_case:    smart_case.unwrap_or(false),
+			multiline:     multiline.unwrap_or(false),
+			fixed_strings: fixed_strings.unwrap_or(false),
+			word:          word_regexp.unwrap_or(false),
+		}
+	}
+
+	/// Trigram query any file matching `pattern` must satisfy.
+	fn index_query(self, pattern: &str) -> grep_index::Query {
+		let source = if self.fixed_strings {
+			Cow::Borrowed(pattern)
+		} else {
+			sanitize_braces(pattern)
+		};
+		grep_index::Query::for_pattern(
+			&source,
+			self.fixed_strings,
+			self.ignore_case || self.smart_case,
+		)
+	}
+}
+
+fn build_matcher(pattern: &str, flags: MatchFlags) -> Result<CompiledMatcher> {
+	compile_matcher(pattern, flags, None)
+}
+
+/// [`build_matcher`] with an optional line terminator that matches may not
+/// cross.
+fn compile_matcher(
+	pattern: &str,
+	flags: MatchFlags,
+	line_terminator: Option<u8>,
+) -> Result<CompiledMatcher> {
+	// Literal patterns are escaped by the builder; brace repair only applies to
+	// regexes.
+	let source = if flags.fixed_strings {
+		Cow::Borrowed(pattern)
+	} else {
+		sanitize_braces(pattern)
+	};
+	let regex = RegexMatcherBuilder::new()
+		.case_insensitive(flags.ignore_case)
+		.case_smart(flags.smart_case)
+		.multi_line(flags.multiline)
+		.fixed_strings(flags.fixed_strings)
+		.word(flags.word)
+		.line_terminator(line_terminator)
+		.build(&source)
+		.map_err(|err| Error::from_reason(format!("Regex error: {err}")))?;
+	let groups = if flags.fixed_strings {
+		Vec::new()
+	} else {
+		named_groups(&source, &regex)
+	};
+	Ok(CompiledMatcher { regex, groups, patterns: Vec::new() })
+}
+
+/// Patterns of a multi-pattern search plus the compiled file-level query.
+struct PatternSet {
+	ids:     Vec<String>,
+	/// Union of the patterns, holding each one's matcher in `patterns`.
+	matcher: CompiledMatcher,
+	query:   FileQuery,
+}
+
+/// [`GrepFileQuery`] with ids resolved to pattern indices.
+#[derive(Default)]
+struct FileQuery {
+	all:  Vec<usize>,
+	any:  Vec<usize>,
+	none: Vec<usize>,
+	near: Vec<(usize, usize, u64)>,
+}
+
+impl FileQuery {
+	/// Evaluate the query against the sorted hit line numbers of each pattern.
+	fn accepts(&self, lines: &[Vec<u64>]) -> bool {
+		lines.iter().any(|hits| !hits.is_empty())
+			&& self.all.iter().all(|&index| !lines[index].is_empty())
+			&& (self.any.is_empty() || self.any.iter().any(|&index| !lines[index].is_empty()))
+			&& self.none.iter().all(|&index| lines[index].is_empty())
+			&& self
+				.near
+				.iter()
+				.all(|&(first, second, within)| lines_within(&lines[first], &lines[second], within))
+	}
+}
+
+/// Whether any pair of sorted line numbers from `a` and `b` is at most
+/// `within` lines apart.
+const fn lines_within(a: &[u64], b: &[u64], within: u64) -> bool {
+	let (mut i, mut j) = (0, 0);
+	while i < a.len() && j < b.len() {
+		if a[i].abs_diff(b[j]) <= within {
+			return true;
+		}
+		if a[i] < b[j] {
+			i += 1;
+		} else {
+			j += 1;
+		}
+	}
+	false
+}
+
+fn build_pattern_set(
+	patterns: Vec<GrepPattern>,
+	query: Option<GrepFileQuery>,
+	flags: MatchFlags,
+) -> Result<PatternSet> {
+	if patterns.is_empty() {
+		return Err(Error::from_reason("`patterns` must include at least one pattern"));
+	}
+	let mut ids = Vec::with_capacity(patterns.len());
+	let mut sources = Vec::with_capacity(patterns.len());
+	let mut matchers = Vec::with_capacity(patterns.len());
+	for GrepPattern { id, pattern } in patterns {
+		if ids.contains(&id) {
+			return Err(Error::from_reason(format!("Duplicate pattern id '{id}'")));
+		}
+		let matcher = build_matcher(&pattern, flags)
+			.map_err(|err| Error::from_reason(format!("{id}: {}", err.reason)))?;
+		ids.push(id);
+		sources.push(pattern);
+		matchers.push(matcher);
+	}
+	let matcher = build_union_matcher(&sources, matchers, flags)?;
+
+	let resolve = |id: &str| {
+		ids.iter()
+			.position(|known| known == id)
+			.ok_or_else(|| Error::from_reason(format!("Unknown pattern id '{id}' in fileQuery")))
+	};
+	let resolve_all = |list: Option<Vec<String>>| -> Result<Vec<usize>> {
+		list
+			.unwrap_or_default()
+			.iter()
+			.map(|id| resolve(id))
+			.collect()
+	};
+	let query = match query {
+		Some(GrepFileQuery { all, any, none, near }) => FileQuery {
+			all:  resolve_all(all)?,
+			any:  resolve_all(any)?,
+			none: resolve_all(none)?,
+			near: near
+				.unwrap_or_default()
+				.iter()
+				.map(|near| Ok((resolve(&near.first)?, resolve(&near.second)?, near.lines as u64)))
+				.collect::<Result<_>>()?,
+		},
+		None => FileQuery::default(),
+	};
+
+	Ok(PatternSet { ids, matcher, query })
+}
+
+/// Compile the union of a pattern set so each file is searched once for all
+/// of them. Smart case is decided per pattern, so with it the union ignores
+/// case and the matcher of each pattern settles which lines it hits.
+fn build_union_matcher(
+	patterns: &[String],
+	matchers: Vec<CompiledMatcher>,
+	flags: MatchFlags,
+) -> Result<CompiledMatcher> {
+	let sources: Vec<Cow<'_, str>> = patterns
+		.iter()
+		.map(|pattern| {
+			if flags.fixed_strings {
+				Cow::Borrowed(pattern.as_str())
+			} else {
+				sanitize_braces(pattern)
+			}
+		})
+		.collect();
+	let regex = RegexMatcherBuilder::new()
+		.case_insensitive(flags.ignore_case || flags.smart_case)
+		.multi_line(flags.multiline)
+		.fixed_strings(flags.fixed_strings)
+		.word(flags.word)
+		.build_many(&sources)
+		.map_err(|err| Error::from_reason(format!("Regex error: {err}")))?;
+	Ok(CompiledMatcher { regex, groups: Vec::new(), patterns: matchers })
+}
+
+struct MultiFileResult {
+	relative_path: String,
+	matches:       Vec<CollectedMatch>,
+	encoding:      TextEncoding,
+	stats:         SearchStats,
+}
+
+/// Search one file for every pattern of `set` and keep the hits only when
+/// the file satisfies the query.
+fn search_file_multi(
+	entry: &FileEntry,
+	set: &PatternSet,
+	params: SearchParams,
+	max_file_bytes: Option<u64>,
+) -> std::result::Result<MultiFileResult, GrepSkippedFile> {
+	let (mut opened, len, encoding) = open_entry(entry, max_file_bytes, params)?;
+
+	// Count mode still needs line numbers to evaluate `near` clauses.
+	let params = SearchParams { mode: OutputMode::Content, max_count: None, offset: 0, ..params };
+	let search = |opened: &mut OpenEntry<'_>, matcher: &CompiledMatcher| {
+		opened
+			.search(matcher, len, encoding, params)
+			.map_err(|err| io_skip(entry, &err))
+			.and_then(|search| check_binary(entry, search, params.binary))
+	};
+	let patterns = &set.matcher.patterns;
+	let mut stats = SearchStats { bytes_searched: len, ..SearchStats::default() };
+	let mut matches = if params.multiline {
+		// A multiline hit of the union can hide an overlapping hit of another
+		// pattern, so each pattern runs on its own over the content read once.
+		opened = opened.buffer().map_err(|err| io_skip(entry, &err))?;
+		let mut matches = Vec::new();
+		for (index, matcher) in patterns.iter().enumerate() {
+			let result = search(&mut opened, matcher)?;
+			stats.elapsed += result.elapsed;
+			stats.binary_offset = stats.binary_offset.or(result.binary_offset);
+			matches.extend(
+				result
+					.matches
+					.into_iter()
+					.map(|matched| CollectedMatch { pattern: Some(index), ..matched }),
+			);
+		}
+		matches.sort_by_key(|matched| (matched.line_number, matched.pattern));
+		matches
+	} else {
+		let result = search(&mut opened, &set.matcher)?;
+		stats.elapsed = result.elapsed;
+		stats.binary_offset = result.binary_offset;
+		result.matches
+	};
+
+	let mut lines = vec![Vec::new(); patterns.len()];
+	for matched in &matches {
+		if let Some(index) = matched.pattern {
+			lines[index].push(matched.line_number);
+		}
+	}
+	if !set.query.accepts(&lines) {
+		matches.clear();
+	}
+	Ok(MultiFileResult { relative_path: entry.relative_path.clone(), matches, encoding, stats })
+}
+
+/// Multi-pattern search: files are searched in parallel batches taken in
+/// path order, so offset/limit apply over the merged hits in path order and
+/// each batch's hits stream to `on_match` as soon as it is done.
+fn run_multi_search(
+	entries: &[FileEntry],
+	set: &PatternSet,
+	params: SearchParams,
+	max_file_bytes: Option<u64>,
+	mut json: Option<&mut JsonLines<'_>>,
+	on_match: Option<&ThreadsafeFunction<GrepMatch>>,
+	ct: &task::CancelToken,
+) -> Result<GrepResult> {
+	let mut ordered: Vec<&FileEntry> = entries.iter().collect();
+	ordered.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+	let emit = |matches: &mut Vec<GrepMatch>, grep_match: GrepMatch| {
+		if let Some(callback) = on_match {
+			callback.call(Ok(grep_match.clone()), ThreadsafeFunctionCallMode::NonBlocking);
+		}
+		matches.push(grep_match);
+	};
+
+	let mut matches = Vec::new();
+	let mut skipped_files = Vec::new();
+	let mut total_matches = 0u64;
+	let mut files_with_matches = 0u32;
+	let mut files_searched = 0u32;
+	let mut skipped = 0u64;
+	let mut collected = 0u64;
+	let mut limit_reached = false;
+
+	'batches: for batch in ordered.chunks(rayon::current_num_threads() * 4) {
+		let outcomes = batch
+			.par_iter()
+			.map(|entry| {
+				ct.heartbeat()?;
+				Ok(search_file_multi(entry, set, params, max_file_bytes))
+			})
+			.collect::<Result<Vec<_>>>()?;
+		for outcome in outcomes {
+			let result = match outcome {
+				Ok(result) => result,
+				Err(skip) => {
+					skipped_files.push(skip);
+					continue;
+				},
+			};
+			files_searched = files_searched.saturating_add(1);
+			if result.matches.is_empty() {
+				if let Some(json) = json.as_deref_mut() {
+					json.file(&result.relative_path, [], result.stats);
+				}
+				continue;
+			}
+			files_with_matches = files_with_matches.saturating_add(1);
+			total_matches = total_matches.saturating_add(result.matches.len() as u64);
+
+			match params.mode {
+				OutputMode::Content | OutputMode::Json | OutputMode::Hunks => {
+					let mut accepted = Vec::new();
+					for matched in result.matches {
+						if limit_reached {
+							break;
+						}
+						if skipped < params.offset {
+							skipped += 1;
+							continue;
+						}
+						accepted.push(matched);
+						collected += 1;
+						limit_reached = params.max_count.is_some_and(|max| collected >= max);
+					}
+					if let Some(json) = json.as_deref_mut() {
+						json.file(&result.relative_path, &accepted, result.stats);
+					}
+					for matched in accepted {
+						let pattern = matched.pattern;
+						let mut grep_match =
+							to_grep_match(&result.relative_path, matched, result.encoding);
+						grep_match.pattern_id = pattern.map(|index| set.ids[index].clone());
+						emit(&mut matches, grep_match);
+					}
+				},
+				OutputMode::Count => {
+					let grep_match = GrepMatch {
+						path:           result.relative_path,
+						line_number:    0,
+						line:           String::new(),
+						context_before: None,
+						context_after:  None,
+						truncated:      None,
+						submatches:     None,
+						pattern_id:     None,
+						encoding:       None,
+						binary:         None,
+						json:           None,
+						match_count:    Some(crate::utils::clamp_u32(result.matches.len() as u64)),
+					};
+					emit(&mut matches, grep_match);
+				},
+			}
+			if limit_reached {
+				break 'batches;
+			}
+		}
+	}
+
+	Ok(GrepResult {
+		matches,
+		total_matches: crate::utils::clamp_u32(total_matches),
+		files_with_matches,
+		files_searched,
+		limit_reached: if limit_reached { Some(true) } else { None },
+		skipped: sorted_skips(skipped_files),
+		json: None,
+		hunks: None,
+	})
+}
+
+/// Order skipped files by path; `None` when nothing was skipped.
+fn sorted_skips(mut skipped: Vec<GrepSkippedFile>) -> Option<Vec<GrepSkippedFile>> {
+	if skipped.is_empty() {
+		return None;
+	}
+	skipped.sort_by(|a, b| a.path.cmp(&b.path));
+	Some(skipped)
+}
+
+/// Compile a replace matcher. Outside multiline mode the regex is not allowed
+/// to match line terminators, so replacements never join lines implicitly.
+fn build_replace_matcher(pattern: &str, flags: MatchFlags) -> Result<CompiledMatcher> {
+	compile_matcher(pattern, flags, (!flags.multiline).then_some(b'\n'))
+}
+
+/// A single regex hit and its expanded replacement.
+struct Replacement {
+	start: usize,
+	end:   usize,
+	text:  Vec<u8>,
+}
+
+fn find_replacements(
+	matcher: &CompiledMatcher,
+	content: &[u8],
+	template: &[u8],
+) -> io::Result<Vec<Replacement>> {
+	let mut replacements = Vec::new();
+	let mut caps = matcher.regex.new_captures().map_err(io::Error::other)?;
+	matcher
+		.regex
+		.captures_iter(content, &mut caps, |caps| {
+			let Some(range) = caps.get(0) else {
+				return true;
+			};
+			let mut text = Vec::new();
+			caps.interpolate(|name| matcher.regex.capture_index(name), content, template, &mut text);
+			replacements.push(Replacement { start: range.start(), end: range.end(), text });
+			true
+		})
+		.map_err(io::Error::other)?;
+	Ok(replacements)
+}
+
+/// Expand a byte range to the full lines it touches. A range that already
+/// ends right after a newline stays on that line boundary.
+fn expand_to_lines(content: &[u8], start: usize, end: usize) -> (usize, usize) {
+	let line_start = content[..start]
+		.iter()
+		.rposition(|&b| b == b'\n')
+		.map_or(0, |idx| idx + 1);
+	if end > start && content[end - 1] == b'\n' {
+		return (line_start, end);
 	}
-	None
+	let line_end = content[end..]
+		.iter()
+		.position(|&b| b == b'\n')
+		.map_or(content.len(), |idx| end + idx + 1);
+	(line_start, line_end)
 }
 
-/// Escape `{` and `}` that don't form valid repetition quantifiers.
-///
-/// Patterns like `${platform}` or `a{b}` contain braces the regex engine
-/// rejects as malformed repetitions.  Since such braces can never be valid
-/// regex syntax, turning them into `\{` / `\}` is semantics-preserving
-/// and avoids confusing error messages for callers who pass literal text
-/// fragments (e.g. JS template strings).
-fn sanitize_braces(pattern: &str) -> Cow<'_, str> {
-	let bytes = pattern.as_bytes();
-	if !bytes.contains(&b'{') && !bytes.contains(&b'}') {
-		return Cow::Borrowed(pattern);
-	}
-
-	let len = bytes.len();
-	let mut result = String::with_capacity(len + 8);
-	let mut modified = false;
-	let mut i = 0;
+fn trim_line_ending(bytes: &[u8]) -> String {
+	let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+	let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+	String::from_utf8_lossy(bytes).into_owned()
+}
 
-	while i < len {
-		// Pass escaped characters through unchanged.
-		if bytes[i] == b'\\' && i + 1 < len {
-			result.push('\\');
-			i += 1;
-			let ch = pattern[i..].chars().next().unwrap();
-			result.push(ch);
-			i += ch.len_utf8();
-			if matches!(ch, 'p' | 'P' | 'x' | 'u') && i < len && bytes[i] == b'{' {
-				if let Some(end) = find_braced_escape_end(bytes, i) {
-					result.push_str(&pattern[i..=end]);
-					i = end + 1;
-				} else {
-					result.push_str(&pattern[i..]);
-					i = len;
-				}
-			}
-			continue;
-		}
+/// Apply replacements to `content`, returning the rewritten bytes plus the
+/// changed line blocks. Replacements whose lines overlap share one block.
+fn apply_replacements(
+	content: &[u8],
+	replacements: &[Replacement],
+) -> (Vec<u8>, Vec<GrepReplaceChange>) {
+	let mut output = Vec::with_capacity(content.len());
+	let mut changes = Vec::new();
+	let mut cursor = 0usize;
+	let mut index = 0usize;
 
-		if bytes[i] == b'{' {
-			if let Some(end) = find_valid_repetition(bytes, i) {
-				result.push_str(&pattern[i..=end]);
-				i = end + 1;
-				continue;
-			}
-			result.push_str("\\{");
-			i += 1;
-			modified = true;
-			continue;
+	while index < replacements.len() {
+		let (block_start, mut block_end) =
+			expand_to_lines(content, replacements[index].start, replacements[index].end);
+		let mut block_last = index + 1;
+		while block_last < replacements.len() && replacements[block_last].start < block_end {
+			let (_, next_end) =
+				expand_to_lines(content, replacements[block_last].start, replacements[block_last].end);
+			block_end = block_end.max(next_end);
+			block_last += 1;
 		}
 
-		if bytes[i] == b'}' {
-			result.push_str("\\}");
-			i += 1;
-			modified = true;
-			continue;
+		output.extend_from_slice(&content[cursor..block_start]);
+		let after_start = output.len();
+		let mut block_cursor = block_start;
+		for replacement in &replacements[index..block_last] {
+			output.extend_from_slice(&content[block_cursor..replacement.start]);
+			output.extend_from_slice(&replacement.text);
+			block_cursor = replacement.end;
 		}
+		output.extend_from_slice(&content[block_cursor..block_end]);
 
-		let ch = pattern[i..].chars().next().unwrap();
-		result.push(ch);
-		i += ch.len_utf8();
+		let line_number = content[..block_start].split(|&b| b == b'\n').count();
+		changes.push(GrepReplaceChange {
+			line_number:  crate::utils::clamp_u32(line_number as u64),
+			before:       trim_line_ending(&content[block_start..block_end]),
+			after:        trim_line_ending(&output[after_start..]),
+			replacements: crate::utils::clamp_u32((block_last - index) as u64),
+		});
+		cursor = block_end;
+		index = block_last;
 	}
 
-	if modified {
-		Cow::Owned(result)
-	} else {
-		Cow::Borrowed(pattern)
+	output.extend_from_slice(&content[cursor..]);
+	(output, changes)
+}
+
+/// Write `content` to `path` via a sibling temp file and rename, keeping the
+/// original permissions. Symlinks are resolved first, so a link stays in
+/// place and the file it points to is rewritten.
+fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+	let path = &std::fs::canonicalize(path)?;
+	let parent = path.parent().unwrap_or_else(|| Path::new("."));
+	let file_name = path
+		.file_name()
+		.and_then(|name| name.to_str())
+		.unwrap_or("file");
+	let unique = std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map_or(0, |duration| duration.as_nanos());
+	let temp_path =
+		parent.join(format!(".{file_name}.{}.{unique}.pi-replace.tmp", std::process::id()));
+	let result = (|| {
+		std::fs::write(&temp_path, content)?;
+		if let Ok(metadata) = std::fs::metadata(path) {
+			std::fs::set_permissions(&temp_path, metadata.permissions())?;
+		}
+		std::fs::rename(&temp_path, path)
+	})();
+	if result.is_err() {
+		let _ = std::fs::remove_file(&temp_path);
 	}
+	result
 }
 
-#[cfg(test)]
-mod tests {
-	use super::sanitize_braces;
+struct ReplaceConfig {
+	pattern:     String,
+	replacement: String,
+	path:        String,
+	glob:        Option<Either<String, Vec<String>>>,
+	exclude:     Option<Vec<String>>,
+	type_filter: Option<Either<String, Vec<String>>>,
+	flags:       MatchFlags,
+	hidden:      bool,
+	gitignore:   bool,
+	cache:       bool,
+	dry_run:     bool,
+}
 
-	#[test]
-	fn preserves_unicode_property_escapes() {
-		assert_eq!(sanitize_braces(r"\p{Greek}").as_ref(), r"\p{Greek}");
-	}
+/// Replacements computed for one file, written once every file is matched.
+struct PlannedReplace {
+	path:     PathBuf,
+	/// Hash of the content the replacements were computed from.
+	original: u64,
+	output:   Vec<u8>,
+	file:     GrepReplaceFile,
+}
 
-	#[test]
-	fn preserves_hex_brace_escapes() {
-		assert_eq!(sanitize_braces(r"\x{41}").as_ref(), r"\x{41}");
-	}
+enum FileReplaceOutcome {
+	Unchanged,
+	Changed(PlannedReplace),
+	Skipped(GrepSkippedFile),
+	Failed(String),
+}
 
-	#[test]
-	fn preserves_malformed_braced_escapes() {
-		assert_eq!(sanitize_braces(r"\p{Greek").as_ref(), r"\p{Greek");
-	}
+fn content_hash(content: &[u8]) -> u64 {
+	let mut hasher = std::hash::DefaultHasher::new();
+	content.hash(&mut hasher);
+	hasher.finish()
+}
 
-	#[test]
-	fn escapes_non_quantifier_braces() {
-		assert_eq!(sanitize_braces("${platform}").as_ref(), "$\\{platform\\}");
+/// Compute the replacements for one file without writing it. Files too large,
+/// binary or UTF-16 encoded are reported as skipped.
+fn plan_replace(
+	entry: &FileEntry,
+	matcher: &CompiledMatcher,
+	template: &[u8],
+) -> FileReplaceOutcome {
+	let content = match std::fs::metadata(&entry.path) {
+		Ok(metadata) if metadata.len() > MAX_REPLACE_FILE_BYTES => {
+			return FileReplaceOutcome::Skipped(skipped_file(
+				entry,
+				SkipReason::TooLarge,
+				format!(
+					"{} bytes exceeds the {MAX_REPLACE_FILE_BYTES} byte replace limit",
+					metadata.len()
+				),
+			));
+		},
+		Ok(_) => match std::fs::read(&entry.path) {
+			Ok(content) => content,
+			Err(err) => return FileReplaceOutcome::Failed(format!("{}: {err}", entry.relative_path)),
+		},
+		Err(err) => return FileReplaceOutcome::Failed(format!("{}: {err}", entry.relative_path)),
+	};
+	let encoding =
+		TextEncoding::detect(&content[..content.len().min(ENCODING_SNIFF_BYTES as usize)]);
+	if matches!(encoding, TextEncoding::Utf16Le | TextEncoding::Utf16Be) {
+		return FileReplaceOutcome::Skipped(skipped_file(
+			entry,
+			SkipReason::Encoding,
+			format!("{} text is not rewritten", encoding.label()),
+		));
 	}
+	if let Some(offset) = content.iter().position(|&b| b == 0) {
+		return FileReplaceOutcome::Skipped(skipped_file(
+			entry,
+			SkipReason::Binary,
+			format!("NUL byte at offset {offset}"),
+		));
+	}
+	let replacements = match find_replacements(matcher, &content, template) {
+		Ok(replacements) if !replacements.is_empty() => replacements,
+		Ok(_) => return FileReplaceOutcome::Unchanged,
+		Err(err) => return FileReplaceOutcome::Failed(format!("{}: {err}", entry.relative_path)),
+	};
+	let (output, changes) = apply_replacements(&content, &replacements);
+	FileReplaceOutcome::Changed(PlannedReplace {
+		path: entry.path.clone(),
+		original: content_hash(&content),
+		output,
+		file: GrepReplaceFile {
+			path: entry.relative_path.clone(),
+			count: crate::utils::clamp_u32(replacements.len() as u64),
+			changes,
+		},
+	})
+}
 
-	#[test]
-	fn preserves_valid_quantifiers() {
-		assert_eq!(sanitize_braces("a{2,4}").as_ref(), "a{2,4}");
+/// Write a planned file unless it changed since its replacements were
+/// computed.
+fn write_planned(planned: &PlannedReplace) -> io::Result<()> {
+	let current = std::fs::read(&planned.path)?;
+	if content_hash(&current) != planned.original {
+		return Err(io::Error::other("file changed since it was read"));
 	}
+	if planned.output == current {
+		return Ok(());
+	}
+	write_atomic(&planned.path, &planned.output)
 }
-fn build_matcher(
-	pattern: &str,
-	ignore_case: bool,
-	multiline: bool,
-) -> Result<grep_regex::RegexMatcher> {
-	let sanitized = sanitize_braces(pattern);
-	RegexMatcherBuilder::new()
-		.case_insensitive(ignore_case)
-		.multi_line(multiline)
-		.build(&sanitized)
-		.map_err(|err| Error::from_reason(format!("Regex error: {err}")))
+
+fn grep_replace_sync(config: ReplaceConfig, ct: task::CancelToken) -> Result<GrepReplaceResult> {
+	let search_path = resolve_search_path(&config.path)?;
+	let metadata = std::fs::metadata(&search_path)
+		.map_err(|err| Error::from_reason(format!("Path not found: {err}")))?;
+	let matcher = build_replace_matcher(&config.pattern, config.flags)?;
+	let glob_filter =
+		glob_util::compile_glob_filter(config.glob.as_ref(), config.exclude.as_deref(), true)?;
+	let type_filter = resolve_type_filter(config.type_filter.as_ref())?;
+
+	let entries = if metadata.is_file() {
+		if type_filter
+			.as_ref()
+			.is_some_and(|filter| !filter.matches(&search_path))
+		{
+			Vec::new()
+		} else {
+			let relative_path = search_path.to_string_lossy().into_owned();
+			vec![FileEntry { path: search_path, relative_path, blob: None }]
+		}
+	} else {
+		scan_entries(
+			&search_path,
+			config.hidden,
+			config.gitignore,
+			config.cache,
+			EntryFilters {
+				glob:    glob_filter.as_ref(),
+				types:   type_filter.as_ref(),
+				index:   None,
+				changed: None,
+			},
+			&ct,
+		)?
+	};
+	ct.heartbeat()?;
+
+	// Nothing is written until every file is matched, so cancelling never
+	// leaves a partial rewrite behind.
+	let template = config.replacement.as_bytes();
+	let outcomes: Vec<FileReplaceOutcome> = entries
+		.par_iter()
+		.map(|entry| {
+			ct.heartbeat()?;
+			Ok(plan_replace(entry, &matcher, template))
+		})
+		.collect::<Result<_>>()?;
+
+	let mut planned = Vec::new();
+	let mut skipped = Vec::new();
+	let mut errors = Vec::new();
+	for outcome in outcomes {
+		match outcome {
+			FileReplaceOutcome::Unchanged => {},
+			FileReplaceOutcome::Changed(file) => planned.push(file),
+			FileReplaceOutcome::Skipped(file) => skipped.push(file),
+			FileReplaceOutcome::Failed(error) => errors.push(error),
+		}
+	}
+	if !config.dry_run {
+		let failed: Vec<String> = planned
+			.par_iter()
+			.filter_map(|planned| {
+				write_planned(planned)
+					.err()
+					.map(|err| format!("Failed to write {}: {err}", planned.file.path))
+			})
+			.collect();
+		errors.extend(failed);
+	}
+	let mut files: Vec<GrepReplaceFile> = planned.into_iter().map(|planned| planned.file).collect();
+	files.sort_by(|a, b| a.path.cmp(&b.path));
+	errors.sort();
+	let total_replacements = files.iter().map(|file| file.count as u64).sum();
+
+	Ok(GrepReplaceResult {
+		total_replacements: crate::utils::clamp_u32(total_replacements),
+		files_touched: crate::utils::clamp_u32(files.len() as u64),
+		files_searched: crate::utils::clamp_u32(entries.len() as u64),
+		applied: !config.dry_run,
+		errors: (!errors.is_empty()).then_some(errors),
+		skipped: sorted_skips(skipped),
+		files,
+	})
 }
 
+/// Search `entries` in parallel and return the results in path order. With
+/// `json`, each worker renders its file's records and streams them as soon as
+/// the file is done, one file at a time.
 fn run_parallel_search(
 	entries: &[FileEntry],
-	matcher: &grep_regex::RegexMatcher,
-	context_before: u32,
-	context_after: u32,
-	max_columns: Option<u32>,
-	mode: OutputMode,
-) -> Vec<FileSearchResult> {
-	let params =
-		SearchParams { context_before, context_after, max_columns, mode, max_count: None, offset: 0 };
-	let mut results: Vec<FileSearchResult> = entries
+	matcher: &CompiledMatcher,
+	params: SearchParams,
+	max_file_bytes: Option<u64>,
+	json: Option<&JsonLines<'_>>,
+) -> (Vec<FileSearchResult>, Vec<GrepSkippedFile>) {
+	let params = SearchParams { max_count: None, offset: 0, ..params };
+	let streaming = Mutex::new(());
+	let (mut results, skipped): (Vec<_>, Vec<_>) = entries
 		.par_iter()
-		.filter_map(|entry| {
-			let file = File::open(&entry.path).ok()?;
-			let reader = file.take(MAX_FILE_BYTES);
-			let search = run_search_reader(matcher, reader, params).ok()?;
-			Some(FileSearchResult {
-				relative_path: entry.relative_path.clone(),
-				matches:       search.matches,
-				match_count:   search.match_count,
-			})
+		.map(|entry| {
+			let search = search_entry(entry, matcher, params, max_file_bytes)?;
+			let records = json.map(|json| {
+				let file = FileRecords::render(&entry.relative_path, &search.matches, search.stats());
+				if let Some(on_match) = json.on_match {
+					let _streaming = streaming.lock();
+					file.send(on_match);
+				}
+				file
+			});
+			Ok(FileSearchResult { relative_path: entry.relative_path.clone(), search, records })
 		})
-		.collect();
+		.partition_map(|outcome| match outcome {
+			Ok(result) => rayon::iter::Either::Left(result),
+			Err(skipped) => rayon::iter::Either::Right(skipped),
+		});
 
 	results.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
-	results
+	(results, skipped)
 }
 
 fn run_sequential_search(
 	entries: &[FileEntry],
-	matcher: &grep_regex::RegexMatcher,
+	matcher: &CompiledMatcher,
 	params: SearchParams,
+	max_file_bytes: Option<u64>,
+	skipped: &mut Vec<GrepSkippedFile>,
+	mut json: Option<&mut JsonLines<'_>>,
 ) -> (Vec<GrepMatch>, u64, u32, u32, bool) {
 	let SearchParams { mode, max_count, offset, .. } = params;
 	let mut matches = Vec::new();
@@ -840,17 +3667,18 @@ fn run_sequential_search(
 			break;
 		}
 
-		// Open file and search directly - no intermediate buffer, no precheck scan
-		let Ok(file) = File::open(&entry.path) else {
-			continue;
-		};
-		files_searched = files_searched.saturating_add(1);
-		let reader = file.take(MAX_FILE_BYTES);
-
 		let file_params = SearchParams { max_count: remaining, offset: file_offset, ..params };
-		let Ok(search) = run_search_reader(matcher, reader, file_params) else {
-			continue;
+		let search = match search_entry(entry, matcher, file_params, max_file_bytes) {
+			Ok(search) => search,
+			Err(skip) => {
+				skipped.push(skip);
+				continue;
+			},
 		};
+		files_searched = files_searched.saturating_add(1);
+		if let Some(json) = json.as_deref_mut() {
+			json.file(&entry.relative_path, &search.matches, search.stats());
+		}
 
 		if search.match_count == 0 {
 			continue;
@@ -861,9 +3689,9 @@ fn run_sequential_search(
 		collected = collected.saturating_add(search.collected);
 
 		match mode {
-			OutputMode::Content => {
+			OutputMode::Content | OutputMode::Json | OutputMode::Hunks => {
 				for matched in search.matches {
-					matches.push(to_grep_match(&entry.relative_path, matched));
+					matches.push(to_grep_match(&entry.relative_path, matched, search.encoding));
 				}
 			},
 			OutputMode::Count => {
@@ -874,6 +3702,11 @@ fn run_sequential_search(
 					context_before: None,
 					context_after:  None,
 					truncated:      None,
+					submatches:     None,
+					pattern_id:     None,
+					encoding:       None,
+					binary:         None,
+					json:           None,
 					match_count:    Some(crate::utils::clamp_u32(search.match_count)),
 				});
 			},
@@ -888,10 +3721,17 @@ fn run_sequential_search(
 }
 
 fn search_sync(content: &[u8], options: SearchOptions) -> SearchResult {
-	let ignore_case = options.ignore_case.unwrap_or(false);
-	let multiline = options.multiline.unwrap_or(false);
+	let flags = MatchFlags::resolve(
+		options.ignore_case,
+		options.smart_case,
+		options.multiline,
+		options.fixed_strings,
+		options.word_regexp,
+	);
+	let multiline = flags.multiline;
 	let mode = parse_output_mode(options.mode.as_deref());
-	let matcher = match build_matcher(&options.pattern, ignore_case, multiline) {
+	let binary = BinaryMode::parse(options.binary.as_deref());
+	let matcher = match build_matcher(&options.pattern, flags) {
 		Ok(matcher) => matcher,
 		Err(err) => return empty_search_result(Some(err.to_string())),
 	};
@@ -901,38 +3741,174 @@ fn search_sync(content: &[u8], options: SearchOptions) -> SearchResult {
 	let max_columns = options.max_columns;
 	let max_count = options.max_count.map(u64::from);
 	let offset = options.offset.unwrap_or(0) as u64;
-	let params =
-		SearchParams { context_before, context_after, max_columns, mode, max_count, offset };
+	let params = SearchParams {
+		context_before,
+		context_after,
+		max_columns,
+		mode,
+		max_count,
+		offset,
+		multiline,
+		binary,
+		syntax: None,
+	};
 
 	let result = match run_search(&matcher, content, params) {
 		Ok(result) => result,
 		Err(err) => return empty_search_result(Some(err.to_string())),
 	};
+	let encoding = Some(result.encoding.label().to_string());
+	let binary_found = result.binary_offset.is_some().then_some(true);
+	let Ok(result) = apply_binary_mode(result, binary) else {
+		return SearchResult { encoding, binary: binary_found, ..empty_search_result(None) };
+	};
 
 	SearchResult {
-		matches:       result.matches.into_iter().map(to_public_match).collect(),
-		match_count:   crate::utils::clamp_u32(result.match_count),
+		matches: result.matches.into_iter().map(to_public_match).collect(),
+		match_count: crate::utils::clamp_u32(result.match_count),
 		limit_reached: result.limit_reached,
-		error:         None,
+		error: None,
+		encoding,
+		binary: binary_found,
+	}
+}
+
+fn hunk_context_line(context: ContextLine) -> GrepHunkLine {
+	GrepHunkLine {
+		line_number: context.line_number,
+		line:        context.line,
+		kind:        "context".to_string(),
+		truncated:   None,
+		submatches:  None,
+		pattern_ids: None,
 	}
 }
 
+/// Add `line` to `lines` (sorted by line number). A line already present is
+/// upgraded to a match, and hits from several patterns on one line merge.
+fn merge_hunk_line(lines: &mut Vec<GrepHunkLine>, line: GrepHunkLine) {
+	match lines.binary_search_by_key(&line.line_number, |existing| existing.line_number) {
+		Err(index) => lines.insert(index, line),
+		Ok(_) if line.kind == "context" => {},
+		Ok(index) if lines[index].kind == "context" => lines[index] = line,
+		Ok(index) => {
+			let existing = &mut lines[index];
+			existing
+				.submatches
+				.get_or_insert_default()
+				.extend(line.submatches.into_iter().flatten());
+			existing
+				.pattern_ids
+				.get_or_insert_default()
+				.extend(line.pattern_ids.into_iter().flatten());
+		},
+	}
+}
+
+/// Group matches (ordered by path, then line) into hunks: a match joins the
+/// previous hunk when its context touches or overlaps it.
+fn build_hunks(matches: Vec<GrepMatch>) -> Vec<GrepHunk> {
+	let mut hunks: Vec<GrepHunk> = Vec::new();
+	for matched in matches {
+		let GrepMatch {
+			path,
+			line_number,
+			line,
+			context_before,
+			context_after,
+			truncated,
+			submatches,
+			pattern_id,
+			encoding,
+			..
+		} = matched;
+		// A multiline match covers several lines.
+		let match_end = line_number + crate::utils::clamp_u32(line.matches('\n').count() as u64);
+		let mut lines: Vec<GrepHunkLine> = context_before
+			.into_iter()
+			.flatten()
+			.map(hunk_context_line)
+			.collect();
+		let start_line = lines.first().map_or(line_number, |first| first.line_number);
+		lines.push(GrepHunkLine {
+			line_number,
+			line,
+			kind: "match".to_string(),
+			truncated,
+			submatches,
+			pattern_ids: pattern_id.map(|id| vec![id]),
+		});
+		lines.extend(context_after.into_iter().flatten().map(hunk_context_line));
+		let end_line = lines
+			.last()
+			.map_or(match_end, |last| last.line_number.max(match_end));
+
+		match hunks.last_mut() {
+			Some(hunk) if hunk.path == path && start_line <= hunk.end_line.saturating_add(1) => {
+				hunk.start_line = hunk.start_line.min(start_line);
+				hunk.end_line = hunk.end_line.max(end_line);
+				for line in lines {
+					merge_hunk_line(&mut hunk.lines, line);
+				}
+			},
+			_ => hunks.push(GrepHunk { path, start_line, end_line, lines, encoding }),
+		}
+	}
+	hunks
+}
+
 fn grep_sync(
 	options: GrepConfig,
 	on_match: Option<&ThreadsafeFunction<GrepMatch>>,
 	ct: task::CancelToken,
 ) -> Result<GrepResult> {
+	if parse_output_mode(options.mode.as_deref()) == OutputMode::Hunks {
+		// Hunks are assembled from the final matches so offset/limit apply to
+		// matches exactly as in content mode; none exists before the search ends.
+		if on_match.is_some() {
+			return Err(Error::from_reason(
+				"`onMatch` is not supported with mode \"hunks\"; read `hunks` from the result",
+			));
+		}
+		let options = GrepConfig { mode: None, ..options };
+		let mut result = grep_sync(options, None, ct)?;
+		result.hunks = Some(build_hunks(std::mem::take(&mut result.matches)));
+		return Ok(result);
+	}
 	let search_path = resolve_search_path(&options.path)?;
-	let metadata = std::fs::metadata(&search_path)
-		.map_err(|err| Error::from_reason(format!("Path not found: {err}")))?;
-	let ignore_case = options.ignore_case.unwrap_or(false);
-	let multiline = options.multiline.unwrap_or(false);
+	let rev = options
+		.rev
+		.as_deref()
+		.map(str::trim)
+		.filter(|rev| !rev.is_empty());
+	// At a revision the path only has to exist in that revision's tree.
+	let is_file = match rev {
+		Some(_) => false,
+		None => std::fs::metadata(&search_path)
+			.map_err(|err| Error::from_reason(format!("Path not found: {err}")))?
+			.is_file(),
+	};
+	let flags = MatchFlags::resolve(
+		options.ignore_case,
+		options.smart_case,
+		options.multiline,
+		options.fixed_strings,
+		options.word_regexp,
+	);
+	let multiline = flags.multiline;
 	let output_mode = parse_output_mode(options.mode.as_deref());
-	let matcher = build_matcher(&options.pattern, ignore_case, multiline)?;
+	let binary = BinaryMode::parse(options.binary.as_deref());
+	let syntax = SyntaxFilter::parse(options.syntax.as_ref())?;
+	// In `json` mode the callback receives records instead of matches.
+	let (on_match, mut json) = if output_mode == OutputMode::Json {
+		(None, Some(JsonLines::new(on_match)))
+	} else {
+		(on_match, None)
+	};
 
 	let (context_before, context_after) =
 		resolve_context(options.context, options.context_before, options.context_after);
-	let (context_before, context_after) = if output_mode == OutputMode::Content {
+	let (context_before, context_after) = if output_mode.collects_lines() {
 		(context_before, context_after)
 	} else {
 		(0, 0)
@@ -943,33 +3919,103 @@ fn grep_sync(
 	let include_hidden = options.hidden.unwrap_or(true);
 	let use_gitignore = options.gitignore.unwrap_or(true);
 	let use_cache = options.cache.unwrap_or(false);
-	let glob_set = glob_util::try_compile_glob(options.glob.as_deref(), true)?;
-	let type_filter = resolve_type_filter(options.type_filter.as_deref());
+	let max_file_bytes = resolve_max_file_bytes(options.max_file_size);
+	let glob_filter =
+		glob_util::compile_glob_filter(options.glob.as_ref(), options.exclude.as_deref(), true)?;
+	let type_filter = resolve_type_filter(options.type_filter.as_ref())?;
+	let use_index = options.index.unwrap_or(false) && rev.is_none();
+	let changed = git::ChangedSince::parse(options.changed.as_deref(), options.changed_base)?
+		.map(|since| git::changed_under(&search_path, &since))
+		.transpose()?;
+	let outside_filters = |path: &Path| {
+		type_filter
+			.as_ref()
+			.is_some_and(|filter| !filter.matches(path))
+			|| changed
+				.as_ref()
+				.is_some_and(|changed| !changed.contains(path))
+	};
 
-	if metadata.is_file() {
-		if let Some(filter) = type_filter.as_ref()
-			&& !matches_type_filter(&search_path, filter)
-		{
-			return Ok(GrepResult {
-				matches:            Vec::new(),
-				total_matches:      0,
-				files_with_matches: 0,
-				files_searched:     0,
-				limit_reached:      None,
+	let pattern = match (options.pattern, options.patterns) {
+		(Some(_), Some(_)) => {
+			return Err(Error::from_reason("Specify either `pattern` or `patterns`, not both"));
+		},
+		(None, None) => return Err(Error::from_reason("`pattern` or `patterns` is required")),
+		(None, Some(patterns)) => {
+			// A file is only reported when at least one pattern matches.
+			let index_query = use_index.then(|| {
+				grep_index::Query::or(
+					patterns
+						.iter()
+						.map(|entry| flags.index_query(&entry.pattern)),
+				)
 			});
-		}
+			let set = build_pattern_set(patterns, options.file_query, flags)?;
+			let filters = EntryFilters {
+				glob:    glob_filter.as_ref(),
+				types:   type_filter.as_ref(),
+				index:   index_query.as_ref(),
+				changed: changed.as_ref(),
+			};
+			let entries = if let Some(rev) = rev {
+				revision_entries(&search_path, rev, include_hidden, filters, &ct)?
+			} else if is_file {
+				if outside_filters(&search_path) {
+					Vec::new()
+				} else {
+					let relative_path = search_path.to_string_lossy().into_owned();
+					vec![FileEntry { path: search_path, relative_path, blob: None }]
+				}
+			} else {
+				scan_entries(&search_path, include_hidden, use_gitignore, use_cache, filters, &ct)?
+			};
+			ct.heartbeat()?;
+			let result = run_multi_search(
+				&entries,
+				&set,
+				SearchParams {
+					context_before,
+					context_after,
+					max_columns,
+					mode: output_mode,
+					max_count,
+					offset,
+					multiline,
+					binary,
+					syntax,
+				},
+				max_file_bytes,
+				json.as_mut(),
+				on_match,
+				&ct,
+			)?;
+			return Ok(GrepResult { json: json.map(JsonLines::finish), ..result });
+		},
+		(Some(pattern), None) => pattern,
+	};
+	if options.file_query.is_some() {
+		return Err(Error::from_reason("`fileQuery` requires `patterns`"));
+	}
+	let matcher = build_matcher(&pattern, flags)?;
+	let index_query = use_index.then(|| flags.index_query(&pattern));
 
-		let Ok(file) = File::open(&search_path) else {
+	if is_file {
+		if outside_filters(&search_path) {
 			return Ok(GrepResult {
 				matches:            Vec::new(),
 				total_matches:      0,
 				files_with_matches: 0,
 				files_searched:     0,
 				limit_reached:      None,
+				skipped:            None,
+				json:               json.map(JsonLines::finish),
+				hunks:              None,
 			});
-		};
-		let reader = file.take(MAX_FILE_BYTES);
+		}
 
+		let path_string = search_path.to_string_lossy().into_owned();
+		let entry =
+			FileEntry { path: search_path, relative_path: path_string, blob: None };
 		let params = SearchParams {
 			context_before,
 			context_after,
@@ -977,9 +4023,28 @@ fn grep_sync(
 			mode: output_mode,
 			max_count,
 			offset,
+			multiline,
+			binary,
+			syntax,
 		};
-		let search = run_search_reader(&matcher, reader, params)
-			.map_err(|err| Error::from_reason(format!("Search failed: {err}")))?;
+		let search = match search_entry(&entry, &matcher, params, max_file_bytes) {
+			Ok(search) => search,
+			Err(skipped) => {
+				return Ok(GrepResult {
+					matches:            Vec::new(),
+					total_matches:      0,
+					files_with_matches: 0,
+					files_searched:     0,
+					limit_reached:      None,
+					skipped:            Some(vec![skipped]),
+					json:               json.map(JsonLines::finish),
+					hunks:              None,
+				});
+			},
+		};
+		if let Some(json) = json.as_mut() {
+			json.file(&entry.relative_path, &search.matches, search.stats());
+		}
 
 		if search.match_count == 0 {
 			return Ok(GrepResult {
@@ -988,15 +4053,18 @@ fn grep_sync(
 				files_with_matches: 0,
 				files_searched:     1,
 				limit_reached:      None,
+				skipped:            None,
+				json:               json.map(JsonLines::finish),
+				hunks:              None,
 			});
 		}
 
-		let path_string = search_path.to_string_lossy().into_owned();
+		let path_string = entry.relative_path;
 		let mut matches = Vec::new();
 		match output_mode {
-			OutputMode::Content => {
+			OutputMode::Content | OutputMode::Json | OutputMode::Hunks => {
 				for matched in search.matches {
-					matches.push(to_grep_match(&path_string, matched));
+					matches.push(to_grep_match(&path_string, matched, search.encoding));
 				}
 			},
 			OutputMode::Count => {
@@ -1007,6 +4075,11 @@ fn grep_sync(
 					context_before: None,
 					context_after:  None,
 					truncated:      None,
+					submatches:     None,
+					pattern_id:     None,
+					encoding:       None,
+					binary:         None,
+					json:           None,
 					match_count:    Some(crate::utils::clamp_u32(search.match_count)),
 				});
 			},
@@ -1021,22 +4094,21 @@ fn grep_sync(
 			files_with_matches: 1,
 			files_searched: 1,
 			limit_reached: if limit_reached { Some(true) } else { None },
+			skipped: None,
+			json: json.map(JsonLines::finish),
+			hunks: None,
 		});
 	}
 
-	let entries = if use_cache {
-		let scan = fs_cache::get_or_scan(&search_path, include_hidden, use_gitignore, &ct)?;
-		let mut entries =
-			collect_files(&search_path, &scan.entries, glob_set.as_ref(), type_filter.as_ref());
-		if entries.is_empty() && scan.cache_age_ms >= fs_cache::empty_recheck_ms() {
-			let fresh =
-				fs_cache::force_rescan(&search_path, include_hidden, use_gitignore, true, &ct)?;
-			entries = collect_files(&search_path, &fresh, glob_set.as_ref(), type_filter.as_ref());
-		}
-		entries
-	} else {
-		let fresh = fs_cache::force_rescan(&search_path, include_hidden, use_gitignore, false, &ct)?;
-		collect_files(&search_path, &fresh, glob_set.as_ref(), type_filter.as_ref())
+	let filters = EntryFilters {
+		glob:    glob_filter.as_ref(),
+		types:   type_filter.as_ref(),
+		index:   index_query.as_ref(),
+		changed: changed.as_ref(),
+	};
+	let entries = match rev {
+		Some(rev) => revision_entries(&search_path, rev, include_hidden, filters, &ct)?,
+		None => scan_entries(&search_path, include_hidden, use_gitignore, use_cache, filters, &ct)?,
 	};
 	// Check cancellation before heavy work
 	ct.heartbeat()?;
@@ -1047,35 +4119,50 @@ fn grep_sync(
 			files_with_matches: 0,
 			files_searched:     0,
 			limit_reached:      None,
+			skipped:            None,
+			json:               json.map(JsonLines::finish),
+			hunks:              None,
 		});
 	}
 
 	let allow_parallel = max_count.is_none() && offset == 0;
 	if allow_parallel {
-		let results = run_parallel_search(
+		let (results, skipped) = run_parallel_search(
 			&entries,
 			&matcher,
-			context_before,
-			context_after,
-			max_columns,
-			output_mode,
+			SearchParams {
+				context_before,
+				context_after,
+				max_columns,
+				mode: output_mode,
+				max_count,
+				offset,
+				multiline,
+				binary,
+				syntax,
+			},
+			max_file_bytes,
+			json.as_ref(),
 		);
 		let mut matches = Vec::new();
 		let mut total_matches = 0u64;
 		let mut files_with_matches = 0u32;
 		let files_searched = crate::utils::clamp_u32(results.len() as u64);
 
-		for result in results {
-			if result.match_count == 0 {
+		for FileSearchResult { relative_path, search, records } in results {
+			if let (Some(json), Some(records)) = (json.as_mut(), records) {
+				json.append(records);
+			}
+			if search.match_count == 0 {
 				continue;
 			}
 			files_with_matches = files_with_matches.saturating_add(1);
-			total_matches = total_matches.saturating_add(result.match_count);
+			total_matches = total_matches.saturating_add(search.match_count);
 
 			match output_mode {
-				OutputMode::Content => {
-					for matched in result.matches {
-						let grep_match = to_grep_match(&result.relative_path, matched);
+				OutputMode::Content | OutputMode::Json | OutputMode::Hunks => {
+					for matched in search.matches {
+						let grep_match = to_grep_match(&relative_path, matched, search.encoding);
 						if let Some(callback) = on_match {
 							callback.call(Ok(grep_match.clone()), ThreadsafeFunctionCallMode::NonBlocking);
 						}
@@ -1084,13 +4171,18 @@ fn grep_sync(
 				},
 				OutputMode::Count => {
 					let grep_match = GrepMatch {
-						path:           result.relative_path.clone(),
+						path:           relative_path,
 						line_number:    0,
 						line:           String::new(),
 						context_before: None,
 						context_after:  None,
 						truncated:      None,
-						match_count:    Some(crate::utils::clamp_u32(result.match_count)),
+						submatches:     None,
+						pattern_id:     None,
+						encoding:       None,
+						binary:         None,
+						json:           None,
+						match_count:    Some(crate::utils::clamp_u32(search.match_count)),
 					};
 					if let Some(callback) = on_match {
 						callback.call(Ok(grep_match.clone()), ThreadsafeFunctionCallMode::NonBlocking);
@@ -1106,18 +4198,32 @@ fn grep_sync(
 			files_with_matches,
 			files_searched,
 			limit_reached: None,
+			skipped: sorted_skips(skipped),
+			json: json.map(JsonLines::finish),
+			hunks: None,
 		});
 	}
 
+	let mut skipped = Vec::new();
 	let (matches, total_matches, files_with_matches, files_searched, limit_reached) =
-		run_sequential_search(&entries, &matcher, SearchParams {
-			context_before,
-			context_after,
-			max_columns,
-			mode: output_mode,
-			max_count,
-			offset,
-		});
+		run_sequential_search(
+			&entries,
+			&matcher,
+			SearchParams {
+				context_before,
+				context_after,
+				max_columns,
+				mode: output_mode,
+				max_count,
+				offset,
+				multiline,
+				binary,
+				syntax,
+			},
+			max_file_bytes,
+			&mut skipped,
+			json.as_mut(),
+		);
 
 	// Fire callbacks for sequential search results
 	if let Some(callback) = on_match {
@@ -1132,6 +4238,9 @@ fn grep_sync(
 		files_with_matches,
 		files_searched,
 		limit_reached: if limit_reached { Some(true) } else { None },
+		skipped: sorted_skips(skipped),
+		json: json.map(JsonLines::finish),
+		hunks: None,
 	})
 }
 
@@ -1163,8 +4272,7 @@ pub fn search(content: Either<JsString, Uint8Array>, options: SearchOptions) ->
 /// # Arguments
 /// - `content`: `Uint8Array`/`Buffer` (zero-copy) or `string` (UTF-8).
 /// - `pattern`: `Uint8Array`/`Buffer` (zero-copy) or `string` (UTF-8).
-/// - `ignore_case`: Case-insensitive matching.
-/// - `multiline`: Enable multiline regex mode.
+/// - `options`: Case, multiline, literal and word-boundary flags.
 ///
 /// # Returns
 /// True if any match exists; false on no match.
@@ -1172,8 +4280,7 @@ pub fn search(content: Either<JsString, Uint8Array>, options: SearchOptions) ->
 pub fn has_match(
 	content: Either<JsString, Uint8Array>,
 	pattern: Either<JsString, Uint8Array>,
-	ignore_case: bool,
-	multiline: bool,
+	options: Option<HasMatchOptions>,
 ) -> Result<bool> {
 	// Hold JsStringUtf8 on the stack and borrow - no copy
 	let content_utf8;
@@ -1200,8 +4307,11 @@ pub fn has_match(
 		},
 	};
 
-	let matcher = build_matcher(pattern_ref, ignore_case, multiline)?;
-	Ok(matcher.is_match(content_slice).unwrap_or(false))
+	let HasMatchOptions { ignore_case, multiline, fixed_strings, word_regexp, smart_case } =
+		options.unwrap_or_default();
+	let flags = MatchFlags::resolve(ignore_case, smart_case, multiline, fixed_strings, word_regexp);
+	let matcher = build_matcher(pattern_ref, flags)?;
+	Ok(matcher.regex.is_match(content_slice).unwrap_or(false))
 }
 
 /// Search files for a regex pattern.
@@ -1221,14 +4331,21 @@ pub fn grep(
 ) -> task::Async<GrepResult> {
 	let GrepOptions {
 		pattern,
+		patterns,
+		file_query,
 		path,
 		glob,
+		exclude,
 		type_filter,
 		ignore_case,
 		multiline,
+		fixed_strings,
+		word_regexp,
+		smart_case,
 		hidden,
 		gitignore,
 		cache,
+		max_file_size,
 		max_count,
 		offset,
 		context_before,
@@ -1236,20 +4353,33 @@ pub fn grep(
 		context,
 		max_columns,
 		mode,
+		binary,
+		syntax,
+		index,
+		rev,
+		changed,
+		changed_base,
 		timeout_ms,
 		signal,
 	} = options;
 
 	let config = GrepConfig {
 		pattern,
+		patterns,
+		file_query,
 		path,
 		glob,
+		exclude,
 		type_filter,
 		ignore_case,
 		multiline,
+		fixed_strings,
+		word_regexp,
+		smart_case,
 		hidden,
 		gitignore,
 		cache,
+		max_file_size,
 		max_count,
 		offset,
 		context_before,
@@ -1257,8 +4387,64 @@ pub fn grep(
 		context,
 		max_columns,
 		mode,
+		binary,
+		syntax,
+		index,
+		rev,
+		changed,
+		changed_base,
 	};
 
 	let ct = task::CancelToken::new(timeout_ms, signal);
 	task::blocking("grep", ct, move |ct| grep_sync(config, on_match.as_ref(), ct))
 }
+
+/// Regex search-and-replace across files.
+///
+/// Walks the same file set as [`grep`] (glob, type, hidden and gitignore
+/// filters) and expands `replacement` for every match. Runs as a dry run by
+/// default; with `dryRun: false` each changed file is written atomically once
+/// every file has been matched, unless it changed in the meantime. Files too
+/// large, binary or UTF-16 encoded are reported in `skipped`.
+///
+/// # Returns
+/// Per-file change blocks with before/after lines and replacement counts.
+#[napi(js_name = "grepReplace")]
+pub fn grep_replace(options: GrepReplaceOptions<'_>) -> task::Async<GrepReplaceResult> {
+	let GrepReplaceOptions {
+		pattern,
+		replacement,
+		path,
+		glob,
+		exclude,
+		type_filter,
+		ignore_case,
+		multiline,
+		fixed_strings,
+		word_regexp,
+		smart_case,
+		hidden,
+		gitignore,
+		cache,
+		dry_run,
+		signal,
+		timeout_ms,
+	} = options;
+
+	let config = ReplaceConfig {
+		pattern,
+		replacement,
+		path,
+		glob,
+		exclude,
+		type_filter,
+		flags: MatchFlags::resolve(ignore_case, smart_case, multiline, fixed_strings, word_regexp),
+		hidden: hidden.unwrap_or(true),
+		gitignore: gitignore.unwrap_or(true),
+		cache: cache.unwrap_or(false),
+		dry_run: dry_run.unwrap_or(true),
+	};
+
+	let ct = task::CancelToken::new(timeout_ms, signal);
+	task::blocking("grep_replace", ct, move |ct| grep_replace_sync(config, ct))
+}
@@ -0,0 +1,1022 @@
+//! Persistent trigram index that lets `grep` skip files that cannot match.
+//!
+//! # Overview
+//! An index covers one search root. For every file it stores the size and
+//! mtime seen by the [`fs_cache`] scan, and posting lists map each byte
+//! trigram (ASCII letters folded to lowercase) to the files containing it. A pattern is reduced to an AND/OR
+//! [`Query`] over the trigrams of the literals any match must contain, and only
+//! files satisfying the query are handed to `grep_searcher`.
+//!
+//! # Freshness
+//! Every indexed `grep` checks the scan against the index: new files and files
+//! whose size or mtime changed are re-tokenized first. Files the query would
+//! prune are stat'ed again, since a cached scan can predate an edit.
+//! Re-tokenized files are answered from their own trigram sets until enough of
+//! them accumulate to justify merging them into the posting lists. Files that are not UTF-8, contain NUL
+//! bytes, or exceed [`MAX_INDEXED_FILE_BYTES`] are stored without trigrams and
+//! always searched.
+//!
+//! # Storage
+//! Indexes stay in memory once loaded and are written to `$GREP_INDEX_DIR`
+//! (default: `pi-natives/grep-index` in the user's cache directory, created
+//! with mode 0700), one file per root holding the file table and the posting
+//! lists.
+
+use std::{
+	collections::{HashMap, HashSet},
+	io,
+	path::{Path, PathBuf},
+	sync::{Arc, LazyLock},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use dashmap::DashMap;
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use parking_lot::Mutex;
+use rayon::prelude::*;
+use regex_syntax::{
+	ParserBuilder,
+	hir::{Class, Hir, HirKind},
+};
+
+use crate::{
+	fs_cache::{self, FileType, GlobMatch},
+	grep::{ENCODING_SNIFF_BYTES, TextEncoding},
+	task,
+};
+
+/// Files larger than this are tracked without trigrams and always searched.
+const MAX_INDEXED_FILE_BYTES: u64 = 16 * 1024 * 1024;
+/// Magic header (including format version) of persisted index files.
+const MAGIC: &[u8; 8] = b"PIGRIX02";
+/// Largest class still folded into a literal byte (e.g. `[aA]`).
+const MAX_FOLDED_CLASS_LEN: u32 = 4;
+
+static INDEXES: LazyLock<DashMap<PathBuf, Arc<Mutex<TrigramIndex>>>> = LazyLock::new(DashMap::new);
+
+// ═══════════════════════════════════════════════════════════════════════════
+// Queries
+// ═══════════════════════════════════════════════════════════════════════════
+
+/// Trigram requirements a file must meet to possibly match a pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Query {
+	/// No constraint; every file is a candidate.
+	All,
+	/// The file must contain this trigram.
+	Trigram(u32),
+	/// Every sub-query must hold.
+	And(Vec<Self>),
+	/// At least one sub-query must hold.
+	Or(Vec<Self>),
+}
+
+impl Query {
+	/// Build the query for a grep pattern.
+	///
+	/// `case_insensitive` must be set whenever the pattern may match without
+	/// regard to case (including smart case). Patterns that fail to parse
+	/// yield [`Query::All`].
+	pub fn for_pattern(pattern: &str, fixed_strings: bool, case_insensitive: bool) -> Self {
+		if fixed_strings {
+			return literal_query(pattern.as_bytes(), case_insensitive);
+		}
+		ParserBuilder::new()
+			.case_insensitive(case_insensitive)
+			.build()
+			.parse(pattern)
+			.map_or(Self::All, |hir| hir_query(&hir))
+	}
+
+	/// Conjunction, flattened and without [`Query::All`] terms.
+	fn and(parts: Vec<Self>) -> Self {
+		let mut flat = Vec::with_capacity(parts.len());
+		for part in parts {
+			match part {
+				Self::All => {},
+				Self::And(inner) => flat.extend(inner),
+				other => flat.push(other),
+			}
+		}
+		match flat.len() {
+			0 => Self::All,
+			1 => flat.pop().unwrap_or(Self::All),
+			_ => Self::And(flat),
+		}
+	}
+
+	/// Disjunction; [`Query::All`] when any branch is unconstrained.
+	pub fn or(parts: impl IntoIterator<Item = Self>) -> Self {
+		let mut flat = Vec::new();
+		for part in parts {
+			match part {
+				Self::All => return Self::All,
+				Self::Or(inner) => flat.extend(inner),
+				other => flat.push(other),
+			}
+		}
+		match flat.len() {
+			0 => Self::All,
+			1 => flat.pop().unwrap_or(Self::All),
+			_ => Self::Or(flat),
+		}
+	}
+
+	/// Evaluate against one file's sorted trigram set.
+	fn matches(&self, trigrams: &[u32]) -> bool {
+		match self {
+			Self::All => true,
+			Self::Trigram(trigram) => trigrams.binary_search(trigram).is_ok(),
+			Self::And(parts) => parts.iter().all(|part| part.matches(trigrams)),
+			Self::Or(parts) => parts.iter().any(|part| part.matches(trigrams)),
+		}
+	}
+}
+
+const fn trigram(a: u8, b: u8, c: u8) -> u32 {
+	u32::from_be_bytes([0, a.to_ascii_lowercase(), b.to_ascii_lowercase(), c.to_ascii_lowercase()])
+}
+
+/// All trigrams of a literal. Under case-insensitive matching, non-ASCII bytes
+/// may match other byte sequences, so they split the literal.
+fn literal_query(bytes: &[u8], case_insensitive: bool) -> Query {
+	let mut parts = Vec::new();
+	let runs: Box<dyn Iterator<Item = &[u8]>> = if case_insensitive {
+		Box::new(bytes.split(|byte| !byte.is_ascii()))
+	} else {
+		Box::new(std::iter::once(bytes))
+	};
+	for run in runs {
+		parts.extend(
+			run.windows(3)
+				.map(|window| Query::Trigram(trigram(window[0], window[1], window[2]))),
+		);
+	}
+	Query::and(parts)
+}
+
+/// Bytes a node contributes to an enclosing literal run, if it is a literal,
+/// a class that folds to one ASCII byte, or a zero-width assertion.
+fn literal_bytes(hir: &Hir) -> Option<Vec<u8>> {
+	match hir.kind() {
+		HirKind::Empty | HirKind::Look(_) => Some(Vec::new()),
+		HirKind::Literal(literal) => Some(literal.0.to_vec()),
+		HirKind::Class(class) => folded_class_byte(class).map(|byte| vec![byte]),
+		_ => None,
+	}
+}
+
+/// The single lowercase ASCII byte a small class matches up to case.
+fn folded_class_byte(class: &Class) -> Option<u8> {
+	let mut folded = None;
+	let mut count = 0;
+	let mut fold = |value: u32| -> Option<()> {
+		count += 1;
+		let byte = u8::try_from(value).ok().filter(u8::is_ascii)?;
+		let byte = byte.to_ascii_lowercase();
+		if *folded.get_or_insert(byte) != byte || count > MAX_FOLDED_CLASS_LEN {
+			return None;
+		}
+		Some(())
+	};
+	match class {
+		Class::Unicode(class) => {
+			for range in class.ranges() {
+				for ch in range.start()..=range.end() {
+					fold(ch as u32)?;
+				}
+			}
+		},
+		Class::Bytes(class) => {
+			for range in class.ranges() {
+				for byte in range.start()..=range.end() {
+					fold(u32::from(byte))?;
+				}
+			}
+		},
+	}
+	folded
+}
+
+fn hir_query(hir: &Hir) -> Query {
+	match hir.kind() {
+		HirKind::Empty | HirKind::Look(_) | HirKind::Class(_) => Query::All,
+		HirKind::Literal(literal) => literal_query(&literal.0, false),
+		HirKind::Repetition(repetition) if repetition.min > 0 => hir_query(&repetition.sub),
+		HirKind::Repetition(_) => Query::All,
+		HirKind::Capture(capture) => hir_query(&capture.sub),
+		HirKind::Concat(subs) => {
+			// Adjacent literal pieces form one run so trigrams span them.
+			let mut parts = Vec::new();
+			let mut run = Vec::new();
+			for sub in subs {
+				if let Some(bytes) = literal_bytes(sub) {
+					run.extend(bytes);
+					continue;
+				}
+				parts.push(literal_query(&std::mem::take(&mut run), false));
+				parts.push(hir_query(sub));
+			}
+			parts.push(literal_query(&run, false));
+			Query::and(parts)
+		},
+		HirKind::Alternation(subs) => Query::or(subs.iter().map(hir_query)),
+	}
+}
+
+fn intersect(a: &[u32], b: &[u32]) -> Vec<u32> {
+	let mut out = Vec::with_capacity(a.len().min(b.len()));
+	let (mut i, mut j) = (0, 0);
+	while i < a.len() && j < b.len() {
+		match a[i].cmp(&b[j]) {
+			std::cmp::Ordering::Less => i += 1,
+			std::cmp::Ordering::Greater => j += 1,
+			std::cmp::Ordering::Equal => {
+				out.push(a[i]);
+				i += 1;
+				j += 1;
+			},
+		}
+	}
+	out
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// Index
+// ═══════════════════════════════════════════════════════════════════════════
+
+struct IndexedFile {
+	path:    String,
+	size:    u64,
+	mtime:   u64,
+	/// Whether the file's trigrams are indexed; other files are always
+	/// searched.
+	indexed: bool,
+}
+
+struct TrigramIndex {
+	root:       PathBuf,
+	files:      Vec<IndexedFile>,
+	by_path:    HashMap<String, usize>,
+	/// Sorted ids of the files containing each trigram.
+	postings:   HashMap<u32, Vec<u32>>,
+	/// Trigrams of files re-tokenized since `postings` was merged (empty when
+	/// unindexed). These files are answered from their own sets and their
+	/// entries in `postings` are stale.
+	pending:    HashMap<usize, Vec<u32>>,
+	updated_at: f64,
+}
+
+/// Size and mtime (ms) of a scanned file, as recorded by `fs_cache`.
+fn stamp(entry: &GlobMatch) -> (u64, u64) {
+	(entry.size.unwrap_or(0.0) as u64, entry.mtime.unwrap_or(0.0) as u64)
+}
+
+fn now_ms() -> f64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0.0, |duration| duration.as_millis() as f64)
+}
+
+/// Read and tokenize one file; `None` when it must always be searched.
+fn tokenize(path: &Path, size: u64) -> Option<Vec<u32>> {
+	if size > MAX_INDEXED_FILE_BYTES {
+		return None;
+	}
+	let content = std::fs::read(path).ok()?;
+	let prefix = &content[..content.len().min(ENCODING_SNIFF_BYTES as usize)];
+	if TextEncoding::detect(prefix) != TextEncoding::Utf8 || content.contains(&0) {
+		return None;
+	}
+	let mut trigrams: Vec<u32> = content
+		.windows(3)
+		.map(|window| trigram(window[0], window[1], window[2]))
+		.collect();
+	trigrams.sort_unstable();
+	trigrams.dedup();
+	Some(trigrams)
+}
+
+impl TrigramIndex {
+	fn new(root: PathBuf) -> Self {
+		Self {
+			root,
+			files: Vec::new(),
+			by_path: HashMap::new(),
+			postings: HashMap::new(),
+			pending: HashMap::new(),
+			updated_at: now_ms(),
+		}
+	}
+
+	fn from_parts(
+		root: PathBuf,
+		files: Vec<IndexedFile>,
+		postings: HashMap<u32, Vec<u32>>,
+		updated_at: f64,
+	) -> Self {
+		let by_path = files
+			.iter()
+			.enumerate()
+			.map(|(id, file)| (file.path.clone(), id))
+			.collect();
+		Self { files, by_path, postings, updated_at, ..Self::new(root) }
+	}
+
+	/// Fold the trigram sets of re-tokenized files into `postings`.
+	fn merge_pending(&mut self) {
+		if self.pending.is_empty() {
+			return;
+		}
+		let pending = std::mem::take(&mut self.pending);
+		self.postings.retain(|_, ids| {
+			ids.retain(|&id| !pending.contains_key(&(id as usize)));
+			!ids.is_empty()
+		});
+		for (id, trigrams) in pending {
+			for trigram in trigrams {
+				self.postings.entry(trigram).or_default().push(id as u32);
+			}
+		}
+		for ids in self.postings.values_mut() {
+			ids.sort_unstable();
+		}
+	}
+
+	/// Drop files missing from `present`, renumbering the rest.
+	fn prune(&mut self, present: &HashSet<&str>) -> usize {
+		let mut remap = Vec::with_capacity(self.files.len());
+		let mut kept = 0u32;
+		for file in &self.files {
+			if present.contains(file.path.as_str()) {
+				remap.push(Some(kept));
+				kept += 1;
+			} else {
+				remap.push(None);
+			}
+		}
+		let removed = self.files.len() - kept as usize;
+		if removed == 0 {
+			return 0;
+		}
+		let mut id = 0;
+		self.files.retain(|_| {
+			id += 1;
+			remap[id - 1].is_some()
+		});
+		self.by_path = self
+			.files
+			.iter()
+			.enumerate()
+			.map(|(id, file)| (file.path.clone(), id))
+			.collect();
+		self.postings.retain(|_, ids| {
+			*ids = ids.iter().filter_map(|&id| remap[id as usize]).collect();
+			!ids.is_empty()
+		});
+		self.pending = std::mem::take(&mut self.pending)
+			.into_iter()
+			.filter_map(|(id, trigrams)| Some((remap[id]? as usize, trigrams)))
+			.collect();
+		removed
+	}
+
+	/// Re-tokenize new and changed files from `scanned`. With `prune`, files
+	/// missing from the scan are dropped as well. Returns how many files
+	/// changed.
+	fn refresh(
+		&mut self,
+		scanned: &[GlobMatch],
+		prune: bool,
+		ct: &task::CancelToken,
+	) -> Result<usize> {
+		let stale: Vec<(Option<usize>, &GlobMatch)> = scanned
+			.iter()
+			.filter(|entry| entry.file_type == FileType::File)
+			.filter_map(|entry| {
+				let id = self.by_path.get(&entry.path).copied();
+				let fresh = id.is_some_and(|id| {
+					let file = &self.files[id];
+					(file.size, file.mtime) == stamp(entry)
+				});
+				(!fresh).then_some((id, entry))
+			})
+			.collect();
+		let root = &self.root;
+		let tokenized = stale
+			.par_iter()
+			.map(|(id, entry)| {
+				ct.heartbeat()?;
+				let (size, mtime) = stamp(entry);
+				let trigrams = tokenize(&root.join(&entry.path), size);
+				let file =
+					IndexedFile { path: entry.path.clone(), size, mtime, indexed: trigrams.is_some() };
+				Ok((*id, file, trigrams.unwrap_or_default()))
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		let mut changed = tokenized.len();
+		for (id, file, trigrams) in tokenized {
+			let id = id.unwrap_or_else(|| {
+				self.by_path.insert(file.path.clone(), self.files.len());
+				self.files.push(IndexedFile {
+					path:    String::new(),
+					size:    0,
+					mtime:   0,
+					indexed: false,
+				});
+				self.files.len() - 1
+			});
+			self.files[id] = file;
+			self.pending.insert(id, trigrams);
+		}
+
+		if prune {
+			let present: HashSet<&str> = scanned.iter().map(|entry| entry.path.as_str()).collect();
+			changed += self.prune(&present);
+			self.merge_pending();
+		} else if self.pending.len() * 8 > self.files.len() {
+			self.merge_pending();
+		}
+		if changed > 0 {
+			self.updated_at = now_ms();
+		}
+		Ok(changed)
+	}
+
+	/// Scanned files that `candidates` leaves out but whose size or mtime no
+	/// longer matches the index, stamped afresh. `fs_cache` may serve a scan
+	/// from before an edit, so pruning must not rely on its stamps alone.
+	fn restat_pruned(&self, scanned: &[GlobMatch], candidates: &HashSet<String>) -> Vec<GlobMatch> {
+		scanned
+			.par_iter()
+			.filter(|entry| entry.file_type == FileType::File && !candidates.contains(&entry.path))
+			.filter_map(|entry| {
+				let id = *self.by_path.get(&entry.path)?;
+				let (file_type, mtime, size) =
+					fs_cache::classify_file_type(&self.root.join(&entry.path))?;
+				let current = GlobMatch { path: entry.path.clone(), file_type, mtime, size };
+				let file = &self.files[id];
+				((file.size, file.mtime) != stamp(&current)).then_some(current)
+			})
+			.collect()
+	}
+
+	/// File ids from the posting lists satisfying `query`; `None` means every
+	/// file.
+	fn eval(&self, query: &Query) -> Option<Vec<u32>> {
+		match query {
+			Query::All => None,
+			Query::Trigram(trigram) => Some(self.postings.get(trigram).cloned().unwrap_or_default()),
+			Query::And(parts) => parts
+				.iter()
+				.filter_map(|part| self.eval(part))
+				.reduce(|acc, ids| intersect(&acc, &ids)),
+			Query::Or(parts) => {
+				let mut ids = Vec::new();
+				for part in parts {
+					ids.extend(self.eval(part)?);
+				}
+				ids.sort_unstable();
+				ids.dedup();
+				Some(ids)
+			},
+		}
+	}
+
+	/// Paths that may match `query`; `None` when nothing can be pruned.
+	fn candidates(&self, query: &Query) -> Option<HashSet<String>> {
+		let ids = self.eval(query)?;
+		let mut paths: HashSet<String> = ids
+			.into_iter()
+			.map(|id| id as usize)
+			.filter(|id| !self.pending.contains_key(id))
+			.map(|id| self.files[id].path.clone())
+			.collect();
+		for (id, file) in self.files.iter().enumerate() {
+			let candidate = !file.indexed
+				|| self
+					.pending
+					.get(&id)
+					.is_some_and(|trigrams| query.matches(trigrams));
+			if candidate {
+				paths.insert(file.path.clone());
+			}
+		}
+		Some(paths)
+	}
+
+	fn stats(&self) -> GrepIndexStats {
+		let index_path = index_file(&self.root);
+		let disk_bytes = std::fs::metadata(&index_path).map_or(0.0, |meta| meta.len() as f64);
+		let unindexed = self.files.iter().filter(|file| !file.indexed).count();
+		GrepIndexStats {
+			root: self.root.to_string_lossy().into_owned(),
+			index_path: index_path.to_string_lossy().into_owned(),
+			files: crate::utils::clamp_u32(self.files.len() as u64),
+			unindexed_files: crate::utils::clamp_u32(unindexed as u64),
+			trigrams: crate::utils::clamp_u32(self.postings.len() as u64),
+			pending_files: crate::utils::clamp_u32(self.pending.len() as u64),
+			disk_bytes,
+			updated_at: self.updated_at,
+		}
+	}
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// Persistence
+// ═══════════════════════════════════════════════════════════════════════════
+
+/// `$GREP_INDEX_DIR`, else `pi-natives/grep-index` in the user's cache
+/// directory, else in a per-user directory under the temp dir.
+fn index_dir() -> PathBuf {
+	if let Some(dir) = std::env::var_os("GREP_INDEX_DIR") {
+		return PathBuf::from(dir);
+	}
+	user_cache_dir()
+		.map_or_else(temp_cache_dir, |dir| dir.join("pi-natives"))
+		.join("grep-index")
+}
+
+/// `%LOCALAPPDATA%`, `~/Library/Caches`, or `$XDG_CACHE_HOME` (default
+/// `~/.cache`).
+fn user_cache_dir() -> Option<PathBuf> {
+	let var = |name| {
+		std::env::var_os(name)
+			.filter(|value| !value.is_empty())
+			.map(PathBuf::from)
+	};
+	if cfg!(windows) {
+		var("LOCALAPPDATA")
+	} else if cfg!(target_os = "macos") {
+		var("HOME").map(|home| home.join("Library").join("Caches"))
+	} else {
+		var("XDG_CACHE_HOME").or_else(|| var("HOME").map(|home| home.join(".cache")))
+	}
+}
+
+#[cfg(unix)]
+fn temp_cache_dir() -> PathBuf {
+	// SAFETY: `libc::getuid` has no preconditions and cannot fail.
+	let uid = unsafe { libc::getuid() };
+	std::env::temp_dir().join(format!("pi-natives-{uid}"))
+}
+
+/// The temp dir is already per-user on Windows.
+#[cfg(not(unix))]
+fn temp_cache_dir() -> PathBuf {
+	std::env::temp_dir().join("pi-natives")
+}
+
+/// Create `dir` and its missing parents accessible to the current user only,
+/// refusing a directory owned by someone else.
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+	use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+	std::fs::DirBuilder::new()
+		.recursive(true)
+		.mode(0o700)
+		.create(dir)?;
+	let metadata = std::fs::symlink_metadata(dir)?;
+	// SAFETY: `libc::getuid` has no preconditions and cannot fail.
+	if !metadata.is_dir() || metadata.uid() != unsafe { libc::getuid() } {
+		return Err(io::Error::new(
+			io::ErrorKind::PermissionDenied,
+			format!("{} is not a directory owned by the current user", dir.display()),
+		));
+	}
+	if metadata.permissions().mode() & 0o777 != 0o700 {
+		std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+	}
+	Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+	std::fs::create_dir_all(dir)
+}
+
+/// Stable FNV-1a hash used to name index files.
+fn fnv1a(bytes: &[u8]) -> u64 {
+	bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+		(hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+	})
+}
+
+fn index_file(root: &Path) -> PathBuf {
+	index_dir().join(format!("{:016x}.idx", fnv1a(root.as_os_str().as_encoded_bytes())))
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+	while value >= 0x80 {
+		out.push((value as u8) | 0x80);
+		value >>= 7;
+	}
+	out.push(value as u8);
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+	out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+	out.extend_from_slice(bytes);
+}
+
+/// Serialize the file table and the posting lists; pending trigram sets must
+/// be merged first.
+fn encode(index: &TrigramIndex) -> Vec<u8> {
+	debug_assert!(index.pending.is_empty(), "pending trigrams are merged before encoding");
+	let mut out = Vec::new();
+	out.extend_from_slice(MAGIC);
+	write_bytes(&mut out, index.root.as_os_str().as_encoded_bytes());
+	out.extend_from_slice(&index.updated_at.to_le_bytes());
+	out.extend_from_slice(&(index.files.len() as u32).to_le_bytes());
+	for file in &index.files {
+		write_bytes(&mut out, file.path.as_bytes());
+		out.extend_from_slice(&file.size.to_le_bytes());
+		out.extend_from_slice(&file.mtime.to_le_bytes());
+		out.push(u8::from(file.indexed));
+	}
+	out.extend_from_slice(&(index.postings.len() as u32).to_le_bytes());
+	for (&trigram, ids) in &index.postings {
+		out.extend_from_slice(&trigram.to_le_bytes());
+		out.extend_from_slice(&(ids.len() as u32).to_le_bytes());
+		let mut previous = 0;
+		for &id in ids {
+			write_varint(&mut out, id - previous);
+			previous = id;
+		}
+	}
+	out
+}
+
+/// Cursor over a persisted index; every read fails on truncated input.
+struct Reader<'a> {
+	bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+		let (head, tail) = self.bytes.split_at_checked(len)?;
+		self.bytes = tail;
+		Some(head)
+	}
+
+	fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+		self.take(N)?.try_into().ok()
+	}
+
+	fn u32(&mut self) -> Option<u32> {
+		self.array().map(u32::from_le_bytes)
+	}
+
+	fn u64(&mut self) -> Option<u64> {
+		self.array().map(u64::from_le_bytes)
+	}
+
+	fn bytes(&mut self) -> Option<&'a [u8]> {
+		let len = self.u32()? as usize;
+		self.take(len)
+	}
+
+	fn varint(&mut self) -> Option<u32> {
+		let mut value = 0u32;
+		for shift in (0..35).step_by(7) {
+			let byte = self.take(1)?[0];
+			value |= u32::from(byte & 0x7f) << shift;
+			if byte & 0x80 == 0 {
+				return Some(value);
+			}
+		}
+		None
+	}
+}
+
+fn decode(bytes: &[u8], root: &Path) -> Option<TrigramIndex> {
+	let mut reader = Reader { bytes };
+	if reader.take(MAGIC.len())? != MAGIC || reader.bytes()? != root.as_os_str().as_encoded_bytes() {
+		return None;
+	}
+	let updated_at = f64::from_le_bytes(reader.array()?);
+	let count = reader.u32()? as usize;
+	let mut files = Vec::with_capacity(count.min(1 << 20));
+	for _ in 0..count {
+		let path = String::from_utf8(reader.bytes()?.to_vec()).ok()?;
+		let size = reader.u64()?;
+		let mtime = reader.u64()?;
+		let indexed = reader.take(1)?[0] != 0;
+		files.push(IndexedFile { path, size, mtime, indexed });
+	}
+	let trigrams = reader.u32()? as usize;
+	let mut postings = HashMap::with_capacity(trigrams.min(1 << 20));
+	for _ in 0..trigrams {
+		let trigram = reader.u32()?;
+		let len = reader.u32()? as usize;
+		let mut ids = Vec::with_capacity(len.min(count));
+		let mut previous = 0u32;
+		for _ in 0..len {
+			previous = previous.checked_add(reader.varint()?)?;
+			if previous as usize >= count {
+				return None;
+			}
+			ids.push(previous);
+		}
+		postings.insert(trigram, ids);
+	}
+	Some(TrigramIndex::from_parts(root.to_path_buf(), files, postings, updated_at))
+}
+
+fn persist(index: &mut TrigramIndex) -> io::Result<()> {
+	index.merge_pending();
+	let path = index_file(&index.root);
+	let dir = path.parent().unwrap_or_else(|| Path::new("."));
+	create_private_dir(dir)?;
+	let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+	let result =
+		std::fs::write(&temp_path, encode(index)).and_then(|()| std::fs::rename(&temp_path, &path));
+	if result.is_err() {
+		let _ = std::fs::remove_file(&temp_path);
+	}
+	result
+}
+
+fn load(root: &Path) -> Option<TrigramIndex> {
+	let bytes = std::fs::read(index_file(root)).ok()?;
+	decode(&bytes, root)
+}
+
+fn persist_error(err: &io::Error) -> Error {
+	Error::from_reason(format!("Failed to write grep index: {err}"))
+}
+
+/// Canonical key for `root` so equivalent paths share one index.
+fn index_root(root: &Path) -> PathBuf {
+	std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf())
+}
+
+/// The in-memory index for `root`, loading it from disk if needed. Returns
+/// `None` when no index exists and `create` is false.
+fn get_index(root: &Path, create: bool) -> Option<Arc<Mutex<TrigramIndex>>> {
+	if let Some(index) = INDEXES.get(root) {
+		return Some(Arc::clone(&index));
+	}
+	let index = match load(root) {
+		Some(index) => index,
+		None if create => TrigramIndex::new(root.to_path_buf()),
+		None => return None,
+	};
+	let index = INDEXES
+		.entry(root.to_path_buf())
+		.or_insert_with(|| Arc::new(Mutex::new(index)));
+	Some(Arc::clone(&index))
+}
+
+/// Paths under `root` that may match `query`, after bringing the index up to
+/// date with `scanned`. The index is created (and persisted) on first use.
+///
+/// Returns `None` when the query cannot prune anything.
+pub fn candidate_paths(
+	root: &Path,
+	scanned: &[GlobMatch],
+	query: &Query,
+	ct: &task::CancelToken,
+) -> Result<Option<HashSet<String>>> {
+	let key = index_root(root);
+	let Some(index) = get_index(&key, true) else {
+		return Ok(None);
+	};
+	let mut index = index.lock();
+	let was_empty = index.files.is_empty();
+	if index.refresh(scanned, false, ct)? > 0 && was_empty {
+		persist(&mut index).map_err(|err| persist_error(&err))?;
+	}
+	let Some(candidates) = index.candidates(query) else {
+		return Ok(None);
+	};
+	let moved = index.restat_pruned(scanned, &candidates);
+	if moved.is_empty() {
+		return Ok(Some(candidates));
+	}
+	index.refresh(&moved, false, ct)?;
+	Ok(index.candidates(query))
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// N-API
+// ═══════════════════════════════════════════════════════════════════════════
+
+/// Options for building or updating a grep index.
+#[napi(object)]
+pub struct GrepIndexOptions<'env> {
+	/// Root directory to index.
+	pub path:       String,
+	/// Include hidden files (default: true).
+	pub hidden:     Option<bool>,
+	/// Respect .gitignore files (default: true).
+	pub gitignore:  Option<bool>,
+	/// Abort signal for cancelling the operation.
+	pub signal:     Option<Unknown<'env>>,
+	/// Timeout in milliseconds for the operation.
+	#[napi(js_name = "timeoutMs")]
+	pub timeout_ms: Option<u32>,
+}
+
+/// Summary of a persistent grep index.
+#[napi(object)]
+pub struct GrepIndexStats {
+	/// Indexed root directory.
+	pub root:            String,
+	/// Location of the index file.
+	#[napi(js_name = "indexPath")]
+	pub index_path:      String,
+	/// Files tracked by the index.
+	pub files:           u32,
+	/// Files stored without trigrams (non-UTF-8, binary or too large); these
+	/// are always searched.
+	#[napi(js_name = "unindexedFiles")]
+	pub unindexed_files: u32,
+	/// Distinct trigrams in the posting lists.
+	pub trigrams:        u32,
+	/// Files re-tokenized since the posting lists were last rebuilt.
+	#[napi(js_name = "pendingFiles")]
+	pub pending_files:   u32,
+	/// Size of the index file in bytes (0 when not written yet).
+	#[napi(js_name = "diskBytes")]
+	pub disk_bytes:      f64,
+	/// Last change in milliseconds since Unix epoch.
+	#[napi(js_name = "updatedAt")]
+	pub updated_at:      f64,
+}
+
+fn sync_index(
+	options: &GrepIndexConfig,
+	rebuild: bool,
+	ct: &task::CancelToken,
+) -> Result<GrepIndexStats> {
+	let root = index_root(&fs_cache::resolve_search_path(&options.path)?);
+	let scanned =
+		fs_cache::force_rescan(&root, options.include_hidden, options.use_gitignore, true, ct)?;
+	if rebuild {
+		INDEXES.remove(&root);
+	}
+	let index = if rebuild {
+		let index = Arc::new(Mutex::new(TrigramIndex::new(root.clone())));
+		INDEXES.insert(root, Arc::clone(&index));
+		index
+	} else {
+		get_index(&root, true).ok_or_else(|| Error::from_reason("Failed to open grep index"))?
+	};
+	let mut index = index.lock();
+	index.refresh(&scanned, true, ct)?;
+	persist(&mut index).map_err(|err| persist_error(&err))?;
+	Ok(index.stats())
+}
+
+struct GrepIndexConfig {
+	path:           String,
+	include_hidden: bool,
+	use_gitignore:  bool,
+}
+
+fn index_task(
+	tag: &'static str,
+	options: GrepIndexOptions<'_>,
+	rebuild: bool,
+) -> task::Async<GrepIndexStats> {
+	let GrepIndexOptions { path, hidden, gitignore, signal, timeout_ms } = options;
+	let config = GrepIndexConfig {
+		path,
+		include_hidden: hidden.unwrap_or(true),
+		use_gitignore: gitignore.unwrap_or(true),
+	};
+	let ct = task::CancelToken::new(timeout_ms, signal);
+	task::blocking(tag, ct, move |ct| sync_index(&config, rebuild, &ct))
+}
+
+/// Build the trigram index for a directory from scratch and persist it.
+///
+/// # Errors
+/// Returns an error when the path is not a directory, the scan is cancelled,
+/// or the index cannot be written.
+#[napi(js_name = "buildGrepIndex")]
+pub fn build_grep_index(options: GrepIndexOptions<'_>) -> task::Async<GrepIndexStats> {
+	index_task("grep_index_build", options, true)
+}
+
+/// Bring a directory's trigram index up to date: re-tokenize new and changed
+/// files, drop deleted ones, rebuild posting lists and persist. Creates the
+/// index when none exists.
+///
+/// # Errors
+/// Same as [`build_grep_index`].
+#[napi(js_name = "updateGrepIndex")]
+pub fn update_grep_index(options: GrepIndexOptions<'_>) -> task::Async<GrepIndexStats> {
+	index_task("grep_index_update", options, false)
+}
+
+/// Describe the trigram index for a directory, loading it from disk if
+/// needed. Returns `null` when the directory has no index.
+#[napi(js_name = "getGrepIndexStats")]
+pub fn get_grep_index_stats(path: String) -> Result<Option<GrepIndexStats>> {
+	let root = index_root(&fs_cache::resolve_search_path(&path)?);
+	Ok(get_index(&root, false).map(|index| index.lock().stats()))
+}
+
+/// Drop the trigram index for a directory from memory and disk. Returns
+/// whether an index existed.
+#[napi(js_name = "dropGrepIndex")]
+pub fn drop_grep_index(path: String) -> bool {
+	let root = fs_cache::resolve_search_path(&path)
+		.map_or_else(|_| PathBuf::from(&path), |root| index_root(&root));
+	let in_memory = INDEXES.remove(&root).is_some();
+	let on_disk = std::fs::remove_file(index_file(&root)).is_ok();
+	in_memory || on_disk
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::TempDir;
+
+	fn scanned(path: &str, content: &[u8], root: &Path) -> GlobMatch {
+		std::fs::write(root.join(path), content).expect("temp file should be written");
+		let (file_type, mtime, size) =
+			fs_cache::classify_file_type(&root.join(path)).expect("temp file should exist");
+		GlobMatch { path: path.to_string(), file_type, mtime, size }
+	}
+
+	#[test]
+	fn queries_require_literal_trigrams() {
+		let abc = Query::Trigram(trigram(b'a', b'b', b'c'));
+		assert_eq!(Query::for_pattern("abc", false, false), abc);
+		assert_eq!(Query::for_pattern("ABC", false, true), abc);
+		assert_eq!(Query::for_pattern("a.c", false, false), Query::All);
+		assert_eq!(Query::for_pattern("x?abc", false, false), abc);
+		assert_eq!(Query::for_pattern("abc|a.", false, false), Query::All);
+		assert_eq!(Query::for_pattern("(", false, false), Query::All);
+		assert_eq!(Query::for_pattern("a.c", true, false), Query::Trigram(trigram(b'a', b'.', b'c')));
+		assert_eq!(
+			Query::for_pattern("abcd", false, false),
+			Query::And(vec![abc, Query::Trigram(trigram(b'b', b'c', b'd'))])
+		);
+	}
+
+	#[test]
+	fn prunes_and_refreshes_candidates() {
+		let dir = TempDir::new("grep-index");
+		let root = &dir.root;
+		let ct = task::CancelToken::default();
+		let mut index = TrigramIndex::new(root.clone());
+
+		let mut entries = vec![
+			scanned("a.txt", b"needle here", root),
+			scanned("b.txt", b"haystack", root),
+			scanned("c.txt", b"bin\0needle", root),
+		];
+		assert_eq!(index.refresh(&entries, true, &ct).unwrap(), 3);
+		let query = Query::for_pattern("Needle", false, true);
+		let mut paths: Vec<_> = index.candidates(&query).unwrap().into_iter().collect();
+		paths.sort();
+		assert_eq!(paths, ["a.txt", "c.txt"]);
+
+		entries[1] = scanned("b.txt", b"a needle appeared", root);
+		assert_eq!(index.refresh(&entries, false, &ct).unwrap(), 1);
+		assert!(index.candidates(&query).unwrap().contains("b.txt"));
+
+		// A scan served from before an edit still reports the old stamp; the
+		// file is stat'ed again before it is pruned.
+		let stale = entries[1].clone();
+		entries[1] = scanned("b.txt", b"hay again", root);
+		index.refresh(&entries, false, &ct).unwrap();
+		std::fs::write(root.join("b.txt"), b"needle is back").unwrap();
+		let candidates = index.candidates(&query).unwrap();
+		assert!(!candidates.contains("b.txt"));
+		let moved = index.restat_pruned(&[stale], &candidates);
+		assert_eq!(moved.len(), 1);
+		index.refresh(&moved, false, &ct).unwrap();
+		assert!(index.candidates(&query).unwrap().contains("b.txt"));
+
+		index.merge_pending();
+		assert_eq!(index.pending.len(), 0);
+		let restored = decode(&encode(&index), root).expect("index should round-trip");
+		assert_eq!(restored.files.len(), 3);
+		assert_eq!(restored.candidates(&query), index.candidates(&query));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn creates_index_dirs_private_to_the_user() {
+		use std::os::unix::fs::PermissionsExt;
+
+		let temp = TempDir::new("grep-index-dir");
+		let root = temp.root.join("cache");
+		let dir = root.join("grep-index");
+		create_private_dir(&dir).unwrap();
+		std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+		create_private_dir(&dir).unwrap();
+		for path in [&root, &dir] {
+			let mode = std::fs::metadata(path).unwrap().permissions().mode();
+			assert_eq!(mode & 0o777, 0o700);
+		}
+	}
+}
@@ -0,0 +1,231 @@
+//! Syntax classes for `grep` matches: code, comments and string literals.
+//!
+//! # Overview
+//! A file whose extension maps to a [`SupportLang`] is parsed once with its
+//! tree-sitter grammar and flattened into a sorted list of comment and string
+//! spans; every other byte is code. Interpolations inside strings (template
+//! substitutions, f-string fields, shell expansions) are carved back out as
+//! code, so `${user.name}` in a template literal is found by a code search.
+//!
+//! Node kinds are matched by name rather than per grammar: named nodes whose
+//! kind contains `comment` are comments, and named nodes whose kind contains
+//! `string`, `char_literal`, `character_literal` or `heredoc` are strings.
+
+use std::path::Path;
+
+use ast_grep_core::{Language, tree_sitter::LanguageExt};
+use napi::{Either, Error, Result};
+use tree_sitter::{Node, Parser};
+
+use crate::language::SupportLang;
+
+/// Syntax class of a byte in a parsed file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyntaxKind {
+	Code,
+	Comment,
+	String,
+}
+
+impl SyntaxKind {
+	fn parse(name: &str) -> Option<Self> {
+		match name {
+			"code" => Some(Self::Code),
+			"comment" | "comments" => Some(Self::Comment),
+			"string" | "strings" => Some(Self::String),
+			_ => None,
+		}
+	}
+
+	/// Classify a named node kind; `None` for nodes that only contain others.
+	fn of_node(kind: &str) -> Option<Self> {
+		if kind.contains("comment") {
+			Some(Self::Comment)
+		} else if kind.contains("string")
+			|| kind.ends_with("char_literal")
+			|| kind.ends_with("character_literal")
+			|| kind.contains("heredoc")
+		{
+			Some(Self::String)
+		} else {
+			None
+		}
+	}
+}
+
+/// Named node kinds inside a string literal that hold code again.
+fn is_interpolation(kind: &str) -> bool {
+	kind.contains("interpolation")
+		|| kind.contains("substitution")
+		|| kind.contains("interpolated")
+		|| kind.contains("expansion")
+}
+
+/// The syntax classes a search keeps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SyntaxFilter {
+	code:    bool,
+	comment: bool,
+	string:  bool,
+}
+
+impl SyntaxFilter {
+	/// Parse the `syntax` option: class names separated by commas or spaces,
+	/// each optionally negated with `!`. Only negations keep every other class.
+	pub fn parse(spec: Option<&Either<String, Vec<String>>>) -> Result<Option<Self>> {
+		let tokens: Vec<&str> = match spec {
+			None => return Ok(None),
+			Some(Either::A(value)) => vec![value.as_str()],
+			Some(Either::B(values)) => values.iter().map(String::as_str).collect(),
+		};
+
+		let mut include = Vec::new();
+		let mut exclude = Vec::new();
+		let names = tokens
+			.into_iter()
+			.flat_map(|token| token.split([',', ' ', '\t']))
+			.map(str::trim)
+			.filter(|token| !token.is_empty());
+		for token in names {
+			let (negated, name) = match token.strip_prefix('!') {
+				Some(rest) => (true, rest),
+				None => (false, token),
+			};
+			let kind = SyntaxKind::parse(&name.to_lowercase()).ok_or_else(|| {
+				Error::from_reason(format!(
+					"Unknown syntax class `{name}` (expected code, comment or string)"
+				))
+			})?;
+			if negated {
+				exclude.push(kind);
+			} else {
+				include.push(kind);
+			}
+		}
+		if include.is_empty() && exclude.is_empty() {
+			return Ok(None);
+		}
+
+		let keeps =
+			|kind| (include.is_empty() || include.contains(&kind)) && !exclude.contains(&kind);
+		Ok(Some(Self {
+			code:    keeps(SyntaxKind::Code),
+			comment: keeps(SyntaxKind::Comment),
+			string:  keeps(SyntaxKind::String),
+		}))
+	}
+
+	pub const fn allows(self, kind: SyntaxKind) -> bool {
+		match kind {
+			SyntaxKind::Code => self.code,
+			SyntaxKind::Comment => self.comment,
+			SyntaxKind::String => self.string,
+		}
+	}
+}
+
+/// Comment and string spans of one parsed file.
+pub struct SyntaxMap {
+	/// Disjoint `(start, end, kind)` byte ranges sorted by start.
+	spans: Vec<(usize, usize, SyntaxKind)>,
+}
+
+impl SyntaxMap {
+	/// Parse `source` with the grammar for `path`'s extension. Returns `None`
+	/// when no grammar applies or parsing fails.
+	pub fn parse(path: &Path, source: &[u8]) -> Option<Self> {
+		let lang = SupportLang::from_path(path)?;
+		let mut parser = Parser::new();
+		parser.set_language(&lang.get_ts_language()).ok()?;
+		let tree = parser.parse(source, None)?;
+		Some(Self::from_root(tree.root_node()))
+	}
+
+	fn from_root(root: Node<'_>) -> Self {
+		let mut spans = Vec::new();
+		// Explicit stack: deeply nested expressions would overflow recursion.
+		let mut stack = vec![root];
+		while let Some(node) = stack.pop() {
+			let kind = if node.is_named() {
+				SyntaxKind::of_node(node.kind())
+			} else {
+				None
+			};
+			let mut cursor = node.walk();
+			match kind {
+				Some(SyntaxKind::String) => {
+					let mut start = node.start_byte();
+					for child in node.named_children(&mut cursor) {
+						if is_interpolation(child.kind()) {
+							if child.start_byte() > start {
+								spans.push((start, child.start_byte(), SyntaxKind::String));
+							}
+							start = child.end_byte();
+							stack.push(child);
+						}
+					}
+					if node.end_byte() > start {
+						spans.push((start, node.end_byte(), SyntaxKind::String));
+					}
+				},
+				Some(kind) => spans.push((node.start_byte(), node.end_byte(), kind)),
+				None => stack.extend(node.children(&mut cursor)),
+			}
+		}
+		spans.sort_unstable_by_key(|&(start, ..)| start);
+		Self { spans }
+	}
+
+	/// Syntax class of the byte at `offset`.
+	pub fn kind_at(&self, offset: usize) -> SyntaxKind {
+		let index = self.spans.partition_point(|&(start, ..)| start <= offset);
+		match index.checked_sub(1).map(|index| self.spans[index]) {
+			Some((_, end, kind)) if offset < end => kind,
+			_ => SyntaxKind::Code,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn kinds(path: &str, source: &str, needle: &str) -> Vec<SyntaxKind> {
+		let map = SyntaxMap::parse(Path::new(path), source.as_bytes()).expect("grammar should apply");
+		source
+			.match_indices(needle)
+			.map(|(offset, _)| map.kind_at(offset))
+			.collect()
+	}
+
+	#[test]
+	fn classifies_code_comments_and_strings() {
+		use SyntaxKind::{Code, Comment, String};
+
+		let rust = "// call user\nfn user() { let s = \"user\"; /* user */ user(); }\n";
+		assert_eq!(kinds("lib.rs", rust, "user"), [Comment, Code, String, Comment, Code]);
+
+		let ts = "const a = `hi ${user} and user`; // user\nuser;\n";
+		assert_eq!(kinds("a.ts", ts, "user"), [Code, String, Comment, Code]);
+
+		let py = "# user\ndef f(user):\n    return f\"{user} user\"\n";
+		assert_eq!(kinds("a.py", py, "user"), [Comment, Code, Code, String]);
+
+		assert!(SyntaxMap::parse(Path::new("notes.unknown"), b"user").is_none());
+	}
+
+	#[test]
+	fn parses_filter_specs() {
+		let only = |spec: &str| {
+			SyntaxFilter::parse(Some(&Either::A(spec.to_string())))
+				.unwrap()
+				.unwrap()
+		};
+		let comments = only("comment");
+		assert!(comments.allows(SyntaxKind::Comment) && !comments.allows(SyntaxKind::Code));
+		let not_strings = only("!string");
+		assert!(not_strings.allows(SyntaxKind::Code) && !not_strings.allows(SyntaxKind::String));
+		assert!(SyntaxFilter::parse(Some(&Either::A("docs".to_string()))).is_err());
+		assert!(SyntaxFilter::parse(None).unwrap().is_none());
+	}
+}
@@ -0,0 +1,196 @@
+//! Grammars registered at runtime from shared libraries.
+//!
+//! A registered grammar is addressed by a [`DynamicLang`] handle, an index into
+//! a table that only grows. Its library stays loaded for the life of the
+//! process, since parsed trees and compiled patterns point into it.
+
+use std::{borrow::Cow, path::Path, sync::LazyLock};
+
+use ast_grep_core::{
+	Language,
+	matcher::{Pattern, PatternBuilder, PatternError},
+	tree_sitter::{LanguageExt, StrDoc, TSLanguage},
+};
+use libloading::Library;
+use parking_lot::RwLock;
+use tree_sitter_language::LanguageFn;
+
+/// Names, file associations and pattern handling of a grammar to register.
+pub struct GrammarSpec {
+	/// Canonical name, lowercase.
+	pub name:       String,
+	pub aliases:    Vec<String>,
+	/// Extensions without the leading dot.
+	pub extensions: Vec<String>,
+	/// Exact file names such as `Dockerfile`.
+	pub file_names: Vec<String>,
+	/// Stand-in for `$` in patterns when the grammar rejects `$` in
+	/// identifiers; `None` keeps `$` as is.
+	pub expando:    Option<char>,
+}
+
+struct Grammar {
+	spec:     GrammarSpec,
+	/// Leaked once per registration so `canonical_name` can stay `&'static`.
+	name:     &'static str,
+	language: TSLanguage,
+	_library: Option<Library>,
+}
+
+impl Grammar {
+	fn answers_to(&self, name: &str) -> bool {
+		self.spec.name.eq_ignore_ascii_case(name)
+			|| self
+				.spec
+				.aliases
+				.iter()
+				.any(|alias| alias.eq_ignore_ascii_case(name))
+	}
+}
+
+static GRAMMARS: LazyLock<RwLock<Vec<Grammar>>> = LazyLock::new(|| RwLock::new(Vec::new()));
+
+/// Handle of a grammar registered at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DynamicLang(u16);
+
+impl DynamicLang {
+	fn with<R>(self, f: impl FnOnce(&Grammar) -> R) -> R {
+		f(&GRAMMARS.read()[usize::from(self.0)])
+	}
+
+	pub fn name(self) -> &'static str {
+		self.with(|grammar| grammar.name)
+	}
+
+	pub fn aliases(self) -> Vec<String> {
+		self.with(|grammar| grammar.spec.aliases.clone())
+	}
+
+	pub fn extensions(self) -> Vec<String> {
+		self.with(|grammar| grammar.spec.extensions.clone())
+	}
+
+	pub fn file_names(self) -> Vec<String> {
+		self.with(|grammar| grammar.spec.file_names.clone())
+	}
+
+	/// Every registered grammar, in registration order.
+	pub fn all() -> Vec<Self> {
+		(0..GRAMMARS.read().len())
+			.filter_map(|index| u16::try_from(index).ok())
+			.map(Self)
+			.collect()
+	}
+
+	/// The grammar registered under `name` or one of its aliases.
+	pub fn find(name: &str) -> Option<Self> {
+		let grammars = GRAMMARS.read();
+		let index = grammars
+			.iter()
+			.position(|grammar| grammar.answers_to(name))?;
+		u16::try_from(index).ok().map(Self)
+	}
+
+	/// The grammar claiming `path` by file name or extension; the latest
+	/// registration wins.
+	pub fn from_path(path: &Path) -> Option<Self> {
+		let file_name = path.file_name()?.to_str()?;
+		let extension = path.extension().and_then(|ext| ext.to_str());
+		let grammars = GRAMMARS.read();
+		let index = grammars.iter().rposition(|grammar| {
+			grammar.spec.file_names.iter().any(|name| name == file_name)
+				|| extension.is_some_and(|ext| grammar.spec.extensions.iter().any(|known| known == ext))
+		})?;
+		u16::try_from(index).ok().map(Self)
+	}
+}
+
+impl Language for DynamicLang {
+	fn kind_to_id(&self, kind: &str) -> u16 {
+		self.with(|grammar| grammar.language.id_for_node_kind(kind, true))
+	}
+
+	fn field_to_id(&self, field: &str) -> Option<u16> {
+		self.with(|grammar| grammar.language.field_id_for_name(field).map(|f| f.get()))
+	}
+
+	fn expando_char(&self) -> char {
+		self.with(|grammar| grammar.spec.expando).unwrap_or('$')
+	}
+
+	fn pre_process_pattern<'q>(&self, query: &'q str) -> Cow<'q, str> {
+		match self.with(|grammar| grammar.spec.expando) {
+			Some(expando) => super::pre_process_pattern(expando, query),
+			None => Cow::Borrowed(query),
+		}
+	}
+
+	fn build_pattern(&self, builder: &PatternBuilder) -> Result<Pattern, PatternError> {
+		builder.build(|src| StrDoc::try_new(src, *self))
+	}
+}
+
+impl LanguageExt for DynamicLang {
+	fn get_ts_language(&self) -> TSLanguage {
+		self.with(|grammar| grammar.language.clone())
+	}
+}
+
+/// Load the grammar exported as `symbol` (default `tree_sitter_<name>`, with
+/// `-` as `_`) from the shared library at `path` and register it.
+///
+/// Loading runs the library's initializers, so only trusted grammar builds
+/// (e.g. from `tree-sitter build`) should be registered.
+pub fn register_library(
+	spec: GrammarSpec,
+	path: &Path,
+	symbol: Option<&str>,
+) -> Result<DynamicLang, String> {
+	// SAFETY: loading a library runs its initializers; the caller vouches for
+	// the grammar build, as documented on the registration API.
+	let library = unsafe { Library::new(path) }
+		.map_err(|err| format!("Failed to load grammar library {}: {err}", path.display()))?;
+	let symbol =
+		symbol.map_or_else(|| format!("tree_sitter_{}", spec.name.replace('-', "_")), str::to_string);
+	// SAFETY: tree-sitter grammars export their language as a C function with
+	// this signature; the library is stored next to the language it returns,
+	// so the grammar tables outlive every use.
+	let language = unsafe {
+		let function = library
+			.get::<unsafe extern "C" fn() -> *const ()>(symbol.as_bytes())
+			.map_err(|err| format!("Grammar library {} lacks `{symbol}`: {err}", path.display()))?;
+		TSLanguage::new(LanguageFn::from_raw(*function))
+	};
+	register(spec, language, Some(library))
+}
+
+/// Register an already loaded `language` under `spec`.
+pub fn register(
+	spec: GrammarSpec,
+	language: TSLanguage,
+	library: Option<Library>,
+) -> Result<DynamicLang, String> {
+	let abi = language.abi_version();
+	let supported = tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION..=tree_sitter::LANGUAGE_VERSION;
+	if !supported.contains(&abi) {
+		return Err(format!(
+			"Grammar '{}' uses tree-sitter ABI {abi}; supported: {}..={}",
+			spec.name,
+			supported.start(),
+			supported.end()
+		));
+	}
+
+	let mut grammars = GRAMMARS.write();
+	let taken = std::iter::once(&spec.name)
+		.chain(&spec.aliases)
+		.find(|name| grammars.iter().any(|grammar| grammar.answers_to(name)));
+	if let Some(name) = taken {
+		return Err(format!("A grammar named '{name}' is already registered"));
+	}
+	let index = u16::try_from(grammars.len()).map_err(|_| "Too many grammars registered")?;
+	let name = Box::leak(spec.name.clone().into_boxed_str());
+	grammars.push(Grammar { spec, name, language, _library: library });
+	Ok(DynamicLang(index))
+}
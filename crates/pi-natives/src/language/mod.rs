@@ -1,8 +1,10 @@
 //! Vendored and extended language definitions for ast-grep integration.
 //!
 //! Originally derived from `ast-grep-language` v0.39.9, stripped of
-//! serde/ignore machinery, and extended with additional languages.
+//! serde/ignore machinery, and extended with additional languages. Grammars
+//! loaded at runtime join through [`SupportLang::Dynamic`].
 
+pub mod dynamic;
 mod parsers;
 
 use std::{borrow::Cow, collections::HashMap, fmt, path::Path};
@@ -13,6 +15,7 @@ use ast_grep_core::{
 	meta_var::MetaVariable,
 	tree_sitter::{LanguageExt, StrDoc, TSLanguage, TSRange},
 };
+pub use dynamic::{DynamicLang, GrammarSpec};
 
 /// Implements a stub language (no expando / `pre_process_pattern` needed).
 /// Use when the language grammar accepts `$VAR` as valid identifiers.
@@ -142,7 +145,6 @@ impl_lang!(TypeScript, language_typescript);
 impl_lang!(Yaml, language_yaml);
 
 // New stub languages
-impl_lang!(Markdown, language_markdown);
 impl_lang!(Toml, language_toml);
 impl_lang!(Diff, language_diff);
 impl_lang!(Xml, language_xml);
@@ -243,6 +245,67 @@ fn node_to_range<D: Doc>(node: &Node<D>) -> TSRange {
 	TSRange { start_byte: r.start, end_byte: r.end, start_point: sp, end_point: ep }
 }
 
+// ── Markdown (custom implementation with fenced code injections) ────────
+
+#[derive(Clone, Copy, Debug)]
+pub struct Markdown;
+
+impl Language for Markdown {
+	fn kind_to_id(&self, kind: &str) -> u16 {
+		self.get_ts_language().id_for_node_kind(kind, true)
+	}
+
+	fn field_to_id(&self, field: &str) -> Option<u16> {
+		self
+			.get_ts_language()
+			.field_id_for_name(field)
+			.map(|f| f.get())
+	}
+
+	fn build_pattern(&self, builder: &PatternBuilder) -> Result<Pattern, PatternError> {
+		builder.build(|src| StrDoc::try_new(src, *self))
+	}
+}
+
+impl LanguageExt for Markdown {
+	fn get_ts_language(&self) -> TSLanguage {
+		parsers::language_markdown()
+	}
+
+	fn extract_injections<L: LanguageExt>(
+		&self,
+		root: Node<StrDoc<L>>,
+	) -> HashMap<String, Vec<TSRange>> {
+		let mut map = HashMap::new();
+		let matcher = KindMatcher::new("fenced_code_block", root.lang().clone());
+		for block in root.find_all(matcher) {
+			let Some(injected) = find_fence_lang(&block) else {
+				continue;
+			};
+			let content = block.children().find(|c| c.kind() == "code_fence_content");
+			if let Some(content) = content {
+				map.entry(injected)
+					.or_insert_with(Vec::new)
+					.push(node_to_range(&content));
+			}
+		}
+		map
+	}
+}
+
+/// Language of a fenced code block: the leading word of its info string, so
+/// `ts title="a.ts"` and `ts{1,3}` both name `ts`.
+fn find_fence_lang<D: Doc>(block: &Node<D>) -> Option<String> {
+	let info = block.children().find(|c| c.kind() == "info_string")?;
+	let text = info.text();
+	let name: String = text
+		.trim_start()
+		.chars()
+		.take_while(|c| c.is_alphanumeric() || matches!(c, '+' | '#' | '-' | '_'))
+		.collect();
+	(!name.is_empty()).then(|| name.to_ascii_lowercase())
+}
+
 // ── SupportLang enum ────────────────────────────────────────────────────
 
 /// All supported languages for ast-grep structural search/replace.
@@ -286,9 +349,12 @@ pub enum SupportLang {
 	Xml,
 	Yaml,
 	Zig,
+	/// A grammar registered at runtime.
+	Dynamic(DynamicLang),
 }
 
 impl SupportLang {
+	/// Every built-in language; see [`DynamicLang::all`] for registered ones.
 	pub const fn all_langs() -> &'static [Self] {
 		use SupportLang::*;
 		&[
@@ -298,9 +364,15 @@ impl SupportLang {
 		]
 	}
 
+	/// Whether files of this language embed regions of other languages
+	/// (HTML `<script>`/`<style>` blocks, Markdown fenced code).
+	pub const fn has_injections(self) -> bool {
+		matches!(self, Self::Html | Self::Markdown)
+	}
+
 	/// The canonical lowercase name used as a stable key in alias maps,
 	/// file-type inference results, and error messages.
-	pub const fn canonical_name(self) -> &'static str {
+	pub fn canonical_name(self) -> &'static str {
 		match self {
 			Self::Bash => "bash",
 			Self::C => "c",
@@ -340,13 +412,17 @@ impl SupportLang {
 			Self::Xml => "xml",
 			Self::Yaml => "yaml",
 			Self::Zig => "zig",
+			Self::Dynamic(lang) => lang.name(),
 		}
 	}
 }
 
 impl fmt::Display for SupportLang {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-		write!(f, "{self:?}")
+		match self {
+			Self::Dynamic(lang) => f.write_str(lang.name()),
+			_ => write!(f, "{self:?}"),
+		}
 	}
 }
 
@@ -394,6 +470,7 @@ macro_rules! execute_lang_method {
 			S::Xml => Xml.$method($($pname,)*),
 			S::Yaml => Yaml.$method($($pname,)*),
 			S::Zig => Zig.$method($($pname,)*),
+			S::Dynamic(lang) => lang.$method($($pname,)*),
 		}
 	};
 }
@@ -440,6 +517,7 @@ impl LanguageExt for SupportLang {
 	) -> HashMap<String, Vec<TSRange>> {
 		match self {
 			Self::Html => Html.extract_injections(root),
+			Self::Markdown => Markdown.extract_injections(root),
 			_ => HashMap::new(),
 		}
 	}
@@ -447,7 +525,7 @@ impl LanguageExt for SupportLang {
 
 // ── File extension mapping ──────────────────────────────────────────────
 
-const fn extensions(lang: SupportLang) -> &'static [&'static str] {
+pub(crate) const fn extensions(lang: SupportLang) -> &'static [&'static str] {
 	use SupportLang::*;
 	match lang {
 		Bash => {
@@ -462,7 +540,9 @@ const fn extensions(lang: SupportLang) -> &'static [&'static str] {
 		Go => &["go"],
 		Haskell => &["hs"],
 		Hcl => &["hcl", "tf", "tfvars"],
-		Html => &["html", "htm", "xhtml"],
+		// Vue and Svelte components parse as HTML so their <script> and <style>
+		// blocks are searched as injections.
+		Html => &["html", "htm", "xhtml", "vue", "svelte"],
 		Java => &["java"],
 		JavaScript => &["cjs", "js", "mjs", "jsx"],
 		Json => &["json"],
@@ -490,11 +570,15 @@ const fn extensions(lang: SupportLang) -> &'static [&'static str] {
 		Xml => &["xml", "xsl", "xslt", "svg", "plist"],
 		Yaml => &["yaml", "yml"],
 		Zig => &["zig"],
+		Dynamic(_) => &[],
 	}
 }
 
-/// Guess language from file extension.
+/// Guess language from file extension; registered grammars take precedence.
 fn from_extension(path: &Path) -> Option<SupportLang> {
+	if let Some(lang) = DynamicLang::from_path(path) {
+		return Some(SupportLang::Dynamic(lang));
+	}
 	let ext = path.extension()?.to_str()?;
 	// Special cases: Makefile has no extension
 	if ext.is_empty() {
@@ -23,22 +23,37 @@
 
 pub mod appearance;
 pub mod ast;
+pub(crate) mod ast_fix;
+pub(crate) mod ast_rule;
 pub mod clipboard;
+pub(crate) mod diff;
 pub mod fd;
 pub mod fs_cache;
+pub(crate) mod git;
 pub mod glob;
 pub mod glob_util;
+pub mod grammar;
 pub mod grep;
+pub mod grep_index;
+pub(crate) mod grep_syntax;
 pub mod highlight;
 pub mod html;
 pub mod image;
 pub mod keys;
 pub mod language;
+pub mod outline;
+pub mod parse_cache;
 pub mod prof;
 pub mod projfs_overlay;
 pub mod ps;
 pub mod pty;
 pub mod shell;
+pub mod structural_diff;
+pub mod syntax_check;
 pub mod task;
+#[cfg(test)]
+mod test_util;
 pub mod text;
+pub mod ts_query;
+pub mod type_filter;
 pub(crate) mod utils;
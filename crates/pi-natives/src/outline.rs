@@ -0,0 +1,812 @@
+//! Per-file symbol outlines built on the tree-sitter grammars of `astGrep`.
+//!
+//! # Overview
+//! `outline` parses one file (or in-memory `content`) and lists its
+//! declarations in document order, each with a normalized kind, name, one-line
+//! signature, line range and the index of its enclosing symbol. Node kinds are
+//! mapped by name across grammars, plus a few per-language rules: Elixir
+//! `def`/`defmodule` calls, Swift and Kotlin `class` declarations that are
+//! really structs, enums or extensions, and Zig containers bound to `const`.
+//! Function bodies are not descended into, so local helpers stay out.
+//!
+//! Data and markup formats outline as `section` (Markdown headings, TOML
+//! tables, HCL blocks, CSS rules, diff files and hunks), `key` (JSON, YAML,
+//! TOML and Nix keys, two levels deep) and `target` (Make rules). HTML, XML
+//! and regex sources have no outline.
+
+use std::path::Path;
+
+use ast_grep_core::{Doc, Node, tree_sitter::LanguageExt};
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+
+use crate::{ast::load_single_source, language::SupportLang, task};
+
+/// Longest signature returned, in characters.
+const MAX_SIGNATURE_CHARS: usize = 200;
+/// Data keys nested deeper than this are left out.
+const MAX_KEY_DEPTH: u32 = 2;
+
+#[napi(object)]
+pub struct OutlineOptions<'env> {
+	/// File to outline; with `content`, only used to infer the language.
+	pub path:       Option<String>,
+	/// Source text to outline instead of reading `path`.
+	pub content:    Option<String>,
+	pub lang:       Option<String>,
+	pub signal:     Option<Unknown<'env>>,
+	#[napi(js_name = "timeoutMs")]
+	pub timeout_ms: Option<u32>,
+}
+
+#[napi(object)]
+pub struct OutlineSymbol {
+	/// `function`, `method`, `class`, `struct`, `enum`, `trait`, `interface`,
+	/// `const`, `module`, `impl`, `type`, `section`, `key` or `target`.
+	pub kind:       String,
+	/// Tree-sitter node kind, e.g. `function_item`.
+	#[napi(js_name = "nodeKind")]
+	pub node_kind:  String,
+	pub name:       String,
+	/// Declaration header up to its body, whitespace collapsed.
+	pub signature:  String,
+	#[napi(js_name = "startLine")]
+	pub start_line: u32,
+	#[napi(js_name = "endLine")]
+	pub end_line:   u32,
+	/// Index of the enclosing symbol in `symbols`.
+	pub parent:     Option<u32>,
+	/// Nesting depth; top-level symbols are 0.
+	pub depth:      u32,
+}
+
+#[napi(object)]
+pub struct OutlineResult {
+	/// Canonical name of the language used.
+	pub lang:         String,
+	pub symbols:      Vec<OutlineSymbol>,
+	#[napi(js_name = "parseErrors")]
+	pub parse_errors: Option<Vec<String>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SymbolKind {
+	Function,
+	Method,
+	Class,
+	Struct,
+	Enum,
+	Trait,
+	Interface,
+	Const,
+	Module,
+	Impl,
+	Type,
+	Section,
+	Key,
+	Target,
+}
+
+impl SymbolKind {
+	pub(crate) const fn as_str(self) -> &'static str {
+		match self {
+			Self::Function => "function",
+			Self::Method => "method",
+			Self::Class => "class",
+			Self::Struct => "struct",
+			Self::Enum => "enum",
+			Self::Trait => "trait",
+			Self::Interface => "interface",
+			Self::Const => "const",
+			Self::Module => "module",
+			Self::Impl => "impl",
+			Self::Type => "type",
+			Self::Section => "section",
+			Self::Key => "key",
+			Self::Target => "target",
+		}
+	}
+
+	/// Whether declarations nested inside belong to the outline.
+	const fn is_container(self) -> bool {
+		!matches!(self, Self::Function | Self::Method | Self::Const | Self::Type | Self::Target)
+	}
+
+	/// Whether a function declared directly inside is a method.
+	const fn holds_methods(self) -> bool {
+		matches!(
+			self,
+			Self::Class | Self::Struct | Self::Enum | Self::Trait | Self::Interface | Self::Impl
+		)
+	}
+}
+
+/// Symbol kind of a node kind shared by several grammars.
+pub(crate) fn generic_kind(kind: &str) -> Option<SymbolKind> {
+	let kind = match kind {
+		"function_declaration"
+		| "function_definition"
+		| "function_item"
+		| "function_signature_item"
+		| "function_signature"
+		| "generator_function_declaration"
+		| "procedure_declaration"
+		| "task_declaration"
+		| "modifier_definition" => SymbolKind::Function,
+		"method_definition"
+		| "method_declaration"
+		| "method_signature"
+		| "abstract_method_signature"
+		| "method"
+		| "singleton_method"
+		| "constructor_declaration" => SymbolKind::Method,
+		"class_definition"
+		| "class_specifier"
+		| "class"
+		| "abstract_class_declaration"
+		| "object_declaration"
+		| "object_definition"
+		| "companion_object"
+		| "class_interface"
+		| "contract_declaration"
+		| "library_declaration"
+		| "record_declaration" => SymbolKind::Class,
+		"struct_item" | "struct_specifier" | "struct_declaration" | "struct_definition"
+		| "union_item" | "union_specifier" => SymbolKind::Struct,
+		"enum_item" | "enum_specifier" | "enum_declaration" | "enum_definition" => SymbolKind::Enum,
+		"trait_item" | "trait_declaration" | "trait_definition" => SymbolKind::Trait,
+		"interface_declaration" | "protocol_declaration" => SymbolKind::Interface,
+		"const_item" | "static_item" | "const_spec" | "const_element" | "const_statement"
+		| "preproc_def" => SymbolKind::Const,
+		"mod_item"
+		| "module"
+		| "namespace_definition"
+		| "namespace_declaration"
+		| "file_scoped_namespace_declaration"
+		| "internal_module"
+		| "module_definition"
+		| "module_declaration" => SymbolKind::Module,
+		"impl_item" | "class_implementation" => SymbolKind::Impl,
+		"type_item"
+		| "type_alias_declaration"
+		| "type_alias"
+		| "type_definition"
+		| "abstract_definition" => SymbolKind::Type,
+		_ => return None,
+	};
+	Some(kind)
+}
+
+/// Symbol kind of `node`, if it declares one. `parent` is the kind of the
+/// enclosing symbol.
+fn classify<D: Doc>(
+	lang: SupportLang,
+	node: &Node<'_, D>,
+	parent: Option<SymbolKind>,
+) -> Option<SymbolKind> {
+	use SupportLang as L;
+	use SymbolKind as K;
+
+	let kind = node.kind();
+	let top_level = parent.is_none_or(|parent| parent == K::Module);
+	let symbol = match (lang, kind.as_ref()) {
+		(L::Elixir, "call") => elixir_definition(node)?.0,
+		(L::Haskell, "function") => K::Function,
+		(L::Haskell, "bind") if top_level => K::Const,
+		(L::Haskell, "class") => K::Interface,
+		(L::Haskell, "instance") => K::Impl,
+		(L::Haskell, "data_type" | "newtype" | "type_synomym" | "type_family") => K::Type,
+		(L::Haskell, _) => return None,
+		(L::Zig, "variable_declaration") => {
+			let container = node
+				.children()
+				.find_map(|child| match child.kind().as_ref() {
+					"struct_declaration" | "union_declaration" | "opaque_declaration" => Some(K::Struct),
+					"enum_declaration" => Some(K::Enum),
+					_ => None,
+				});
+			match container {
+				Some(kind) => kind,
+				None if top_level && has_token(node, "const") => K::Const,
+				None => return None,
+			}
+		},
+		(L::Zig, "struct_declaration" | "union_declaration" | "enum_declaration") => return None,
+		(L::Python | L::Starlark, "assignment") => {
+			let name = node.field("left")?;
+			let constant = parent.is_none()
+				&& name.kind() == "identifier"
+				&& name
+					.text()
+					.chars()
+					.all(|ch| ch.is_ascii_uppercase() || ch.is_ascii_digit() || ch == '_');
+			if !constant {
+				return None;
+			}
+			K::Const
+		},
+		(L::Kotlin, "property_declaration") => {
+			let constant = node.children().any(|child| {
+				child.kind() == "modifiers"
+					&& child.text().split_whitespace().any(|word| word == "const")
+			});
+			if !(top_level && constant) {
+				return None;
+			}
+			K::Const
+		},
+		(L::JavaScript | L::TypeScript | L::Tsx, "variable_declarator") if top_level => {
+			let value = node.field("value");
+			let is_function = value.as_ref().is_some_and(|value| {
+				matches!(
+					value.kind().as_ref(),
+					"arrow_function" | "function_expression" | "function" | "generator_function"
+				)
+			});
+			let is_const = node
+				.parent()
+				.is_some_and(|decl| decl.kind() == "lexical_declaration" && has_token(&decl, "const"));
+			match (is_function, is_const) {
+				(true, _) => K::Function,
+				(false, true) => K::Const,
+				(false, false) => return None,
+			}
+		},
+		(L::Hcl, "block") => K::Section,
+		(L::Nix, "binding") => {
+			let is_function = node
+				.children()
+				.any(|child| child.kind() == "function_expression");
+			if is_function { K::Function } else { K::Key }
+		},
+		(L::Markdown, "section") => {
+			let heading = node.children().next()?;
+			if !heading.kind().ends_with("heading") {
+				return None;
+			}
+			K::Section
+		},
+		(L::Toml, "table" | "table_array_element") => K::Section,
+		(L::Toml | L::Json, "pair") | (L::Yaml, "block_mapping_pair" | "flow_pair") => K::Key,
+		(L::Css, "rule_set" | "media_statement" | "keyframes_statement" | "supports_statement") => {
+			K::Section
+		},
+		(L::Make, "rule") => K::Target,
+		(L::Make, "variable_assignment") if top_level => K::Const,
+		(L::Diff, "block" | "hunk") => K::Section,
+		(
+			L::Html | L::Xml | L::Regex | L::Json | L::Yaml | L::Toml | L::Css | L::Make | L::Diff,
+			_,
+		) => {
+			return None;
+		},
+		// Declarations without a body only reference the type.
+		(_, "struct_specifier" | "union_specifier" | "enum_specifier" | "class_specifier")
+			if node.field("body").is_none() =>
+		{
+			return None;
+		},
+		(_, "class_declaration") => class_declaration_kind(node),
+		(_, "type_spec") => match node
+			.field("type")
+			.map(|ty| ty.kind().into_owned())
+			.as_deref()
+		{
+			Some("struct_type") => K::Struct,
+			Some("interface_type") => K::Interface,
+			_ => K::Type,
+		},
+		(_, kind) => generic_kind(kind)?,
+	};
+
+	let promote = symbol == K::Function && parent.is_some_and(SymbolKind::holds_methods);
+	Some(if promote { K::Method } else { symbol })
+}
+
+/// Whether `node` has an anonymous child token `token`.
+fn has_token<D: Doc>(node: &Node<'_, D>, token: &str) -> bool {
+	node
+		.children()
+		.any(|child| !child.is_named() && child.kind() == token)
+}
+
+/// Swift and Kotlin spell structs, enums, interfaces and extensions as
+/// `class_declaration` with a different keyword.
+fn class_declaration_kind<D: Doc>(node: &Node<'_, D>) -> SymbolKind {
+	let keyword = node.children().find(|child| !child.is_named());
+	match keyword.as_ref().map(Node::kind).as_deref() {
+		Some("struct") => SymbolKind::Struct,
+		Some("enum") => SymbolKind::Enum,
+		Some("interface") => SymbolKind::Interface,
+		Some("extension") => SymbolKind::Impl,
+		_ => {
+			let is_enum = node.children().any(|child| {
+				child.kind() == "modifiers"
+					&& child.text().split_whitespace().any(|word| word == "enum")
+			});
+			if is_enum {
+				SymbolKind::Enum
+			} else {
+				SymbolKind::Class
+			}
+		},
+	}
+}
+
+/// Kind and name node of an Elixir `def`/`defmodule`/... call.
+fn elixir_definition<'r, D: Doc>(node: &Node<'r, D>) -> Option<(SymbolKind, Node<'r, D>)> {
+	let target = node
+		.field("target")
+		.or_else(|| node.children().find(Node::is_named))?;
+	let kind = match target.text().as_ref() {
+		"def" | "defp" | "defmacro" | "defmacrop" | "defguard" | "defguardp" => SymbolKind::Function,
+		"defmodule" => SymbolKind::Module,
+		"defprotocol" => SymbolKind::Interface,
+		"defimpl" => SymbolKind::Impl,
+		_ => return None,
+	};
+	let arguments = node.children().find(|child| child.kind() == "arguments")?;
+	let mut name = arguments.children().find(Node::is_named)?;
+	// `def name(args)`, `def name(args) when guard`
+	loop {
+		let inner = match name.kind().as_ref() {
+			"call" => name
+				.field("target")
+				.or_else(|| name.children().find(Node::is_named)),
+			"binary_operator" => name.field("left"),
+			_ => None,
+		};
+		match inner {
+			Some(inner) => name = inner,
+			None => break,
+		}
+	}
+	Some((kind, name))
+}
+
+fn is_identifier_kind(kind: &str) -> bool {
+	kind.ends_with("identifier")
+		|| matches!(
+			kind,
+			"name"
+				| "constant"
+				| "word"
+				| "variable"
+				| "alias"
+				| "attrpath"
+				| "bare_key"
+				| "dotted_key"
+				| "quoted_key"
+		)
+}
+
+fn is_body_kind(kind: &str) -> bool {
+	kind.contains("body")
+		|| kind.contains("block")
+		|| matches!(kind, "compound_statement" | "declaration_list" | "field_declaration_list")
+}
+
+/// First identifier among the named children of `node`, looking `depth`
+/// levels into children that are neither bodies nor parameter lists.
+pub(crate) fn first_identifier<'r, D: Doc>(
+	node: &Node<'r, D>,
+	depth: usize,
+) -> Option<Node<'r, D>> {
+	let children: Vec<_> = node.children().filter(Node::is_named).collect();
+	if let Some(ident) = children
+		.iter()
+		.find(|child| is_identifier_kind(&child.kind()))
+	{
+		return Some(ident.clone());
+	}
+	if depth == 0 {
+		return None;
+	}
+	children.iter().find_map(|child| {
+		let kind = child.kind();
+		let skip = is_body_kind(&kind)
+			|| kind.contains("parameter")
+			|| kind.contains("argument")
+			|| generic_kind(&kind).is_some();
+		if skip {
+			None
+		} else {
+			first_identifier(child, depth - 1)
+		}
+	})
+}
+
+fn symbol_name<D: Doc>(lang: SupportLang, node: &Node<'_, D>) -> Option<String> {
+	use SupportLang as L;
+
+	let text = |node: Node<'_, D>| node.text().into_owned();
+	let name = match (lang, node.kind().as_ref()) {
+		(L::Elixir, "call") => text(elixir_definition(node)?.1),
+		(L::Markdown, "section") => {
+			let heading = node.text();
+			let line = heading.lines().next().unwrap_or_default();
+			line.trim().trim_matches('#').trim().to_string()
+		},
+		(L::Css, "rule_set") => text(node.children().find(|child| child.kind() == "selectors")?),
+		(L::Css, _) => header(node),
+		(L::Diff, "block") => {
+			let file = node.children().find(|child| child.kind() == "new_file")?;
+			let path = text(file.children().find(|child| child.kind() == "filename")?);
+			path.strip_prefix("b/").map(str::to_string).unwrap_or(path)
+		},
+		(L::Diff, "hunk") => text(node.children().find(|child| child.kind() == "location")?),
+		(L::Julia, "function_definition") => {
+			let signature = node.children().find(|child| child.kind() == "signature")?;
+			text(first_identifier(&signature, 2)?)
+		},
+		(L::Verilog, "function_declaration" | "task_declaration") => {
+			text(first_identifier(&node.children().find(Node::is_named)?, 0)?)
+		},
+		(L::Make, "rule") => text(node.children().find(|child| child.kind() == "targets")?),
+		(L::Hcl, "block") => node
+			.children()
+			.filter(|child| matches!(child.kind().as_ref(), "identifier" | "string_lit"))
+			.map(|child| child.text().trim_matches('"').to_string())
+			.collect::<Vec<_>>()
+			.join(" "),
+		(L::Json, "pair") => text(node.field("key")?).trim_matches('"').to_string(),
+		(L::Toml, "table" | "table_array_element" | "pair") => text(
+			node
+				.children()
+				.find(|child| is_identifier_kind(&child.kind()))?,
+		),
+		(_, "impl_item") => {
+			let ty = text(node.field("type")?);
+			match node.field("trait") {
+				Some(trait_name) => format!("{} for {ty}", trait_name.text()),
+				None => ty,
+			}
+		},
+		_ => {
+			let named = node.field("name").or_else(|| {
+				let mut declarator = node.field("declarator")?;
+				while let Some(inner) = declarator.field("declarator") {
+					declarator = inner;
+				}
+				Some(declarator)
+			});
+			let named = named
+				.or_else(|| node.field("key"))
+				.or_else(|| node.field("left"))
+				.or_else(|| first_identifier(node, 3))?;
+			text(named)
+		},
+	};
+	let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+	(!name.is_empty()).then_some(name)
+}
+
+/// Body of a declaration, when it has one.
+pub(crate) fn body<'r, D: Doc>(node: &Node<'r, D>) -> Option<Node<'r, D>> {
+	node
+		.field("body")
+		.or_else(|| node.field("value").and_then(|value| value.field("body")))
+		.or_else(|| {
+			node
+				.children()
+				.find(|child| child.is_named() && is_body_kind(&child.kind()))
+		})
+}
+
+fn body_start<D: Doc>(node: &Node<'_, D>) -> Option<usize> {
+	body(node).map(|body| body.range().start)
+}
+
+/// Text of `node` before its body (or its first line), whitespace collapsed
+/// and trailing openers (`{`, `:`, `=`, `do`) removed.
+fn header<D: Doc>(node: &Node<'_, D>) -> String {
+	let text = node.text();
+	let start = node.range().start;
+	let head = match body_start(node) {
+		Some(body) if body > start => &text[..body - start],
+		_ => text.lines().next().unwrap_or_default(),
+	};
+	let mut header = head.split_whitespace().collect::<Vec<_>>().join(" ");
+	loop {
+		let trimmed = header
+			.trim_end_matches(['{', ':', '=', ' '])
+			.trim_end_matches(" do")
+			.len();
+		if trimmed == header.len() {
+			break;
+		}
+		header.truncate(trimmed);
+	}
+	if let Some((cut, _)) = header.char_indices().nth(MAX_SIGNATURE_CHARS) {
+		header.truncate(cut);
+		header.push_str("...");
+	}
+	header
+}
+
+/// 1-based line range; a node ending at column 0 ends on the line before.
+pub(crate) fn line_range<D: Doc>(node: &Node<'_, D>) -> (u32, u32) {
+	let start = node.start_pos();
+	let end = node.end_pos();
+	let end_line = if end.column(node) == 0 && end.line() > start.line() {
+		end.line()
+	} else {
+		end.line() + 1
+	};
+	(to_u32(start.line() + 1), to_u32(end_line))
+}
+
+fn to_u32(value: usize) -> u32 {
+	value.min(u32::MAX as usize) as u32
+}
+
+/// A declaration found by [`declarations`].
+pub(crate) struct Declaration<'r, D: Doc> {
+	pub kind:   SymbolKind,
+	pub name:   String,
+	pub node:   Node<'r, D>,
+	/// Index of the enclosing declaration.
+	pub parent: Option<usize>,
+	pub depth:  u32,
+}
+
+/// Declarations of the tree rooted at `root`, in document order.
+pub(crate) fn declarations<'r, D: Doc>(
+	lang: SupportLang,
+	root: Node<'r, D>,
+	ct: &task::CancelToken,
+) -> Result<Vec<Declaration<'r, D>>> {
+	let mut found: Vec<Declaration<'r, D>> = Vec::new();
+	// Explicit stack of (node, enclosing symbol index); children are pushed
+	// in reverse so they pop in document order. The root itself (Python's
+	// `module`) is never a symbol.
+	let mut stack: Vec<_> = root
+		.children()
+		.map(|child| (child, None::<usize>))
+		.collect();
+	stack.reverse();
+	while let Some((node, parent)) = stack.pop() {
+		ct.heartbeat()?;
+		let parent_kind = parent.map(|index| found[index].kind);
+		let depth = parent.map_or(0, |index| found[index].depth + 1);
+		let symbol = if node.is_named() {
+			classify(lang, &node, parent_kind)
+				.filter(|kind| *kind != SymbolKind::Key || depth < MAX_KEY_DEPTH)
+				.and_then(|kind| Some((kind, symbol_name(lang, &node)?)))
+		} else {
+			None
+		};
+
+		let children_parent = match symbol {
+			Some((kind, name)) => {
+				found.push(Declaration { kind, name, node: node.clone(), parent, depth });
+				if !kind.is_container() {
+					continue;
+				}
+				Some(found.len() - 1)
+			},
+			None => parent,
+		};
+		let first_child = stack.len();
+		stack.extend(node.children().map(|child| (child, children_parent)));
+		stack[first_child..].reverse();
+	}
+	Ok(found)
+}
+
+/// Outline of the tree rooted at `root`, in document order.
+fn collect_symbols<D: Doc>(
+	lang: SupportLang,
+	root: Node<'_, D>,
+	ct: &task::CancelToken,
+) -> Result<Vec<OutlineSymbol>> {
+	let symbols = declarations(lang, root, ct)?
+		.into_iter()
+		.map(|declaration| {
+			let (start_line, end_line) = line_range(&declaration.node);
+			OutlineSymbol {
+				kind: declaration.kind.as_str().to_string(),
+				node_kind: declaration.node.kind().into_owned(),
+				name: declaration.name,
+				signature: header(&declaration.node),
+				start_line,
+				end_line,
+				parent: declaration.parent.map(to_u32),
+				depth: declaration.depth,
+			}
+		})
+		.collect();
+	Ok(symbols)
+}
+
+#[napi(js_name = "outline")]
+pub fn outline(options: OutlineOptions<'_>) -> task::Async<OutlineResult> {
+	let OutlineOptions { path, content, lang, signal, timeout_ms } = options;
+	let ct = task::CancelToken::new(timeout_ms, signal);
+
+	task::blocking("outline", ct, move |ct| {
+		let (language, source) =
+			load_single_source("outline", path.as_deref().map(Path::new), content, lang.as_deref())?;
+
+		let ast = language.ast_grep(source);
+		let parse_errors = ast
+			.root()
+			.dfs()
+			.any(|node| node.is_error())
+			.then(|| vec!["parse error (syntax tree contains error nodes)".to_string()]);
+		let symbols = collect_symbols(language, ast.root(), &ct)?;
+		Ok(OutlineResult { lang: language.canonical_name().to_string(), symbols, parse_errors })
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn outline_of(lang: SupportLang, source: &str) -> Vec<(String, String, Option<u32>)> {
+		let ast = lang.ast_grep(source);
+		let Ok(symbols) = collect_symbols(lang, ast.root(), &task::CancelToken::default()) else {
+			panic!("outline should not be cancelled");
+		};
+		symbols
+			.into_iter()
+			.map(|symbol| (symbol.kind, symbol.name, symbol.parent))
+			.collect()
+	}
+
+	fn kinds_and_names(lang: SupportLang, source: &str) -> Vec<String> {
+		outline_of(lang, source)
+			.into_iter()
+			.map(|(kind, name, _)| format!("{kind} {name}"))
+			.collect()
+	}
+
+	#[test]
+	fn outlines_nested_declarations() {
+		let rust = "mod m {\n    pub struct S;\n    impl Display for S {\n        fn fmt(&self) {\n            fn \
+		            local() {}\n        }\n    }\n    const X: i32 = 1;\n}\nfn main() {}\n";
+		assert_eq!(outline_of(SupportLang::Rust, rust), [
+			("module".to_string(), "m".to_string(), None),
+			("struct".to_string(), "S".to_string(), Some(0)),
+			("impl".to_string(), "Display for S".to_string(), Some(0)),
+			("method".to_string(), "fmt".to_string(), Some(2)),
+			("const".to_string(), "X".to_string(), Some(0)),
+			("function".to_string(), "main".to_string(), None),
+		]);
+
+		let ast = SupportLang::TypeScript.ast_grep(
+			"export const handler = async (event: Event): Promise<void> => {\n  run();\n};\n",
+		);
+		let Ok(symbols) =
+			collect_symbols(SupportLang::TypeScript, ast.root(), &task::CancelToken::default())
+		else {
+			panic!("outline should not be cancelled");
+		};
+		assert_eq!(symbols[0].signature, "handler = async (event: Event): Promise<void> =>");
+		assert_eq!((symbols[0].start_line, symbols[0].end_line), (1, 3));
+	}
+
+	#[test]
+	fn covers_languages() {
+		use SupportLang as L;
+
+		let cases: &[(SupportLang, &str, &[&str])] = &[
+			(
+				L::TypeScript,
+				"const X = 1;\ninterface I { m(): void }\ntype T = 1;\nclass C { m() {} }\n",
+				&["const X", "interface I", "method m", "type T", "class C", "method m"],
+			),
+			(L::Python, "X = 1\n@d\ndef f(a):\n    pass\nclass C:\n    def m(self): pass\n", &[
+				"const X",
+				"function f",
+				"class C",
+				"method m",
+			]),
+			(
+				L::Go,
+				"package p\nconst X = 1\ntype S struct{}\ntype I interface{}\nfunc (s S) M() {}\n",
+				&["const X", "struct S", "interface I", "method M"],
+			),
+			(
+				L::Cpp,
+				"namespace n { class C { void f(); }; }\nstruct S x;\nint g(int x) { return x; }\n",
+				&["module n", "class C", "function g"],
+			),
+			(L::Swift, "struct S {}\nenum E { case a }\nextension S {}\nprotocol P {}\n", &[
+				"struct S",
+				"enum E",
+				"impl S",
+				"interface P",
+			]),
+			(
+				L::Kotlin,
+				"interface I\nenum class E { A }\nconst val X = 1\nobject O { fun f() = 1 }\n",
+				&["interface I", "enum E", "const X", "class O", "method f"],
+			),
+			(L::Elixir, "defmodule M do\n  def f(a), do: a\n  defp g do\n  end\nend\n", &[
+				"module M",
+				"function f",
+				"function g",
+			]),
+			(L::Zig, "const S = struct { fn f() void {} };\npub fn main() void {}\n", &[
+				"struct S",
+				"method f",
+				"function main",
+			]),
+			(L::Julia, "module M\nstruct S end\nfunction f(x) x end\nend\n", &[
+				"module M",
+				"struct S",
+				"function f",
+			]),
+			(L::Ruby, "module M\n  class C\n    def f; end\n  end\nend\n", &[
+				"module M", "class C", "method f",
+			]),
+			(L::Haskell, "data T = T\nclass C a where\ng x = x\n", &[
+				"type T",
+				"interface C",
+				"function g",
+			]),
+			(L::Markdown, "# Title\ntext\n## Sub\nmore\n", &["section Title", "section Sub"]),
+			(L::Json, "{\"a\": {\"b\": {\"c\": 1}}, \"d\": 2}\n", &["key a", "key b", "key d"]),
+			(L::Toml, "[package]\nname = \"x\"\n", &["section package", "key name"]),
+			(L::Hcl, "resource \"aws\" \"web\" {\n  a = 1\n}\n", &["section resource aws web"]),
+			(L::Make, "all: a b\n\techo hi\n", &["target all"]),
+			(L::Css, "a { color: red }\n", &["section a"]),
+			(L::Bash, "f() { echo; }\n", &["function f"]),
+			(L::Java, "class A { static final int X = 1; A() {} void f() {} enum E { B } }\n", &[
+				"class A", "method A", "method f", "enum E",
+			]),
+			(L::CSharp, "namespace N { class C { void F() {} } record R(int A); }\n", &[
+				"module N", "class C", "method F", "class R",
+			]),
+			(
+				L::Php,
+				"<?php\nnamespace N;\nclass C { function f() {} const X = 1; }\nfunction g() {}\n",
+				&["module N", "class C", "method f", "const X", "function g"],
+			),
+			(L::Lua, "local function f() end\nfunction M.g() end\n", &["function f", "function M.g"]),
+			(L::Scala, "object O { def f(x: Int): Int = x }\ntrait T\n", &[
+				"class O", "method f", "trait T",
+			]),
+			(
+				L::ObjC,
+				"@interface A : NSObject\n- (void)f;\n@end\n@implementation A\n- (void)f {}\n@end\n",
+				&["class A", "method f", "impl A", "method f"],
+			),
+			(L::Nix, "{ f = x: x; a = 1; b = { c = 2; }; }\n", &[
+				"function f",
+				"key a",
+				"key b",
+				"key c",
+			]),
+			(L::Yaml, "a:\n  b: 1\nc: 2\n", &["key a", "key b", "key c"]),
+			(L::Odin, "package p\nS :: struct { x: int }\nf :: proc() {}\n", &[
+				"struct S",
+				"function f",
+			]),
+			(L::Verilog, "module m(input a); function f; endfunction endmodule\n", &[
+				"module m",
+				"function f",
+			]),
+			(
+				L::C,
+				"#define N 1\ntypedef int T;\nstruct S { int a; };\nint main(void) { return 0; }\n",
+				&["const N", "type T", "struct S", "function main"],
+			),
+			(L::JavaScript, "function* g() {}\nexport default class A { m() {} }\n", &[
+				"function g",
+				"class A",
+				"method m",
+			]),
+			(L::Starlark, "def f():\n    pass\n", &["function f"]),
+			(L::Solidity, "contract C { function f() public {} }\n", &["class C", "method f"]),
+			(L::Diff, "diff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n+b\n", &[
+				"section x",
+				"section @@ -1 +1 @@",
+			]),
+		];
+		for (lang, source, expected) in cases {
+			assert_eq!(kinds_and_names(*lang, source), *expected, "{lang:?}");
+		}
+	}
+}
@@ -0,0 +1,330 @@
+//! Parsed-tree cache for repeated structural queries (`astGrep`, `astEdit`).
+//!
+//! Trees are keyed by absolute path and language, and a cached tree is only
+//! reused while the file's mtime and size are unchanged. When a file has
+//! changed, or was invalidated through `invalidateFsScanCache`, its previous
+//! tree seeds an incremental tree-sitter re-parse instead of a full one.
+//! Entries unused for longer than the TTL are dropped, and the least recently
+//! used ones go first once either size limit is exceeded.
+//!
+//! # Policy Configuration (environment overrides)
+//! - `AST_PARSE_CACHE_TTL_MS`       – default `300000`; `0` disables the cache
+//! - `AST_PARSE_CACHE_MAX_ENTRIES`  – default `256`
+//! - `AST_PARSE_CACHE_MAX_BYTES`    – default `67108864` (source bytes held)
+
+use std::{
+	io,
+	path::{Path, PathBuf},
+	sync::{
+		LazyLock,
+		atomic::{AtomicUsize, Ordering},
+	},
+	time::{Duration, Instant, SystemTime},
+};
+
+use ast_grep_core::{
+	AstGrep,
+	tree_sitter::{LanguageExt, StrDoc},
+};
+use dashmap::DashMap;
+use tree_sitter::{InputEdit, Parser, Point, Tree};
+
+use crate::language::SupportLang;
+
+// ═══════════════════════════════════════════════════════════════════════════
+// Cache policy
+// ═══════════════════════════════════════════════════════════════════════════
+
+const DEFAULT_CACHE_TTL_MS: u64 = 300_000;
+const DEFAULT_MAX_CACHE_ENTRIES: usize = 256;
+const DEFAULT_MAX_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+fn env_u64(name: &str, default: u64) -> u64 {
+	std::env::var(name)
+		.ok()
+		.and_then(|v| v.parse().ok())
+		.unwrap_or(default)
+}
+
+fn env_usize(name: &str, default: usize) -> usize {
+	std::env::var(name)
+		.ok()
+		.and_then(|v| v.parse().ok())
+		.unwrap_or(default)
+}
+
+fn cache_ttl_ms() -> u64 {
+	env_u64("AST_PARSE_CACHE_TTL_MS", DEFAULT_CACHE_TTL_MS)
+}
+
+fn max_cache_entries() -> usize {
+	env_usize("AST_PARSE_CACHE_MAX_ENTRIES", DEFAULT_MAX_CACHE_ENTRIES)
+}
+
+fn max_cache_bytes() -> usize {
+	env_usize("AST_PARSE_CACHE_MAX_BYTES", DEFAULT_MAX_CACHE_BYTES)
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// Cache internals
+// ═══════════════════════════════════════════════════════════════════════════
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct CacheKey {
+	path: PathBuf,
+	lang: SupportLang,
+}
+
+struct CacheEntry {
+	mtime:     Option<SystemTime>,
+	size:      u64,
+	/// Set by invalidation: the tree must be re-parsed before it is reused,
+	/// but still seeds the incremental re-parse.
+	stale:     bool,
+	source:    String,
+	tree:      Tree,
+	last_used: Instant,
+}
+
+static PARSE_CACHE: LazyLock<DashMap<CacheKey, CacheEntry>> = LazyLock::new(DashMap::new);
+
+/// Source bytes held by [`PARSE_CACHE`], kept in step by [`cache_insert`] and
+/// [`cache_remove`].
+static CACHE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+fn cache_insert(key: CacheKey, entry: CacheEntry) {
+	CACHE_BYTES.fetch_add(entry.source.len(), Ordering::Relaxed);
+	if let Some(old) = PARSE_CACHE.insert(key, entry) {
+		CACHE_BYTES.fetch_sub(old.source.len(), Ordering::Relaxed);
+	}
+}
+
+fn cache_remove(key: &CacheKey) -> Option<CacheEntry> {
+	let (_, entry) = PARSE_CACHE.remove(key)?;
+	CACHE_BYTES.fetch_sub(entry.source.len(), Ordering::Relaxed);
+	Some(entry)
+}
+
+/// Drop the entries `keep` rejects.
+fn cache_retain(mut keep: impl FnMut(&CacheEntry) -> bool) {
+	PARSE_CACHE.retain(|_, entry| {
+		let kept = keep(entry);
+		if !kept {
+			CACHE_BYTES.fetch_sub(entry.source.len(), Ordering::Relaxed);
+		}
+		kept
+	});
+}
+
+/// How a parsed file was obtained.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Reuse {
+	/// The cached tree was still current.
+	Hit,
+	/// The file changed and was re-parsed incrementally from the cached tree.
+	Incremental,
+	/// No cached tree existed.
+	Fresh,
+}
+
+/// Drop idle entries, then the least recently used ones until both limits
+/// hold.
+fn evict(ttl: Duration) {
+	cache_retain(|entry| entry.last_used.elapsed() < ttl);
+	let (max_entries, max_bytes) = (max_cache_entries(), max_cache_bytes());
+	let within_limits =
+		|| PARSE_CACHE.len() <= max_entries && CACHE_BYTES.load(Ordering::Relaxed) <= max_bytes;
+	if within_limits() {
+		return;
+	}
+	let mut by_age: Vec<(Instant, CacheKey)> = PARSE_CACHE
+		.iter()
+		.map(|entry| (entry.last_used, entry.key().clone()))
+		.collect();
+	by_age.sort_unstable_by_key(|(last_used, _)| *last_used);
+	for (_, key) in by_age {
+		if within_limits() {
+			break;
+		}
+		cache_remove(&key);
+	}
+}
+
+/// Row and byte column of byte `offset` in `text`.
+fn point_at(text: &str, offset: usize) -> Point {
+	// `offset` may fall inside a multi-byte character, so stay on bytes.
+	let mut lines = text.as_bytes()[..offset].split(|&byte| byte == b'\n');
+	let column = lines.next_back().map_or(0, <[u8]>::len);
+	Point { row: lines.count(), column }
+}
+
+/// The single edit turning `old` into `new`: everything between their common
+/// prefix and common suffix.
+fn input_edit(old: &str, new: &str) -> InputEdit {
+	let prefix = old
+		.bytes()
+		.zip(new.bytes())
+		.take_while(|(a, b)| a == b)
+		.count();
+	let suffix = old
+		.bytes()
+		.rev()
+		.zip(new.bytes().rev())
+		.take(old.len().min(new.len()) - prefix)
+		.take_while(|(a, b)| a == b)
+		.count();
+	InputEdit {
+		start_byte:       prefix,
+		old_end_byte:     old.len() - suffix,
+		new_end_byte:     new.len() - suffix,
+		start_position:   point_at(old, prefix),
+		old_end_position: point_at(old, old.len() - suffix),
+		new_end_position: point_at(new, new.len() - suffix),
+	}
+}
+
+/// Parse `source`, reusing the unchanged parts of `previous` (the tree of
+/// `previous_source`) when given.
+fn parse_source(
+	lang: SupportLang,
+	source: &str,
+	previous: Option<(&str, &Tree)>,
+) -> io::Result<Tree> {
+	let mut parser = Parser::new();
+	parser
+		.set_language(&lang.get_ts_language())
+		.map_err(|err| io::Error::other(format!("Failed to load grammar: {err}")))?;
+	let old_tree = previous.map(|(previous_source, tree)| {
+		let mut tree = tree.clone();
+		tree.edit(&input_edit(previous_source, source));
+		tree
+	});
+	parser
+		.parse(source, old_tree.as_ref())
+		.ok_or_else(|| io::Error::other("Failed to parse source"))
+}
+
+fn parse_with_reuse(
+	path: &Path,
+	lang: SupportLang,
+) -> io::Result<(AstGrep<StrDoc<SupportLang>>, Reuse)> {
+	let ttl = cache_ttl_ms();
+	if ttl == 0 {
+		// Caching disabled – always parse fresh.
+		return Ok((lang.ast_grep(std::fs::read_to_string(path)?), Reuse::Fresh));
+	}
+
+	// Stat before reading, so a write racing the read leaves a stale mtime
+	// behind and forces a re-parse next time.
+	let metadata = std::fs::metadata(path)?;
+	let (mtime, size) = (metadata.modified().ok(), metadata.len());
+	let key = CacheKey { path: path.to_path_buf(), lang };
+	let now = Instant::now();
+
+	if let Some(mut entry) = PARSE_CACHE.get_mut(&key)
+		&& !entry.stale
+		&& entry.mtime.is_some()
+		&& entry.mtime == mtime
+		&& entry.size == size
+	{
+		entry.last_used = now;
+		let doc = StrDoc { src: entry.source.clone(), lang, tree: entry.tree.clone() };
+		return Ok((AstGrep::doc(doc), Reuse::Hit));
+	}
+
+	let source = std::fs::read_to_string(path)?;
+	let previous = cache_remove(&key);
+	let tree = parse_source(
+		lang,
+		&source,
+		previous
+			.as_ref()
+			.map(|entry| (entry.source.as_str(), &entry.tree)),
+	)?;
+	let reuse = if previous.is_some() {
+		Reuse::Incremental
+	} else {
+		Reuse::Fresh
+	};
+
+	if source.len() <= max_cache_bytes() {
+		cache_insert(key, CacheEntry {
+			mtime,
+			size,
+			stale: false,
+			source: source.clone(),
+			tree: tree.clone(),
+			last_used: now,
+		});
+		evict(Duration::from_millis(ttl));
+	}
+	Ok((AstGrep::doc(StrDoc { src: source, lang, tree }), reuse))
+}
+
+// ═══════════════════════════════════════════════════════════════════════════
+// Cache API
+// ═══════════════════════════════════════════════════════════════════════════
+
+/// Read and parse the file at absolute `path` as `lang`, reusing a cached
+/// tree while the file is unchanged.
+pub fn parse_file(path: &Path, lang: SupportLang) -> io::Result<AstGrep<StrDoc<SupportLang>>> {
+	parse_with_reuse(path, lang).map(|(ast, _)| ast)
+}
+
+/// Mark cached trees at or under `target` for re-parsing.
+///
+/// The trees are kept so the next parse of the file can be incremental.
+pub fn invalidate_path(target: &Path) {
+	for mut entry in PARSE_CACHE.iter_mut() {
+		if entry.key().path.starts_with(target) {
+			entry.stale = true;
+		}
+	}
+}
+
+/// Clear the entire parse cache.
+pub fn invalidate_all() {
+	cache_retain(|_| false);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::TempDir;
+
+	fn parse(path: &Path) -> (String, Reuse) {
+		let (ast, reuse) = parse_with_reuse(path, SupportLang::Rust).expect("file should parse");
+		(ast.root().get_inner_node().to_sexp(), reuse)
+	}
+
+	#[test]
+	fn reuses_trees_until_the_file_changes() {
+		let dir = TempDir::new("parse-cache");
+		let path = dir.write("reuse.rs", "fn main() {}\n");
+		assert_eq!(parse(&path).1, Reuse::Fresh);
+		assert_eq!(parse(&path).1, Reuse::Hit);
+
+		let edited = "fn main() {}\nfn helper(x: u32) -> u32 { x }\n";
+		std::fs::write(&path, edited).expect("temp file should be rewritten");
+		let (sexp, reuse) = parse(&path);
+		assert_eq!(reuse, Reuse::Incremental);
+		let fresh = SupportLang::Rust.ast_grep(edited);
+		assert_eq!(sexp, fresh.root().get_inner_node().to_sexp());
+
+		invalidate_path(&path);
+		assert_eq!(parse(&path).1, Reuse::Incremental);
+		assert_eq!(parse(&path).1, Reuse::Hit);
+	}
+
+	#[test]
+	fn incremental_edits_cover_the_changed_span() {
+		let edit = input_edit("fn a() {}\nfn b() {}\n", "fn a() {}\nfn bc() {}\n");
+		assert_eq!((edit.start_byte, edit.old_end_byte, edit.new_end_byte), (14, 14, 15));
+		assert_eq!(edit.start_position, Point { row: 1, column: 4 });
+		assert_eq!(edit.new_end_position, Point { row: 1, column: 5 });
+
+		// Repeated bytes around the edit must not make prefix and suffix overlap.
+		let edit = input_edit("aaaa", "aa");
+		assert_eq!((edit.start_byte, edit.old_end_byte, edit.new_end_byte), (2, 4, 2));
+	}
+}
@@ -0,0 +1,632 @@
+//! AST-aware diff between two versions of one source file.
+//!
+//! # Overview
+//! `structuralDiff` parses both versions with the same grammar, lists their
+//! declarations the way `outline` does, and pairs them up: first by qualified
+//! name (the kinds and names of the declaration and its containers), then by
+//! kind and name alone (moved to another container), then by identical
+//! non-empty bodies (renamed). Paired declarations are compared token by
+//! token, split into parameters, the rest of the signature and the body, so
+//! whitespace and layout changes never show up; edits confined to comments are
+//! reported as such. A declaration that keeps its container but changes place
+//! among its siblings is reported as moved. Code outside any declaration
+//! (imports, top-level statements) is compared as a whole.
+
+use std::{
+	collections::{HashMap, HashSet},
+	ops::Range,
+	path::Path,
+};
+
+use ast_grep_core::{
+	Node,
+	tree_sitter::{LanguageExt, StrDoc},
+};
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+
+use crate::{
+	ast::load_single_source,
+	language::SupportLang,
+	outline::{self, SymbolKind},
+	task,
+};
+
+#[napi(object)]
+pub struct StructuralDiffOptions<'env> {
+	/// Source text of the earlier version.
+	pub before:     String,
+	/// Source text of the later version.
+	pub after:      String,
+	pub lang:       Option<String>,
+	/// File both versions belong to; only used to infer the language.
+	pub path:       Option<String>,
+	pub signal:     Option<Unknown<'env>>,
+	#[napi(js_name = "timeoutMs")]
+	pub timeout_ms: Option<u32>,
+}
+
+#[napi(object)]
+pub struct StructuralChange {
+	/// `added`, `removed`, `modified` or `moved`.
+	pub kind:              String,
+	/// Declaration kind as reported by `outline`, e.g. `function`; `module`
+	/// for code outside any declaration.
+	#[napi(js_name = "symbolKind")]
+	pub symbol_kind:       String,
+	/// Declaration name; empty for code outside any declaration.
+	pub name:              String,
+	/// Names of the enclosing declarations and this one, joined with `.`.
+	#[napi(js_name = "qualifiedName")]
+	pub qualified_name:    String,
+	/// What changed, e.g. `body changed` or ``parameter `x` added``.
+	pub details:           Vec<String>,
+	/// One line for the whole change, e.g. `function foo: body changed`.
+	pub summary:           String,
+	/// 1-based line range in `before`, unless added.
+	#[napi(js_name = "beforeStartLine")]
+	pub before_start_line: Option<u32>,
+	#[napi(js_name = "beforeEndLine")]
+	pub before_end_line:   Option<u32>,
+	/// 1-based line range in `after`, unless removed.
+	#[napi(js_name = "afterStartLine")]
+	pub after_start_line:  Option<u32>,
+	#[napi(js_name = "afterEndLine")]
+	pub after_end_line:    Option<u32>,
+}
+
+#[napi(object)]
+pub struct StructuralDiffResult {
+	/// Canonical name of the language used.
+	pub lang:         String,
+	/// Changes in the order of the `after` version; removals sit at their line
+	/// in `before`.
+	pub changes:      Vec<StructuralChange>,
+	/// No change beyond whitespace and layout.
+	pub unchanged:    bool,
+	#[napi(js_name = "parseErrors")]
+	pub parse_errors: Option<Vec<String>>,
+}
+
+type TsNode<'r> = Node<'r, StrDoc<SupportLang>>;
+
+struct Param {
+	name: String,
+	/// Code tokens joined with single spaces.
+	text: String,
+}
+
+/// A declaration reduced to what the comparison needs.
+struct Item {
+	kind:      SymbolKind,
+	name:      String,
+	qualified: String,
+	/// Kinds and names of the containers and this declaration, with an
+	/// occurrence suffix for repeats (overloads, redefinitions).
+	key:       String,
+	parent:    Option<usize>,
+	/// Code tokens outside the body, parameters and nested declarations,
+	/// without the name.
+	signature: Vec<String>,
+	params:    Option<Vec<Param>>,
+	/// Code tokens of the body, nested declarations left out.
+	body:      Option<Vec<String>>,
+	comments:  Vec<String>,
+	lines:     (u32, u32),
+}
+
+/// Declarations of one version and its code outside them.
+struct Version {
+	items:            Vec<Item>,
+	top_level:        Vec<String>,
+	top_comments:     Vec<String>,
+	has_parse_errors: bool,
+}
+
+/// Code tokens and comments under `node`, leaving out subtrees for which
+/// `skip` holds. Whitespace-only tokens are dropped so layout never counts.
+fn tokens(node: &TsNode<'_>, skip: impl Fn(&TsNode<'_>) -> bool) -> (Vec<String>, Vec<String>) {
+	let mut code = Vec::new();
+	let mut comments = Vec::new();
+	let mut stack = vec![node.clone()];
+	while let Some(node) = stack.pop() {
+		if node.kind().contains("comment") {
+			comments.push(node.text().trim().to_string());
+		} else if node.is_leaf() {
+			let text = node.text();
+			if !text.trim().is_empty() {
+				code.push(text.into_owned());
+			}
+		} else {
+			let first_child = stack.len();
+			stack.extend(node.children().filter(|child| !skip(child)));
+			stack[first_child..].reverse();
+		}
+	}
+	(code, comments)
+}
+
+/// Parameter list of a function-like declaration.
+fn parameters<'r>(node: &TsNode<'r>) -> Option<TsNode<'r>> {
+	node
+		.field("parameters")
+		.or_else(|| {
+			node
+				.field("declarator")
+				.and_then(|declarator| declarator.field("parameters"))
+		})
+		.or_else(|| {
+			node
+				.children()
+				.find(|child| child.is_named() && child.kind().contains("parameters"))
+		})
+}
+
+fn param_of(node: &TsNode<'_>) -> Param {
+	let text = tokens(node, |_| false).0.join(" ");
+	let name = if node.kind().ends_with("identifier") {
+		node.text().into_owned()
+	} else {
+		outline::first_identifier(node, 2)
+			.map_or_else(|| text.clone(), |ident| ident.text().into_owned())
+	};
+	Param { name, text }
+}
+
+fn version(lang: SupportLang, source: String, ct: &task::CancelToken) -> Result<Version> {
+	let ast = lang.ast_grep(source);
+	let root = ast.root();
+	let declarations = outline::declarations(lang, root.clone(), ct)?;
+	let ranges: HashSet<Range<usize>> = declarations
+		.iter()
+		.map(|declaration| declaration.node.range())
+		.collect();
+	let nested = |node: &TsNode<'_>| ranges.contains(&node.range());
+
+	let mut items: Vec<Item> = Vec::with_capacity(declarations.len());
+	let mut occurrences: HashMap<String, usize> = HashMap::new();
+	for declaration in &declarations {
+		ct.heartbeat()?;
+		let node = &declaration.node;
+		let body = outline::body(node);
+		let params = parameters(node).filter(|params| {
+			body
+				.as_ref()
+				.is_none_or(|body| !body.range().contains(&params.range().start))
+		});
+		let body_range = body.as_ref().map(Node::range);
+		let params_range = params.as_ref().map(Node::range);
+		let (mut signature, mut comments) = tokens(node, |child| {
+			let range = child.range();
+			nested(child)
+				|| body_range.as_ref() == Some(&range)
+				|| params_range.as_ref() == Some(&range)
+		});
+		if let Some(name) = signature
+			.iter()
+			.position(|token| *token == declaration.name)
+		{
+			signature.remove(name);
+		}
+		let body = body.map(|body| {
+			let (code, body_comments) = tokens(&body, nested);
+			comments.extend(body_comments);
+			code
+		});
+		let params = params.map(|params| {
+			params
+				.children()
+				.filter(|child| child.is_named() && !child.kind().contains("comment"))
+				.map(|child| param_of(&child))
+				.collect()
+		});
+
+		let parent = declaration.parent.map(|index| &items[index]);
+		let base = format!(
+			"{}/{} {}",
+			parent.map_or("", |parent| parent.key.as_str()),
+			declaration.kind.as_str(),
+			declaration.name
+		);
+		let seen = occurrences.entry(base.clone()).or_default();
+		let key = if *seen == 0 {
+			base
+		} else {
+			format!("{base}#{seen}")
+		};
+		*seen += 1;
+		let qualified = match parent {
+			Some(parent) => format!("{}.{}", parent.qualified, declaration.name),
+			None => declaration.name.clone(),
+		};
+		items.push(Item {
+			kind: declaration.kind,
+			name: declaration.name.clone(),
+			qualified,
+			key,
+			parent: declaration.parent,
+			signature,
+			params,
+			body,
+			comments,
+			lines: outline::line_range(node),
+		});
+	}
+
+	let (top_level, top_comments) = tokens(&root, nested);
+	let has_parse_errors = root.dfs().any(|node| node.is_error());
+	Ok(Version { items, top_level, top_comments, has_parse_errors })
+}
+
+/// Whether two kinds can be the same declaration; a function moved into a
+/// class becomes a method.
+fn same_kind(old: SymbolKind, new: SymbolKind) -> bool {
+	use SymbolKind as K;
+	old == new || matches!((old, new), (K::Function, K::Method) | (K::Method, K::Function))
+}
+
+/// Pairs of (before, after) indices of the same declaration.
+fn pair_items(before: &[Item], after: &[Item]) -> Vec<(usize, usize)> {
+	let mut pairs = Vec::new();
+	let mut before_taken = vec![false; before.len()];
+	let mut after_taken = vec![false; after.len()];
+	let by_key: HashMap<&str, usize> = after
+		.iter()
+		.enumerate()
+		.map(|(index, item)| (item.key.as_str(), index))
+		.collect();
+	for (index, item) in before.iter().enumerate() {
+		if let Some(&other) = by_key.get(item.key.as_str()) {
+			pairs.push((index, other));
+			before_taken[index] = true;
+			after_taken[other] = true;
+		}
+	}
+
+	// Same kind and name in another container, then the same body under
+	// another name, where a body without code (`{}`) proves nothing.
+	let passes: [fn(&Item, &Item) -> bool; 2] = [
+		|old, new| same_kind(old.kind, new.kind) && old.name == new.name,
+		|old, new| {
+			let has_code = |body: &Vec<String>| {
+				body
+					.iter()
+					.any(|token| token.chars().any(char::is_alphanumeric))
+			};
+			old.kind == new.kind && old.body.as_ref().is_some_and(has_code) && old.body == new.body
+		},
+	];
+	for same in passes {
+		for (index, item) in before.iter().enumerate() {
+			if before_taken[index] {
+				continue;
+			}
+			if let Some(other) =
+				(0..after.len()).find(|&other| !after_taken[other] && same(item, &after[other]))
+			{
+				pairs.push((index, other));
+				before_taken[index] = true;
+				after_taken[other] = true;
+			}
+		}
+	}
+	pairs.sort_unstable();
+	pairs
+}
+
+/// Before indices of paired declarations whose order among their siblings
+/// changed: those outside the longest run kept in order.
+fn reordered(pairs: &[(usize, usize)], before: &[Item], after: &[Item]) -> HashSet<usize> {
+	let after_of: HashMap<usize, usize> = pairs.iter().copied().collect();
+	let mut siblings: HashMap<Option<usize>, Vec<(usize, usize)>> = HashMap::new();
+	for &(old, new) in pairs {
+		let same_container = match (before[old].parent, after[new].parent) {
+			(None, None) => true,
+			(Some(old_parent), Some(new_parent)) => after_of.get(&old_parent) == Some(&new_parent),
+			_ => false,
+		};
+		if same_container {
+			siblings
+				.entry(after[new].parent)
+				.or_default()
+				.push((old, new));
+		}
+	}
+
+	let mut moved = HashSet::new();
+	for group in siblings.values() {
+		// `pairs` is sorted by before index, so only the after indices can be
+		// out of order; keep their longest increasing subsequence.
+		let mut best = vec![1usize; group.len()];
+		let mut previous = vec![None; group.len()];
+		for current in 0..group.len() {
+			for earlier in 0..current {
+				if group[earlier].1 < group[current].1 && best[earlier] + 1 > best[current] {
+					best[current] = best[earlier] + 1;
+					previous[current] = Some(earlier);
+				}
+			}
+		}
+		let mut kept = HashSet::new();
+		let mut cursor = (0..group.len()).max_by_key(|&index| best[index]);
+		while let Some(index) = cursor {
+			kept.insert(index);
+			cursor = previous[index];
+		}
+		moved.extend(
+			group
+				.iter()
+				.enumerate()
+				.filter(|(index, _)| !kept.contains(index))
+				.map(|(_, &(old, _))| old),
+		);
+	}
+	moved
+}
+
+fn parameter_changes(old: &[Param], new: &[Param]) -> Vec<String> {
+	let find = |params: &[Param], name: &str| params.iter().position(|param| param.name == name);
+	let mut details = Vec::new();
+	for param in new {
+		if find(old, &param.name).is_none() {
+			details.push(format!("parameter `{}` added", param.name));
+		}
+	}
+	for param in old {
+		match find(new, &param.name) {
+			None => details.push(format!("parameter `{}` removed", param.name)),
+			Some(index) if new[index].text != param.text => {
+				details.push(format!("parameter `{}` changed", param.name));
+			},
+			Some(_) => {},
+		}
+	}
+	// With no names added or removed, both lists hold the same names.
+	let names = |params: &[Param]| {
+		params
+			.iter()
+			.map(|param| param.name.clone())
+			.collect::<Vec<_>>()
+	};
+	if details.is_empty() && names(old) != names(new) {
+		details.push("parameters reordered".to_string());
+	}
+	details
+}
+
+/// What changed between two versions of one declaration, names aside.
+fn modifications(old: &Item, new: &Item) -> Vec<String> {
+	let mut details = Vec::new();
+	match (&old.params, &new.params) {
+		(Some(old_params), Some(new_params)) => {
+			details.extend(parameter_changes(old_params, new_params));
+		},
+		(None, None) => {},
+		_ => details.push("parameters changed".to_string()),
+	}
+	if old.signature != new.signature {
+		let has_body = old.body.is_some() || new.body.is_some();
+		details.push(
+			if has_body {
+				"signature changed"
+			} else {
+				"definition changed"
+			}
+			.to_string(),
+		);
+	}
+	if old.body != new.body {
+		details.push("body changed".to_string());
+	}
+	if details.is_empty() && old.comments != new.comments {
+		details.push("comments changed".to_string());
+	}
+	details
+}
+
+fn container(items: &[Item], item: &Item) -> String {
+	item
+		.parent
+		.map_or_else(|| "top level".to_string(), |parent| format!("`{}`", items[parent].qualified))
+}
+
+fn change(kind: &str, item: &Item, details: Vec<String>) -> StructuralChange {
+	let label = format!("{} {}", item.kind.as_str(), item.qualified);
+	let summary = if details.is_empty() {
+		format!("{label} {kind}")
+	} else {
+		format!("{label}: {}", details.join("; "))
+	};
+	StructuralChange {
+		kind: kind.to_string(),
+		symbol_kind: item.kind.as_str().to_string(),
+		name: item.name.clone(),
+		qualified_name: item.qualified.clone(),
+		details,
+		summary,
+		before_start_line: None,
+		before_end_line: None,
+		after_start_line: None,
+		after_end_line: None,
+	}
+}
+
+/// Declaration-level changes from `before` to `after`.
+fn diff_versions(before: &Version, after: &Version) -> Vec<StructuralChange> {
+	let (old_items, new_items) = (&before.items, &after.items);
+	let pairs = pair_items(old_items, new_items);
+	let reordered = reordered(&pairs, old_items, new_items);
+	let after_of: HashMap<usize, usize> = pairs.iter().copied().collect();
+	let paired_after: HashSet<usize> = pairs.iter().map(|&(_, new)| new).collect();
+
+	let mut changes = Vec::new();
+	for &(old_index, new_index) in &pairs {
+		let (old, new) = (&old_items[old_index], &new_items[new_index]);
+		let mut details = Vec::new();
+		if old.name != new.name {
+			details.push(format!("renamed from `{}`", old.name));
+		}
+		let same_container = match (old.parent, new.parent) {
+			(None, None) => true,
+			(Some(old_parent), Some(new_parent)) => after_of.get(&old_parent) == Some(&new_parent),
+			_ => false,
+		};
+		let moved = if !same_container {
+			details.push(format!(
+				"moved from {} to {}",
+				container(old_items, old),
+				container(new_items, new)
+			));
+			true
+		} else if reordered.contains(&old_index) {
+			details.push("reordered".to_string());
+			true
+		} else {
+			false
+		};
+		details.extend(modifications(old, new));
+		if details.is_empty() {
+			continue;
+		}
+		let mut entry = change(if moved { "moved" } else { "modified" }, new, details);
+		entry.before_start_line = Some(old.lines.0);
+		entry.before_end_line = Some(old.lines.1);
+		entry.after_start_line = Some(new.lines.0);
+		entry.after_end_line = Some(new.lines.1);
+		changes.push(entry);
+	}
+
+	// Declarations inside an added or removed container are covered by it.
+	for (index, item) in old_items.iter().enumerate() {
+		if !after_of.contains_key(&index)
+			&& item
+				.parent
+				.is_none_or(|parent| after_of.contains_key(&parent))
+		{
+			let mut entry = change("removed", item, Vec::new());
+			entry.before_start_line = Some(item.lines.0);
+			entry.before_end_line = Some(item.lines.1);
+			changes.push(entry);
+		}
+	}
+	for (index, item) in new_items.iter().enumerate() {
+		if !paired_after.contains(&index)
+			&& item
+				.parent
+				.is_none_or(|parent| paired_after.contains(&parent))
+		{
+			let mut entry = change("added", item, Vec::new());
+			entry.after_start_line = Some(item.lines.0);
+			entry.after_end_line = Some(item.lines.1);
+			changes.push(entry);
+		}
+	}
+	changes.sort_by_key(|entry| entry.after_start_line.or(entry.before_start_line));
+
+	let top_level = if before.top_level != after.top_level {
+		Some("top-level code changed")
+	} else if before.top_comments != after.top_comments {
+		Some("top-level comments changed")
+	} else {
+		None
+	};
+	if let Some(detail) = top_level {
+		changes.insert(0, StructuralChange {
+			kind:              "modified".to_string(),
+			symbol_kind:       "module".to_string(),
+			name:              String::new(),
+			qualified_name:    String::new(),
+			details:           vec![detail.to_string()],
+			summary:           detail.to_string(),
+			before_start_line: None,
+			before_end_line:   None,
+			after_start_line:  None,
+			after_end_line:    None,
+		});
+	}
+	changes
+}
+
+#[napi(js_name = "structuralDiff")]
+pub fn structural_diff(options: StructuralDiffOptions<'_>) -> task::Async<StructuralDiffResult> {
+	let StructuralDiffOptions { before, after, lang, path, signal, timeout_ms } = options;
+	let ct = task::CancelToken::new(timeout_ms, signal);
+
+	task::blocking("structuralDiff", ct, move |ct| {
+		let (language, before) = load_single_source(
+			"structuralDiff",
+			path.as_deref().map(Path::new),
+			Some(before),
+			lang.as_deref(),
+		)?;
+		let before = version(language, before, &ct)?;
+		let after = version(language, after, &ct)?;
+		let parse_errors = [("before", &before), ("after", &after)]
+			.into_iter()
+			.filter(|(_, version)| version.has_parse_errors)
+			.map(|(label, _)| format!("{label}: parse error (syntax tree contains error nodes)"))
+			.collect::<Vec<_>>();
+		let changes = diff_versions(&before, &after);
+		Ok(StructuralDiffResult {
+			lang: language.canonical_name().to_string(),
+			unchanged: changes.is_empty(),
+			changes,
+			parse_errors: (!parse_errors.is_empty()).then_some(parse_errors),
+		})
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn summaries(lang: SupportLang, before: &str, after: &str) -> Vec<String> {
+		let ct = task::CancelToken::default();
+		let (Ok(before), Ok(after)) =
+			(version(lang, before.to_string(), &ct), version(lang, after.to_string(), &ct))
+		else {
+			panic!("parsing should not be cancelled");
+		};
+		diff_versions(&before, &after)
+			.into_iter()
+			.map(|change| change.summary)
+			.collect()
+	}
+
+	#[test]
+	fn ignores_formatting_changes() {
+		let before = "fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n";
+		let after = "fn add(\n\ta: i32,\n\tb: i32,\n) -> i32 { a+b }\n";
+		assert_eq!(summaries(SupportLang::Rust, before, after), Vec::<String>::new());
+
+		let commented = "/// Adds.\nfn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n";
+		assert_eq!(summaries(SupportLang::Rust, before, commented), ["top-level comments changed"]);
+	}
+
+	#[test]
+	fn reports_declaration_changes() {
+		let before = "use std::io;\n\nfn foo() -> u32 {\n    1\n}\n\nfn bar(a: u32) {}\n\nfn gone() \
+		              {}\n\nfn old_name(x: u32) -> u32 {\n    x * 2\n}\n";
+		let after = "use std::fmt;\n\nfn foo() -> u32 {\n    2\n}\n\nfn bar(a: u64, b: u32) \
+		             {}\n\nfn new_name(x: u32) -> u32 {\n    x * 2\n}\n\nfn fresh() {}\n";
+		assert_eq!(summaries(SupportLang::Rust, before, after), [
+			"top-level code changed",
+			"function foo: body changed",
+			"function bar: parameter `b` added; parameter `a` changed",
+			"function new_name: renamed from `old_name`",
+			"function gone removed",
+			"function fresh added",
+		]);
+	}
+
+	#[test]
+	fn reports_moved_declarations() {
+		let before =
+			"class A {\n  run() { return 1; }\n  stop() { return 2; }\n}\nfunction helper() {}\n";
+		let after = "class A {\n  stop() { return 2; }\n  run() { return 1; }\n  helper() {}\n}\n";
+		assert_eq!(summaries(SupportLang::TypeScript, before, after), [
+			"method A.run: reordered",
+			"method A.helper: moved from top level to `A`; signature changed",
+		]);
+	}
+}
@@ -0,0 +1,230 @@
+//! Syntax validation for edited files before they are written.
+//!
+//! # Overview
+//! `validateSyntax` parses one source text with its tree-sitter grammar and
+//! lists every `ERROR` node (input the parser had to skip) and `MISSING` node
+//! (a token the parser inserted to recover, such as a closing brace) with its
+//! 1-based line/column range, the source line it starts on and the kind of its
+//! parent node. Only subtrees flagged with errors are walked, and an `ERROR`
+//! node is reported once without listing the errors nested inside it.
+
+use std::path::Path;
+
+use ast_grep_core::tree_sitter::LanguageExt;
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use tree_sitter::{Node, Parser};
+
+use crate::{ast::load_single_source, task};
+
+/// Errors returned when `limit` is not given.
+const DEFAULT_ERROR_LIMIT: u32 = 100;
+/// Longest excerpt returned, in characters.
+const MAX_EXCERPT_CHARS: usize = 120;
+
+#[napi(object)]
+pub struct ValidateSyntaxOptions<'env> {
+	/// Source text to check; read from `path` when omitted.
+	pub content:    Option<String>,
+	/// File the content belongs to; names the language unless `lang` is set.
+	pub path:       Option<String>,
+	pub lang:       Option<String>,
+	/// Most errors to return (default 100); `totalErrors` counts them all.
+	pub limit:      Option<u32>,
+	pub signal:     Option<Unknown<'env>>,
+	#[napi(js_name = "timeoutMs")]
+	pub timeout_ms: Option<u32>,
+}
+
+#[napi(object)]
+pub struct SyntaxIssue {
+	/// `error` for input the parser skipped, `missing` for an inserted token.
+	pub kind:         String,
+	/// Expected node kind for `missing` (e.g. `}`), otherwise `ERROR`.
+	#[napi(js_name = "nodeKind")]
+	pub node_kind:    String,
+	/// Kind of the enclosing node, e.g. `function_item`.
+	#[napi(js_name = "parentKind")]
+	pub parent_kind:  Option<String>,
+	pub message:      String,
+	#[napi(js_name = "startLine")]
+	pub start_line:   u32,
+	#[napi(js_name = "startColumn")]
+	pub start_column: u32,
+	#[napi(js_name = "endLine")]
+	pub end_line:     u32,
+	#[napi(js_name = "endColumn")]
+	pub end_column:   u32,
+	#[napi(js_name = "byteStart")]
+	pub byte_start:   u32,
+	#[napi(js_name = "byteEnd")]
+	pub byte_end:     u32,
+	/// Source line the issue starts on, trimmed.
+	pub excerpt:      String,
+}
+
+#[napi(object)]
+pub struct ValidateSyntaxResult {
+	/// Canonical name of the language used.
+	pub lang:          String,
+	pub valid:         bool,
+	pub errors:        Vec<SyntaxIssue>,
+	#[napi(js_name = "totalErrors")]
+	pub total_errors:  u32,
+	#[napi(js_name = "limitReached")]
+	pub limit_reached: bool,
+}
+
+fn to_u32(value: usize) -> u32 {
+	value.min(u32::MAX as usize) as u32
+}
+
+/// 1-based line and character column of byte `offset`.
+fn line_column(source: &str, offset: usize) -> (u32, u32) {
+	let offset = source.floor_char_boundary(offset);
+	let line_start = source[..offset].rfind('\n').map_or(0, |index| index + 1);
+	let line = source[..line_start].matches('\n').count();
+	(to_u32(line + 1), to_u32(source[line_start..offset].chars().count() + 1))
+}
+
+/// The trimmed source line containing byte `offset`.
+fn line_excerpt(source: &str, offset: usize) -> String {
+	let offset = source.floor_char_boundary(offset);
+	let line_start = source[..offset].rfind('\n').map_or(0, |index| index + 1);
+	let line_end = source[offset..]
+		.find('\n')
+		.map_or(source.len(), |index| offset + index);
+	let line = source[line_start..line_end].trim();
+	match line.char_indices().nth(MAX_EXCERPT_CHARS) {
+		Some((cut, _)) => format!("{}…", &line[..cut]),
+		None => line.to_string(),
+	}
+}
+
+fn issue_of(node: Node<'_>, source: &str) -> SyntaxIssue {
+	let missing = node.is_missing();
+	let (start_line, start_column) = line_column(source, node.start_byte());
+	let (end_line, end_column) = line_column(source, node.end_byte());
+	let message = if missing {
+		format!("missing `{}`", node.kind())
+	} else {
+		let text = source.get(node.byte_range()).unwrap_or_default();
+		match text.split_whitespace().next() {
+			Some(token) => format!("unexpected `{}`", token.chars().take(40).collect::<String>()),
+			None => "unexpected end of input".to_string(),
+		}
+	};
+	SyntaxIssue {
+		kind: if missing { "missing" } else { "error" }.to_string(),
+		node_kind: node.kind().to_string(),
+		parent_kind: node.parent().map(|parent| parent.kind().to_string()),
+		message,
+		start_line,
+		start_column,
+		end_line,
+		end_column,
+		byte_start: to_u32(node.start_byte()),
+		byte_end: to_u32(node.end_byte()),
+		excerpt: line_excerpt(source, node.start_byte()),
+	}
+}
+
+/// Every `ERROR` and `MISSING` node of `source` in document order.
+fn find_issues(
+	lang: &tree_sitter::Language,
+	source: &str,
+	ct: &task::CancelToken,
+) -> Result<Vec<SyntaxIssue>> {
+	let mut parser = Parser::new();
+	parser
+		.set_language(lang)
+		.map_err(|err| Error::from_reason(format!("Failed to load grammar: {err}")))?;
+	let tree = parser
+		.parse(source, None)
+		.ok_or_else(|| Error::from_reason("Failed to parse source"))?;
+
+	let mut issues = Vec::new();
+	// Explicit stack, children pushed in reverse so issues come out in order.
+	let mut stack = vec![tree.root_node()];
+	while let Some(node) = stack.pop() {
+		ct.heartbeat()?;
+		if node.is_error() || node.is_missing() {
+			issues.push(issue_of(node, source));
+			continue;
+		}
+		let mut cursor = node.walk();
+		let first_child = stack.len();
+		stack.extend(node.children(&mut cursor).filter(|child| child.has_error()));
+		stack[first_child..].reverse();
+	}
+	Ok(issues)
+}
+
+#[napi(js_name = "validateSyntax")]
+pub fn validate_syntax(options: ValidateSyntaxOptions<'_>) -> task::Async<ValidateSyntaxResult> {
+	let ValidateSyntaxOptions { content, path, lang, limit, signal, timeout_ms } = options;
+	let ct = task::CancelToken::new(timeout_ms, signal);
+
+	task::blocking("validateSyntax", ct, move |ct| {
+		let (language, source) = load_single_source(
+			"validateSyntax",
+			path.as_deref().map(Path::new),
+			content,
+			lang.as_deref(),
+		)?;
+		let mut errors = find_issues(&language.get_ts_language(), &source, &ct)?;
+		let total_errors = errors.len();
+		let limit = limit.unwrap_or(DEFAULT_ERROR_LIMIT) as usize;
+		errors.truncate(limit);
+		Ok(ValidateSyntaxResult {
+			lang: language.canonical_name().to_string(),
+			valid: total_errors == 0,
+			errors,
+			total_errors: to_u32(total_errors),
+			limit_reached: total_errors > limit,
+		})
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::language::SupportLang;
+
+	fn issues(lang: SupportLang, source: &str) -> Vec<SyntaxIssue> {
+		let Ok(issues) = find_issues(&lang.get_ts_language(), source, &task::CancelToken::default())
+		else {
+			panic!("parsing should not be cancelled");
+		};
+		issues
+	}
+
+	#[test]
+	fn accepts_valid_source() {
+		assert!(issues(SupportLang::Rust, "fn main() {\n\tlet x = 1;\n}\n").is_empty());
+		assert!(issues(SupportLang::Python, "def f():\n    return 1\n").is_empty());
+	}
+
+	#[test]
+	fn reports_missing_tokens() {
+		let found = issues(SupportLang::Rust, "fn main() {\n\tlet x = 1\n}\n");
+		assert_eq!(found.len(), 1);
+		let issue = &found[0];
+		assert_eq!((issue.kind.as_str(), issue.message.as_str()), ("missing", "missing `;`"));
+		assert_eq!(issue.parent_kind.as_deref(), Some("let_declaration"));
+		assert_eq!((issue.start_line, issue.start_column), (2, 11));
+		assert_eq!(issue.excerpt, "let x = 1");
+	}
+
+	#[test]
+	fn reports_skipped_input() {
+		let source = "const a = 1;\nconst b = ) 2;\nconst c = 3;\n";
+		let found = issues(SupportLang::TypeScript, source);
+		assert!(!found.is_empty());
+		let issue = &found[0];
+		assert_eq!((issue.kind.as_str(), issue.node_kind.as_str()), ("error", "ERROR"));
+		assert_eq!(issue.start_line, 2);
+		assert_eq!(issue.excerpt, "const b = ) 2;");
+		assert!(issue.message.starts_with("unexpected `"), "{}", issue.message);
+	}
+}
@@ -0,0 +1,42 @@
+//! Helpers shared by the crate's unit tests.
+
+use std::{
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicU64, Ordering},
+};
+
+/// A fresh directory under the system temp dir, removed on drop.
+///
+/// Names combine the process id with a per-process counter, so tests running
+/// in parallel (or in concurrent test binaries) never share a directory.
+pub struct TempDir {
+	pub root: PathBuf,
+}
+
+impl TempDir {
+	pub fn new(name: &str) -> Self {
+		static NEXT: AtomicU64 = AtomicU64::new(0);
+		let id = NEXT.fetch_add(1, Ordering::Relaxed);
+		let root =
+			std::env::temp_dir().join(format!("pi-natives-test-{}-{id}-{name}", std::process::id()));
+		std::fs::create_dir_all(&root).expect("temp dir should be created");
+		Self { root }
+	}
+
+	/// Write `content` to `relative` below the root, creating parent
+	/// directories, and return its path.
+	pub fn write(&self, relative: impl AsRef<Path>, content: impl AsRef<[u8]>) -> PathBuf {
+		let path = self.root.join(relative);
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent).expect("temp parent dir should be created");
+		}
+		std::fs::write(&path, content).expect("temp file should be written");
+		path
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_dir_all(&self.root);
+	}
+}
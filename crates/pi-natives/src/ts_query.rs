@@ -0,0 +1,267 @@
+//! Raw tree-sitter queries against the grammars compiled into the addon.
+//!
+//! # Overview
+//! `treeSitterQuery` compiles an S-expression query (`.scm` syntax) for one
+//! language, runs it over one file or in-memory `content`, and returns each
+//! match with its captures in document order. Text predicates (`#eq?`,
+//! `#not-eq?`, `#match?`, `#not-match?`, `#any-of?` and their `#any-`
+//! variants) are evaluated by tree-sitter while matching. `#set!` properties
+//! are reported on the match, and any other predicate is left to the caller.
+
+use std::{collections::HashMap, path::Path};
+
+use ast_grep_core::tree_sitter::LanguageExt;
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use tree_sitter::{Node, Parser, Query, QueryCursor, QueryMatch, StreamingIterator};
+
+use crate::{ast::load_single_source, task};
+
+/// Matches returned when `limit` is not given.
+const DEFAULT_MATCH_LIMIT: u32 = 500;
+
+#[napi(object)]
+pub struct TreeSitterQueryOptions<'env> {
+	/// Query source in tree-sitter S-expression syntax.
+	pub query:      String,
+	/// File to query; with `content`, only used to infer the language.
+	pub path:       Option<String>,
+	/// Source text to query instead of reading `path`.
+	pub content:    Option<String>,
+	pub lang:       Option<String>,
+	/// Most matches to return (default 500); `totalMatches` counts them all.
+	pub limit:      Option<u32>,
+	pub signal:     Option<Unknown<'env>>,
+	#[napi(js_name = "timeoutMs")]
+	pub timeout_ms: Option<u32>,
+}
+
+#[napi(object)]
+pub struct TreeSitterCapture {
+	/// Capture name without the leading `@`.
+	pub name:         String,
+	/// Tree-sitter node kind, e.g. `identifier`.
+	pub kind:         String,
+	pub text:         String,
+	#[napi(js_name = "byteStart")]
+	pub byte_start:   u32,
+	#[napi(js_name = "byteEnd")]
+	pub byte_end:     u32,
+	/// Zero-based row of the first byte.
+	#[napi(js_name = "startRow")]
+	pub start_row:    u32,
+	/// Zero-based byte column of the first byte.
+	#[napi(js_name = "startColumn")]
+	pub start_column: u32,
+	#[napi(js_name = "endRow")]
+	pub end_row:      u32,
+	/// Zero-based byte column just past the last byte.
+	#[napi(js_name = "endColumn")]
+	pub end_column:   u32,
+}
+
+#[napi(object)]
+pub struct TreeSitterQueryMatch {
+	/// Index of the matching pattern within the query.
+	#[napi(js_name = "patternIndex")]
+	pub pattern_index: u32,
+	pub captures:      Vec<TreeSitterCapture>,
+	/// `#set!` properties of the pattern; a key without a value maps to "".
+	pub properties:    Option<HashMap<String, String>>,
+}
+
+#[napi(object)]
+pub struct TreeSitterQueryResult {
+	/// Canonical name of the language used.
+	pub lang:          String,
+	/// Capture names declared by the query, in capture-index order.
+	#[napi(js_name = "captureNames")]
+	pub capture_names: Vec<String>,
+	pub matches:       Vec<TreeSitterQueryMatch>,
+	#[napi(js_name = "totalMatches")]
+	pub total_matches: u32,
+	#[napi(js_name = "limitReached")]
+	pub limit_reached: bool,
+	#[napi(js_name = "parseErrors")]
+	pub parse_errors:  Option<Vec<String>>,
+}
+
+fn to_u32(value: usize) -> u32 {
+	value.min(u32::MAX as usize) as u32
+}
+
+fn capture_of(query: &Query, index: u32, node: Node<'_>, source: &str) -> TreeSitterCapture {
+	let (start, end) = (node.start_position(), node.end_position());
+	TreeSitterCapture {
+		name:         query.capture_names()[index as usize].to_string(),
+		kind:         node.kind().to_string(),
+		text:         source
+			.get(node.byte_range())
+			.unwrap_or_default()
+			.to_string(),
+		byte_start:   to_u32(node.start_byte()),
+		byte_end:     to_u32(node.end_byte()),
+		start_row:    to_u32(start.row),
+		start_column: to_u32(start.column),
+		end_row:      to_u32(end.row),
+		end_column:   to_u32(end.column),
+	}
+}
+
+fn match_of(query: &Query, found: &QueryMatch<'_, '_>, source: &str) -> TreeSitterQueryMatch {
+	let settings = query.property_settings(found.pattern_index);
+	TreeSitterQueryMatch {
+		pattern_index: to_u32(found.pattern_index),
+		captures:      found
+			.captures
+			.iter()
+			.map(|capture| capture_of(query, capture.index, capture.node, source))
+			.collect(),
+		properties:    (!settings.is_empty()).then(|| {
+			settings
+				.iter()
+				.map(|property| {
+					(property.key.to_string(), property.value.as_deref().unwrap_or_default().to_string())
+				})
+				.collect()
+		}),
+	}
+}
+
+/// Run `query_source` over `source`, keeping the first `limit` matches.
+fn run_query(
+	ts_language: &tree_sitter::Language,
+	query_source: &str,
+	source: &str,
+	limit: u32,
+	ct: &task::CancelToken,
+) -> Result<(Query, Vec<TreeSitterQueryMatch>, u32, bool)> {
+	let query = Query::new(ts_language, query_source)
+		.map_err(|err| Error::from_reason(format!("Invalid tree-sitter query: {err}")))?;
+	let mut parser = Parser::new();
+	parser
+		.set_language(ts_language)
+		.map_err(|err| Error::from_reason(format!("Failed to load grammar: {err}")))?;
+	let tree = parser
+		.parse(source, None)
+		.ok_or_else(|| Error::from_reason("Failed to parse source"))?;
+	let root = tree.root_node();
+
+	let mut matches = Vec::new();
+	let mut total_matches = 0u32;
+	let mut cursor = QueryCursor::new();
+	let mut found = cursor.matches(&query, root, source.as_bytes());
+	while let Some(found) = found.next() {
+		ct.heartbeat()?;
+		total_matches = total_matches.saturating_add(1);
+		if matches.len() < limit as usize {
+			matches.push(match_of(&query, found, source));
+		}
+	}
+	Ok((query, matches, total_matches, root.has_error()))
+}
+
+#[napi(js_name = "treeSitterQuery")]
+pub fn tree_sitter_query(
+	options: TreeSitterQueryOptions<'_>,
+) -> task::Async<TreeSitterQueryResult> {
+	let TreeSitterQueryOptions { query, path, content, lang, limit, signal, timeout_ms } = options;
+	let ct = task::CancelToken::new(timeout_ms, signal);
+
+	task::blocking("treeSitterQuery", ct, move |ct| {
+		let (language, source) = load_single_source(
+			"treeSitterQuery",
+			path.as_deref().map(Path::new),
+			content,
+			lang.as_deref(),
+		)?;
+		let limit = limit.unwrap_or(DEFAULT_MATCH_LIMIT);
+		let (query, matches, total_matches, has_error) =
+			run_query(&language.get_ts_language(), &query, &source, limit, &ct)?;
+		Ok(TreeSitterQueryResult {
+			lang: language.canonical_name().to_string(),
+			capture_names: query
+				.capture_names()
+				.iter()
+				.map(ToString::to_string)
+				.collect(),
+			limit_reached: total_matches > limit,
+			matches,
+			total_matches,
+			parse_errors: has_error
+				.then(|| vec!["parse error (syntax tree contains error nodes)".to_string()]),
+		})
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::language::SupportLang;
+
+	fn query(lang: SupportLang, query: &str, source: &str) -> Vec<TreeSitterQueryMatch> {
+		let Ok((_, matches, ..)) =
+			run_query(&lang.get_ts_language(), query, source, u32::MAX, &task::CancelToken::default())
+		else {
+			panic!("query should run");
+		};
+		matches
+	}
+
+	#[test]
+	fn reports_captures_with_ranges() {
+		let source = "fn main() {}\nfn helper_one() {}\n";
+		let matches =
+			query(SupportLang::Rust, "(function_item name: (identifier) @name) @item", source);
+		assert_eq!(matches.len(), 2);
+		let name = &matches[1].captures[1];
+		assert_eq!(
+			(name.name.as_str(), name.kind.as_str(), name.text.as_str()),
+			("name", "identifier", "helper_one")
+		);
+		assert_eq!((name.byte_start, name.byte_end), (16, 26));
+		assert_eq!((name.start_row, name.start_column, name.end_row, name.end_column), (1, 3, 1, 13));
+		assert_eq!(matches[1].captures[0].name, "item");
+	}
+
+	#[test]
+	fn applies_text_predicates_and_properties() {
+		let source = "fn main() {}\nfn helper_one() {}\nfn helper_two() {}\n";
+		let names = |query_source: &str| -> Vec<String> {
+			query(SupportLang::Rust, query_source, source)
+				.into_iter()
+				.flat_map(|found| found.captures.into_iter().map(|capture| capture.text))
+				.collect()
+		};
+		assert_eq!(names(r#"((identifier) @name (#eq? @name "main"))"#), ["main"]);
+		assert_eq!(names(r#"((identifier) @name (#match? @name "^helper_"))"#), [
+			"helper_one",
+			"helper_two"
+		]);
+		assert_eq!(names(r#"((identifier) @name (#not-match? @name "one|two"))"#), ["main"]);
+
+		let matches = query(
+			SupportLang::Rust,
+			r#"((identifier) @name (#eq? @name "main") (#set! entry "yes"))"#,
+			source,
+		);
+		let Some(properties) = &matches[0].properties else {
+			panic!("properties should be set");
+		};
+		assert_eq!(properties.get("entry").map(String::as_str), Some("yes"));
+	}
+
+	#[test]
+	fn rejects_invalid_queries() {
+		let Err(err) = run_query(
+			&SupportLang::Rust.get_ts_language(),
+			"(no_such_node) @x",
+			"fn main() {}",
+			1,
+			&task::CancelToken::default(),
+		) else {
+			panic!("unknown node kinds should be rejected");
+		};
+		assert!(err.reason.contains("Invalid node type"), "{}", err.reason);
+	}
+}
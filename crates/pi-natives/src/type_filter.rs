@@ -0,0 +1,327 @@
+//! File type table behind grep's `type` filter.
+//!
+//! Built-in types cover common languages and config formats; more can be
+//! registered at runtime with `registerFileType()`, which also overrides a
+//! built-in of the same name. A filter is a list of type names, where a
+//! leading `!` excludes the type and unknown names fall back to a file
+//! extension (`min.js` matches `*.min.js`).
+
+use std::{path::Path, sync::LazyLock};
+
+use dashmap::DashMap;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+
+/// Built-in types: names (first is canonical, the rest are aliases) and the
+/// globs matched against file names.
+const BUILTIN_TYPES: &[(&[&str], &[&str])] = &[
+	(&["js", "javascript"], &["*.js", "*.jsx", "*.mjs", "*.cjs"]),
+	(&["ts", "typescript"], &["*.ts", "*.tsx", "*.mts", "*.cts"]),
+	(&["json"], &["*.json", "*.jsonc", "*.json5"]),
+	(&["yaml", "yml"], &["*.yaml", "*.yml"]),
+	(&["toml"], &["*.toml"]),
+	(&["md", "markdown"], &["*.md", "*.markdown", "*.mdx"]),
+	(&["py", "python"], &["*.py", "*.pyi"]),
+	(&["rs", "rust"], &["*.rs"]),
+	(&["go"], &["*.go"]),
+	(&["java"], &["*.java"]),
+	(&["kt", "kotlin"], &["*.kt", "*.kts"]),
+	(&["c"], &["*.c", "*.h"]),
+	(&["cpp", "cxx"], &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hxx", "*.hh"]),
+	(&["cs", "csharp"], &["*.cs", "*.csx"]),
+	(&["php"], &["*.php", "*.phtml"]),
+	(&["rb", "ruby"], &["*.rb", "*.rake", "*.gemspec"]),
+	(&["sh", "bash"], &["*.sh", "*.bash", "*.zsh"]),
+	(&["zsh"], &["*.zsh"]),
+	(&["fish"], &["*.fish"]),
+	(&["html"], &["*.html", "*.htm"]),
+	(&["css"], &["*.css"]),
+	(&["scss"], &["*.scss"]),
+	(&["sass"], &["*.sass"]),
+	(&["less"], &["*.less"]),
+	(&["xml"], &["*.xml"]),
+	(&["docker", "dockerfile"], &["dockerfile"]),
+	(&["make", "makefile"], &["makefile"]),
+	(&["svelte"], &["*.svelte"]),
+	(&["vue"], &["*.vue"]),
+	(&["proto", "protobuf"], &["*.proto"]),
+	(&["tf", "terraform"], &["*.tf", "*.tfvars", "*.tf.json"]),
+	(&["sql"], &["*.sql"]),
+	(&["graphql", "gql"], &["*.graphql", "*.gql"]),
+	(&["swift"], &["*.swift"]),
+	(&["lua"], &["*.lua"]),
+	(&["test"], &["*.test.*", "*.spec.*", "*_test.*", "*_spec.*", "test_*.*"]),
+];
+
+/// Types registered at runtime, keyed by lowercase name.
+static CUSTOM_TYPES: LazyLock<DashMap<String, FileTypeDefinition>> = LazyLock::new(DashMap::new);
+
+/// A named file type usable in grep `type` filters.
+#[napi(object)]
+#[derive(Clone)]
+pub struct FileTypeDefinition {
+	/// Type name (e.g. "proto"); matched case-insensitively.
+	pub name:    String,
+	/// Alternative names for the type.
+	pub aliases: Option<Vec<String>>,
+	/// Globs matched against file names (e.g. "*.tf").
+	pub globs:   Option<Vec<String>>,
+	/// Exact file names (e.g. "Jenkinsfile").
+	pub names:   Option<Vec<String>>,
+}
+
+impl FileTypeDefinition {
+	fn patterns(&self) -> impl Iterator<Item = String> + '_ {
+		let globs = self.globs.iter().flatten().cloned();
+		let names = self
+			.names
+			.iter()
+			.flatten()
+			.map(|name| globset::escape(name));
+		globs.chain(names)
+	}
+
+	fn answers_to(&self, name: &str) -> bool {
+		self.name.eq_ignore_ascii_case(name)
+			|| self
+				.aliases
+				.iter()
+				.flatten()
+				.any(|alias| alias.eq_ignore_ascii_case(name))
+	}
+}
+
+fn builtin_definition(names: &[&str], globs: &[&str]) -> FileTypeDefinition {
+	let aliases = names[1..]
+		.iter()
+		.map(|alias| (*alias).to_string())
+		.collect::<Vec<_>>();
+	FileTypeDefinition {
+		name:    names[0].to_string(),
+		aliases: (!aliases.is_empty()).then_some(aliases),
+		globs:   Some(globs.iter().map(|glob| (*glob).to_string()).collect()),
+		names:   None,
+	}
+}
+
+/// Compiled `type` filter: a file must match one of the included types (when
+/// any are given) and none of the excluded ones.
+pub struct TypeFilter {
+	include: Option<GlobSet>,
+	exclude: Option<GlobSet>,
+}
+
+impl TypeFilter {
+	/// Check a path's file name against the filter.
+	pub fn matches(&self, path: &Path) -> bool {
+		let Some(name) = path.file_name() else {
+			return false;
+		};
+		if self.exclude.as_ref().is_some_and(|set| set.is_match(name)) {
+			return false;
+		}
+		self.include.as_ref().is_none_or(|set| set.is_match(name))
+	}
+}
+
+/// Globs for one type name: registered types first, then built-ins, then the
+/// name itself as an extension (or as a glob when it contains wildcards).
+fn type_patterns(name: &str) -> Vec<String> {
+	let custom = CUSTOM_TYPES
+		.get(name)
+		.map(|custom| custom.patterns().collect())
+		.or_else(|| {
+			CUSTOM_TYPES
+				.iter()
+				.find(|entry| entry.answers_to(name))
+				.map(|custom| custom.patterns().collect())
+		});
+	if let Some(patterns) = custom {
+		return patterns;
+	}
+	if let Some((_, globs)) = BUILTIN_TYPES
+		.iter()
+		.find(|(names, _)| names.contains(&name))
+	{
+		return globs.iter().map(|glob| (*glob).to_string()).collect();
+	}
+	if name.contains(['*', '?', '[', '{']) {
+		return vec![name.to_string()];
+	}
+	vec![format!("*.{}", globset::escape(name)), globset::escape(name)]
+}
+
+fn compile_patterns(patterns: &[String]) -> Result<Option<GlobSet>> {
+	if patterns.is_empty() {
+		return Ok(None);
+	}
+	let mut builder = GlobSetBuilder::new();
+	for pattern in patterns {
+		let glob = GlobBuilder::new(pattern)
+			.case_insensitive(true)
+			.build()
+			.map_err(|err| Error::from_reason(format!("Invalid type glob '{pattern}': {err}")))?;
+		builder.add(glob);
+	}
+	builder
+		.build()
+		.map(Some)
+		.map_err(|err| Error::from_reason(format!("Failed to build type matcher: {err}")))
+}
+
+/// Resolve a `type` option (one string, possibly comma-separated, or a list)
+/// into a filter. Returns `Ok(None)` when no types are given.
+pub fn resolve_type_filter(
+	spec: Option<&Either<String, Vec<String>>>,
+) -> Result<Option<TypeFilter>> {
+	let tokens: Vec<&str> = match spec {
+		None => return Ok(None),
+		Some(Either::A(value)) => vec![value.as_str()],
+		Some(Either::B(values)) => values.iter().map(String::as_str).collect(),
+	};
+
+	let mut include = Vec::new();
+	let mut exclude = Vec::new();
+	let names = tokens
+		.into_iter()
+		.flat_map(|token| token.split([',', ' ', '\t']))
+		.map(str::trim)
+		.filter(|token| !token.is_empty());
+	for token in names {
+		let (negated, name) = match token.strip_prefix('!') {
+			Some(rest) => (true, rest),
+			None => (false, token),
+		};
+		let name = name.trim_start_matches('.').to_lowercase();
+		if name.is_empty() {
+			continue;
+		}
+		let target = if negated { &mut exclude } else { &mut include };
+		target.extend(type_patterns(&name));
+	}
+
+	if include.is_empty() && exclude.is_empty() {
+		return Ok(None);
+	}
+	Ok(Some(TypeFilter {
+		include: compile_patterns(&include)?,
+		exclude: compile_patterns(&exclude)?,
+	}))
+}
+
+/// Register a file type for grep `type` filters, replacing any registered or
+/// built-in type with the same name.
+///
+/// # Errors
+/// Returns an error for empty or reserved names, or when a glob is invalid.
+#[napi(js_name = "registerFileType")]
+pub fn register_file_type(definition: FileTypeDefinition) -> Result<()> {
+	let name = definition.name.trim().to_lowercase();
+	if name.is_empty() || name.starts_with('!') || name.contains([',', ' ', '\t']) {
+		return Err(Error::from_reason(format!("Invalid file type name '{}'", definition.name)));
+	}
+	let patterns = definition.patterns().collect::<Vec<_>>();
+	if patterns.is_empty() {
+		return Err(Error::from_reason(format!("File type '{name}' needs `globs` or `names`")));
+	}
+	compile_patterns(&patterns)?;
+	CUSTOM_TYPES.insert(name.clone(), FileTypeDefinition { name, ..definition });
+	Ok(())
+}
+
+/// Remove a registered file type. Built-in types cannot be removed; removing
+/// an override restores the built-in definition.
+///
+/// # Returns
+/// True if a registered type was removed.
+#[napi(js_name = "unregisterFileType")]
+pub fn unregister_file_type(name: String) -> bool {
+	CUSTOM_TYPES.remove(&name.trim().to_lowercase()).is_some()
+}
+
+/// List every file type usable in `type` filters: built-ins (with any
+/// registered overrides applied) followed by registered types, by name.
+#[napi(js_name = "getFileTypes")]
+pub fn get_file_types() -> Vec<FileTypeDefinition> {
+	let mut types = BUILTIN_TYPES
+		.iter()
+		.map(|(names, globs)| match CUSTOM_TYPES.get(names[0]) {
+			Some(custom) => custom.clone(),
+			None => builtin_definition(names, globs),
+		})
+		.collect::<Vec<_>>();
+	let mut custom = CUSTOM_TYPES
+		.iter()
+		.filter(|entry| {
+			!BUILTIN_TYPES
+				.iter()
+				.any(|(names, _)| names[0] == entry.key())
+		})
+		.map(|entry| entry.value().clone())
+		.collect::<Vec<_>>();
+	custom.sort_by(|a, b| a.name.cmp(&b.name));
+	types.extend(custom);
+	types
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn filter(spec: &str) -> TypeFilter {
+		resolve_type_filter(Some(&Either::A(spec.to_string())))
+			.expect("type filter should compile")
+			.expect("type filter should not be empty")
+	}
+
+	#[test]
+	fn builtin_types_match_extensions_and_names() {
+		let ts = filter("typescript");
+		assert!(ts.matches(Path::new("src/app.tsx")));
+		assert!(!ts.matches(Path::new("src/app.js")));
+		assert!(filter("docker").matches(Path::new("Dockerfile")));
+	}
+
+	#[test]
+	fn combines_and_negates_types() {
+		let spec = filter("ts,js !test !min.js");
+		assert!(spec.matches(Path::new("a/index.ts")));
+		assert!(spec.matches(Path::new("a/vendor.js")));
+		assert!(!spec.matches(Path::new("a/index.test.ts")));
+		assert!(!spec.matches(Path::new("a/vendor.min.js")));
+		assert!(!spec.matches(Path::new("a/readme.md")));
+
+		let only_negated = filter("!md");
+		assert!(only_negated.matches(Path::new("a.rs")));
+		assert!(!only_negated.matches(Path::new("a.md")));
+	}
+
+	#[test]
+	fn unknown_names_fall_back_to_extensions() {
+		let spec = filter(".astro");
+		assert!(spec.matches(Path::new("page.astro")));
+		assert!(!spec.matches(Path::new("page.ts")));
+	}
+
+	#[test]
+	fn registered_types_are_resolved() {
+		register_file_type(FileTypeDefinition {
+			name:    "ci-test-type".into(),
+			aliases: Some(vec!["ci-alias".into()]),
+			globs:   Some(vec!["*.pipeline.yml".into()]),
+			names:   Some(vec!["Jenkinsfile".into()]),
+		})
+		.unwrap();
+		let spec = filter("ci-alias");
+		assert!(spec.matches(Path::new("Jenkinsfile")));
+		assert!(spec.matches(Path::new("deploy.pipeline.yml")));
+		assert!(!spec.matches(Path::new("deploy.yml")));
+		assert!(
+			get_file_types()
+				.iter()
+				.any(|def| def.name == "ci-test-type")
+		);
+		assert!(unregister_file_type("ci-test-type".into()));
+	}
+}
@@ -1,19 +1,50 @@
 /**
- * Native AST structural search and rewrite wrappers.
+ * Native AST structural search, rewrite, outline, tree-sitter query, syntax validation, structural diff and grammar registration wrappers.
  */
 
 import { native } from "../native";
-import type { AstFindOptions, AstFindResult, AstReplaceOptions, AstReplaceResult } from "./types";
+import type {
+	AstFindOptions,
+	AstFindResult,
+	AstReplaceOptions,
+	AstReplaceResult,
+	OutlineOptions,
+	OutlineResult,
+	StructuralDiffOptions,
+	StructuralDiffResult,
+	TreeSitterQueryOptions,
+	TreeSitterQueryResult,
+	ValidateSyntaxOptions,
+	ValidateSyntaxResult,
+} from "./types";
 
 export type {
 	AstFindMatch,
 	AstFindOptions,
 	AstFindResult,
+	AstMatchScope,
 	AstReplaceChange,
 	AstReplaceFileChange,
 	AstReplaceOptions,
 	AstReplaceResult,
 	AstStrictness,
+	GrammarDefinition,
+	GrammarInfo,
+	OutlineOptions,
+	OutlineResult,
+	OutlineSymbol,
+	OutlineSymbolKind,
+	StructuralChange,
+	StructuralChangeKind,
+	StructuralDiffOptions,
+	StructuralDiffResult,
+	SyntaxIssue,
+	TreeSitterCapture,
+	TreeSitterQueryMatch,
+	TreeSitterQueryOptions,
+	TreeSitterQueryResult,
+	ValidateSyntaxOptions,
+	ValidateSyntaxResult,
 } from "./types";
 
 export async function astGrep(options: AstFindOptions): Promise<AstFindResult> {
@@ -23,3 +54,21 @@ export async function astGrep(options: AstFindOptions): Promise<AstFindResult> {
 export async function astEdit(options: AstReplaceOptions): Promise<AstReplaceResult> {
 	return native.astEdit(options);
 }
+
+export async function outline(options: OutlineOptions): Promise<OutlineResult> {
+	return native.outline(options);
+}
+
+export async function treeSitterQuery(options: TreeSitterQueryOptions): Promise<TreeSitterQueryResult> {
+	return native.treeSitterQuery(options);
+}
+
+export async function validateSyntax(options: ValidateSyntaxOptions): Promise<ValidateSyntaxResult> {
+	return native.validateSyntax(options);
+}
+
+export async function structuralDiff(options: StructuralDiffOptions): Promise<StructuralDiffResult> {
+	return native.structuralDiff(options);
+}
+
+export const { registerGrammar, getGrammars } = native;
@@ -3,20 +3,39 @@
  */
 
 import type { Cancellable } from "../bindings";
+import type { ContextLine, GitChangedFilter } from "../grep/types";
 
 export type AstStrictness = "cst" | "smart" | "ast" | "relaxed" | "signature";
 
 export interface AstFindOptions extends Cancellable {
 	patterns?: string[];
+	/**
+	 * ast-grep rule documents as YAML or JSON (`---` separated documents or a list), searched alongside
+	 * `patterns`: `rule` with pattern/kind/regex, inside/has/follows/precedes (`stopBy`, `field`),
+	 * all/any/not/matches, plus `constraints`, `utils`, `id` and `language`
+	 */
+	rules?: string;
+	/**
+	 * Pattern language; Markdown fences and HTML/Vue/Svelte `<script>`/`<style>` blocks in it are searched too.
+	 * When omitted, embedded blocks are searched in whatever language they declare.
+	 */
 	lang?: string;
 	path?: string;
-	glob?: string;
+	glob?: string | string[];
+	exclude?: string[];
 	selector?: string;
 	strictness?: AstStrictness;
 	limit?: number;
 	offset?: number;
 	includeMeta?: boolean;
+	/** Source lines to return around each match; also reports the enclosing named `scope` */
 	context?: number;
+	/** Search file contents at this git revision (e.g. `HEAD~1`) instead of the working tree */
+	rev?: string;
+	/** Only search files changed versus HEAD, the index, staged in the index, or the merge base */
+	changed?: GitChangedFilter;
+	/** Branch for `changed: "mergeBase"` (default: upstream, then `origin/HEAD`, `main`, `master`) */
+	changedBase?: string;
 }
 
 export interface AstFindMatch {
@@ -29,6 +48,23 @@ export interface AstFindMatch {
 	endLine: number;
 	endColumn: number;
 	metaVariables?: Record<string, string>;
+	/** Id of the rule document that matched (for `rules`; defaults to `rule-<n>`) */
+	ruleId?: string;
+	/** Lines before the match (with `context`) */
+	contextBefore?: ContextLine[];
+	/** Lines after the match (with `context`) */
+	contextAfter?: ContextLine[];
+	/** Innermost named definition containing the match (with `context`) */
+	scope?: AstMatchScope;
+}
+
+/** A named definition (function, class, impl, ...) enclosing an `astGrep` match. */
+export interface AstMatchScope {
+	/** Tree-sitter node kind, e.g. `function_item` or `class_declaration` */
+	kind: string;
+	name: string;
+	startLine: number;
+	endLine: number;
 }
 
 export interface AstFindResult {
@@ -42,15 +78,23 @@ export interface AstFindResult {
 
 export interface AstReplaceOptions extends Cancellable {
 	rewrites?: Record<string, string>;
+	/** ast-grep rule documents as YAML or JSON, each with a `fix` template; a shared `language` stands in for `lang` */
+	rules?: string;
+	/** ast-grep `transform` mapping (YAML or JSON) whose outputs `rewrites` templates can reference */
+	transform?: string;
+	/** Pattern language; Markdown fences and HTML/Vue/Svelte `<script>`/`<style>` blocks in it are searched too */
 	lang?: string;
 	path?: string;
-	glob?: string;
+	glob?: string | string[];
+	exclude?: string[];
 	selector?: string;
 	strictness?: AstStrictness;
 	dryRun?: boolean;
 	maxReplacements?: number;
 	maxFiles?: number;
 	failOnParseError?: boolean;
+	/** Attach a unified diff to each `fileChanges` entry */
+	diff?: boolean;
 }
 
 export interface AstReplaceChange {
@@ -69,6 +113,8 @@ export interface AstReplaceChange {
 export interface AstReplaceFileChange {
 	path: string;
 	count: number;
+	/** Unified diff of the file, when `diff` was requested */
+	diff?: string;
 }
 
 export interface AstReplaceResult {
@@ -82,9 +128,215 @@ export interface AstReplaceResult {
 	parseErrors?: string[];
 }
 
+export interface OutlineOptions extends Cancellable {
+	/** File to outline; with `content`, only used to infer the language */
+	path?: string;
+	/** Source text to outline instead of reading `path` */
+	content?: string;
+	lang?: string;
+}
+
+export type OutlineSymbolKind =
+	| "function"
+	| "method"
+	| "class"
+	| "struct"
+	| "enum"
+	| "trait"
+	| "interface"
+	| "const"
+	| "module"
+	| "impl"
+	| "type"
+	| "section"
+	| "key"
+	| "target";
+
+export interface OutlineSymbol {
+	kind: OutlineSymbolKind;
+	/** Tree-sitter node kind, e.g. `function_item` */
+	nodeKind: string;
+	name: string;
+	/** Declaration header up to its body, whitespace collapsed */
+	signature: string;
+	startLine: number;
+	endLine: number;
+	/** Index of the enclosing symbol in `symbols` */
+	parent?: number;
+	/** Nesting depth; top-level symbols are 0 */
+	depth: number;
+}
+
+export interface OutlineResult {
+	/** Canonical name of the language used */
+	lang: string;
+	symbols: OutlineSymbol[];
+	parseErrors?: string[];
+}
+
+export interface TreeSitterQueryOptions extends Cancellable {
+	/** Query in tree-sitter S-expression (`.scm`) syntax; `#eq?`, `#match?` and `#any-of?` are evaluated */
+	query: string;
+	/** File to query; with `content`, only used to infer the language */
+	path?: string;
+	/** Source text to query instead of reading `path` */
+	content?: string;
+	lang?: string;
+	/** Most matches to return (default 500); `totalMatches` counts them all */
+	limit?: number;
+}
+
+export interface TreeSitterCapture {
+	/** Capture name without the leading `@` */
+	name: string;
+	/** Tree-sitter node kind, e.g. `identifier` */
+	kind: string;
+	text: string;
+	byteStart: number;
+	byteEnd: number;
+	/** Zero-based row, as in tree-sitter points */
+	startRow: number;
+	/** Zero-based byte column */
+	startColumn: number;
+	endRow: number;
+	endColumn: number;
+}
+
+export interface TreeSitterQueryMatch {
+	/** Index of the matching pattern within the query */
+	patternIndex: number;
+	captures: TreeSitterCapture[];
+	/** `#set!` properties of the pattern; a key without a value maps to `""` */
+	properties?: Record<string, string>;
+}
+
+export interface TreeSitterQueryResult {
+	/** Canonical name of the language used */
+	lang: string;
+	/** Capture names declared by the query, in capture-index order */
+	captureNames: string[];
+	matches: TreeSitterQueryMatch[];
+	totalMatches: number;
+	limitReached: boolean;
+	parseErrors?: string[];
+}
+
+export interface ValidateSyntaxOptions extends Cancellable {
+	/** Source text to check; read from `path` when omitted */
+	content?: string;
+	/** File the content belongs to; names the language unless `lang` is set */
+	path?: string;
+	lang?: string;
+	/** Most errors to return (default 100); `totalErrors` counts them all */
+	limit?: number;
+}
+
+export interface SyntaxIssue {
+	/** `error` for input the parser skipped, `missing` for a token it had to insert */
+	kind: "error" | "missing";
+	/** Expected node kind for `missing` (e.g. `}`), otherwise `ERROR` */
+	nodeKind: string;
+	/** Kind of the enclosing node, e.g. `function_item` */
+	parentKind?: string;
+	message: string;
+	startLine: number;
+	startColumn: number;
+	endLine: number;
+	endColumn: number;
+	byteStart: number;
+	byteEnd: number;
+	/** Source line the issue starts on, trimmed */
+	excerpt: string;
+}
+
+export interface ValidateSyntaxResult {
+	/** Canonical name of the language used */
+	lang: string;
+	valid: boolean;
+	errors: SyntaxIssue[];
+	totalErrors: number;
+	limitReached: boolean;
+}
+
+export interface StructuralDiffOptions extends Cancellable {
+	/** Source text of the earlier version */
+	before: string;
+	/** Source text of the later version */
+	after: string;
+	lang?: string;
+	/** File both versions belong to; only used to infer the language */
+	path?: string;
+}
+
+export type StructuralChangeKind = "added" | "removed" | "modified" | "moved";
+
+export interface StructuralChange {
+	kind: StructuralChangeKind;
+	/** Declaration kind as reported by `outline()`, e.g. `function`; `module` for code outside any declaration */
+	symbolKind: OutlineSymbolKind | "module";
+	/** Declaration name; empty for code outside any declaration */
+	name: string;
+	/** Names of the enclosing declarations and this one, joined with `.` */
+	qualifiedName: string;
+	/** What changed, e.g. `body changed` or ``parameter `x` added`` */
+	details: string[];
+	/** One line for the whole change, e.g. `function foo: body changed` */
+	summary: string;
+	/** 1-based line range in `before`, unless added */
+	beforeStartLine?: number;
+	beforeEndLine?: number;
+	/** 1-based line range in `after`, unless removed */
+	afterStartLine?: number;
+	afterEndLine?: number;
+}
+
+export interface StructuralDiffResult {
+	/** Canonical name of the language used */
+	lang: string;
+	/** Changes in the order of the `after` version; removals sit at their line in `before` */
+	changes: StructuralChange[];
+	/** No change beyond whitespace and layout */
+	unchanged: boolean;
+	parseErrors?: string[];
+}
+
+export interface GrammarDefinition {
+	/** Language name used as `lang`; lowercase letters, digits, `-` and `_` */
+	name: string;
+	/** Shared library exporting the grammar (`.so`, `.dylib` or `.dll`), e.g. from `tree-sitter build` */
+	path: string;
+	/** Exported language function (default `tree_sitter_<name>`, `-` as `_`) */
+	symbol?: string;
+	/** Other names accepted as `lang` */
+	aliases?: string[];
+	/** File extensions inferred as this language, with or without the dot */
+	extensions?: string[];
+	/** Exact file names inferred as this language, e.g. `Justfile` */
+	fileNames?: string[];
+	/** Character substituted for `$` in patterns, for grammars whose identifiers cannot contain `$` */
+	expandoChar?: string;
+}
+
+export interface GrammarInfo {
+	name: string;
+	aliases: string[];
+	extensions: string[];
+	fileNames: string[];
+	/** False for grammars added with `registerGrammar()` */
+	builtin: boolean;
+}
+
 declare module "../bindings" {
 	interface NativeBindings {
 		astGrep(options: AstFindOptions): Promise<AstFindResult>;
 		astEdit(options: AstReplaceOptions): Promise<AstReplaceResult>;
+		outline(options: OutlineOptions): Promise<OutlineResult>;
+		treeSitterQuery(options: TreeSitterQueryOptions): Promise<TreeSitterQueryResult>;
+		validateSyntax(options: ValidateSyntaxOptions): Promise<ValidateSyntaxResult>;
+		structuralDiff(options: StructuralDiffOptions): Promise<StructuralDiffResult>;
+		/** Load a tree-sitter grammar from a shared library for the AST APIs. */
+		registerGrammar(definition: GrammarDefinition): GrammarInfo;
+		/** List built-in and registered tree-sitter grammars. */
+		getGrammars(): GrammarInfo[];
 	}
 }
@@ -33,10 +33,11 @@ export async function glob(options: GlobOptions, onMatch?: (match: GlobMatch) =>
 }
 
 /**
- * Invalidate the filesystem scan cache.
+ * Invalidate the filesystem scan cache and the AST parse cache.
  *
- * When called with a path, removes entries for roots containing that path.
- * When called without a path, clears the entire cache.
+ * When called with a path, removes entries for roots containing that path and
+ * marks parsed trees under it for re-parsing. When called without a path,
+ * clears both caches.
  */
 export function invalidateFsScanCache(path?: string): void {
 	native.invalidateFsScanCache(path);
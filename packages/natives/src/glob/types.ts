@@ -15,8 +15,10 @@ export enum FileType {
 
 /** Options for discovering files and directories. */
 export interface GlobOptions extends Cancellable {
-	/** Glob pattern to match (e.g., `*.ts`). */
-	pattern: string;
+	/** Glob pattern(s) to match (e.g., `*.ts`); `!`-prefixed entries exclude (e.g., `["*.ts", "!*.d.ts"]`). */
+	pattern: string | string[];
+	/** Globs to exclude (e.g., `["dist/**"]`). */
+	exclude?: string[];
 	/** Directory to search. */
 	path: string;
 	/** Filter by file type: "file", "dir", or "symlink". Symlinks match file/dir filters when their target type matches. */
@@ -45,6 +47,8 @@ export interface GlobMatch {
 	fileType: FileType;
 	/** Modification time in milliseconds since epoch, if available. */
 	mtime?: number;
+	/** Size in bytes (of the link itself for symlinks), if available. */
+	size?: number;
 }
 
 /** Result of a find operation. */
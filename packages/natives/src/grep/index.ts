@@ -5,28 +5,70 @@
 import { native } from "../native";
 import type {
 	ContextLine,
+	FileTypeDefinition,
 	FuzzyFindMatch,
 	FuzzyFindOptions,
 	FuzzyFindResult,
+	GitChangedFilter,
+	GrepFileQuery,
+	GrepHunk,
+	GrepHunkLine,
+	GrepIndexOptions,
+	GrepIndexStats,
 	GrepMatch,
+	GrepNearQuery,
 	GrepOptions,
+	GrepPattern,
+	GrepPatternOptions,
+	GrepPatternSetOptions,
+	GrepReplaceChange,
+	GrepReplaceFile,
+	GrepReplaceOptions,
+	GrepReplaceResult,
 	GrepResult,
+	GrepSearchOptions,
+	GrepSkippedFile,
 	GrepSummary,
+	GrepSyntaxClass,
+	HasMatchOptions,
+	SearchMatch,
 	SearchOptions,
 	SearchResult,
+	Submatch,
 } from "./types";
 
 export type {
 	ContextLine,
+	FileTypeDefinition,
 	FuzzyFindMatch,
 	FuzzyFindOptions,
 	FuzzyFindResult,
+	GitChangedFilter,
+	GrepFileQuery,
+	GrepHunk,
+	GrepHunkLine,
+	GrepIndexOptions,
+	GrepIndexStats,
 	GrepMatch,
+	GrepNearQuery,
 	GrepOptions,
+	GrepPattern,
+	GrepPatternOptions,
+	GrepPatternSetOptions,
+	GrepReplaceChange,
+	GrepReplaceFile,
+	GrepReplaceOptions,
+	GrepReplaceResult,
 	GrepResult,
+	GrepSearchOptions,
+	GrepSkippedFile,
 	GrepSummary,
+	GrepSyntaxClass,
+	HasMatchOptions,
+	SearchMatch,
 	SearchOptions,
 	SearchResult,
+	Submatch,
 };
 
 /**
@@ -38,6 +80,16 @@ export async function grep(options: GrepOptions, onMatch?: (match: GrepMatch) =>
 	return native.grep(options, cb);
 }
 
+/**
+ * Regex search-and-replace across files.
+ *
+ * Uses the same walk, glob and type filters as `grep()`. Dry run by default;
+ * pass `dryRun: false` to write changed files atomically.
+ */
+export async function grepReplace(options: GrepReplaceOptions): Promise<GrepReplaceResult> {
+	return native.grepReplace(options);
+}
+
 /**
  * Search a single file's content for a pattern.
  * Lower-level API for when you already have file content.
@@ -48,6 +100,26 @@ export function searchContent(content: string | Uint8Array, options: SearchOptio
 	return native.search(content, options);
 }
 
+export const { registerFileType, unregisterFileType, getFileTypes } = native;
+
+/**
+ * Build the persistent trigram index for a directory from scratch.
+ *
+ * `grep({ index: true })` uses it to skip files that cannot match.
+ */
+export async function buildGrepIndex(options: GrepIndexOptions): Promise<GrepIndexStats> {
+	return native.buildGrepIndex(options);
+}
+
+/**
+ * Re-index new and changed files, drop deleted ones and persist the index.
+ */
+export async function updateGrepIndex(options: GrepIndexOptions): Promise<GrepIndexStats> {
+	return native.updateGrepIndex(options);
+}
+
+export const { getGrepIndexStats, dropGrepIndex } = native;
+
 /**
  * Quick check if content contains a pattern match.
  *
@@ -56,16 +128,17 @@ export function searchContent(content: string | Uint8Array, options: SearchOptio
 export function hasMatch(
 	content: string | Uint8Array,
 	pattern: string | Uint8Array,
-	options?: { ignoreCase?: boolean; multiline?: boolean },
+	options?: HasMatchOptions,
 ): boolean {
-	return native.hasMatch(content, pattern, options?.ignoreCase ?? false, options?.multiline ?? false);
+	return native.hasMatch(content, pattern, options);
 }
 
 /**
  * Fuzzy file path search for autocomplete.
  *
  * Searches for files and directories whose paths contain the query substring
- * (case-insensitive). Respects .gitignore by default.
+ * (case-insensitive). Respects .gitignore by default. File contents are never
+ * read; use `grep()` to search them with encoding detection.
  */
 export async function fuzzyFind(options: FuzzyFindOptions): Promise<FuzzyFindResult> {
 	return native.fuzzyFind(options);
@@ -4,26 +4,80 @@
 
 import type { Cancellable, TsFunc } from "../bindings";
 
-/** Options for searching files. */
-export interface GrepOptions extends Cancellable {
+/** A named pattern for multi-pattern grep. */
+export interface GrepPattern {
+	/** Identifier reported on every hit produced by this pattern */
+	id: string;
 	/** Regex pattern to search for */
 	pattern: string;
+}
+
+/** Proximity constraint: `first` must match within `lines` lines of `second`. */
+export interface GrepNearQuery {
+	/** Pattern id of the first pattern */
+	first: string;
+	/** Pattern id of the second pattern */
+	second: string;
+	/** Maximum distance in lines between the two hits */
+	lines: number;
+}
+
+/**
+ * File-level boolean composition over pattern ids.
+ * A file is reported only when every clause holds.
+ */
+export interface GrepFileQuery {
+	/** Pattern ids that must all match somewhere in the file */
+	all?: string[];
+	/** Pattern ids of which at least one must match */
+	any?: string[];
+	/** Pattern ids that must not match anywhere in the file */
+	none?: string[];
+	/** Proximity constraints between pairs of patterns */
+	near?: GrepNearQuery[];
+}
+
+/**
+ * Changed-files filter: working tree vs `HEAD` (`head`), working tree vs index (`index`),
+ * index vs `HEAD` (`staged`), or working tree vs the merge base with a branch (`mergeBase`).
+ * Untracked files count as changed except for `staged`.
+ */
+export type GitChangedFilter = "head" | "index" | "staged" | "mergeBase";
+
+/**
+ * Syntax class of a match location, from the file's tree-sitter grammar: `comment`, `string` literal,
+ * or `code` (everything else, including interpolations inside strings). Prefix with `!` to exclude.
+ */
+export type GrepSyntaxClass = "code" | "comment" | "string" | "!code" | "!comment" | "!string";
+
+/** Options for searching files, shared by single- and multi-pattern searches. */
+export interface GrepSearchOptions extends Cancellable {
 	/** Directory or file to search */
 	path: string;
-	/** Glob filter for filenames (e.g., "*.ts") */
-	glob?: string;
-	/** Filter by file type (e.g., "js", "py", "rust") */
-	type?: string;
+	/** Glob filter(s) for filenames; `!`-prefixed entries exclude (e.g., "*.ts", ["*.ts", "!*.d.ts"]) */
+	glob?: string | string[];
+	/** Globs to exclude (e.g., ["generated/**"]) */
+	exclude?: string[];
+	/** File types to include, or exclude with a `!` prefix (e.g., "ts", "ts,!test", ["js", "!min.js"]) */
+	type?: string | string[];
 	/** Case-insensitive search */
 	ignoreCase?: boolean;
 	/** Enable multiline matching */
 	multiline?: boolean;
+	/** Treat the pattern as a literal string instead of a regex */
+	fixedStrings?: boolean;
+	/** Only match at word boundaries */
+	wordRegexp?: boolean;
+	/** Case-insensitive unless the pattern contains an uppercase letter */
+	smartCase?: boolean;
 	/** Include hidden files (default: true) */
 	hidden?: boolean;
 	/** Respect .gitignore files (default: true) */
 	gitignore?: boolean;
 	/** Enable shared filesystem scan cache (default: false). */
 	cache?: boolean;
+	/** Skip files larger than this many bytes (default: 256 MiB; 0 disables the cap) */
+	maxFileSize?: number;
 	/** Maximum number of matches to return */
 	maxCount?: number;
 	/** Skip first N matches */
@@ -36,10 +90,51 @@ export interface GrepOptions extends Cancellable {
 	context?: number;
 	/** Truncate lines longer than this (characters) */
 	maxColumns?: number;
-	/** Output mode */
-	mode?: "content" | "filesWithMatches" | "count";
+	/**
+	 * Output mode; `json` also returns `rg --json` records in `json` (in path order) and streams them to
+	 * `onMatch` as each file finishes, with lines as searched (`bytes` in base64 when not UTF-8), `hunks` returns matches merged with their context in `hunks` once the search ends (passing `onMatch` with it is rejected)
+	 */
+	mode?: "content" | "filesWithMatches" | "count" | "json" | "hunks";
+	/**
+	 * Binary file handling: skip (default; hits found before the first NUL byte are kept),
+	 * report "binary file matches", or search as text
+	 */
+	binary?: "skip" | "summary" | "text";
+	/**
+	 * Only report hits in these syntax classes, e.g. `"code"`, `["comment", "string"]` or `"!comment"`.
+	 * Files without a supported grammar are searched but never match.
+	 */
+	syntax?: GrepSyntaxClass | GrepSyntaxClass[];
+	/** Prune files with the persistent trigram index for `path`, creating it on first use (default: false; ignored with `rev`) */
+	index?: boolean;
+	/** Search file contents at this git revision (e.g. `HEAD~1`) instead of the working tree */
+	rev?: string;
+	/** Only search files changed versus HEAD, the index, staged in the index, or the merge base */
+	changed?: GitChangedFilter;
+	/** Branch for `changed: "mergeBase"` (default: upstream, then `origin/HEAD`, `main`, `master`) */
+	changedBase?: string;
 }
 
+/** Options for searching files for one regex pattern. */
+export interface GrepPatternOptions extends GrepSearchOptions {
+	/** Regex pattern to search for */
+	pattern: string;
+	patterns?: never;
+	fileQuery?: never;
+}
+
+/** Options for searching files for several named patterns in one pass. */
+export interface GrepPatternSetOptions extends GrepSearchOptions {
+	pattern?: never;
+	/** Patterns searched together; each hit reports its `patternId` */
+	patterns: GrepPattern[];
+	/** File-level boolean query over `patterns` ids */
+	fileQuery?: GrepFileQuery;
+}
+
+/** Options for searching files: a single `pattern` or a set of named `patterns`. */
+export type GrepOptions = GrepPatternOptions | GrepPatternSetOptions;
+
 /** A context line returned around a match. */
 export interface ContextLine {
 	/** 1-indexed line number. */
@@ -48,6 +143,30 @@ export interface ContextLine {
 	line: string;
 }
 
+/** A single regex hit within a matched line (or multiline block). */
+export interface Submatch {
+	/** Matched text. */
+	text: string;
+	/** UTF-16 offset of the match start within `line`. */
+	start: number;
+	/** UTF-16 offset of the match end (exclusive) within `line`. */
+	end: number;
+	/** Byte offset of the match start within `line`. */
+	byteStart: number;
+	/** Byte offset of the match end (exclusive) within `line`. */
+	byteEnd: number;
+	/** 1-indexed line number where the match starts. */
+	lineNumber: number;
+	/** 1-indexed column (UTF-16 units) where the match starts. */
+	column: number;
+	/** 1-indexed line number where the match ends. */
+	endLineNumber: number;
+	/** 1-indexed column (UTF-16 units) just past the end of the match. */
+	endColumn: number;
+	/** Named capture groups that participated in the match. */
+	groups?: Record<string, string>;
+}
+
 /** A single grep match or per-file count entry. */
 export interface GrepMatch {
 	/** File path for the match (relative for directory searches). */
@@ -62,8 +181,18 @@ export interface GrepMatch {
 	contextAfter?: ContextLine[];
 	/** Whether the line was truncated. */
 	truncated?: boolean;
+	/** Individual regex hits within the line (content mode only). */
+	submatches?: Submatch[];
+	/** Id of the pattern that produced this hit (multi-pattern searches only). */
+	patternId?: string;
 	/** Per-file match count (count mode only). */
 	matchCount?: number;
+	/** Detected source encoding when the file is not UTF-8 (e.g. "UTF-16LE", "windows-1252"). */
+	encoding?: string;
+	/** Set on the "binary file matches" entry emitted in `summary` binary mode. */
+	binary?: boolean;
+	/** One `rg --json` record; in `json` mode `onMatch` receives one entry per record. */
+	json?: string;
 }
 
 /** Summary stats for a grep run. */
@@ -78,10 +207,82 @@ export interface GrepSummary {
 	limitReached?: boolean;
 }
 
+/** A file grep did not search. */
+export interface GrepSkippedFile {
+	/** File path (relative for directory searches). */
+	path: string;
+	/** Why the file was skipped; `encoding` (UTF-16 text) only comes from `grepReplace()`. */
+	reason: "tooLarge" | "binary" | "encoding" | "permissionDenied" | "ioError";
+	/** Human-readable detail (size, offending offset or OS error). */
+	message?: string;
+}
+
 /** Full grep result including matches and summary counts. */
 export interface GrepResult extends GrepSummary {
 	/** Matches or per-file counts, depending on mode. */
 	matches: GrepMatch[];
+	/** Files that were not searched, with the reason. */
+	skipped?: GrepSkippedFile[];
+	/** `rg --json` output as JSON Lines (`json` mode only). */
+	json?: string;
+	/** Matches merged with their context into contiguous blocks (`hunks` mode only; `matches` is empty then). */
+	hunks?: GrepHunk[];
+}
+
+/** A line of a grep hunk. */
+export interface GrepHunkLine {
+	/** 1-indexed line number (where a multiline match starts). */
+	lineNumber: number;
+	/** Line content (trimmed line ending). */
+	line: string;
+	/** Whether the line matched or is surrounding context. */
+	kind: "match" | "context";
+	/** Whether the line was truncated. */
+	truncated?: boolean;
+	/** Individual regex hits within a match line. */
+	submatches?: Submatch[];
+	/** Ids of the patterns matching this line (multi-pattern searches only). */
+	patternIds?: string[];
+}
+
+/** Matches and their context merged into one contiguous block, like a `--`-separated block of `rg -C` output. */
+export interface GrepHunk {
+	/** File path (relative for directory searches). */
+	path: string;
+	/** First line of the hunk. */
+	startLine: number;
+	/** Last line of the hunk. */
+	endLine: number;
+	/** Match and context lines in line order, each line at most once. */
+	lines: GrepHunkLine[];
+	/** Detected source encoding when the file is not UTF-8. */
+	encoding?: string;
+}
+
+/** A named file type usable in grep `type` filters. */
+export interface FileTypeDefinition {
+	/** Type name (e.g. "proto"); matched case-insensitively. */
+	name: string;
+	/** Alternative names for the type. */
+	aliases?: string[];
+	/** Globs matched against file names (e.g. "*.tf"). */
+	globs?: string[];
+	/** Exact file names (e.g. "Jenkinsfile"). */
+	names?: string[];
+}
+
+/** Options for {@link hasMatch}. */
+export interface HasMatchOptions {
+	/** Case-insensitive search */
+	ignoreCase?: boolean;
+	/** Enable multiline matching */
+	multiline?: boolean;
+	/** Treat the pattern as a literal string instead of a regex */
+	fixedStrings?: boolean;
+	/** Only match at word boundaries */
+	wordRegexp?: boolean;
+	/** Case-insensitive unless the pattern contains an uppercase letter */
+	smartCase?: boolean;
 }
 
 /** Options for searching in-memory content. */
@@ -92,6 +293,12 @@ export interface SearchOptions {
 	ignoreCase?: boolean;
 	/** Enable multiline matching */
 	multiline?: boolean;
+	/** Treat the pattern as a literal string instead of a regex */
+	fixedStrings?: boolean;
+	/** Only match at word boundaries */
+	wordRegexp?: boolean;
+	/** Case-insensitive unless the pattern contains an uppercase letter */
+	smartCase?: boolean;
 	/** Maximum number of matches to return */
 	maxCount?: number;
 	/** Skip first N matches */
@@ -106,6 +313,8 @@ export interface SearchOptions {
 	maxColumns?: number;
 	/** Output mode */
 	mode?: "content" | "count";
+	/** Binary content handling: skip (default), report "binary file matches", or search as text */
+	binary?: "skip" | "summary" | "text";
 }
 
 /** A single content match. */
@@ -120,6 +329,8 @@ export interface SearchMatch {
 	contextAfter?: ContextLine[];
 	/** Whether the line was truncated. */
 	truncated?: boolean;
+	/** Individual regex hits within the line. */
+	submatches?: Submatch[];
 }
 
 /** Result of searching in-memory content. */
@@ -132,6 +343,88 @@ export interface SearchResult {
 	limitReached: boolean;
 	/** Error message, if any. */
 	error?: string;
+	/** Detected text encoding of the content (e.g. "UTF-8", "UTF-16LE"). */
+	encoding?: string;
+	/** Whether binary data was found in the content. */
+	binary?: boolean;
+}
+
+/** Options for regex search-and-replace across files. */
+export interface GrepReplaceOptions extends Cancellable {
+	/** Regex pattern to search for */
+	pattern: string;
+	/** Replacement template (`$1`, `${name}`, `$$` for a literal `$`) */
+	replacement: string;
+	/** Directory or file to search */
+	path: string;
+	/** Glob filter(s) for filenames; `!`-prefixed entries exclude (e.g., "*.ts", ["*.ts", "!*.d.ts"]) */
+	glob?: string | string[];
+	/** Globs to exclude (e.g., ["generated/**"]) */
+	exclude?: string[];
+	/** File types to include, or exclude with a `!` prefix (e.g., "ts", "ts,!test", ["js", "!min.js"]) */
+	type?: string | string[];
+	/** Case-insensitive search */
+	ignoreCase?: boolean;
+	/** Allow matches to span multiple lines */
+	multiline?: boolean;
+	/** Treat the pattern as a literal string instead of a regex */
+	fixedStrings?: boolean;
+	/** Only match at word boundaries */
+	wordRegexp?: boolean;
+	/** Case-insensitive unless the pattern contains an uppercase letter */
+	smartCase?: boolean;
+	/** Include hidden files (default: true) */
+	hidden?: boolean;
+	/** Respect .gitignore files (default: true) */
+	gitignore?: boolean;
+	/** Enable shared filesystem scan cache (default: false). */
+	cache?: boolean;
+	/**
+	 * Compute changes without writing files (default: true). Otherwise files are written once every
+	 * file is matched (cancelling before then writes nothing), symlinks are written through, and a
+	 * file modified since it was read is reported in `errors` instead of being overwritten.
+	 */
+	dryRun?: boolean;
+}
+
+/** A contiguous block of lines changed by a replacement. */
+export interface GrepReplaceChange {
+	/** 1-indexed line number of the first affected line. */
+	lineNumber: number;
+	/** Original line(s), without the trailing line ending. */
+	before: string;
+	/** Rewritten line(s), without the trailing line ending. */
+	after: string;
+	/** Number of regex replacements inside this block. */
+	replacements: number;
+}
+
+/** Replacements computed (and optionally applied) for a single file. */
+export interface GrepReplaceFile {
+	/** File path (relative for directory searches). */
+	path: string;
+	/** Number of regex replacements in the file. */
+	count: number;
+	/** Changed line blocks in file order. */
+	changes: GrepReplaceChange[];
+}
+
+/** Result of a search-and-replace run. */
+export interface GrepReplaceResult {
+	/** Files with at least one replacement, sorted by path. */
+	files: GrepReplaceFile[];
+	/** Total replacements across all files. */
+	totalReplacements: number;
+	/** Number of files with at least one replacement. */
+	filesTouched: number;
+	/** Number of files searched. */
+	filesSearched: number;
+	/** Whether changes were written to disk. */
+	applied: boolean;
+	/** Per-file read/write failures, including files changed on disk since they were matched. */
+	errors?: string[];
+	/** Files left untouched because they are too large, binary or UTF-16. */
+	skipped?: GrepSkippedFile[];
 }
 
 /** Options for fuzzy file path search. */
@@ -168,20 +461,65 @@ export interface FuzzyFindResult {
 	totalMatches: number;
 }
 
+/** Options for building or updating a persistent grep index. */
+export interface GrepIndexOptions extends Cancellable {
+	/** Root directory to index. */
+	path: string;
+	/** Include hidden files (default: true). */
+	hidden?: boolean;
+	/** Respect .gitignore files (default: true). */
+	gitignore?: boolean;
+}
+
+/** Summary of a persistent grep index. */
+export interface GrepIndexStats {
+	/** Indexed root directory. */
+	root: string;
+	/** Location of the index file. */
+	indexPath: string;
+	/** Files tracked by the index. */
+	files: number;
+	/** Files stored without trigrams (non-UTF-8, binary or too large); these are always searched. */
+	unindexedFiles: number;
+	/** Distinct trigrams in the posting lists. */
+	trigrams: number;
+	/** Files re-tokenized since the posting lists were last rebuilt. */
+	pendingFiles: number;
+	/** Size of the index file in bytes (0 when not written yet). */
+	diskBytes: number;
+	/** Last change in milliseconds since epoch. */
+	updatedAt: number;
+}
+
 declare module "../bindings" {
 	interface NativeBindings {
 		/** Fuzzy file path search for autocomplete. */
 		fuzzyFind(options: FuzzyFindOptions): Promise<FuzzyFindResult>;
 		/** Search files for a regex pattern. */
 		grep(options: GrepOptions, onMatch?: TsFunc<GrepMatch>): Promise<GrepResult>;
+		/** Regex search-and-replace across files. */
+		grepReplace(options: GrepReplaceOptions): Promise<GrepReplaceResult>;
+		/** Build a directory's trigram index from scratch. */
+		buildGrepIndex(options: GrepIndexOptions): Promise<GrepIndexStats>;
+		/** Bring a directory's trigram index up to date. */
+		updateGrepIndex(options: GrepIndexOptions): Promise<GrepIndexStats>;
+		/** Describe a directory's trigram index, or null when there is none. */
+		getGrepIndexStats(path: string): GrepIndexStats | null;
+		/** Drop a directory's trigram index; returns true if one existed. */
+		dropGrepIndex(path: string): boolean;
 		/** Search in-memory content for a regex pattern. */
 		search(content: string | Uint8Array, options: SearchOptions): SearchResult;
+		/** Register (or replace) a file type for grep `type` filters. */
+		registerFileType(definition: FileTypeDefinition): void;
+		/** Remove a registered file type; returns true if one was removed. */
+		unregisterFileType(name: string): boolean;
+		/** List built-in and registered file types. */
+		getFileTypes(): FileTypeDefinition[];
 		/** Quick check if content matches a pattern. */
 		hasMatch(
 			content: string | Uint8Array,
 			pattern: string | Uint8Array,
-			ignoreCase: boolean,
-			multiline: boolean,
+			options?: HasMatchOptions,
 		): boolean;
 	}
 }
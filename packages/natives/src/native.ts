@@ -247,8 +247,16 @@ function validateNative(bindings: NativeBindings, source: string): void {
 	checkFn("glob");
 	checkFn("fuzzyFind");
 	checkFn("grep");
+	checkFn("grepReplace");
+	checkFn("buildGrepIndex");
+	checkFn("updateGrepIndex");
+	checkFn("getGrepIndexStats");
+	checkFn("dropGrepIndex");
 	checkFn("search");
 	checkFn("hasMatch");
+	checkFn("registerFileType");
+	checkFn("unregisterFileType");
+	checkFn("getFileTypes");
 	checkFn("htmlToMarkdown");
 	checkFn("highlightCode");
 	checkFn("supportsLanguage");
@@ -273,6 +281,12 @@ function validateNative(bindings: NativeBindings, source: string): void {
 	checkFn("invalidateFsScanCache");
 	checkFn("astGrep");
 	checkFn("astEdit");
+	checkFn("outline");
+	checkFn("treeSitterQuery");
+	checkFn("validateSyntax");
+	checkFn("structuralDiff");
+	checkFn("registerGrammar");
+	checkFn("getGrammars");
 	checkFn("detectMacOSAppearance");
 	checkFn("MacAppearanceObserver");
 	checkFn("projfsOverlayProbe");
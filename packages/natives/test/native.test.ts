@@ -1,18 +1,34 @@
 import { beforeAll, describe, expect, it } from "bun:test";
+import { execFileSync } from "node:child_process";
 import * as fs from "node:fs/promises";
 import * as os from "node:os";
 import * as path from "node:path";
 import {
+	buildGrepIndex,
+	dropGrepIndex,
 	FileType,
 	fuzzyFind,
+	getFileTypes,
+	getGrammars,
+	getGrepIndexStats,
 	type GlobMatch,
 	glob,
 	grep,
+	grepReplace,
+	hasMatch,
 	htmlToMarkdown,
 	invalidateFsScanCache,
+	outline,
 	PtySession,
+	registerFileType,
+	registerGrammar,
 	sanitizeText,
+	structuralDiff,
+	treeSitterQuery,
 	truncateToWidth,
+	unregisterFileType,
+	updateGrepIndex,
+	validateSyntax,
 	visibleWidth,
 	wrapTextWithAnsi,
 } from "../src/index";
@@ -86,6 +102,118 @@ describe("pi-natives", () => {
 			expect(result.totalMatches).toBe(2); // "Test" in title + "test" in body
 		});
 
+		it("should report submatch spans", async () => {
+			const result = await grep({
+				pattern: "(?P<word>hel+o)",
+				path: testDir,
+				glob: "file1.ts",
+			});
+
+			const [match] = result.matches;
+			expect(result.matches.length).toBe(2);
+			expect(match.submatches?.length).toBe(1);
+			const first = match.submatches![0];
+			expect(match.line.slice(first.start, first.end)).toBe("hello");
+			expect(first.column).toBe(first.start + 1);
+			expect(first.groups?.word).toBe("hello");
+		});
+
+		it("should compose multiple patterns with a file query", async () => {
+			const result = await grep({
+				patterns: [
+					{ id: "export", pattern: "export function" },
+					{ id: "todo", pattern: "TODO" },
+					{ id: "fixme", pattern: "FIXME" },
+				],
+				fileQuery: { all: ["export"], none: ["fixme"], near: [{ first: "export", second: "todo", lines: 1 }] },
+				path: testDir,
+			});
+
+			expect(result.filesWithMatches).toBe(1);
+			expect(result.matches.map(match => match.patternId)).toEqual(["export", "todo"]);
+			expect(result.matches.every(match => match.path === "file1.ts")).toBe(true);
+		});
+
+		it("should stream multi-pattern hits to onMatch", async () => {
+			const streamed: string[] = [];
+			const patterns = [
+				{ id: "todo", pattern: "TODO" },
+				{ id: "fixme", pattern: "FIXME" },
+			];
+			const result = await grep({ patterns, path: testDir }, match => {
+				streamed.push(`${match.path}:${match.lineNumber}:${match.patternId}`);
+			});
+
+			expect(result.matches.length).toBeGreaterThan(0);
+			expect(streamed).toEqual(result.matches.map(match => `${match.path}:${match.lineNumber}:${match.patternId}`));
+			// @ts-expect-error `pattern` is required unless `patterns` is given
+			await expect(grep({ path: testDir })).rejects.toThrow();
+		});
+
+		it("should support literal and word-boundary matching", async () => {
+			const literal = await grep({ pattern: "hello()", path: testDir, fixedStrings: true });
+			expect(literal.totalMatches).toBe(1);
+
+			const word = await grep({ pattern: "Search", path: testDir, wordRegexp: true, smartCase: true });
+			expect(word.totalMatches).toBe(0);
+
+			expect(hasMatch("a.b(c)", "b(c", { fixedStrings: true })).toBe(true);
+			expect(hasMatch("catalog", "LOG", { wordRegexp: true, ignoreCase: true })).toBe(false);
+		});
+
+		it("should report skipped files", async () => {
+			const scopedDir = await fs.mkdtemp(path.join(os.tmpdir(), "natives-skip-"));
+			await fs.writeFile(path.join(scopedDir, "blob.bin"), Buffer.from("needle\0\x01"));
+			await fs.writeFile(path.join(scopedDir, "big.txt"), `${"x".repeat(2048)}\nneedle\n`);
+			await fs.writeFile(path.join(scopedDir, "small.txt"), "needle\n");
+
+			const result = await grep({ pattern: "needle", path: scopedDir, maxFileSize: 1024 });
+			expect(result.totalMatches).toBe(1);
+			expect(result.skipped?.map(file => [file.path, file.reason])).toEqual([
+				["big.txt", "tooLarge"],
+				["blob.bin", "binary"],
+			]);
+			await fs.rm(scopedDir, { recursive: true, force: true });
+		});
+
+		it("should transcode UTF-16 files and summarize binary matches", async () => {
+			const scopedDir = await fs.mkdtemp(path.join(os.tmpdir(), "natives-encoding-"));
+			await fs.writeFile(path.join(scopedDir, "strings.rc"), Buffer.from('\ufeffIDS_TITLE "needle"\r\n', "utf16le"));
+			await fs.writeFile(path.join(scopedDir, "blob.bin"), Buffer.from("needle\0\x01"));
+
+			const result = await grep({ pattern: "needle", path: scopedDir, binary: "summary" });
+			expect(result.matches.map(match => [match.path, match.encoding, match.binary])).toEqual([
+				["blob.bin", undefined, true],
+				["strings.rc", "UTF-16LE", undefined],
+			]);
+			await fs.rm(scopedDir, { recursive: true, force: true });
+		});
+
+		it("should combine, negate and register file types", async () => {
+			const combined = await grep({ pattern: "export", path: testDir, type: "ts,!md", mode: "count" });
+			expect(combined.filesWithMatches).toBe(3);
+
+			registerFileType({ name: "history", globs: ["history-*.ts"] });
+			const custom = await grep({ pattern: "export", path: testDir, type: ["ts", "!history"], mode: "count" });
+			expect(custom.filesWithMatches).toBe(2);
+			expect(getFileTypes().some(def => def.name === "history")).toBe(true);
+			expect(unregisterFileType("history")).toBe(true);
+		});
+
+		it("should emit rg --json records", async () => {
+			const streamed: string[] = [];
+			const result = await grep({ pattern: "TODO", path: testDir, mode: "json" }, match => {
+				if (match.json) streamed.push(match.json);
+			});
+
+			const records = result.json!.trimEnd().split("\n").map(line => JSON.parse(line));
+			expect(records.map(record => record.type)).toEqual(["begin", "match", "end", "summary"]);
+			expect(records[1].data.path.text).toBe("file1.ts");
+			expect(records[1].data.submatches[0].match.text).toBe("TODO");
+			expect(streamed.length).toBe(4);
+			expect(result.matches.length).toBe(1);
+		});
+
 		it("should return filesWithMatches mode", async () => {
 			const result = await grep({
 				pattern: "return",
@@ -162,6 +290,236 @@ describe("pi-natives", () => {
 			expect(hiddenIncluded.matches.some(match => match.path.endsWith(".hidden-ignored.ts"))).toBe(true);
 		});
 	});
+	describe("grepReplace", () => {
+		it("should preview and apply regex replacements", async () => {
+			const scopedDir = await fs.mkdtemp(path.join(os.tmpdir(), "natives-replace-"));
+			const target = path.join(scopedDir, "calls.ts");
+			await fs.writeFile(target, "oldName(1);\nkeep();\noldName(2);\n");
+
+			const preview = await grepReplace({
+				pattern: "oldName\\((\\d)\\)",
+				replacement: "newName($1)",
+				path: scopedDir,
+			});
+			expect(preview.applied).toBe(false);
+			expect(preview.totalReplacements).toBe(2);
+			expect(preview.files[0].changes.map(change => change.after)).toEqual(["newName(1);", "newName(2);"]);
+			expect(await fs.readFile(target, "utf8")).toContain("oldName(1)");
+
+			const applied = await grepReplace({
+				pattern: "oldName\\((\\d)\\)",
+				replacement: "newName($1)",
+				path: scopedDir,
+				dryRun: false,
+			});
+			expect(applied.applied).toBe(true);
+			expect(await fs.readFile(target, "utf8")).toBe("newName(1);\nkeep();\nnewName(2);\n");
+
+			const literal = await grepReplace({
+				pattern: "newName(1)",
+				replacement: "first()",
+				path: scopedDir,
+				fixedStrings: true,
+			});
+			expect(literal.totalReplacements).toBe(1);
+			expect(literal.files[0].changes.map(change => change.after)).toEqual(["first();"]);
+			await fs.rm(scopedDir, { recursive: true, force: true });
+		});
+	});
+
+	describe("grep hunks", () => {
+		it("should merge overlapping context into one hunk", async () => {
+			const scopedDir = await fs.mkdtemp(path.join(os.tmpdir(), "natives-hunks-"));
+			await fs.writeFile(path.join(scopedDir, "notes.txt"), "a\nhit 1\nb\nhit 2\nc\n\n\n\nhit 3\n");
+
+			const result = await grep({ pattern: "hit", path: scopedDir, mode: "hunks", context: 1 });
+			expect(result.matches).toEqual([]);
+			expect(result.totalMatches).toBe(3);
+			expect(result.hunks?.map(hunk => [hunk.startLine, hunk.endLine])).toEqual([
+				[1, 5],
+				[8, 9],
+			]);
+			expect(result.hunks?.[0].lines.map(line => line.kind)).toEqual([
+				"context",
+				"match",
+				"context",
+				"match",
+				"context",
+			]);
+
+			const limited = await grep({ pattern: "hit", path: scopedDir, mode: "hunks", context: 1, maxCount: 1 });
+			expect(limited.hunks?.map(hunk => [hunk.startLine, hunk.endLine])).toEqual([[1, 3]]);
+			await expect(grep({ pattern: "hit", path: scopedDir, mode: "hunks" }, () => {})).rejects.toThrow("hunks");
+			await fs.rm(scopedDir, { recursive: true, force: true });
+		});
+	});
+
+	describe("grep syntax", () => {
+		it("should keep only hits in the requested syntax classes", async () => {
+			const scopedDir = await fs.mkdtemp(path.join(os.tmpdir(), "natives-syntax-"));
+			await fs.writeFile(
+				path.join(scopedDir, "user.ts"),
+				'// TODO user\nconst user = "user";\nconsole.log(`${user}`);\n',
+			);
+			await fs.writeFile(path.join(scopedDir, "notes.txt"), "user\n");
+
+			const code = await grep({ pattern: "user", path: scopedDir, syntax: "code" });
+			expect(code.matches.map(match => match.lineNumber)).toEqual([2, 3]);
+			expect(code.matches[0].submatches?.map(submatch => submatch.column)).toEqual([7]);
+
+			const comments = await grep({ pattern: "user", path: scopedDir, syntax: "comment" });
+			expect(comments.totalMatches).toBe(1);
+			expect(comments.matches[0].line).toBe("// TODO user");
+
+			const notCode = await grep({ pattern: "user", path: scopedDir, syntax: ["!code"] });
+			expect(notCode.matches.map(match => match.lineNumber)).toEqual([1, 2]);
+
+			await expect(grep({ pattern: "user", path: scopedDir, syntax: "docs" as "code" })).rejects.toThrow();
+			await fs.rm(scopedDir, { recursive: true, force: true });
+		});
+	});
+
+	describe("outline", () => {
+		it("should list nested declarations with kinds and parents", async () => {
+			const result = await outline({
+				lang: "typescript",
+				content: "export class A {\n\tm(x: number) {\n\t\treturn x;\n\t}\n}\nfunction f() {}\n",
+			});
+			expect(result.lang).toBe("typescript");
+			expect(result.symbols.map(symbol => [symbol.kind, symbol.name, symbol.parent])).toEqual([
+				["class", "A", undefined],
+				["method", "m", 0],
+				["function", "f", undefined],
+			]);
+			expect(result.symbols[1].signature).toBe("m(x: number)");
+			expect([result.symbols[0].startLine, result.symbols[0].endLine]).toEqual([1, 5]);
+			await expect(outline({ content: "x" })).rejects.toThrow();
+		});
+	});
+
+	describe("treeSitterQuery", () => {
+		it("should return captures filtered by text predicates", async () => {
+			const result = await treeSitterQuery({
+				lang: "rust",
+				content: "fn main() {}\nfn helper_one() {}\n",
+				query: '(function_item name: (identifier) @name (#match? @name "^helper")) @item',
+			});
+			expect(result.captureNames).toEqual(["name", "item"]);
+			expect(result.totalMatches).toBe(1);
+			const [item, name] = result.matches[0].captures;
+			expect([item.name, item.kind]).toEqual(["item", "function_item"]);
+			expect([name.text, name.byteStart, name.startRow, name.startColumn]).toEqual(["helper_one", 16, 1, 3]);
+			await expect(treeSitterQuery({ lang: "rust", content: "", query: "(nope) @x" })).rejects.toThrow();
+		});
+	});
+
+	describe("validateSyntax", () => {
+		it("should report missing and unexpected tokens with locations", async () => {
+			const valid = await validateSyntax({ lang: "rust", content: "fn main() {\n\tlet x = 1;\n}\n" });
+			expect(valid.valid).toBe(true);
+			expect(valid.errors).toEqual([]);
+
+			const broken = await validateSyntax({ path: "main.rs", content: "fn main() {\n\tlet x = 1\n}\n" });
+			expect(broken.valid).toBe(false);
+			expect(broken.errors[0]).toMatchObject({
+				kind: "missing",
+				message: "missing `;`",
+				parentKind: "let_declaration",
+				startLine: 2,
+				startColumn: 11,
+				excerpt: "let x = 1",
+			});
+		});
+	});
+
+	describe("structuralDiff", () => {
+		it("should describe declaration changes and ignore formatting", async () => {
+			const before = "fn foo() -> u32 {\n    1\n}\n\nfn bar(a: u32) {}\n";
+			const reformatted = await structuralDiff({
+				lang: "rust",
+				before,
+				after: "fn foo() -> u32 { 1 }\nfn bar(a: u32) {}\n",
+			});
+			expect(reformatted.unchanged).toBe(true);
+
+			const after = "fn foo() -> u32 {\n    2\n}\n\nfn bar(a: u32, b: u32) {}\n\nfn baz() {}\n";
+			const result = await structuralDiff({ path: "lib.rs", before, after });
+			expect(result.changes.map(change => change.summary)).toEqual([
+				"function foo: body changed",
+				"function bar: parameter `b` added",
+				"function baz added",
+			]);
+			expect(result.changes[2]).toMatchObject({ kind: "added", symbolKind: "function", afterStartLine: 7 });
+		});
+	});
+
+	describe("grammars", () => {
+		it("should list built-in grammars and reject unusable registrations", () => {
+			const typescript = getGrammars().find(grammar => grammar.name === "typescript");
+			expect(typescript?.builtin).toBe(true);
+			expect(typescript?.aliases).toContain("ts");
+			expect(() => registerGrammar({ name: "rust", path: "/nonexistent.so" })).toThrow("built-in");
+			expect(() => registerGrammar({ name: "mylang", path: "/nonexistent/libmylang.so" })).toThrow(
+				"Failed to load grammar library",
+			);
+		});
+	});
+
+	describe("grep index", () => {
+		it("should prune candidates and pick up changed files", async () => {
+			const scopedDir = await fs.mkdtemp(path.join(os.tmpdir(), "natives-index-"));
+			await fs.writeFile(path.join(scopedDir, "a.ts"), "const needle = 1;\n");
+			await fs.writeFile(path.join(scopedDir, "b.ts"), "const haystack = 2;\n");
+
+			const built = await buildGrepIndex({ path: scopedDir });
+			expect(built.files).toBe(2);
+			expect(built.diskBytes).toBeGreaterThan(0);
+
+			const pruned = await grep({ pattern: "needle", path: scopedDir, index: true });
+			expect(pruned.totalMatches).toBe(1);
+			expect(pruned.filesSearched).toBe(1);
+
+			await fs.writeFile(path.join(scopedDir, "b.ts"), "const needles = 2;\n");
+			const refreshed = await grep({ pattern: "needle", path: scopedDir, index: true });
+			expect(refreshed.totalMatches).toBe(2);
+
+			await fs.rm(path.join(scopedDir, "a.ts"));
+			const updated = await updateGrepIndex({ path: scopedDir });
+			expect(updated.files).toBe(1);
+			expect(getGrepIndexStats(scopedDir)?.files).toBe(1);
+
+			expect(dropGrepIndex(scopedDir)).toBe(true);
+			expect(getGrepIndexStats(scopedDir)).toBeNull();
+			await fs.rm(scopedDir, { recursive: true, force: true });
+		});
+	});
+
+	describe("grep at git revisions", () => {
+		it("should search committed blobs and only changed files", async () => {
+			const repo = await fs.mkdtemp(path.join(os.tmpdir(), "natives-git-"));
+			const git = (...args: string[]) =>
+				execFileSync("git", ["-c", "user.name=test", "-c", "user.email=test@example.com", ...args], {
+					cwd: repo,
+				});
+			git("init", "-q");
+			await fs.writeFile(path.join(repo, "a.ts"), "legacyCall();\n");
+			await fs.writeFile(path.join(repo, "b.ts"), "legacyCall();\n");
+			git("add", ".");
+			git("commit", "-q", "--no-gpg-sign", "-m", "init");
+			await fs.writeFile(path.join(repo, "a.ts"), "modernCall();\n");
+
+			const atHead = await grep({ pattern: "legacyCall", path: repo, rev: "HEAD" });
+			expect(atHead.matches.map(match => match.path)).toEqual(["a.ts", "b.ts"]);
+
+			const working = await grep({ pattern: "Call", path: repo, changed: "head" });
+			expect(working.matches.map(match => match.line)).toEqual(["modernCall();"]);
+
+			const oldChanged = await grep({ pattern: "legacyCall", path: repo, rev: "HEAD", changed: "head" });
+			expect(oldChanged.matches.map(match => match.path)).toEqual(["a.ts"]);
+			await fs.rm(repo, { recursive: true, force: true });
+		});
+	});
+
 	describe("fuzzyFind", () => {
 		it("should match abbreviated fuzzy queries across separators", async () => {
 			const result = await fuzzyFind({
@@ -187,6 +545,16 @@ describe("pi-natives", () => {
 			expect(result.matches.every((m: GlobMatch) => m.path.endsWith(".ts"))).toBe(true);
 		});
 
+		it("should apply negated and excluded globs", async () => {
+			const result = await glob({
+				pattern: ["*.ts", "*.md", "!history-*.ts"],
+				path: testDir,
+				exclude: ["readme.md"],
+			});
+
+			expect(result.matches.map(m => m.path).sort()).toEqual(["file1.ts", "file2.ts"]);
+		});
+
 		it("should filter by file type", async () => {
 			const result = await glob({
 				pattern: "*",